@@ -9,16 +9,34 @@
 //!     PHONE_AGENT_API_KEY: API key for model authentication (default: EMPTY)
 //!     PHONE_AGENT_MAX_STEPS: Maximum steps per task (default: 100)
 //!     PHONE_AGENT_DEVICE_ID: ADB device ID for multi-device setups
+//!     PHONE_AGENT_ADB_PATH: Path to the adb binary (default: adb from PATH)
+//!     PHONE_AGENT_ADB_PORT: Port for --connect addresses given without one
+//!     PHONE_AGENT_IDLE_TIMEOUT: Seconds without input before interactive mode exits
+//!     RUST_LOG: Structured log filter, e.g. `phone_agent=debug` (default: warn)
+//!
+//! Custom apps are loaded from `~/.config/autoglm/apps.toml` if it exists.
 
 use anyhow::{anyhow, Result};
 use clap::Parser;
+use phone_agent::diagnostics::backend_versions;
+use phone_agent::xctest::{check_wda_status, wda_info};
 use phone_agent::{
-    list_supported_apps, set_device_type, AdbConnection, AgentConfig, DeviceType, Language,
-    ModelClient, ModelConfig, PhoneAgent,
+    get_adb_path, get_device_factory, get_message, list_supported_apps, load_app_overrides,
+    set_adb_path, set_device_type, AdbConnection, AgentConfig, ConfirmationCallback, DeviceType,
+    JobSpec, Language, ModelClient, ModelConfig, PhoneAgent, ScreenshotEncoding, StepCallback,
+    StepResult, TakeoverCallback,
 };
 use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
 use tokio::process::Command;
+use tokio::sync::{oneshot, watch};
+use tokio::time::MissedTickBehavior;
+use tracing_subscriber::EnvFilter;
 
 /// Phone Agent - AI-powered phone automation
 #[derive(Parser, Debug)]
@@ -51,6 +69,15 @@ use tokio::process::Command;
 
     # Run a specific task
     autoglm "Open WeChat and send a message"
+
+    # Run a task with settings from a job file
+    autoglm --job job.yaml
+
+    # Save the current screen as a PNG and exit
+    autoglm --screenshot screen.png
+
+    # Watch the screen in a browser at http://127.0.0.1:8080/ while a task runs
+    autoglm --watch "Open WeChat and send a message"
 "#)]
 struct Cli {
     // Model options
@@ -75,10 +102,18 @@ struct Cli {
     #[arg(short = 'd', long, env = "PHONE_AGENT_DEVICE_ID")]
     device_id: Option<String>,
 
+    /// Path to the adb binary (default: adb from PATH)
+    #[arg(long, env = "PHONE_AGENT_ADB_PATH", value_name = "PATH")]
+    adb_path: Option<String>,
+
     /// Connect to remote device (e.g., 192.168.1.100:5555)
     #[arg(short = 'c', long, value_name = "ADDRESS")]
     connect: Option<String>,
 
+    /// After --connect, wait up to this many seconds for the device to be ready
+    #[arg(long, env = "PHONE_AGENT_CONNECT_WAIT", value_name = "SECONDS")]
+    connect_wait: Option<u64>,
+
     /// Disconnect from remote device (or 'all' to disconnect all)
     #[arg(long, value_name = "ADDRESS", num_args = 0..=1, default_missing_value = "all")]
     disconnect: Option<String>,
@@ -109,10 +144,18 @@ struct Cli {
     #[arg(short = 'q', long)]
     quiet: bool,
 
+    /// Output format: text, or json for one JSON object per step and a final result
+    #[arg(long, default_value = "text", value_parser = ["text", "json"])]
+    format: String,
+
     /// List supported apps and exit
     #[arg(long)]
     list_apps: bool,
 
+    /// Print versions of the device tools (for bug reports) and exit
+    #[arg(long)]
+    versions: bool,
+
     /// Language for system prompt (cn or en, default: cn)
     #[arg(long, env = "PHONE_AGENT_LANG", default_value = "cn", value_parser = ["cn", "en"])]
     lang: String,
@@ -125,78 +168,85 @@ struct Cli {
     #[arg(long, env = "PHONE_AGENT_SCREENSHOT_DIR")]
     screenshot_dir: Option<String>,
 
-    /// Task to execute (interactive mode if not provided)
-    task: Option<String>,
-}
+    /// Save each session in --screenshot-dir as one compressed .session.gz file
+    #[arg(long, env = "PHONE_AGENT_COMPRESS_SESSIONS")]
+    compress_sessions: bool,
 
-/// Device type enum matching Python's DeviceType
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum CliDeviceType {
-    Adb,
-    Hdc,
-    Ios,
-}
+    /// Fail Type actions when no text field appears to be focused
+    #[arg(long, env = "PHONE_AGENT_TEXT_FOCUS_CHECK")]
+    text_focus_check: bool,
 
-impl CliDeviceType {
-    fn from_str(s: &str) -> Result<Self> {
-        match s.to_lowercase().as_str() {
-            "adb" => Ok(Self::Adb),
-            "hdc" => Ok(Self::Hdc),
-            "ios" => Ok(Self::Ios),
-            _ => Err(anyhow!("Invalid device type: {}", s)),
-        }
-    }
+    /// Capture the current screen to a PNG file and exit (no model server needed)
+    #[arg(long, value_name = "PATH")]
+    screenshot: Option<String>,
 
-    fn tool_name(&self) -> &'static str {
-        match self {
-            Self::Adb => "ADB",
-            Self::Hdc => "HDC",
-            Self::Ios => "libimobiledevice",
-        }
-    }
+    /// Stream the screen as MJPEG on this local port (default: 8080); without a task, only stream
+    #[arg(long, value_name = "PORT", num_args = 0..=1, default_missing_value = "8080")]
+    watch: Option<u16>,
 
-    fn tool_cmd(&self) -> &'static str {
-        match self {
-            Self::Adb => "adb",
-            Self::Hdc => "hdc",
-            Self::Ios => "idevice_id",
-        }
-    }
+    /// Milliseconds between frames captured for --watch
+    #[arg(long, value_name = "MS", default_value = "500")]
+    watch_interval: u64,
+
+    /// Exit interactive mode after this many seconds without input
+    #[arg(long, env = "PHONE_AGENT_IDLE_TIMEOUT", value_name = "SECS")]
+    idle_timeout: Option<u64>,
+
+    /// Write the resolved prompt, model and timing settings to this JSON file
+    #[arg(long, value_name = "PATH")]
+    dump_config: Option<String>,
+
+    /// Job file (YAML, TOML or JSON) with task and settings; overrides matching flags
+    #[arg(long, value_name = "FILE")]
+    job: Option<String>,
+
+    /// Task to execute (interactive mode if not provided)
+    task: Option<String>,
 }
 
 /// Check system requirements before running the agent
-async fn check_system_requirements(device_type: CliDeviceType, wda_url: &str) -> bool {
+///
+/// When `device_id` is set, that device must be connected and is the one
+/// checked for ADB Keyboard.
+async fn check_system_requirements(
+    device_type: DeviceType,
+    device_id: Option<&str>,
+    wda_url: &str,
+) -> bool {
     println!("\u{1F50D} Checking system requirements...");
     println!("{}", "-".repeat(50));
 
     let mut all_passed = true;
 
     let tool_name = device_type.tool_name();
-    let tool_cmd = device_type.tool_cmd();
+    let tool_cmd = match device_type {
+        DeviceType::Adb => get_adb_path(),
+        _ => device_type.tool_cmd().to_string(),
+    };
 
     // Check 1: Tool installed
     print!("1. Checking {} installation... ", tool_name);
     io::stdout().flush().ok();
 
-    if which::which(tool_cmd).is_err() {
+    if which::which(&tool_cmd).is_err() {
         println!("\u{274C} FAILED");
         println!("   Error: {} is not installed or not in PATH.", tool_name);
         println!("   Solution: Install {}:", tool_name);
         match device_type {
-            CliDeviceType::Adb => {
+            DeviceType::Adb => {
                 println!("     - macOS: brew install android-platform-tools");
                 println!("     - Linux: sudo apt install android-tools-adb");
                 println!(
                     "     - Windows: Download from https://developer.android.com/studio/releases/platform-tools"
                 );
             }
-            CliDeviceType::Hdc => {
+            DeviceType::Hdc => {
                 println!(
                     "     - Download from HarmonyOS SDK or https://gitee.com/openharmony/docs"
                 );
                 println!("     - Add to PATH environment variable");
             }
-            CliDeviceType::Ios => {
+            DeviceType::Ios => {
                 println!("     - macOS: brew install libimobiledevice");
                 println!("     - Linux: sudo apt-get install libimobiledevice-utils");
             }
@@ -205,24 +255,24 @@ async fn check_system_requirements(device_type: CliDeviceType, wda_url: &str) ->
     } else {
         // Double check by running version command
         let version_result = match device_type {
-            CliDeviceType::Adb => {
+            DeviceType::Adb => {
                 tokio::time::timeout(
                     Duration::from_secs(10),
-                    Command::new(tool_cmd).arg("version").output(),
+                    Command::new(&tool_cmd).arg("version").output(),
                 )
                 .await
             }
-            CliDeviceType::Hdc => {
+            DeviceType::Hdc => {
                 tokio::time::timeout(
                     Duration::from_secs(10),
-                    Command::new(tool_cmd).arg("-v").output(),
+                    Command::new(&tool_cmd).arg("-v").output(),
                 )
                 .await
             }
-            CliDeviceType::Ios => {
+            DeviceType::Ios => {
                 tokio::time::timeout(
                     Duration::from_secs(10),
-                    Command::new(tool_cmd).arg("-l").output(),
+                    Command::new(&tool_cmd).arg("-l").output(),
                 )
                 .await
             }
@@ -270,30 +320,39 @@ async fn check_system_requirements(device_type: CliDeviceType, wda_url: &str) ->
     io::stdout().flush().ok();
 
     let devices_result = match device_type {
-        CliDeviceType::Adb => check_adb_devices().await,
-        CliDeviceType::Hdc => check_hdc_devices().await,
-        CliDeviceType::Ios => check_ios_devices().await,
+        DeviceType::Adb => check_adb_devices().await,
+        DeviceType::Hdc => check_hdc_devices().await,
+        DeviceType::Ios => check_ios_devices().await,
     };
 
     match devices_result {
+        Ok(devices) if device_id.is_some_and(|id| !devices.iter().any(|d| d == id)) => {
+            println!("\u{274C} FAILED");
+            println!(
+                "   Error: Device {} is not connected.",
+                device_id.unwrap_or_default()
+            );
+            println!("   Connected: {}", devices.join(", "));
+            all_passed = false;
+        }
         Ok(devices) if devices.is_empty() => {
             println!("\u{274C} FAILED");
             println!("   Error: No devices connected.");
             println!("   Solution:");
             match device_type {
-                CliDeviceType::Adb => {
+                DeviceType::Adb => {
                     println!("     1. Enable USB debugging on your Android device");
                     println!("     2. Connect via USB and authorize the connection");
                     println!("     3. Or connect remotely: autoglm --connect <ip>:<port>");
                 }
-                CliDeviceType::Hdc => {
+                DeviceType::Hdc => {
                     println!("     1. Enable USB debugging on your HarmonyOS device");
                     println!("     2. Connect via USB and authorize the connection");
                     println!(
                         "     3. Or connect remotely: autoglm --device-type hdc --connect <ip>:<port>"
                     );
                 }
-                CliDeviceType::Ios => {
+                DeviceType::Ios => {
                     println!("     1. Connect your iOS device via USB");
                     println!("     2. Unlock device and tap 'Trust This Computer'");
                     println!("     3. Verify: idevice_id -l");
@@ -327,16 +386,19 @@ async fn check_system_requirements(device_type: CliDeviceType, wda_url: &str) ->
 
     // Check 3: ADB Keyboard (for ADB) or WebDriverAgent (for iOS) or skip for HDC
     match device_type {
-        CliDeviceType::Adb => {
+        DeviceType::Adb => {
             print!("3. Checking ADB Keyboard... ");
             io::stdout().flush().ok();
 
-            match check_adb_keyboard().await {
+            match check_adb_keyboard(device_id).await {
                 Ok(true) => println!("\u{2705} OK"),
                 Ok(false) => {
-                    println!("\u{274C} FAILED");
-                    println!("   Error: ADB Keyboard is not installed on the device.");
-                    println!("   Solution:");
+                    println!("\u{26A0}\u{FE0F} WARNING");
+                    println!("   ADB Keyboard is not installed on the device.");
+                    println!(
+                        "   ASCII text is typed with `input text`; other text needs ADB Keyboard."
+                    );
+                    println!("   To install it:");
                     println!("     1. Download ADB Keyboard APK from:");
                     println!(
                         "        https://github.com/senzhk/ADBKeyBoard/blob/master/ADBKeyboard.apk"
@@ -345,21 +407,19 @@ async fn check_system_requirements(device_type: CliDeviceType, wda_url: &str) ->
                     println!(
                         "     3. Enable it in Settings > System > Languages & Input > Virtual Keyboard"
                     );
-                    all_passed = false;
                 }
                 Err(e) => {
-                    println!("\u{274C} FAILED");
-                    println!("   Error: {}", e);
-                    all_passed = false;
+                    println!("\u{26A0}\u{FE0F} WARNING");
+                    println!("   Could not check for ADB Keyboard: {}", e);
                 }
             }
         }
-        CliDeviceType::Hdc => {
+        DeviceType::Hdc => {
             print!("3. Skipping keyboard check for HarmonyOS... ");
             io::stdout().flush().ok();
             println!("\u{2705} OK (using native input)");
         }
-        CliDeviceType::Ios => {
+        DeviceType::Ios => {
             print!("3. Checking WebDriverAgent ({})... ", wda_url);
             io::stdout().flush().ok();
 
@@ -401,7 +461,7 @@ async fn check_system_requirements(device_type: CliDeviceType, wda_url: &str) ->
 async fn check_adb_devices() -> Result<Vec<String>> {
     let output = tokio::time::timeout(
         Duration::from_secs(10),
-        Command::new("adb").arg("devices").output(),
+        Command::new(get_adb_path()).arg("devices").output(),
     )
     .await
     .map_err(|_| anyhow!("adb devices timeout"))??;
@@ -456,15 +516,14 @@ async fn check_ios_devices() -> Result<Vec<String>> {
 }
 
 /// Check if ADB Keyboard is installed
-async fn check_adb_keyboard() -> Result<bool> {
+async fn check_adb_keyboard(device_id: Option<&str>) -> Result<bool> {
+    let mut cmd = Command::new(get_adb_path());
+    if let Some(id) = device_id {
+        cmd.arg("-s").arg(id);
+    }
     let output = tokio::time::timeout(
         Duration::from_secs(10),
-        Command::new("adb")
-            .arg("shell")
-            .arg("ime")
-            .arg("list")
-            .arg("-s")
-            .output(),
+        cmd.arg("shell").arg("ime").arg("list").arg("-s").output(),
     )
     .await
     .map_err(|_| anyhow!("adb shell ime list timeout"))??;
@@ -473,30 +532,6 @@ async fn check_adb_keyboard() -> Result<bool> {
     Ok(stdout.contains("com.android.adbkeyboard/.AdbIME"))
 }
 
-/// Check WebDriverAgent status
-async fn check_wda_status(wda_url: &str) -> Result<bool> {
-    // Simple HTTP check - in production would use reqwest
-    let status_url = format!("{}/status", wda_url.trim_end_matches('/'));
-
-    // Use curl for simplicity
-    let output = tokio::time::timeout(
-        Duration::from_secs(5),
-        Command::new("curl")
-            .arg("-s")
-            .arg("-o")
-            .arg("/dev/null")
-            .arg("-w")
-            .arg("%{http_code}")
-            .arg(&status_url)
-            .output(),
-    )
-    .await
-    .map_err(|_| anyhow!("WDA status check timeout"))??;
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    Ok(stdout.trim() == "200")
-}
-
 /// Check if the model API is accessible
 async fn check_model_api(base_url: &str, model_name: &str, api_key: &str) -> bool {
     println!("\u{1F50D} Checking model API...");
@@ -507,9 +542,12 @@ async fn check_model_api(base_url: &str, model_name: &str, api_key: &str) -> boo
 
     // Create model client and send a test request
     let model_config = ModelConfig::new(base_url, model_name).with_api_key(api_key);
-    let client = ModelClient::new(model_config);
+    let result = match ModelClient::new(model_config) {
+        Ok(client) => client.test_connection().await,
+        Err(e) => Err(e),
+    };
 
-    match client.test_connection().await {
+    match result {
         Ok(_) => {
             println!("\u{2705} OK");
             println!("{}", "-".repeat(50));
@@ -544,15 +582,15 @@ async fn check_model_api(base_url: &str, model_name: &str, api_key: &str) -> boo
 
 /// Handle device-related commands
 async fn handle_device_commands(args: &Cli) -> Result<bool> {
-    let device_type = CliDeviceType::from_str(&args.device_type)?;
+    let device_type = args.device_type.parse::<DeviceType>()?;
 
     // Handle iOS-specific commands
-    if device_type == CliDeviceType::Ios {
+    if device_type == DeviceType::Ios {
         return handle_ios_device_commands(args).await;
     }
 
     // Handle HDC-specific commands
-    if device_type == CliDeviceType::Hdc {
+    if device_type == DeviceType::Hdc {
         return handle_hdc_device_commands(args).await;
     }
 
@@ -567,10 +605,11 @@ async fn handle_device_commands(args: &Cli) -> Result<bool> {
             println!("Connected devices:");
             println!("{}", "-".repeat(60));
             for device in devices {
-                let status_icon = if device.status == "device" {
-                    "\u{2713}"
+                let status = device.device_status();
+                let (status_icon, status_info) = if status.is_ready() {
+                    ("\u{2713}", String::new())
                 } else {
-                    "\u{2717}"
+                    ("\u{2717}", format!(" - {}", status.describe()))
                 };
                 let conn_type = format!("{:?}", device.connection_type);
                 let model_info = device
@@ -578,8 +617,8 @@ async fn handle_device_commands(args: &Cli) -> Result<bool> {
                     .map(|m| format!(" ({})", m))
                     .unwrap_or_default();
                 println!(
-                    "  {} {:<30} [{}]{}",
-                    status_icon, device.device_id, conn_type, model_info
+                    "  {} {:<30} [{}]{}{}",
+                    status_icon, device.device_id, conn_type, model_info, status_info
                 );
             }
         }
@@ -592,6 +631,19 @@ async fn handle_device_commands(args: &Cli) -> Result<bool> {
         match conn.connect(addr, 10).await {
             Ok(msg) => {
                 println!("\u{2713} {}", msg);
+                if let Some(secs) = args.connect_wait {
+                    let device_id = conn.device_address(addr)?;
+                    let wait = conn.wait_for_device(
+                        Some(&device_id),
+                        Duration::from_secs(secs),
+                        Duration::from_millis(500),
+                    );
+                    if let Err(e) = wait.await {
+                        println!("\u{2717} {}", e);
+                        return Ok(true);
+                    }
+                    println!("\u{2713} {} is ready", device_id);
+                }
                 return Ok(false); // Continue if connection succeeded
             }
             Err(e) => {
@@ -645,6 +697,140 @@ async fn handle_device_commands(args: &Cli) -> Result<bool> {
     Ok(false)
 }
 
+/// Capture the current screen of the device and write it to `path` as a PNG
+async fn capture_screenshot(
+    device_type: DeviceType,
+    device_id: Option<&str>,
+    path: &str,
+) -> Result<()> {
+    if device_type != DeviceType::Adb {
+        return Err(anyhow!(
+            "--screenshot is not yet supported for {} devices",
+            device_type.tool_name()
+        ));
+    }
+
+    let screenshot = get_device_factory()
+        .read()
+        .await
+        .get_screenshot(device_id, 10)
+        .await?;
+    screenshot.save(path)?;
+    println!(
+        "\u{2713} Saved {}x{} screenshot to {}",
+        screenshot.width, screenshot.height, path
+    );
+    Ok(())
+}
+
+/// JPEG quality of the frames streamed by --watch
+const WATCH_JPEG_QUALITY: u8 = 70;
+
+/// Serve the device screen as an MJPEG stream on `127.0.0.1:<port>`
+///
+/// Frames are captured every `interval` while at least one viewer is
+/// connected. Runs until the process exits.
+async fn serve_screen(port: u16, interval: Duration, device_id: Option<String>) -> Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+    let (frames_tx, frames_rx) = watch::channel(Arc::new(Vec::new()));
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            // The accept loop holds one receiver; any other is a viewer
+            if frames_tx.receiver_count() <= 1 {
+                continue;
+            }
+            match capture_frame(device_id.as_deref()).await {
+                Ok(frame) => {
+                    frames_tx.send_replace(Arc::new(frame));
+                }
+                Err(e) => eprintln!("Failed to capture the screen: {}", e),
+            }
+        }
+    });
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let frames = frames_rx.clone();
+        tokio::spawn(async move {
+            // Viewers closing the page end the stream with a write error
+            let _ = stream_frames(stream, frames).await;
+        });
+    }
+}
+
+/// Capture the screen as a JPEG frame
+async fn capture_frame(device_id: Option<&str>) -> Result<Vec<u8>> {
+    let image = get_device_factory()
+        .read()
+        .await
+        .get_screenshot_image(device_id, 10)
+        .await?;
+    Ok(ScreenshotEncoding::Jpeg(WATCH_JPEG_QUALITY).encode(&image)?)
+}
+
+/// Answer an HTTP request with a `multipart/x-mixed-replace` stream of frames
+///
+/// Every path gets the stream, so the URL can be opened directly in a browser.
+async fn stream_frames(
+    mut stream: TcpStream,
+    mut frames: watch::Receiver<Arc<Vec<u8>>>,
+) -> io::Result<()> {
+    let mut request = [0u8; 1024];
+    let _ = stream.read(&mut request).await?;
+
+    stream
+        .write_all(
+            b"HTTP/1.1 200 OK\r\n\
+              Content-Type: multipart/x-mixed-replace; boundary=frame\r\n\
+              Cache-Control: no-cache\r\n\
+              Connection: close\r\n\r\n",
+        )
+        .await?;
+
+    loop {
+        if frames.changed().await.is_err() {
+            return Ok(());
+        }
+        let frame = frames.borrow_and_update().clone();
+        let header = format!(
+            "--frame\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+            frame.len()
+        );
+        stream.write_all(header.as_bytes()).await?;
+        stream.write_all(&frame).await?;
+        stream.write_all(b"\r\n").await?;
+    }
+}
+
+/// Print versions of the device tools, the device OS and WebDriverAgent
+async fn print_versions(
+    device_type: DeviceType,
+    device_id: Option<&str>,
+    wda_url: &str,
+) -> Result<()> {
+    let versions = backend_versions(device_type, device_id, Some(wda_url)).await?;
+    let unknown = || "unknown".to_string();
+
+    println!("autoglm {}", env!("CARGO_PKG_VERSION"));
+    println!(
+        "{}: {}",
+        device_type.tool_name(),
+        versions.client.unwrap_or_else(unknown)
+    );
+    if device_type == DeviceType::Adb {
+        println!("ADB server: {}", versions.server.unwrap_or_else(unknown));
+    }
+    println!("Device OS: {}", versions.device_os.unwrap_or_else(unknown));
+    if device_type == DeviceType::Ios {
+        println!("WebDriverAgent: {}", versions.wda.unwrap_or_else(unknown));
+    }
+    Ok(())
+}
+
 /// Handle iOS device commands
 async fn handle_ios_device_commands(args: &Cli) -> Result<bool> {
     // Handle --list-devices
@@ -669,6 +855,14 @@ async fn handle_ios_device_commands(args: &Cli) -> Result<bool> {
         match check_wda_status(&args.wda_url).await {
             Ok(true) => {
                 println!("\u{2713} WebDriverAgent is running");
+                if let Ok(info) = wda_info(&args.wda_url).await {
+                    if let (Some(os), Some(version)) = (&info.os_name, &info.os_version) {
+                        println!("  Device: {} {}", os, version);
+                    }
+                    if let Some(session_id) = &info.session_id {
+                        println!("  Session: {}", session_id);
+                    }
+                }
             }
             Ok(false) => {
                 println!("\u{2717} WebDriverAgent is not running");
@@ -735,9 +929,9 @@ async fn handle_hdc_device_commands(args: &Cli) -> Result<bool> {
 }
 
 /// Print supported apps
-fn print_supported_apps(device_type: CliDeviceType) {
+fn print_supported_apps(device_type: DeviceType) {
     match device_type {
-        CliDeviceType::Adb => {
+        DeviceType::Adb => {
             println!("Supported Android apps:");
             let mut apps: Vec<_> = list_supported_apps();
             apps.sort();
@@ -745,11 +939,11 @@ fn print_supported_apps(device_type: CliDeviceType) {
                 println!("  - {}", app);
             }
         }
-        CliDeviceType::Hdc => {
+        DeviceType::Hdc => {
             println!("Supported HarmonyOS apps:");
             println!("  (HarmonyOS app list not yet implemented)");
         }
-        CliDeviceType::Ios => {
+        DeviceType::Ios => {
             println!("Supported iOS apps:");
             println!("\nNote: For iOS apps, Bundle IDs are configured in:");
             println!("  phone_agent/config/apps_ios.py");
@@ -776,7 +970,7 @@ fn print_header(args: &Cli, model_config: &ModelConfig, agent_config: &AgentConf
         println!("WDA URL: {}", args.wda_url);
     }
 
-    if let Some(device_id) = &args.device_id {
+    if let Some(device_id) = &agent_config.device_id {
         println!("Device: {}", device_id);
     }
 
@@ -788,29 +982,46 @@ fn print_header(args: &Cli, model_config: &ModelConfig, agent_config: &AgentConf
 }
 
 /// Run interactive mode
-async fn run_interactive_mode(agent: &mut PhoneAgent) -> Result<()> {
-    println!("\nEntering interactive mode. Type 'quit' to exit.\n");
+///
+/// With `idle_timeout`, exits when no task is entered for that long.
+async fn run_interactive_mode(
+    agent: &mut PhoneAgent,
+    idle_timeout: Option<Duration>,
+) -> Result<()> {
+    println!("\nEntering interactive mode. Type 'quit' to exit.");
+    println!("Start a task with '+' to continue from the previous one.\n");
 
-    let stdin = io::stdin();
     let mut stdout = io::stdout();
+    let mut has_run = false;
 
     loop {
         print!("Enter your task: ");
         stdout.flush()?;
 
-        let mut input = String::new();
-        match stdin.lock().read_line(&mut input) {
-            Ok(0) => {
+        let line = read_stdin_line();
+        let line = match idle_timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, line).await {
+                Ok(line) => line,
+                Err(_) => {
+                    println!("\n\nNo input for {} seconds. Goodbye!", timeout.as_secs());
+                    break;
+                }
+            },
+            None => line.await,
+        };
+
+        let input = match line {
+            Ok(Ok(Some(input))) => input,
+            Ok(Ok(None)) => {
                 // EOF
                 println!("\nGoodbye!");
                 break;
             }
-            Ok(_) => {}
-            Err(_) => {
+            _ => {
                 println!("\n\nInterrupted. Goodbye!");
                 break;
             }
-        }
+        };
 
         let task = input.trim();
 
@@ -827,16 +1038,147 @@ async fn run_interactive_mode(agent: &mut PhoneAgent) -> Result<()> {
         }
 
         println!();
-        match agent.run(task).await {
+        let result = match task.strip_prefix('+') {
+            Some(follow_up) => agent.continue_run(follow_up.trim()).await,
+            None => {
+                if has_run {
+                    agent.reset().await;
+                }
+                agent.run(task).await
+            }
+        };
+        has_run = true;
+        match result {
             Ok(result) => println!("\nResult: {}\n", result),
             Err(e) => eprintln!("\nError: {}\n", e),
         }
-        agent.reset().await;
     }
 
     Ok(())
 }
 
+/// Run a task, printing each step and the outcome as JSON lines on stdout
+///
+/// Steps are `{"type":"step","step":N,...}` objects with the fields of
+/// `StepResult`; the run ends with a `{"type":"result",...}` object holding
+/// the `RunOutcome`, or a `{"type":"error","message":..}` object.
+async fn run_json_mode(agent: &mut PhoneAgent, task: &str) -> Result<()> {
+    let steps = AtomicUsize::new(0);
+    agent.set_step_callback(Box::new(move |result| {
+        let step = steps.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut value = serde_json::to_value(result).unwrap_or_default();
+        value["step"] = step.into();
+        print_json_line("step", value);
+    }));
+
+    match agent.run_chain(&[task]).await {
+        Ok(outcomes) => {
+            let value = serde_json::to_value(&outcomes[0]).unwrap_or_default();
+            print_json_line("result", value);
+            Ok(())
+        }
+        Err(e) => {
+            print_json_line("error", serde_json::json!({ "message": e.to_string() }));
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Print streamed thinking, preceded by a heading on a step's first delta
+fn thinking_printer(shown: Arc<AtomicBool>, lang: Language) -> impl Fn(&str) + Send + Sync {
+    move |delta| {
+        if !shown.swap(true, Ordering::Relaxed) {
+            println!("\n{}", "=".repeat(50));
+            println!("\u{1F4AD} {}:", get_message("thinking", lang));
+            println!("{}", "-".repeat(50));
+        }
+        print!("{}", delta);
+        io::stdout().flush().ok();
+    }
+}
+
+/// Print each step's action, and the outcome once the task finishes
+fn step_printer(thinking_shown: Arc<AtomicBool>, lang: Language) -> StepCallback {
+    Box::new(move |result: &StepResult| {
+        if thinking_shown.swap(false, Ordering::Relaxed) {
+            println!();
+        }
+        if let Some(action) = &result.action {
+            println!("{}", "-".repeat(50));
+            println!("\u{1F3AF} {}:", get_message("action", lang));
+            println!(
+                "{}",
+                serde_json::to_string_pretty(action).unwrap_or_else(|_| format!("{:?}", action))
+            );
+            println!("{}\n", "=".repeat(50));
+        }
+
+        if result.finished {
+            let message = result
+                .message
+                .as_deref()
+                .unwrap_or_else(|| get_message("done", lang));
+            if result.success {
+                println!("\n\u{1F389} {}", "=".repeat(48));
+                println!(
+                    "\u{2705} {}: {}",
+                    get_message("task_completed", lang),
+                    message
+                );
+            } else {
+                println!("\n{}", "=".repeat(50));
+                println!("\u{274C} {}: {}", get_message("task_failed", lang), message);
+            }
+            println!("{}\n", "=".repeat(50));
+        }
+    })
+}
+
+/// Print a JSON object tagged with `type` as one line
+fn print_json_line(kind: &str, mut value: serde_json::Value) {
+    if let Some(object) = value.as_object_mut() {
+        object.insert("type".to_string(), kind.into());
+    }
+    println!("{}", value);
+    io::stdout().flush().ok();
+}
+
+/// Confirmation callback for JSON mode: emits an event and prompts on stderr
+fn json_confirmation(message: &str) -> bool {
+    print_json_line("confirmation", serde_json::json!({ "message": message }));
+    eprint!("Sensitive operation: {}\nConfirm? (Y/N): ", message);
+    io::stderr().flush().ok();
+
+    let mut response = String::new();
+    io::stdin().read_line(&mut response).ok();
+    response.trim().eq_ignore_ascii_case("y")
+}
+
+/// Takeover callback for JSON mode: emits an event and prompts on stderr
+fn json_takeover(message: &str) {
+    print_json_line("takeover", serde_json::json!({ "message": message }));
+    eprintln!("{}", message);
+    eprint!("Press Enter after completing manual operation...");
+    io::stderr().flush().ok();
+
+    let mut response = String::new();
+    io::stdin().read_line(&mut response).ok();
+}
+
+/// Read one line from stdin on a background thread, so the wait can time out
+///
+/// Resolves to `None` at end of input. Only one line is read, leaving stdin
+/// free for the confirmation and takeover prompts while a task runs.
+fn read_stdin_line() -> oneshot::Receiver<io::Result<Option<String>>> {
+    let (tx, rx) = oneshot::channel();
+    std::thread::spawn(move || {
+        let mut input = String::new();
+        let result = io::stdin().lock().read_line(&mut input);
+        let _ = tx.send(result.map(|n| (n > 0).then_some(input)));
+    });
+    rx
+}
+
 /// Parse language string to Language enum
 fn parse_lang(lang: &str) -> Language {
     match lang.to_lowercase().as_str() {
@@ -845,18 +1187,49 @@ fn parse_lang(lang: &str) -> Language {
     }
 }
 
+/// Path of the user's app overrides file, `$XDG_CONFIG_HOME/autoglm/apps.toml`
+///
+/// Falls back to `~/.config` when `XDG_CONFIG_HOME` isn't set.
+fn app_overrides_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("autoglm").join("apps.toml"))
+}
+
+/// Install the console log subscriber, filtered by `RUST_LOG`
+///
+/// Spans and events go to stderr so they don't mix with the agent's output.
+fn init_logging() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn"));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .init();
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Cli::parse();
+    init_logging();
 
-    // Parse device type
-    let device_type = CliDeviceType::from_str(&args.device_type)?;
+    // Load the user's app list, if any
+    if let Some(path) = app_overrides_path().filter(|path| path.exists()) {
+        if let Err(e) = load_app_overrides(&path) {
+            eprintln!("Warning: ignoring app list {}: {}", path.display(), e);
+        }
+    }
 
-    // Set device type globally (for non-iOS)
-    if device_type == CliDeviceType::Adb {
-        set_device_type(DeviceType::Adb).await;
+    if let Some(adb_path) = &args.adb_path {
+        set_adb_path(adb_path);
     }
 
+    // Parse device type
+    let device_type = args.device_type.parse::<DeviceType>()?;
+
+    // Set device type globally
+    set_device_type(device_type).await;
+
     // Handle --list-apps (no system check needed)
     if args.list_apps {
         print_supported_apps(device_type);
@@ -868,46 +1241,148 @@ async fn main() -> Result<()> {
         return Ok(());
     }
 
-    // Run system requirements check
-    if !check_system_requirements(device_type, &args.wda_url).await {
-        std::process::exit(1);
+    // Handle --versions
+    if args.versions {
+        print_versions(device_type, args.device_id.as_deref(), &args.wda_url).await?;
+        return Ok(());
+    }
+
+    // Handle --screenshot (no model server needed)
+    if let Some(path) = &args.screenshot {
+        capture_screenshot(device_type, args.device_id.as_deref(), path).await?;
+        return Ok(());
+    }
+
+    // JSON output keeps stdout machine-readable, so the decorated checks,
+    // header and thinking stream are skipped; errors come as JSON instead
+    let json_output = args.format == "json";
+    if json_output && args.task.is_none() && args.job.is_none() {
+        return Err(anyhow!("--format json needs a task or --job"));
+    }
+
+    // Handle --watch: stream alongside the task, or on its own without one
+    if let Some(port) = args.watch {
+        if device_type != DeviceType::Adb {
+            return Err(anyhow!(
+                "--watch is not yet supported for {} devices",
+                device_type.tool_name()
+            ));
+        }
+        let interval = Duration::from_millis(args.watch_interval.max(50));
+        let device_id = args.device_id.clone();
+        eprintln!("Streaming the screen at http://127.0.0.1:{}/", port);
+        if args.task.is_none() && args.job.is_none() {
+            serve_screen(port, interval, device_id).await?;
+            return Ok(());
+        }
+        tokio::spawn(async move {
+            if let Err(e) = serve_screen(port, interval, device_id).await {
+                eprintln!("Screen stream stopped: {}", e);
+            }
+        });
     }
 
-    // Check model API
-    if !check_model_api(&args.base_url, &args.model, &args.apikey).await {
+    // Load job file if provided
+    let job = match &args.job {
+        Some(path) => Some(JobSpec::from_file(path)?),
+        None => None,
+    };
+
+    // Run system requirements check against the device the run will use
+    let device_id = job
+        .as_ref()
+        .and_then(|j| j.device_id.as_deref())
+        .or(args.device_id.as_deref());
+    if !json_output && !check_system_requirements(device_type, device_id, &args.wda_url).await {
         std::process::exit(1);
     }
 
-    // Create configurations and agent
-    let model_config = ModelConfig::new(&args.base_url, &args.model).with_api_key(&args.apikey);
+    // Create configurations
+    let mut model_config = ModelConfig::new(&args.base_url, &args.model).with_api_key(&args.apikey);
 
     let lang = parse_lang(&args.lang);
     let mut agent_config = AgentConfig::new()
         .with_max_steps(args.max_steps)
         .with_lang(lang)
-        .with_verbose(!args.quiet);
+        .with_verbose(!args.quiet && !json_output)
+        .with_text_focus_check(args.text_focus_check);
 
     if let Some(device_id) = &args.device_id {
         agent_config = agent_config.with_device_id(device_id);
     }
 
     if let Some(screenshot_dir) = &args.screenshot_dir {
-        agent_config = agent_config.with_screenshot_dir(screenshot_dir);
+        agent_config = agent_config
+            .with_screenshot_dir(screenshot_dir)
+            .with_compress_sessions(args.compress_sessions);
     }
 
-    // Print header
-    print_header(&args, &model_config, &agent_config);
+    if let Some(ref job) = job {
+        model_config = job.apply_to_model_config(model_config);
+        agent_config = job.apply_to_agent_config(agent_config);
+    }
 
-    // Create agent
-    let mut agent = PhoneAgent::new(Some(model_config), Some(agent_config), None, None).await?;
+    // Shared by the console printers: whether the current step's thinking has started
+    let thinking_shown = Arc::new(AtomicBool::new(false));
+    if json_output || args.quiet {
+        model_config = model_config.with_stream_callback(|_| {});
+    } else {
+        model_config =
+            model_config.with_stream_callback(thinking_printer(thinking_shown.clone(), lang));
+    }
+
+    if !json_output {
+        // Check model API
+        if !check_model_api(
+            &model_config.base_url,
+            &model_config.model_name,
+            &model_config.api_key,
+        )
+        .await
+        {
+            std::process::exit(1);
+        }
+
+        // Print header
+        print_header(&args, &model_config, &agent_config);
+    }
+
+    // Create agent; in JSON mode prompts go to stderr so stdout stays machine-readable
+    let (confirmation, takeover): (Option<ConfirmationCallback>, Option<TakeoverCallback>) =
+        if json_output {
+            (
+                Some(Box::new(json_confirmation)),
+                Some(Box::new(json_takeover)),
+            )
+        } else {
+            (None, None)
+        };
+    let mut agent = PhoneAgent::new(
+        Some(model_config),
+        Some(agent_config),
+        confirmation,
+        takeover,
+    )
+    .await?;
+    if !json_output && !args.quiet {
+        agent.set_step_callback(step_printer(thinking_shown, lang));
+    }
+
+    if let Some(path) = &args.dump_config {
+        agent.dump_effective_config(path)?;
+    }
 
-    // Run with provided task or enter interactive mode
-    if let Some(task) = &args.task {
+    // Run with provided task (or the job's task) or enter interactive mode
+    let task = args.task.clone().or_else(|| job.map(|j| j.task));
+    if let (Some(task), true) = (&task, json_output) {
+        run_json_mode(&mut agent, task).await?;
+    } else if let Some(task) = &task {
         println!("\nTask: {}\n", task);
         let result = agent.run(task).await?;
         println!("\nResult: {}", result);
     } else {
-        run_interactive_mode(&mut agent).await?;
+        let idle_timeout = args.idle_timeout.map(Duration::from_secs);
+        run_interactive_mode(&mut agent, idle_timeout).await?;
     }
 
     Ok(())
@@ -0,0 +1,458 @@
+//! Typed representation of parsed actions
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use super::handler::{parse_action, RelativeRegion};
+use super::spec::{find_action, validate_action};
+use crate::device_factory::ScrollDirection;
+
+/// Half the side of the region a Zoom around a point covers, in relative units
+const DEFAULT_ZOOM_RADIUS: i64 = 150;
+
+/// Most repeats a single Scroll action may ask for
+const MAX_SCROLL_TIMES: u64 = 10;
+
+/// A point in the model's relative 0-1000 coordinates
+pub type RelativePoint = (i64, i64);
+
+/// An action parsed from the model's output
+///
+/// Coordinates are relative (0-1000); [`super::ActionHandler::execute_action`]
+/// maps them onto the screen. Optional parameters the model left out are
+/// `None` and get their defaults when the action runs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Launch {
+        app: String,
+        /// Relaunch even when the app is already in the foreground
+        force: bool,
+    },
+    OpenUrl {
+        url: String,
+    },
+    Tap {
+        element: RelativePoint,
+        /// Confirmation prompt for a sensitive tap, e.g. a payment
+        message: Option<String>,
+    },
+    Type {
+        text: String,
+        /// Append at the cursor instead of replacing the field's contents
+        append: bool,
+    },
+    Swipe {
+        start: RelativePoint,
+        end: RelativePoint,
+        duration: Option<u32>,
+    },
+    /// Swipe from `element` by a relative offset
+    SwipeBy {
+        element: RelativePoint,
+        dx: i64,
+        dy: i64,
+        duration: Option<u32>,
+    },
+    Scroll {
+        direction: ScrollDirection,
+        times: u64,
+    },
+    /// Fling up until the start of the content is reached
+    ScrollToTop,
+    /// Fling down until the end of the content is reached
+    ScrollToBottom,
+    Gesture {
+        points: Vec<RelativePoint>,
+        /// Per-segment durations in milliseconds
+        durations: Vec<u32>,
+    },
+    SwipePath {
+        points: Vec<RelativePoint>,
+        duration: Option<u32>,
+    },
+    Back,
+    Home,
+    DoubleTap {
+        element: RelativePoint,
+    },
+    LongPress {
+        element: RelativePoint,
+        duration: Option<u32>,
+    },
+    Wait {
+        duration: Duration,
+    },
+    /// The model can't complete the task
+    Error {
+        message: Option<String>,
+    },
+    TakeOver {
+        message: Option<String>,
+    },
+    Note {
+        message: Option<String>,
+    },
+    CallApi {
+        instruction: Option<String>,
+    },
+    Interact {
+        message: Option<String>,
+    },
+    /// Ask for a closer look at a region before acting on it
+    Zoom {
+        region: RelativeRegion,
+    },
+    Finish {
+        message: Option<String>,
+    },
+}
+
+impl Action {
+    /// Action name as the model writes it, e.g. `"Double Tap"` or `"finish"`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Launch { .. } => "Launch",
+            Self::OpenUrl { .. } => "OpenURL",
+            Self::Tap { .. } => "Tap",
+            Self::Type { .. } => "Type",
+            Self::Swipe { .. } => "Swipe",
+            Self::SwipeBy { .. } => "SwipeBy",
+            Self::Scroll { .. } => "Scroll",
+            Self::ScrollToTop => "ScrollToTop",
+            Self::ScrollToBottom => "ScrollToBottom",
+            Self::Gesture { .. } => "Gesture",
+            Self::SwipePath { .. } => "SwipePath",
+            Self::Back => "Back",
+            Self::Home => "Home",
+            Self::DoubleTap { .. } => "Double Tap",
+            Self::LongPress { .. } => "Long Press",
+            Self::Wait { .. } => "Wait",
+            Self::Error { .. } => "Error",
+            Self::TakeOver { .. } => "Take_over",
+            Self::Note { .. } => "Note",
+            Self::CallApi { .. } => "Call_API",
+            Self::Interact { .. } => "Interact",
+            Self::Zoom { .. } => "Zoom",
+            Self::Finish { .. } => "finish",
+        }
+    }
+}
+
+impl TryFrom<&HashMap<String, Value>> for Action {
+    type Error = String;
+
+    fn try_from(action: &HashMap<String, Value>) -> Result<Self, String> {
+        let get_str = |key: &str| action.get(key).and_then(|v| v.as_str());
+        let get_string = |key: &str| get_str(key).map(|s| s.to_string());
+        let get_u32 = |key: &str| action.get(key).and_then(|v| v.as_u64()).map(|d| d as u32);
+
+        match get_str("_metadata") {
+            Some("finish") => {
+                return Ok(Action::Finish {
+                    message: get_string("message"),
+                })
+            }
+            Some("do") => {}
+            other => return Err(format!("Unknown action type: {}", other.unwrap_or(""))),
+        }
+
+        validate_action(action)?;
+        let name = get_str("action").unwrap_or("");
+        let spec = find_action(name).ok_or_else(|| format!("Unknown action: {}", name))?;
+
+        // `Self::Error` would be the `TryFrom` error type, so variants are spelled `Action::`.
+        // Keep in sync with the action specs in `spec.rs`
+        let action = match spec.name {
+            "Launch" => Action::Launch {
+                app: get_string("app").ok_or("No app name specified")?,
+                force: action
+                    .get("force")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false),
+            },
+            "OpenURL" => Action::OpenUrl {
+                url: get_string("url").ok_or("No URL specified")?,
+            },
+            "Tap" => Action::Tap {
+                element: point(action, "element")?,
+                message: get_string("message"),
+            },
+            "Type" => Action::Type {
+                text: get_string("text").unwrap_or_default(),
+                append: action
+                    .get("append")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false),
+            },
+            "Swipe" => Action::Swipe {
+                start: point(action, "start")?,
+                end: point(action, "end")?,
+                duration: get_u32("duration"),
+            },
+            "SwipeBy" => {
+                let offset = |key: &str| action.get(key).and_then(|v| v.as_i64()).unwrap_or(0);
+                let (dx, dy) = (offset("dx"), offset("dy"));
+                if dx == 0 && dy == 0 {
+                    return Err("SwipeBy needs a dx or dy offset".to_string());
+                }
+                Action::SwipeBy {
+                    element: point(action, "element")?,
+                    dx,
+                    dy,
+                    duration: get_u32("duration"),
+                }
+            }
+            "Scroll" => {
+                let direction = get_str("direction").unwrap_or("down");
+                Action::Scroll {
+                    direction: ScrollDirection::parse(direction)
+                        .ok_or_else(|| format!("Invalid scroll direction: {}", direction))?,
+                    times: action
+                        .get("times")
+                        .and_then(|v| v.as_u64())
+                        .unwrap_or(1)
+                        .min(MAX_SCROLL_TIMES),
+                }
+            }
+            "Gesture" => Action::Gesture {
+                points: path(action, "Gesture")?,
+                // Either per-segment `durations=[..]` or one `duration` for all segments
+                durations: match action.get("durations").and_then(|v| v.as_array()) {
+                    Some(values) => values
+                        .iter()
+                        .filter_map(|v| v.as_u64())
+                        .map(|d| d as u32)
+                        .collect(),
+                    None => get_u32("duration").into_iter().collect(),
+                },
+            },
+            "SwipePath" => Action::SwipePath {
+                points: path(action, "Swipe path")?,
+                duration: get_u32("duration"),
+            },
+            "ScrollToTop" => Action::ScrollToTop,
+            "ScrollToBottom" => Action::ScrollToBottom,
+            "Back" => Action::Back,
+            "Home" => Action::Home,
+            "Double Tap" => Action::DoubleTap {
+                element: point(action, "element")?,
+            },
+            "Long Press" => Action::LongPress {
+                element: point(action, "element")?,
+                duration: get_u32("duration"),
+            },
+            "Wait" => Action::Wait {
+                duration: wait_duration(action.get("duration")),
+            },
+            "Error" => Action::Error {
+                message: get_string("message"),
+            },
+            "Take_over" => Action::TakeOver {
+                message: get_string("message"),
+            },
+            "Note" => Action::Note {
+                message: get_string("message"),
+            },
+            "Call_API" => Action::CallApi {
+                instruction: get_string("instruction"),
+            },
+            "Interact" => Action::Interact {
+                message: get_string("message").or_else(|| get_string("description")),
+            },
+            "Zoom" => Action::Zoom {
+                region: zoom_region(action)?,
+            },
+            other => return Err(format!("Unknown action: {}", other)),
+        };
+        Ok(action)
+    }
+}
+
+impl TryFrom<HashMap<String, Value>> for Action {
+    type Error = String;
+
+    fn try_from(action: HashMap<String, Value>) -> Result<Self, String> {
+        Self::try_from(&action)
+    }
+}
+
+/// Parse an action from model response into an [`Action`]
+///
+/// Like [`parse_action`], but also checks the action's parameters.
+pub fn parse_action_typed(response: &str) -> Result<Action, String> {
+    Action::try_from(&parse_action(response)?)
+}
+
+/// Read a `[x, y]` parameter
+fn point(action: &HashMap<String, Value>, key: &str) -> Result<RelativePoint, String> {
+    as_point(action.get(key)).ok_or_else(|| format!("Invalid {} coordinates", key))
+}
+
+fn as_point(value: Option<&Value>) -> Option<RelativePoint> {
+    let coords: Vec<i64> = value?
+        .as_array()?
+        .iter()
+        .filter_map(|v| v.as_i64())
+        .collect();
+    match coords.as_slice() {
+        [x, y, ..] => Some((*x, *y)),
+        _ => None,
+    }
+}
+
+/// Read the `points=[[x, y], ...]` parameter of a path with at least two points
+fn path(action: &HashMap<String, Value>, what: &str) -> Result<Vec<RelativePoint>, String> {
+    let points: Vec<RelativePoint> = action
+        .get("points")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| format!("No {} points", what.to_lowercase()))?
+        .iter()
+        .map(|point| as_point(Some(point)))
+        .collect::<Option<_>>()
+        .ok_or_else(|| format!("Invalid {} points", what.to_lowercase()))?;
+
+    if points.len() < 2 {
+        return Err(format!("{} needs at least two points", what));
+    }
+    Ok(points)
+}
+
+/// Parse a wait duration such as `"2 seconds"`, defaulting to one second
+fn wait_duration(value: Option<&Value>) -> Duration {
+    let seconds = match value {
+        Some(Value::Number(n)) => n.as_f64(),
+        Some(Value::String(s)) => s
+            .replace("seconds", "")
+            .replace("second", "")
+            .trim()
+            .parse()
+            .ok(),
+        _ => None,
+    };
+    Duration::from_secs_f64(
+        seconds
+            .filter(|s| s.is_finite() && *s >= 0.0)
+            .unwrap_or(1.0),
+    )
+}
+
+/// Region of a Zoom: `region=[x1, y1, x2, y2]`, or `element=[x, y]` for a
+/// square around a point
+fn zoom_region(action: &HashMap<String, Value>) -> Result<RelativeRegion, String> {
+    let coords = |key: &str| -> Vec<i64> {
+        action
+            .get(key)
+            .and_then(|v| v.as_array())
+            .map(|values| values.iter().filter_map(|v| v.as_i64()).collect())
+            .unwrap_or_default()
+    };
+
+    match (coords("region").as_slice(), coords("element").as_slice()) {
+        ([x1, y1, x2, y2, ..], _) => Ok(RelativeRegion::new(*x1, *y1, *x2, *y2)),
+        (_, [x, y, ..]) => Ok(RelativeRegion::around(*x, *y, DEFAULT_ZOOM_RADIUS)),
+        _ => Err("Zoom needs a region or element".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_action_typed() {
+        assert_eq!(
+            parse_action_typed("do(action=\"Tap\", element=[500, 300])"),
+            Ok(Action::Tap {
+                element: (500, 300),
+                message: None,
+            })
+        );
+        assert_eq!(
+            parse_action_typed("do(action=\"Type_Name\", text=\"Alice\")"),
+            Ok(Action::Type {
+                text: "Alice".to_string(),
+                append: false,
+            })
+        );
+        assert_eq!(
+            parse_action_typed("do(action=\"Wait\", duration=\"2 seconds\")"),
+            Ok(Action::Wait {
+                duration: Duration::from_secs(2),
+            })
+        );
+        assert_eq!(
+            parse_action_typed("finish(message=\"done\")"),
+            Ok(Action::Finish {
+                message: Some("done".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_scroll_to_edge() {
+        assert_eq!(
+            parse_action_typed("do(action=\"ScrollToTop\")"),
+            Ok(Action::ScrollToTop)
+        );
+        assert_eq!(
+            parse_action_typed("do(action=\"ScrollToBottom\")"),
+            Ok(Action::ScrollToBottom)
+        );
+    }
+
+    #[test]
+    fn test_parse_swipe_by() {
+        assert_eq!(
+            parse_action_typed("do(action=\"SwipeBy\", element=[500, 500], dy=-400)"),
+            Ok(Action::SwipeBy {
+                element: (500, 500),
+                dx: 0,
+                dy: -400,
+                duration: None,
+            })
+        );
+        assert_eq!(
+            parse_action_typed("do(action=\"SwipeBy\", element=[500, 500])"),
+            Err("SwipeBy needs a dx or dy offset".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_scroll_clamps_times() {
+        assert_eq!(
+            parse_action_typed("do(action=\"Scroll\", direction=\"down\", times=1000000)"),
+            Ok(Action::Scroll {
+                direction: ScrollDirection::Down,
+                times: MAX_SCROLL_TIMES,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_action_typed_rejects_bad_parameters() {
+        assert_eq!(
+            parse_action_typed("do(action=\"Tap\", element=[500])"),
+            Err("Invalid element coordinates".to_string())
+        );
+        assert_eq!(
+            parse_action_typed("do(action=\"Scroll\", direction=\"sideways\")"),
+            Err("Invalid scroll direction: sideways".to_string())
+        );
+        assert_eq!(
+            parse_action_typed("do(action=\"Gesture\", points=[[100, 100]])"),
+            Err("Gesture needs at least two points".to_string())
+        );
+        assert_eq!(
+            parse_action_typed("do(action=\"Swipe\", start=[0, 0])"),
+            Err("Swipe is missing required parameters: end".to_string())
+        );
+    }
+
+    #[test]
+    fn test_action_name_matches_spec() {
+        let action = parse_action_typed("do(action=\"Long Press\", element=[1, 2])").unwrap();
+        assert_eq!(action.name(), "Long Press");
+        assert!(find_action(action.name()).is_some());
+    }
+}
@@ -1,15 +1,76 @@
 //! Action handler for processing AI model outputs
 
+use futures::future::BoxFuture;
+use regex::{RegexSet, RegexSetBuilder};
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::{self, Write};
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::time::sleep;
+use tracing::{debug, field, instrument, Span};
 
-use crate::config::TIMING_CONFIG;
-use crate::device_factory::get_device_factory;
+use super::action::{Action, RelativePoint};
+use crate::adb::InputBackend;
+use crate::config::{get_package_name, TIMING_CONFIG};
+use crate::device_factory::{DeviceController, GlobalDeviceController, ScrollDirection};
 use crate::error::{AdbError, Result};
 
+/// Default hold time for the Long Press action when no duration is given
+const DEFAULT_LONG_PRESS_MS: u32 = 3000;
+
+/// Default total duration of the SwipePath action
+const DEFAULT_SWIPE_PATH_MS: u32 = 1000;
+
+/// Rectangle in the model's relative 0-1000 coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RelativeRegion {
+    pub left: i64,
+    pub top: i64,
+    pub right: i64,
+    pub bottom: i64,
+}
+
+impl RelativeRegion {
+    /// Create a region from two opposite corners, clamped to the screen
+    pub fn new(x1: i64, y1: i64, x2: i64, y2: i64) -> Self {
+        let clamp = |v: i64| v.clamp(0, 1000);
+        Self {
+            left: clamp(x1.min(x2)),
+            top: clamp(y1.min(y2)),
+            right: clamp(x1.max(x2)),
+            bottom: clamp(y1.max(y2)),
+        }
+    }
+
+    /// Square region of `radius` around a point, clamped to the screen
+    pub fn around(x: i64, y: i64, radius: i64) -> Self {
+        Self::new(x - radius, y - radius, x + radius, y + radius)
+    }
+
+    /// Map a point relative to this region onto relative screen coordinates
+    pub fn to_screen(&self, point: (i64, i64)) -> (i64, i64) {
+        let x = point.0.clamp(0, 1000);
+        let y = point.1.clamp(0, 1000);
+        (
+            self.left + x * (self.right - self.left) / 1000,
+            self.top + y * (self.bottom - self.top) / 1000,
+        )
+    }
+
+    /// Pixel rectangle `(x, y, width, height)` of this region on a `width` x `height` frame
+    pub fn to_pixels(&self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let (x1, y1) = relative_to_absolute((self.left, self.top), width, height);
+        let (x2, y2) = relative_to_absolute((self.right, self.bottom), width, height);
+        (
+            x1 as u32,
+            y1 as u32,
+            (x2 - x1).max(1) as u32,
+            (y2 - y1).max(1) as u32,
+        )
+    }
+}
+
 /// Result of an action execution
 #[derive(Debug, Clone)]
 pub struct ActionResult {
@@ -17,6 +78,8 @@ pub struct ActionResult {
     pub should_finish: bool,
     pub message: Option<String>,
     pub requires_confirmation: bool,
+    /// Region the model asked to see up close before acting (see the Zoom action)
+    pub refine_region: Option<RelativeRegion>,
 }
 
 impl ActionResult {
@@ -27,6 +90,7 @@ impl ActionResult {
             should_finish: false,
             message: None,
             requires_confirmation: false,
+            refine_region: None,
         }
     }
 
@@ -37,6 +101,7 @@ impl ActionResult {
             should_finish: false,
             message: Some(message.into()),
             requires_confirmation: false,
+            refine_region: None,
         }
     }
 
@@ -47,6 +112,26 @@ impl ActionResult {
             should_finish: true,
             message,
             requires_confirmation: false,
+            refine_region: None,
+        }
+    }
+
+    /// Create a result that ends the task without completing it
+    pub fn give_up(message: Option<String>) -> Self {
+        Self {
+            success: false,
+            should_finish: true,
+            message,
+            requires_confirmation: false,
+            refine_region: None,
+        }
+    }
+
+    /// Create a result asking to re-query the model with a zoomed view of `region`
+    pub fn refine(region: RelativeRegion) -> Self {
+        Self {
+            refine_region: Some(region),
+            ..Self::success()
         }
     }
 }
@@ -54,14 +139,38 @@ impl ActionResult {
 /// Callback type for confirmation
 pub type ConfirmationCallback = Box<dyn Fn(&str) -> bool + Send + Sync>;
 
+/// Async callback type for confirmation, e.g. to await a GUI dialog
+pub type AsyncConfirmationCallback = Box<dyn Fn(&str) -> BoxFuture<'static, bool> + Send + Sync>;
+
 /// Callback type for takeover
 pub type TakeoverCallback = Box<dyn Fn(&str) + Send + Sync>;
 
+/// Sync or async confirmation callback
+enum Confirmation {
+    Sync(ConfirmationCallback),
+    Async(AsyncConfirmationCallback),
+}
+
+impl Confirmation {
+    async fn confirm(&self, message: &str) -> bool {
+        match self {
+            Confirmation::Sync(callback) => callback(message),
+            Confirmation::Async(callback) => callback(message).await,
+        }
+    }
+}
+
 /// Handles execution of actions from AI model output
 pub struct ActionHandler {
     device_id: Option<String>,
-    confirmation_callback: ConfirmationCallback,
+    device: Arc<dyn DeviceController>,
+    confirmation: Confirmation,
     takeover_callback: TakeoverCallback,
+    allowed_actions: Option<HashSet<String>>,
+    check_text_focus: bool,
+    dismiss_keyboard: bool,
+    /// Patterns that make Type ask for confirmation when they match the text
+    type_confirmation: Option<RegexSet>,
 }
 
 impl ActionHandler {
@@ -73,98 +182,265 @@ impl ActionHandler {
     ) -> Self {
         Self {
             device_id,
-            confirmation_callback: confirmation_callback
-                .unwrap_or_else(|| Box::new(default_confirmation)),
+            device: Arc::new(GlobalDeviceController::new()),
+            confirmation: Confirmation::Sync(
+                confirmation_callback.unwrap_or_else(|| Box::new(default_confirmation)),
+            ),
             takeover_callback: takeover_callback.unwrap_or_else(|| Box::new(default_takeover)),
+            allowed_actions: None,
+            check_text_focus: false,
+            dismiss_keyboard: false,
+            type_confirmation: None,
         }
     }
 
+    /// Use a specific device controller instead of the global device factory
+    pub fn with_device(mut self, device: Arc<dyn DeviceController>) -> Self {
+        self.device = device;
+        self
+    }
+
+    /// Confirm sensitive actions with an async callback instead of the sync one
+    pub fn with_async_confirmation_callback(mut self, callback: AsyncConfirmationCallback) -> Self {
+        self.set_async_confirmation_callback(callback);
+        self
+    }
+
+    /// Replace the confirmation callback with an async one
+    ///
+    /// Lets GUI or web frontends await the user's choice without blocking the
+    /// runtime.
+    pub fn set_async_confirmation_callback(&mut self, callback: AsyncConfirmationCallback) {
+        self.confirmation = Confirmation::Async(callback);
+    }
+
+    /// Only execute the named actions (e.g. `"Tap"`, `"Back"`), rejecting all others
+    ///
+    /// `finish` is always allowed.
+    pub fn with_allowed_actions(mut self, actions: HashSet<String>) -> Self {
+        self.allowed_actions = Some(actions);
+        self
+    }
+
+    /// Set whether Type checks that a text field is focused before typing
+    ///
+    /// Off by default, since the focus check misfires on apps that draw
+    /// their own text fields.
+    pub fn with_text_focus_check(mut self, enabled: bool) -> Self {
+        self.check_text_focus = enabled;
+        self
+    }
+
+    /// Set whether Type presses Back afterwards to hide a soft keyboard left showing
+    ///
+    /// Off by default; turn it on when the keyboard covers buttons the model
+    /// taps next, e.g. in forms.
+    pub fn with_keyboard_dismiss(mut self, enabled: bool) -> Self {
+        self.dismiss_keyboard = enabled;
+        self
+    }
+
+    /// Ask for confirmation before typing text that matches any of `patterns`
+    ///
+    /// Patterns are case-insensitive regular expressions, so plain keywords
+    /// work as-is, e.g. `["transfer", "rm -rf", "password"]`. The confirmation
+    /// callback gets the text to be typed; declining ends the task.
+    pub fn with_type_confirmation<I, S>(mut self, patterns: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let set = RegexSetBuilder::new(patterns)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| {
+                AdbError::ParseError(format!("Invalid Type confirmation pattern: {}", e))
+            })?;
+        self.type_confirmation = (!set.is_empty()).then_some(set);
+        Ok(self)
+    }
+
+    /// Parse an action string and execute it on the handler's device
+    ///
+    /// Screen dimensions for the relative coordinates are fetched from the
+    /// device, so this works without the agent loop, e.g.
+    /// `handler.execute_str("do(action=\"Tap\", element=[500, 500])")`.
+    pub async fn execute_str(&self, action_str: &str) -> ActionResult {
+        let action = match parse_action(action_str) {
+            Ok(action) => action,
+            Err(e) => return ActionResult::failure(e),
+        };
+
+        let (width, height) = match self.device.get_screen_size(self.device_id.as_deref()).await {
+            Ok(size) => size,
+            Err(e) => return ActionResult::failure(format!("Failed to get screen size: {}", e)),
+        };
+
+        self.execute(&action, width, height).await
+    }
+
+    /// Hand control to the user via the takeover callback
+    pub fn request_takeover(&self, message: &str) {
+        (self.takeover_callback)(message);
+    }
+
     /// Execute an action from the AI model
+    ///
+    /// Converts the parsed action into an [`Action`] and runs it with
+    /// [`ActionHandler::execute_action`].
     pub async fn execute(
         &self,
         action: &HashMap<String, Value>,
         screen_width: u32,
         screen_height: u32,
     ) -> ActionResult {
-        let action_type = action
-            .get("_metadata")
-            .and_then(|v| v.as_str())
-            .unwrap_or("");
-
-        if action_type == "finish" {
-            return ActionResult::finish(
-                action
-                    .get("message")
-                    .and_then(|v| v.as_str())
-                    .map(|s| s.to_string()),
-            );
-        }
-
-        if action_type != "do" {
-            return ActionResult::failure(format!("Unknown action type: {}", action_type));
-        }
-
-        let action_name = action
-            .get("action")
-            .and_then(|v| v.as_str())
-            .unwrap_or("");
-
-        let result = match action_name {
-            "Launch" => self.handle_launch(action).await,
-            "Tap" => self.handle_tap(action, screen_width, screen_height).await,
-            "Type" | "Type_Name" => self.handle_type(action).await,
-            "Swipe" => self.handle_swipe(action, screen_width, screen_height).await,
-            "Back" => self.handle_back().await,
-            "Home" => self.handle_home().await,
-            "Double Tap" => {
-                self.handle_double_tap(action, screen_width, screen_height)
+        match Action::try_from(action) {
+            Ok(action) => {
+                self.execute_action(&action, screen_width, screen_height)
+                    .await
+            }
+            Err(e) => {
+                debug!(error = %e, "action rejected by its spec");
+                ActionResult::failure(format!("Action failed: {}", e))
+            }
+        }
+    }
+
+    /// Execute a typed action
+    #[instrument(
+        name = "action",
+        level = "debug",
+        skip_all,
+        fields(action = field::Empty, duration_ms = field::Empty)
+    )]
+    pub async fn execute_action(
+        &self,
+        action: &Action,
+        screen_width: u32,
+        screen_height: u32,
+    ) -> ActionResult {
+        Span::current().record("action", action.name());
+
+        if let Some(rejected) = self.check_allowed(action) {
+            return rejected;
+        }
+
+        let started = Instant::now();
+
+        let (width, height) = (screen_width, screen_height);
+        let result = match action {
+            Action::Launch { app, force } => self.handle_launch(app, *force).await,
+            Action::OpenUrl { url } => self.handle_open_url(url).await,
+            Action::Tap { element, message } => {
+                self.handle_tap(*element, message.as_deref(), width, height)
+                    .await
+            }
+            Action::Type { text, append } => self.handle_type(text, *append).await,
+            Action::Swipe {
+                start,
+                end,
+                duration,
+            } => {
+                self.handle_swipe(*start, *end, *duration, width, height)
+                    .await
+            }
+            Action::SwipeBy {
+                element,
+                dx,
+                dy,
+                duration,
+            } => {
+                self.handle_swipe_by(*element, (*dx, *dy), *duration, width, height)
                     .await
             }
-            "Long Press" => {
-                self.handle_long_press(action, screen_width, screen_height)
+            Action::Scroll { direction, times } => {
+                self.handle_scroll(*direction, *times, width, height).await
+            }
+            Action::ScrollToTop => self.handle_scroll_to_edge(ScrollDirection::Up).await,
+            Action::ScrollToBottom => self.handle_scroll_to_edge(ScrollDirection::Down).await,
+            Action::Gesture { points, durations } => {
+                self.handle_gesture(points, durations, width, height).await
+            }
+            Action::SwipePath { points, duration } => {
+                self.handle_swipe_path(points, *duration, width, height)
                     .await
             }
-            "Wait" => self.handle_wait(action).await,
-            "Take_over" => self.handle_takeover(action),
-            "Note" => Ok(ActionResult::success()),
-            "Call_API" => Ok(ActionResult::success()),
-            "Interact" => Ok(ActionResult {
-                success: true,
-                should_finish: false,
-                message: Some("User interaction required".to_string()),
-                requires_confirmation: false,
-            }),
-            _ => Err(AdbError::CommandFailed(format!(
-                "Unknown action: {}",
-                action_name
-            ))),
+            Action::Back => self.handle_back().await,
+            Action::Home => self.handle_home().await,
+            Action::DoubleTap { element } => self.handle_double_tap(*element, width, height).await,
+            Action::LongPress { element, duration } => {
+                self.handle_long_press(*element, *duration, width, height)
+                    .await
+            }
+            Action::Wait { duration } => {
+                let max_wait = Duration::try_from_secs_f64(TIMING_CONFIG.action.max_wait)
+                    .unwrap_or(Duration::MAX);
+                sleep((*duration).min(max_wait)).await;
+                Ok(ActionResult::success())
+            }
+            Action::Error { message } => Ok(give_up(message.as_deref())),
+            Action::TakeOver { message } => {
+                self.request_takeover(message.as_deref().unwrap_or("User intervention required"));
+                Ok(ActionResult::success())
+            }
+            Action::Note { .. } | Action::CallApi { .. } => Ok(ActionResult::success()),
+            Action::Interact { message } => {
+                // The model describes the choice the user should make
+                self.request_takeover(message.as_deref().unwrap_or("User interaction required"));
+                Ok(ActionResult::success())
+            }
+            // The agent crops the screenshot and asks again; nothing happens on the device
+            Action::Zoom { region } => Ok(ActionResult::refine(*region)),
+            Action::Finish { message } => Ok(ActionResult::finish(message.clone())),
         };
 
+        let duration_ms = started.elapsed().as_millis() as u64;
+        Span::current().record("duration_ms", duration_ms);
+
         match result {
-            Ok(r) => r,
-            Err(e) => ActionResult::failure(format!("Action failed: {}", e)),
+            Ok(r) => {
+                debug!(success = r.success, "action executed");
+                r
+            }
+            Err(e) => {
+                debug!(error = %e, "action failed");
+                ActionResult::failure(format!("Action failed: {}", e))
+            }
         }
     }
 
-    /// Convert relative coordinates (0-1000) to absolute pixels
-    fn convert_relative_to_absolute(
-        &self,
-        element: &[i64],
-        screen_width: u32,
-        screen_height: u32,
-    ) -> (i32, i32) {
-        let x = (element[0] as f64 / 1000.0 * screen_width as f64) as i32;
-        let y = (element[1] as f64 / 1000.0 * screen_height as f64) as i32;
-        (x, y)
+    /// Reject actions missing from the allowlist; `finish` is always allowed
+    fn check_allowed(&self, action: &Action) -> Option<ActionResult> {
+        let allowed = self.allowed_actions.as_ref()?;
+        if matches!(action, Action::Finish { .. }) || allowed.contains(action.name()) {
+            return None;
+        }
+
+        let mut names: Vec<&str> = allowed.iter().map(|s| s.as_str()).collect();
+        names.sort_unstable();
+        debug!("action rejected by allowlist");
+        Some(ActionResult::failure(format!(
+            "Action \"{}\" is not allowed. Allowed actions: {}",
+            action.name(),
+            names.join(", ")
+        )))
     }
 
-    async fn handle_launch(&self, action: &HashMap<String, Value>) -> Result<ActionResult> {
-        let app_name = action
-            .get("app")
-            .and_then(|v| v.as_str())
-            .ok_or_else(|| AdbError::CommandFailed("No app name specified".to_string()))?;
+    async fn handle_launch(&self, app_name: &str, force: bool) -> Result<ActionResult> {
+        let factory = &self.device;
+
+        // Launching again can reset the app and lose in-progress state
+        if !force {
+            match factory.get_current_app(self.device_id.as_deref()).await {
+                Ok(current) if is_same_app(&current, app_name) => {
+                    debug!(app = app_name, "app already in the foreground");
+                    return Ok(ActionResult::success());
+                }
+                Ok(_) => {}
+                Err(e) => debug!(error = %e, "could not read the foreground app, launching"),
+            }
+        }
 
-        let factory = get_device_factory().read().await;
         let success = factory
             .launch_app(app_name, self.device_id.as_deref(), None)
             .await?;
@@ -176,65 +452,104 @@ impl ActionHandler {
         }
     }
 
+    async fn handle_open_url(&self, url: &str) -> Result<ActionResult> {
+        self.device
+            .open_url(url, self.device_id.as_deref(), None)
+            .await?;
+        Ok(ActionResult::success())
+    }
+
     async fn handle_tap(
         &self,
-        action: &HashMap<String, Value>,
+        element: RelativePoint,
+        message: Option<&str>,
         width: u32,
         height: u32,
     ) -> Result<ActionResult> {
-        let element = action
-            .get("element")
-            .and_then(|v| v.as_array())
-            .ok_or_else(|| AdbError::CommandFailed("No element coordinates".to_string()))?;
-
-        let coords: Vec<i64> = element.iter().filter_map(|v| v.as_i64()).collect();
-
-        if coords.len() < 2 {
-            return Err(AdbError::CommandFailed(
-                "Invalid element coordinates".to_string(),
-            ));
-        }
-
-        let (x, y) = self.convert_relative_to_absolute(&coords, width, height);
+        let (x, y) = relative_to_absolute(element, width, height);
 
         // Check for sensitive operation
-        if let Some(message) = action.get("message").and_then(|v| v.as_str()) {
-            if !(self.confirmation_callback)(message) {
-                return Ok(ActionResult {
-                    success: false,
-                    should_finish: true,
-                    message: Some("User cancelled sensitive operation".to_string()),
-                    requires_confirmation: false,
-                });
+        if let Some(message) = message {
+            if !self.confirmation.confirm(message).await {
+                return Ok(cancelled_sensitive_operation());
             }
         }
 
-        let factory = get_device_factory().read().await;
+        let factory = &self.device;
         factory.tap(x, y, self.device_id.as_deref(), None).await?;
 
         Ok(ActionResult::success())
     }
 
-    async fn handle_type(&self, action: &HashMap<String, Value>) -> Result<ActionResult> {
-        let text = action
-            .get("text")
-            .and_then(|v| v.as_str())
-            .unwrap_or("");
+    async fn handle_type(&self, text: &str, append: bool) -> Result<ActionResult> {
+        let factory = &self.device;
+
+        // Typed content can be as consequential as a tap, e.g. a payee or a command
+        if self
+            .type_confirmation
+            .as_ref()
+            .is_some_and(|patterns| patterns.is_match(text))
+        {
+            let message = format!("Type \"{}\"", text);
+            if !self.confirmation.confirm(&message).await {
+                return Ok(cancelled_sensitive_operation());
+            }
+        }
 
-        let factory = get_device_factory().read().await;
+        // Typing without a focused field sends the text nowhere, or somewhere wrong
+        if self.check_text_focus {
+            match factory
+                .is_text_input_focused(self.device_id.as_deref())
+                .await
+            {
+                Ok(false) => {
+                    return Ok(ActionResult::failure(
+                        "No text field is focused; tap the input field before typing",
+                    ))
+                }
+                Ok(true) => {}
+                Err(e) => debug!(error = %e, "could not check text focus, typing anyway"),
+            }
+        }
+
+        // Without ADB Keyboard, ASCII text can still be typed with `input text`
+        let installed = factory
+            .is_adb_keyboard_installed(self.device_id.as_deref())
+            .await
+            .unwrap_or(true);
+        if InputBackend::for_text(text, installed) == InputBackend::InputText {
+            factory
+                .type_ascii_text(text, !append, self.device_id.as_deref())
+                .await?;
+            sleep(Duration::from_secs_f64(
+                TIMING_CONFIG.action.text_input_delay,
+            ))
+            .await;
+            self.dismiss_keyboard().await;
+            return Ok(ActionResult::success());
+        }
 
-        // Switch to ADB keyboard
-        let original_ime = factory
+        // Switch to ADB keyboard; typing with any other keyboard does nothing
+        let original_ime = match factory
             .detect_and_set_adb_keyboard(self.device_id.as_deref())
-            .await?;
+            .await
+        {
+            Ok(ime) => ime,
+            Err(e) => return Ok(ActionResult::failure(format!("Text was not typed: {}", e))),
+        };
         sleep(Duration::from_secs_f64(
             TIMING_CONFIG.action.keyboard_switch_delay,
         ))
         .await;
 
         // Clear existing text and type new text
-        factory.clear_text(self.device_id.as_deref()).await?;
-        sleep(Duration::from_secs_f64(TIMING_CONFIG.action.text_clear_delay)).await;
+        if !append {
+            factory.clear_text(self.device_id.as_deref()).await?;
+            sleep(Duration::from_secs_f64(
+                TIMING_CONFIG.action.text_clear_delay,
+            ))
+            .await;
+        }
 
         // Type text
         factory.type_text(text, self.device_id.as_deref()).await?;
@@ -248,46 +563,49 @@ impl ActionHandler {
             TIMING_CONFIG.action.keyboard_restore_delay,
         ))
         .await;
+        self.dismiss_keyboard().await;
 
         Ok(ActionResult::success())
     }
 
+    /// Press Back if the soft keyboard is showing and dismissal is enabled
+    ///
+    /// Failures are only logged; the text has been typed either way.
+    async fn dismiss_keyboard(&self) {
+        if !self.dismiss_keyboard {
+            return;
+        }
+        let device_id = self.device_id.as_deref();
+        match self.device.is_keyboard_visible(device_id).await {
+            Ok(true) => {
+                if let Err(e) = self.device.back(device_id, None).await {
+                    debug!(error = %e, "could not dismiss the soft keyboard");
+                }
+            }
+            Ok(false) => {}
+            Err(e) => debug!(error = %e, "could not check the soft keyboard"),
+        }
+    }
+
     async fn handle_swipe(
         &self,
-        action: &HashMap<String, Value>,
+        start: RelativePoint,
+        end: RelativePoint,
+        duration_ms: Option<u32>,
         width: u32,
         height: u32,
     ) -> Result<ActionResult> {
-        let start = action
-            .get("start")
-            .and_then(|v| v.as_array())
-            .ok_or_else(|| AdbError::CommandFailed("Missing start coordinates".to_string()))?;
-
-        let end = action
-            .get("end")
-            .and_then(|v| v.as_array())
-            .ok_or_else(|| AdbError::CommandFailed("Missing end coordinates".to_string()))?;
-
-        let start_coords: Vec<i64> = start.iter().filter_map(|v| v.as_i64()).collect();
-        let end_coords: Vec<i64> = end.iter().filter_map(|v| v.as_i64()).collect();
-
-        if start_coords.len() < 2 || end_coords.len() < 2 {
-            return Err(AdbError::CommandFailed(
-                "Invalid swipe coordinates".to_string(),
-            ));
-        }
+        let (start_x, start_y) = relative_to_absolute(start, width, height);
+        let (end_x, end_y) = relative_to_absolute(end, width, height);
 
-        let (start_x, start_y) = self.convert_relative_to_absolute(&start_coords, width, height);
-        let (end_x, end_y) = self.convert_relative_to_absolute(&end_coords, width, height);
-
-        let factory = get_device_factory().read().await;
+        let factory = &self.device;
         factory
             .swipe(
                 start_x,
                 start_y,
                 end_x,
                 end_y,
-                None,
+                duration_ms,
                 self.device_id.as_deref(),
                 None,
             )
@@ -296,103 +614,171 @@ impl ActionHandler {
         Ok(ActionResult::success())
     }
 
-    async fn handle_back(&self) -> Result<ActionResult> {
-        let factory = get_device_factory().read().await;
-        factory.back(self.device_id.as_deref(), None).await?;
-        Ok(ActionResult::success())
-    }
+    /// Swipe from `start` by a relative offset, stopping at the screen edge
+    async fn handle_swipe_by(
+        &self,
+        start: RelativePoint,
+        offset: (i64, i64),
+        duration_ms: Option<u32>,
+        width: u32,
+        height: u32,
+    ) -> Result<ActionResult> {
+        let (start_x, start_y) = relative_to_absolute(start, width, height);
+        let end = (start.0 + offset.0, start.1 + offset.1);
+        let (end_x, end_y) = relative_to_absolute(end, width, height);
+        let end_x = end_x.clamp(0, width.saturating_sub(1) as i32);
+        let end_y = end_y.clamp(0, height.saturating_sub(1) as i32);
+
+        self.device
+            .swipe(
+                start_x,
+                start_y,
+                end_x,
+                end_y,
+                duration_ms,
+                self.device_id.as_deref(),
+                None,
+            )
+            .await?;
 
-    async fn handle_home(&self) -> Result<ActionResult> {
-        let factory = get_device_factory().read().await;
-        factory.home(self.device_id.as_deref(), None).await?;
         Ok(ActionResult::success())
     }
 
-    async fn handle_double_tap(
+    async fn handle_scroll(
         &self,
-        action: &HashMap<String, Value>,
+        direction: ScrollDirection,
+        times: u64,
         width: u32,
         height: u32,
     ) -> Result<ActionResult> {
-        let element = action
-            .get("element")
-            .and_then(|v| v.as_array())
-            .ok_or_else(|| AdbError::CommandFailed("No element coordinates".to_string()))?;
+        let factory = &self.device;
+        for _ in 0..times {
+            factory
+                .scroll(direction, width, height, self.device_id.as_deref())
+                .await?;
+        }
 
-        let coords: Vec<i64> = element.iter().filter_map(|v| v.as_i64()).collect();
+        Ok(ActionResult::success())
+    }
 
-        if coords.len() < 2 {
-            return Err(AdbError::CommandFailed(
-                "Invalid element coordinates".to_string(),
-            ));
+    async fn handle_scroll_to_edge(&self, direction: ScrollDirection) -> Result<ActionResult> {
+        let reached = self
+            .device
+            .fling_to_edge(direction, self.device_id.as_deref())
+            .await?;
+        if !reached {
+            debug!(?direction, "edge not reached after flinging");
         }
 
-        let (x, y) = self.convert_relative_to_absolute(&coords, width, height);
+        Ok(ActionResult::success())
+    }
 
-        let factory = get_device_factory().read().await;
-        factory
-            .double_tap(x, y, self.device_id.as_deref(), None)
+    async fn handle_gesture(
+        &self,
+        points: &[RelativePoint],
+        durations: &[u32],
+        width: u32,
+        height: u32,
+    ) -> Result<ActionResult> {
+        let points: Vec<(i32, i32)> = points
+            .iter()
+            .map(|point| relative_to_absolute(*point, width, height))
+            .collect();
+
+        self.device
+            .gesture(&points, durations, self.device_id.as_deref())
             .await?;
 
         Ok(ActionResult::success())
     }
 
-    async fn handle_long_press(
+    /// Swipe along a curve through relative waypoints, e.g. for signatures
+    async fn handle_swipe_path(
         &self,
-        action: &HashMap<String, Value>,
+        points: &[RelativePoint],
+        duration_ms: Option<u32>,
         width: u32,
         height: u32,
     ) -> Result<ActionResult> {
-        let element = action
-            .get("element")
-            .and_then(|v| v.as_array())
-            .ok_or_else(|| AdbError::CommandFailed("No element coordinates".to_string()))?;
+        let points: Vec<(i32, i32)> = points
+            .iter()
+            .map(|point| relative_to_absolute(*point, width, height))
+            .collect();
 
-        let coords: Vec<i64> = element.iter().filter_map(|v| v.as_i64()).collect();
+        self.device
+            .swipe_path(
+                &points,
+                duration_ms.unwrap_or(DEFAULT_SWIPE_PATH_MS),
+                self.device_id.as_deref(),
+            )
+            .await?;
 
-        if coords.len() < 2 {
-            return Err(AdbError::CommandFailed(
-                "Invalid element coordinates".to_string(),
-            ));
-        }
+        Ok(ActionResult::success())
+    }
 
-        let (x, y) = self.convert_relative_to_absolute(&coords, width, height);
+    async fn handle_back(&self) -> Result<ActionResult> {
+        let factory = &self.device;
+        factory.back(self.device_id.as_deref(), None).await?;
+        Ok(ActionResult::success())
+    }
+
+    async fn handle_home(&self) -> Result<ActionResult> {
+        let factory = &self.device;
+        factory.home(self.device_id.as_deref(), None).await?;
+        Ok(ActionResult::success())
+    }
+
+    async fn handle_double_tap(
+        &self,
+        element: RelativePoint,
+        width: u32,
+        height: u32,
+    ) -> Result<ActionResult> {
+        let (x, y) = relative_to_absolute(element, width, height);
 
-        let factory = get_device_factory().read().await;
+        let factory = &self.device;
         factory
-            .long_press(x, y, 3000, self.device_id.as_deref(), None)
+            .double_tap(x, y, self.device_id.as_deref(), None)
             .await?;
 
         Ok(ActionResult::success())
     }
 
-    async fn handle_wait(&self, action: &HashMap<String, Value>) -> Result<ActionResult> {
-        let duration_str = action
-            .get("duration")
-            .and_then(|v| v.as_str())
-            .unwrap_or("1 seconds");
+    async fn handle_long_press(
+        &self,
+        element: RelativePoint,
+        duration_ms: Option<u32>,
+        width: u32,
+        height: u32,
+    ) -> Result<ActionResult> {
+        let (x, y) = relative_to_absolute(element, width, height);
 
-        // Parse duration from string like "1 seconds" or "2 seconds"
-        let duration: f64 = duration_str
-            .replace("seconds", "")
-            .replace("second", "")
-            .trim()
-            .parse()
-            .unwrap_or(1.0);
+        let factory = &self.device;
+        factory
+            .long_press(
+                x,
+                y,
+                duration_ms.unwrap_or(DEFAULT_LONG_PRESS_MS),
+                self.device_id.as_deref(),
+                None,
+            )
+            .await?;
 
-        sleep(Duration::from_secs_f64(duration)).await;
         Ok(ActionResult::success())
     }
+}
 
-    fn handle_takeover(&self, action: &HashMap<String, Value>) -> Result<ActionResult> {
-        let message = action
-            .get("message")
-            .and_then(|v| v.as_str())
-            .unwrap_or("User intervention required");
+/// End the task as failed with the model's explanation
+///
+/// Lets the model say it can't complete the task, as opposed to `finish`,
+/// which reports success.
+pub(crate) fn handle_error(action: &HashMap<String, Value>) -> ActionResult {
+    give_up(action.get("message").and_then(|v| v.as_str()))
+}
 
-        (self.takeover_callback)(message);
-        Ok(ActionResult::success())
-    }
+fn give_up(message: Option<&str>) -> ActionResult {
+    let message = message.unwrap_or("Task cannot be completed");
+    ActionResult::give_up(Some(message.to_string()))
 }
 
 /// Default confirmation callback using console input
@@ -415,21 +801,52 @@ fn default_takeover(message: &str) {
     io::stdin().read_line(&mut response).ok();
 }
 
+/// Whether `current`, as reported by `get_current_app`, is the app `target` names
+///
+/// Known apps are compared by package, so aliases of the same app match.
+fn is_same_app(current: &str, target: &str) -> bool {
+    match (get_package_name(current), get_package_name(target)) {
+        (Some(current), Some(target)) => current == target,
+        _ => current.eq_ignore_ascii_case(target),
+    }
+}
+
+/// Result for a sensitive operation the user declined
+fn cancelled_sensitive_operation() -> ActionResult {
+    ActionResult {
+        success: false,
+        should_finish: true,
+        message: Some("User cancelled sensitive operation".to_string()),
+        requires_confirmation: false,
+        refine_region: None,
+    }
+}
+
 /// Parse action from model response
 ///
 /// Returns a HashMap representing the parsed action.
 pub fn parse_action(response: &str) -> std::result::Result<HashMap<String, Value>, String> {
     let response = response.trim();
-    println!("Parsing action: {}", response);
+    debug!(response, "parsing action");
 
     // Handle Type action with special text parsing
-    if response.starts_with("do(action=\"Type\"") || response.starts_with("do(action=\"Type_Name\"")
-    {
+    let type_prefixes = [
+        "do(action=\"Type\"",
+        "do(action=\"Type_Name\"",
+        "do(action='Type'",
+        "do(action='Type_Name'",
+    ];
+    if type_prefixes.iter().any(|p| response.starts_with(p)) {
         if let Some(text_start) = response.find("text=") {
-            let text_part = &response[text_start + 6..]; // Skip 'text="'
-            if let Some(end_pos) = text_part.rfind("\")") {
+            let quoted = &response[text_start + 5..];
+            let quote = quoted.chars().next().filter(|c| *c == '"' || *c == '\'');
+            let text_part = &quoted[quote.map_or(0, |q| q.len_utf8())..]; // Skip the opening quote
+            let closing = format!("{})", quote.map(String::from).unwrap_or_default());
+            if let Some(end_pos) = text_part.rfind(&closing) {
                 let text = &text_part[..end_pos];
-                let mut action = HashMap::new();
+                // Keep fields given before the text, e.g. `append=true`
+                let head = response[..text_start].trim_end().trim_end_matches(',');
+                let mut action = parse_do_action(&format!("{})", head)).unwrap_or_default();
                 action.insert("_metadata".to_string(), json!("do"));
                 action.insert("action".to_string(), json!("Type"));
                 action.insert("text".to_string(), json!(text));
@@ -445,15 +862,35 @@ pub fn parse_action(response: &str) -> std::result::Result<HashMap<String, Value
 
     // Handle finish() actions
     if response.starts_with("finish(") {
-        let message = response
-            .replace("finish(message=", "")
-            .trim_start_matches('"')
-            .trim_end_matches("\")")
-            .to_string();
+        // Structured result, e.g. `finish(message="Found it", data={"price": 12})`
+        let mut data = None;
+        let mut response = response.to_string();
+        if let Some(pos) = response.rfind("data=") {
+            let candidate = response[pos + 5..].trim_end();
+            let candidate = candidate.strip_suffix(')').unwrap_or(candidate);
+            if let Ok(value @ Value::Object(_)) = serde_json::from_str(candidate) {
+                data = Some(value);
+                response = format!("{})", response[..pos].trim_end().trim_end_matches(','));
+            }
+        }
+
+        let message = response.replace("finish(message=", "");
+        let message = message.strip_suffix(')').unwrap_or(&message);
+        let message = match message.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let message = message.strip_prefix(quote).unwrap_or(message);
+                message.strip_suffix(quote).unwrap_or(message)
+            }
+            _ => message,
+        }
+        .to_string();
 
         let mut action = HashMap::new();
         action.insert("_metadata".to_string(), json!("finish"));
         action.insert("message".to_string(), json!(message));
+        if let Some(data) = data {
+            action.insert("data".to_string(), data);
+        }
         return Ok(action);
     }
 
@@ -471,12 +908,15 @@ fn parse_do_action(response: &str) -> std::result::Result<HashMap<String, Value>
         .and_then(|s| s.strip_suffix(")"))
         .ok_or_else(|| "Invalid do() format".to_string())?;
 
-    // Parse key=value pairs
+    // Parse key=value pairs; the first unquoted `=` of a pair separates the
+    // key from the value, later ones belong to the value
     // This is a simplified parser that handles the common cases
     let mut current_key = String::new();
     let mut current_value = String::new();
-    let mut in_string = false;
-    let mut in_array = false;
+    // Quote character of the string being parsed, if any
+    let mut in_string: Option<char> = None;
+    // Nesting depth of `[..]` arrays and `{..}` objects
+    let mut array_depth = 0usize;
     let mut escape_next = false;
     let mut parsing_value = false;
 
@@ -496,28 +936,39 @@ fn parse_do_action(response: &str) -> std::result::Result<HashMap<String, Value>
                     current_value.push(ch);
                 }
             }
-            '"' if !in_array => {
-                in_string = !in_string;
+            // Only a quote opening the value starts a string, so a stray
+            // quote in a bare value such as `url=https://x?a='b` is kept
+            '"' | '\''
+                if array_depth == 0
+                    && (in_string == Some(ch)
+                        || (in_string.is_none()
+                            && parsing_value
+                            && current_value.trim().is_empty())) =>
+            {
+                in_string = match in_string {
+                    Some(_) => None,
+                    None => Some(ch),
+                };
                 if parsing_value {
                     current_value.push(ch);
                 }
             }
-            '[' if !in_string => {
-                in_array = true;
+            '[' | '{' if in_string.is_none() => {
+                array_depth += 1;
                 if parsing_value {
                     current_value.push(ch);
                 }
             }
-            ']' if !in_string => {
-                in_array = false;
+            ']' | '}' if in_string.is_none() => {
+                array_depth = array_depth.saturating_sub(1);
                 if parsing_value {
                     current_value.push(ch);
                 }
             }
-            '=' if !in_string && !in_array && !parsing_value => {
+            '=' if in_string.is_none() && array_depth == 0 && !parsing_value => {
                 parsing_value = true;
             }
-            ',' if !in_string && !in_array => {
+            ',' if in_string.is_none() && array_depth == 0 => {
                 // End of key=value pair
                 if !current_key.is_empty() {
                     let value = parse_value(current_value.trim());
@@ -550,13 +1001,26 @@ fn parse_do_action(response: &str) -> std::result::Result<HashMap<String, Value>
 fn parse_value(s: &str) -> Value {
     let s = s.trim();
 
-    // String value
-    if s.starts_with('"') && s.ends_with('"') {
+    // String value, double- or single-quoted
+    let is_quoted = |q: char| s.len() >= 2 && s.starts_with(q) && s.ends_with(q);
+    if is_quoted('"') || is_quoted('\'') {
         return json!(s[1..s.len() - 1].replace("\\n", "\n").replace("\\t", "\t"));
     }
 
+    // JSON object value, e.g. a structured finish result
+    if s.starts_with('{') && s.ends_with('}') {
+        if let Ok(value @ Value::Object(_)) = serde_json::from_str(s) {
+            return value;
+        }
+    }
+
     // Array value
     if s.starts_with('[') && s.ends_with(']') {
+        // Nested arrays such as gesture points are valid JSON
+        if let Ok(value @ Value::Array(_)) = serde_json::from_str(s) {
+            return value;
+        }
+
         let inner = &s[1..s.len() - 1];
         let elements: Vec<Value> = inner
             .split(',')
@@ -594,6 +1058,178 @@ fn parse_value(s: &str) -> Value {
     json!(s)
 }
 
+/// Convert a `[x, y]` relative coordinate value (0-1000) to absolute pixels
+fn element_to_absolute(value: Option<&Value>, width: u32, height: u32) -> Option<(i32, i32)> {
+    let coords: Vec<i64> = value?
+        .as_array()?
+        .iter()
+        .filter_map(|v| v.as_i64())
+        .collect();
+    if coords.len() < 2 {
+        return None;
+    }
+    Some(relative_to_absolute((coords[0], coords[1]), width, height))
+}
+
+/// Convert relative coordinates (0-1000) to absolute pixels on a `width` x `height` screen
+pub fn relative_to_absolute(rel: (i64, i64), width: u32, height: u32) -> (i32, i32) {
+    let x = (rel.0 as f64 / 1000.0 * width as f64) as i32;
+    let y = (rel.1 as f64 / 1000.0 * height as f64) as i32;
+    (x, y)
+}
+
+/// Parameters holding a point or region, as a flat `[x, y, ...]` list
+const POINT_KEYS: &[&str] = &["element", "start", "end", "region"];
+
+/// Parameters holding a relative offset
+const OFFSET_KEYS: &[&str] = &["dx", "dy"];
+
+/// Rescale an action's coordinates from the model's scale to relative 0-1000
+///
+/// `scale` is the model's coordinate range, e.g. `100.0` for 0-100; an
+/// invalid scale is treated as 1000. A point, or the `dx`/`dy` pair, with a
+/// non-integer value and no value beyond 1.0, such as `[0.5, 0.25]`, is read
+/// as 0-1 whatever the scale. Coordinates already on the 0-1000 scale are
+/// left as they are.
+pub fn normalize_coordinates(action: &mut HashMap<String, Value>, scale: f64) {
+    let scale = if scale.is_finite() && scale > 0.0 {
+        scale
+    } else {
+        1000.0
+    };
+    let rescale = |values: &[&Value]| -> Option<Vec<Value>> {
+        let numbers: Vec<f64> = values.iter().map(|v| v.as_f64()).collect::<Option<_>>()?;
+        let fractional = values
+            .iter()
+            .any(|v| v.is_f64() && v.as_f64().is_some_and(|n| n.fract() != 0.0))
+            && numbers.iter().all(|n| n.abs() <= 1.0);
+        let factor = if fractional { 1000.0 } else { 1000.0 / scale };
+        if factor == 1.0 && values.iter().all(|v| v.is_i64()) {
+            return None;
+        }
+        Some(
+            numbers
+                .iter()
+                .map(|n| json!((n * factor).round() as i64))
+                .collect(),
+        )
+    };
+
+    for key in POINT_KEYS {
+        if let Some(Value::Array(values)) = action.get_mut(*key) {
+            if let Some(scaled) = rescale(&values.iter().collect::<Vec<_>>()) {
+                *values = scaled;
+            }
+        }
+    }
+    if let Some(Value::Array(points)) = action.get_mut("points") {
+        for point in points.iter_mut() {
+            if let Value::Array(values) = point {
+                if let Some(scaled) = rescale(&values.iter().collect::<Vec<_>>()) {
+                    *values = scaled;
+                }
+            }
+        }
+    }
+
+    // dx and dy are one vector, so they share a scale
+    let keys: Vec<&str> = OFFSET_KEYS
+        .iter()
+        .copied()
+        .filter(|key| action.contains_key(*key))
+        .collect();
+    let offsets: Vec<&Value> = keys.iter().filter_map(|key| action.get(*key)).collect();
+    if let Some(scaled) = rescale(&offsets) {
+        for (key, value) in keys.into_iter().zip(scaled) {
+            action.insert(key.to_string(), value);
+        }
+    }
+}
+
+/// Convert absolute pixels on a `width` x `height` screen to relative coordinates (0-1000)
+///
+/// The inverse of [`relative_to_absolute`], rounded to the nearest unit.
+pub fn absolute_to_relative(abs: (i32, i32), width: u32, height: u32) -> (i64, i64) {
+    let x = (abs.0 as f64 * 1000.0 / width.max(1) as f64).round() as i64;
+    let y = (abs.1 as f64 * 1000.0 / height.max(1) as f64).round() as i64;
+    (x, y)
+}
+
+/// Build a human-readable summary of a parsed action
+///
+/// Coordinates are converted to absolute pixels, e.g. `Tap at (540, 960)`.
+pub fn describe_action(
+    action: &HashMap<String, Value>,
+    screen_width: u32,
+    screen_height: u32,
+) -> String {
+    let get_str = |key: &str| action.get(key).and_then(|v| v.as_str());
+
+    if get_str("_metadata") == Some("finish") {
+        return match get_str("message") {
+            Some(message) => format!("Finish: {}", message),
+            None => "Finish".to_string(),
+        };
+    }
+
+    let action_name = get_str("action").unwrap_or("Unknown");
+    let element = element_to_absolute(action.get("element"), screen_width, screen_height);
+
+    match action_name {
+        "Tap" | "Double Tap" | "Long Press" | "Zoom" => match element {
+            Some((x, y)) => format!("{} at ({}, {})", action_name, x, y),
+            None => action_name.to_string(),
+        },
+        "Swipe" => {
+            let start = element_to_absolute(action.get("start"), screen_width, screen_height);
+            let end = element_to_absolute(action.get("end"), screen_width, screen_height);
+            match (start, end) {
+                (Some((x1, y1)), Some((x2, y2))) => {
+                    format!("Swipe from ({}, {}) to ({}, {})", x1, y1, x2, y2)
+                }
+                _ => "Swipe".to_string(),
+            }
+        }
+        "SwipeBy" => {
+            let offset = |key: &str| action.get(key).and_then(|v| v.as_i64()).unwrap_or(0);
+            match element {
+                Some((x, y)) => format!(
+                    "Swipe from ({}, {}) by ({}, {})",
+                    x,
+                    y,
+                    offset("dx"),
+                    offset("dy")
+                ),
+                None => "SwipeBy".to_string(),
+            }
+        }
+        "Gesture" | "SwipePath" => match action.get("points").and_then(|v| v.as_array()) {
+            Some(points) => format!("{} through {} points", action_name, points.len()),
+            None => action_name.to_string(),
+        },
+        "Type" | "Type_Name" => {
+            let text = get_str("text").unwrap_or("");
+            match action.get("append").and_then(|v| v.as_bool()) {
+                Some(true) => format!("Append \"{}\"", text),
+                _ => format!("Type \"{}\"", text),
+            }
+        }
+        "Launch" => format!("Launch {}", get_str("app").unwrap_or("")),
+        "OpenURL" => format!("Open {}", get_str("url").unwrap_or("")),
+        "Wait" => format!("Wait {}", get_str("duration").unwrap_or("1 seconds")),
+        "Scroll" => format!(
+            "Scroll {} x{}",
+            get_str("direction").unwrap_or("down"),
+            action.get("times").and_then(|v| v.as_u64()).unwrap_or(1)
+        ),
+        "Take_over" | "Note" | "Interact" | "Error" | "Impossible" => match get_str("message") {
+            Some(message) => format!("{}: {}", action_name, message),
+            None => action_name.to_string(),
+        },
+        _ => action_name.to_string(),
+    }
+}
+
 /// Helper function for creating 'do' actions
 pub fn do_action(action_name: &str) -> HashMap<String, Value> {
     let mut action = HashMap::new();
@@ -615,6 +1251,11 @@ pub fn finish_action(message: Option<&str>) -> HashMap<String, Value> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::adb::{Screenshot, ScreenshotEncoding};
+    use crate::error::AdbError;
+    use futures::future::BoxFuture;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
 
     #[test]
     fn test_parse_action_tap() {
@@ -631,6 +1272,50 @@ mod tests {
         assert_eq!(result.get("text").unwrap(), "Hello World");
     }
 
+    #[test]
+    fn test_parse_action_type_append() {
+        let after = parse_action("do(action=\"Type\", text=\"more\", append=true)").unwrap();
+        assert_eq!(after.get("text").unwrap(), "more");
+        assert_eq!(after.get("append").unwrap(), true);
+
+        let before = parse_action("do(action=\"Type\", append=true, text=\"more\")").unwrap();
+        assert_eq!(before.get("action").unwrap(), "Type");
+        assert_eq!(before.get("text").unwrap(), "more");
+        assert_eq!(before.get("append").unwrap(), true);
+        assert_eq!(describe_action(&before, 1000, 2000), "Append \"more\"");
+    }
+
+    #[test]
+    fn test_parse_action_open_url() {
+        let action =
+            parse_action("do(action=\"OpenURL\", url=\"https://example.com/?q=a,b\")").unwrap();
+        assert_eq!(action.get("action").unwrap(), "OpenURL");
+        assert_eq!(action.get("url").unwrap(), "https://example.com/?q=a,b");
+        assert_eq!(
+            describe_action(&action, 1000, 2000),
+            "Open https://example.com/?q=a,b"
+        );
+    }
+
+    #[test]
+    fn test_parse_action_values_with_equals() {
+        let quoted =
+            parse_action("do(action=\"OpenURL\", url=\"https://x.com/?a=b&c=d\")").unwrap();
+        assert_eq!(quoted.get("url").unwrap(), "https://x.com/?a=b&c=d");
+
+        let bare = parse_action("do(action=OpenURL, url=https://x.com/?a=b&c=d)").unwrap();
+        assert_eq!(bare.get("action").unwrap(), "OpenURL");
+        assert_eq!(bare.get("url").unwrap(), "https://x.com/?a=b&c=d");
+
+        let stray =
+            parse_action("do(action=OpenURL, url=https://x.com/?q='a=b, app=Maps)").unwrap();
+        assert_eq!(stray.get("url").unwrap(), "https://x.com/?q='a=b");
+        assert_eq!(stray.get("app").unwrap(), "Maps");
+
+        let text = parse_action("do(action=\"Type\", text=\"x=y\")").unwrap();
+        assert_eq!(text.get("text").unwrap(), "x=y");
+    }
+
     #[test]
     fn test_parse_action_finish() {
         let result = parse_action("finish(message=\"Task completed\")").unwrap();
@@ -638,6 +1323,28 @@ mod tests {
         assert_eq!(result.get("message").unwrap(), "Task completed");
     }
 
+    #[test]
+    fn test_parse_action_finish_with_data() {
+        let result = parse_action(
+            "finish(message=\"Found 2 items\", data={\"items\": [\"a\", \"b\"], \"total\": 2})",
+        )
+        .unwrap();
+        assert_eq!(result.get("message").unwrap(), "Found 2 items");
+        assert_eq!(
+            result.get("data").unwrap(),
+            &json!({"items": ["a", "b"], "total": 2})
+        );
+
+        // Text that merely mentions data= stays part of the message
+        let result = parse_action("finish(message=\"Set data=none\")").unwrap();
+        assert_eq!(result.get("message").unwrap(), "Set data=none");
+        assert!(!result.contains_key("data"));
+
+        let result =
+            parse_action("do(action=\"Note\", message=\"ok\", data={\"a\": [1, 2]})").unwrap();
+        assert_eq!(result.get("data").unwrap(), &json!({"a": [1, 2]}));
+    }
+
     #[test]
     fn test_parse_action_swipe() {
         let result = parse_action("do(action=\"Swipe\", start=[100, 500], end=[100, 200])").unwrap();
@@ -645,6 +1352,124 @@ mod tests {
         assert_eq!(result.get("action").unwrap(), "Swipe");
     }
 
+    #[test]
+    fn test_parse_action_scroll() {
+        let result = parse_action("do(action=\"Scroll\", direction=\"up\", times=3)").unwrap();
+        assert_eq!(result.get("action").unwrap(), "Scroll");
+        assert_eq!(result.get("direction").unwrap(), "up");
+        assert_eq!(result.get("times").unwrap(), 3);
+    }
+
+    #[test]
+    fn test_parse_action_single_quotes() {
+        let tap = parse_action("do(action='Tap', element=[500, 300])").unwrap();
+        assert_eq!(tap.get("action").unwrap(), "Tap");
+        assert_eq!(tap.get("element").unwrap(), &json!([500, 300]));
+
+        let typed = parse_action("do(action='Type', text='it\"s fine')").unwrap();
+        assert_eq!(typed.get("action").unwrap(), "Type");
+        assert_eq!(typed.get("text").unwrap(), "it\"s fine");
+
+        let finish = parse_action("finish(message='Done')").unwrap();
+        assert_eq!(finish.get("message").unwrap(), "Done");
+    }
+
+    #[test]
+    fn test_parse_action_bare_identifiers() {
+        let result = parse_action("do(action=Scroll, direction=down, times=2)").unwrap();
+        assert_eq!(result.get("action").unwrap(), "Scroll");
+        assert_eq!(result.get("direction").unwrap(), "down");
+        assert_eq!(result.get("times").unwrap(), 2);
+    }
+
+    #[test]
+    fn test_parse_action_long_press_duration() {
+        let result =
+            parse_action("do(action=\"Long Press\", element=[500, 300], duration=800)").unwrap();
+        assert_eq!(result.get("action").unwrap(), "Long Press");
+        assert_eq!(result.get("duration").and_then(|v| v.as_u64()), Some(800));
+    }
+
+    #[test]
+    fn test_parse_action_gesture() {
+        let result = parse_action(
+            "do(action=\"Gesture\", points=[[100, 200], [500, 800], [900, 200]], durations=[300, 150])",
+        )
+        .unwrap();
+        assert_eq!(
+            result.get("points").unwrap(),
+            &json!([[100, 200], [500, 800], [900, 200]])
+        );
+        assert_eq!(result.get("durations").unwrap(), &json!([300, 150]));
+        assert_eq!(
+            describe_action(&result, 1000, 2000),
+            "Gesture through 3 points"
+        );
+    }
+
+    #[test]
+    fn test_describe_action() {
+        let tap = parse_action("do(action=\"Tap\", element=[500, 400])").unwrap();
+        assert_eq!(describe_action(&tap, 1080, 2400), "Tap at (540, 960)");
+
+        let swipe = parse_action("do(action=\"Swipe\", start=[500, 800], end=[500, 200])").unwrap();
+        assert_eq!(
+            describe_action(&swipe, 1000, 2000),
+            "Swipe from (500, 1600) to (500, 400)"
+        );
+
+        let finish = finish_action(Some("All done"));
+        assert_eq!(describe_action(&finish, 1080, 2400), "Finish: All done");
+    }
+
+    #[test]
+    fn test_coordinate_conversion() {
+        assert_eq!(relative_to_absolute((500, 400), 1080, 2400), (540, 960));
+        assert_eq!(absolute_to_relative((540, 960), 1080, 2400), (500, 400));
+        assert_eq!(absolute_to_relative((1079, 0), 1080, 2400), (999, 0));
+
+        let abs = relative_to_absolute((250, 875), 1080, 2400);
+        assert_eq!(absolute_to_relative(abs, 1080, 2400), (250, 875));
+    }
+
+    #[test]
+    fn test_normalize_coordinates() {
+        let mut tap = parse_action("do(action=\"Tap\", element=[0.5, 0.25])").unwrap();
+        normalize_coordinates(&mut tap, 1000.0);
+        assert_eq!(tap["element"], json!([500, 250]));
+
+        let mut swipe = parse_action("do(action=\"Swipe\", start=[50, 80], end=[50, 20])").unwrap();
+        normalize_coordinates(&mut swipe, 100.0);
+        assert_eq!(swipe["start"], json!([500, 800]));
+        assert_eq!(swipe["end"], json!([500, 200]));
+
+        let mut swipe_by =
+            parse_action("do(action=\"SwipeBy\", element=[50, 50], dy=-40)").unwrap();
+        normalize_coordinates(&mut swipe_by, 100.0);
+        assert_eq!(swipe_by["dy"], json!(-400));
+
+        let mut edge = parse_action("do(action=\"Tap\", element=[1.0, 0.5])").unwrap();
+        normalize_coordinates(&mut edge, 100.0);
+        assert_eq!(edge["element"], json!([1000, 500]));
+
+        let mut small = parse_action("do(action=\"Tap\", element=[0, 1])").unwrap();
+        normalize_coordinates(&mut small, 1000.0);
+        assert_eq!(small["element"], json!([0, 1]));
+
+        let mut offsets =
+            parse_action("do(action=\"SwipeBy\", element=[500, 500], dx=0.5, dy=300)").unwrap();
+        normalize_coordinates(&mut offsets, 1000.0);
+        assert_eq!((&offsets["dx"], &offsets["dy"]), (&json!(1), &json!(300)));
+
+        let mut unit_dy = parse_action("do(action=\"SwipeBy\", element=[500, 500], dy=1)").unwrap();
+        normalize_coordinates(&mut unit_dy, 1000.0);
+        assert_eq!(unit_dy["dy"], json!(1));
+
+        let mut unchanged = parse_action("do(action=\"Tap\", element=[500, 1000])").unwrap();
+        normalize_coordinates(&mut unchanged, 1000.0);
+        assert_eq!(unchanged["element"], json!([500, 1000]));
+    }
+
     #[test]
     fn test_action_result_success() {
         let result = ActionResult::success();
@@ -659,4 +1484,455 @@ mod tests {
         assert!(result.should_finish);
         assert_eq!(result.message, Some("Done".to_string()));
     }
+
+    #[derive(Default)]
+    struct MockDevice {
+        taps: Mutex<Vec<(i32, i32)>>,
+        gestures: Mutex<Vec<Vec<(i32, i32)>>>,
+        keyboard_switch_fails: bool,
+        text_unfocused: bool,
+        keyboard_shown: bool,
+        backs: AtomicUsize,
+        current_app: Option<&'static str>,
+        launches: AtomicUsize,
+        typed: Mutex<Vec<String>>,
+        swipes: Mutex<Vec<(i32, i32, i32, i32)>>,
+    }
+
+    impl DeviceController for MockDevice {
+        fn get_screenshot<'a>(
+            &'a self,
+            _device_id: Option<&'a str>,
+            _timeout: u64,
+        ) -> BoxFuture<'a, Result<Screenshot>> {
+            Box::pin(async {
+                Ok(Screenshot {
+                    base64_data: String::new(),
+                    width: 1000,
+                    height: 2000,
+                    is_sensitive: false,
+                    encoding: ScreenshotEncoding::Png,
+                })
+            })
+        }
+
+        fn get_current_app<'a>(
+            &'a self,
+            _device_id: Option<&'a str>,
+        ) -> BoxFuture<'a, Result<String>> {
+            let current = self.current_app.unwrap_or("System Home");
+            Box::pin(async move { Ok(current.to_string()) })
+        }
+
+        fn tap<'a>(
+            &'a self,
+            x: i32,
+            y: i32,
+            _device_id: Option<&'a str>,
+            _delay: Option<f64>,
+        ) -> BoxFuture<'a, Result<()>> {
+            self.taps.lock().unwrap().push((x, y));
+            Box::pin(async { Ok(()) })
+        }
+
+        fn double_tap<'a>(
+            &'a self,
+            _x: i32,
+            _y: i32,
+            _device_id: Option<&'a str>,
+            _delay: Option<f64>,
+        ) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn long_press<'a>(
+            &'a self,
+            _x: i32,
+            _y: i32,
+            _duration_ms: u32,
+            _device_id: Option<&'a str>,
+            _delay: Option<f64>,
+        ) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn swipe<'a>(
+            &'a self,
+            start_x: i32,
+            start_y: i32,
+            end_x: i32,
+            end_y: i32,
+            _duration_ms: Option<u32>,
+            _device_id: Option<&'a str>,
+            _delay: Option<f64>,
+        ) -> BoxFuture<'a, Result<()>> {
+            self.swipes
+                .lock()
+                .unwrap()
+                .push((start_x, start_y, end_x, end_y));
+            Box::pin(async { Ok(()) })
+        }
+
+        fn back<'a>(
+            &'a self,
+            _device_id: Option<&'a str>,
+            _delay: Option<f64>,
+        ) -> BoxFuture<'a, Result<()>> {
+            self.backs.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async { Ok(()) })
+        }
+
+        fn home<'a>(
+            &'a self,
+            _device_id: Option<&'a str>,
+            _delay: Option<f64>,
+        ) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn launch_app<'a>(
+            &'a self,
+            _app_name: &'a str,
+            _device_id: Option<&'a str>,
+            _delay: Option<f64>,
+        ) -> BoxFuture<'a, Result<bool>> {
+            self.launches.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async { Ok(true) })
+        }
+
+        fn type_text<'a>(
+            &'a self,
+            text: &'a str,
+            _device_id: Option<&'a str>,
+        ) -> BoxFuture<'a, Result<()>> {
+            self.typed.lock().unwrap().push(text.to_string());
+            Box::pin(async { Ok(()) })
+        }
+
+        fn is_text_input_focused<'a>(
+            &'a self,
+            _device_id: Option<&'a str>,
+        ) -> BoxFuture<'a, Result<bool>> {
+            Box::pin(async move { Ok(!self.text_unfocused) })
+        }
+
+        fn is_keyboard_visible<'a>(
+            &'a self,
+            _device_id: Option<&'a str>,
+        ) -> BoxFuture<'a, Result<bool>> {
+            Box::pin(async move { Ok(self.keyboard_shown) })
+        }
+
+        fn clear_text<'a>(&'a self, _device_id: Option<&'a str>) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn detect_and_set_adb_keyboard<'a>(
+            &'a self,
+            _device_id: Option<&'a str>,
+        ) -> BoxFuture<'a, Result<String>> {
+            Box::pin(async move {
+                if self.keyboard_switch_fails {
+                    return Err(AdbError::CommandFailed(
+                        "Failed to switch to ADB Keyboard".to_string(),
+                    ));
+                }
+                Ok(String::new())
+            })
+        }
+
+        fn restore_keyboard<'a>(
+            &'a self,
+            _ime: &'a str,
+            _device_id: Option<&'a str>,
+        ) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn gesture<'a>(
+            &'a self,
+            points: &'a [(i32, i32)],
+            _durations_ms: &'a [u32],
+            _device_id: Option<&'a str>,
+        ) -> BoxFuture<'a, Result<()>> {
+            self.gestures.lock().unwrap().push(points.to_vec());
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_execute_with_mock_device() {
+        let device = Arc::new(MockDevice::default());
+        let handler = ActionHandler::new(None, None, None).with_device(device.clone());
+
+        let action = parse_action("do(action=\"Tap\", element=[500, 500])").unwrap();
+        let result = handler.execute(&action, 1000, 2000).await;
+
+        assert!(result.success);
+        assert_eq!(*device.taps.lock().unwrap(), vec![(500, 1000)]);
+    }
+
+    #[tokio::test]
+    async fn test_type_reports_keyboard_switch_failure() {
+        let device = Arc::new(MockDevice {
+            keyboard_switch_fails: true,
+            ..MockDevice::default()
+        });
+        let handler = ActionHandler::new(None, None, None).with_device(device);
+
+        let result = handler
+            .execute_str("do(action=\"Type\", text=\"你好\")")
+            .await;
+
+        assert!(!result.success);
+        assert_eq!(
+            result.message.as_deref(),
+            Some("Text was not typed: Command execution failed: Failed to switch to ADB Keyboard")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_type_requires_focused_text_field() {
+        let device = Arc::new(MockDevice {
+            text_unfocused: true,
+            ..MockDevice::default()
+        });
+        let handler = ActionHandler::new(None, None, None)
+            .with_device(device.clone())
+            .with_text_focus_check(true);
+
+        let result = handler
+            .execute_str("do(action=\"Type\", text=\"你好\")")
+            .await;
+        assert!(!result.success);
+        assert!(device.typed.lock().unwrap().is_empty());
+
+        let handler = handler.with_text_focus_check(false);
+        let result = handler
+            .execute_str("do(action=\"Type\", text=\"你好\")")
+            .await;
+        assert!(result.success);
+        assert_eq!(*device.typed.lock().unwrap(), vec!["你好".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_type_dismisses_keyboard() {
+        let device = Arc::new(MockDevice {
+            keyboard_shown: true,
+            ..MockDevice::default()
+        });
+        let handler = ActionHandler::new(None, None, None).with_device(device.clone());
+
+        let action = "do(action=\"Type\", text=\"你好\")";
+        assert!(handler.execute_str(action).await.success);
+        assert_eq!(device.backs.load(Ordering::SeqCst), 0);
+
+        let handler = handler.with_keyboard_dismiss(true);
+        assert!(handler.execute_str(action).await.success);
+        assert_eq!(device.backs.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_type_confirmation_patterns() {
+        let device = Arc::new(MockDevice::default());
+        let handler = ActionHandler::new(None, Some(Box::new(|_| false)), None)
+            .with_device(device.clone())
+            .with_type_confirmation(["transfer", r"rm\s+-rf"])
+            .unwrap();
+
+        let result = handler
+            .execute_str("do(action=\"Type\", text=\"Transfer 500 to Bob\")")
+            .await;
+        assert!(!result.success);
+        assert!(result.should_finish);
+        assert!(device.typed.lock().unwrap().is_empty());
+
+        let result = handler
+            .execute_str("do(action=\"Type\", text=\"你好\")")
+            .await;
+        assert!(result.success);
+        assert_eq!(*device.typed.lock().unwrap(), vec!["你好".to_string()]);
+
+        assert!(ActionHandler::new(None, None, None)
+            .with_type_confirmation(["("])
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_launch_skips_foreground_app() {
+        let device = Arc::new(MockDevice {
+            current_app: Some("WeChat"),
+            ..MockDevice::default()
+        });
+        let handler = ActionHandler::new(None, None, None).with_device(device.clone());
+
+        let result = handler
+            .execute_str("do(action=\"Launch\", app=\"wechat\")")
+            .await;
+        assert!(result.success);
+        assert_eq!(device.launches.load(Ordering::SeqCst), 0);
+
+        let result = handler
+            .execute_str("do(action=\"Launch\", app=\"WeChat\", force=true)")
+            .await;
+        assert!(result.success);
+        assert_eq!(device.launches.load(Ordering::SeqCst), 1);
+
+        let result = handler
+            .execute_str("do(action=\"Launch\", app=\"Settings\")")
+            .await;
+        assert!(result.success);
+        assert_eq!(device.launches.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_async_confirmation_cancels_tap() {
+        let device = Arc::new(MockDevice::default());
+        let confirm: AsyncConfirmationCallback = Box::new(|_message| Box::pin(async { false }));
+        let handler = ActionHandler::new(None, None, None)
+            .with_device(device.clone())
+            .with_async_confirmation_callback(confirm);
+
+        let result = handler
+            .execute_str("do(action=\"Tap\", element=[500, 500], message=\"Pay\")")
+            .await;
+
+        assert!(!result.success);
+        assert!(result.should_finish);
+        assert!(device.taps.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_error_action_ends_task_unsuccessfully() {
+        let handler =
+            ActionHandler::new(None, None, None).with_device(Arc::new(MockDevice::default()));
+
+        let result = handler
+            .execute_str("do(action=\"Error\", message=\"App is not installed\")")
+            .await;
+
+        assert!(!result.success);
+        assert!(result.should_finish);
+        assert_eq!(result.message.as_deref(), Some("App is not installed"));
+    }
+
+    #[tokio::test]
+    async fn test_zoom_requests_refinement() {
+        let handler =
+            ActionHandler::new(None, None, None).with_device(Arc::new(MockDevice::default()));
+
+        let result = handler
+            .execute_str("do(action=\"Zoom\", element=[100, 500])")
+            .await;
+        assert!(result.success && !result.should_finish);
+        assert_eq!(
+            result.refine_region,
+            Some(RelativeRegion::new(0, 350, 250, 650))
+        );
+
+        let result = handler.execute_str("do(action=\"Zoom\")").await;
+        assert!(!result.success);
+        assert_eq!(result.refine_region, None);
+
+        let region = RelativeRegion::new(0, 350, 250, 650);
+        assert_eq!(region.to_screen((1000, 0)), (250, 350));
+        assert_eq!(region.to_pixels(1000, 2000), (0, 700, 250, 600));
+    }
+
+    #[tokio::test]
+    async fn test_swipe_path_draws_curve_through_waypoints() {
+        let device = Arc::new(MockDevice::default());
+        let handler = ActionHandler::new(None, None, None).with_device(device.clone());
+
+        let action = "do(action=\"SwipePath\", points=[[100, 100], [500, 900], [900, 100]])";
+        let result = handler
+            .execute(&parse_action(action).unwrap(), 1000, 2000)
+            .await;
+
+        assert!(result.success);
+        let gestures = device.gestures.lock().unwrap();
+        assert_eq!(gestures.len(), 1);
+        assert_eq!(gestures[0].first(), Some(&(100, 200)));
+        assert!(gestures[0].contains(&(500, 1800)));
+        assert_eq!(gestures[0].last(), Some(&(900, 200)));
+    }
+
+    #[tokio::test]
+    async fn test_swipe_by_clamps_to_screen() {
+        let device = Arc::new(MockDevice::default());
+        let handler = ActionHandler::new(None, None, None).with_device(device.clone());
+
+        let result = handler
+            .execute_str("do(action=\"SwipeBy\", element=[500, 500], dy=-400)")
+            .await;
+        assert!(result.success);
+        let result = handler
+            .execute_str("do(action=\"SwipeBy\", element=[500, 800], dx=700, dy=400)")
+            .await;
+        assert!(result.success);
+
+        assert_eq!(
+            *device.swipes.lock().unwrap(),
+            vec![(500, 1000, 500, 200), (500, 1600, 999, 1999)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_interact_hands_over_to_user() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        let takeover: TakeoverCallback = Box::new(move |message: &str| {
+            recorded.lock().unwrap().push(message.to_string());
+        });
+        let handler = ActionHandler::new(None, None, Some(takeover))
+            .with_device(Arc::new(MockDevice::default()));
+
+        let result = handler
+            .execute_str("do(action=\"Interact\", message=\"Pick a contact\")")
+            .await;
+
+        assert!(result.success);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec!["Pick a contact".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_allowed_actions_reject_others() {
+        let device = Arc::new(MockDevice::default());
+        let allowed: HashSet<String> = ["Tap".to_string(), "Back".to_string()].into();
+        let handler = ActionHandler::new(None, None, None)
+            .with_device(device.clone())
+            .with_allowed_actions(allowed);
+
+        let result = handler
+            .execute_str("do(action=\"Launch\", app=\"Settings\")")
+            .await;
+        assert!(!result.success);
+        assert_eq!(
+            result.message.as_deref(),
+            Some("Action \"Launch\" is not allowed. Allowed actions: Back, Tap")
+        );
+
+        let tap = handler.execute_str("do(action=\"Tap\", element=[500, 500])");
+        assert!(tap.await.success);
+        let finish = handler.execute_str("finish(message=\"done\")");
+        assert!(finish.await.success);
+        assert_eq!(device.taps.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_execute_str_uses_device_screen_size() {
+        let device = Arc::new(MockDevice::default());
+        let handler = ActionHandler::new(None, None, None).with_device(device.clone());
+
+        let result = handler
+            .execute_str("do(action=\"Tap\", element=[250, 250])")
+            .await;
+
+        assert!(result.success);
+        assert_eq!(*device.taps.lock().unwrap(), vec![(250, 500)]);
+        assert!(!handler.execute_str("not an action").await.success);
+    }
 }
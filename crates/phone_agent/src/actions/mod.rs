@@ -1,11 +1,19 @@
 //! Action handling module for processing AI model outputs
 //!
 //! This module provides:
+//! - `action`: Typed representation of parsed actions
 //! - `handler`: Action execution and processing
+//! - `spec`: Supported actions and their parameters
 
+mod action;
 mod handler;
+mod spec;
 
 pub use handler::{
-    do_action, finish_action, parse_action, ActionHandler, ActionResult, ConfirmationCallback,
-    TakeoverCallback,
+    absolute_to_relative, describe_action, do_action, finish_action, normalize_coordinates,
+    parse_action, relative_to_absolute, ActionHandler, ActionResult, AsyncConfirmationCallback,
+    ConfirmationCallback, RelativeRegion, TakeoverCallback,
 };
+pub use action::{parse_action_typed, Action, RelativePoint};
+pub use spec::{find_action, supported_actions, validate_action, ActionSpec};
+pub(crate) use handler::handle_error;
@@ -0,0 +1,148 @@
+//! Names and parameters of the actions the handler supports
+
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Name and parameters of a `do(...)` action
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActionSpec {
+    /// Action name, as given in `do(action="...")`
+    pub name: &'static str,
+    /// Other names accepted for the same action
+    pub aliases: &'static [&'static str],
+    /// Parameters the action can't run without
+    pub required: &'static [&'static str],
+    /// Parameters that have a default when omitted
+    pub optional: &'static [&'static str],
+}
+
+impl ActionSpec {
+    const fn new(
+        name: &'static str,
+        required: &'static [&'static str],
+        optional: &'static [&'static str],
+    ) -> Self {
+        Self {
+            name,
+            aliases: &[],
+            required,
+            optional,
+        }
+    }
+
+    const fn with_aliases(mut self, aliases: &'static [&'static str]) -> Self {
+        self.aliases = aliases;
+        self
+    }
+
+    /// Whether `name` refers to this action
+    pub fn matches(&self, name: &str) -> bool {
+        self.name == name || self.aliases.contains(&name)
+    }
+}
+
+/// Every action [`super::Action`] can represent
+const ACTION_SPECS: &[ActionSpec] = &[
+    ActionSpec::new("Launch", &["app"], &["force"]),
+    ActionSpec::new("OpenURL", &["url"], &[]),
+    ActionSpec::new("Tap", &["element"], &["message"]),
+    ActionSpec::new("Type", &[], &["text", "append"]).with_aliases(&["Type_Name"]),
+    ActionSpec::new("Swipe", &["start", "end"], &["duration"]),
+    ActionSpec::new("SwipeBy", &["element"], &["dx", "dy", "duration"]),
+    ActionSpec::new("Scroll", &[], &["direction", "times"]),
+    ActionSpec::new("ScrollToTop", &[], &[]),
+    ActionSpec::new("ScrollToBottom", &[], &[]),
+    ActionSpec::new("Gesture", &["points"], &["durations", "duration"]),
+    ActionSpec::new("SwipePath", &["points"], &["duration"]),
+    ActionSpec::new("Back", &[], &[]),
+    ActionSpec::new("Home", &[], &[]),
+    ActionSpec::new("Double Tap", &["element"], &[]),
+    ActionSpec::new("Long Press", &["element"], &["duration"]),
+    ActionSpec::new("Wait", &[], &["duration"]),
+    ActionSpec::new("Error", &[], &["message"]).with_aliases(&["Impossible"]),
+    ActionSpec::new("Take_over", &[], &["message"]),
+    ActionSpec::new("Note", &[], &["message"]),
+    ActionSpec::new("Call_API", &[], &["instruction"]),
+    ActionSpec::new("Interact", &[], &["message", "description"]),
+    ActionSpec::new("Zoom", &[], &["region", "element"]),
+];
+
+/// List the `do(...)` actions the handler supports, with their parameters
+///
+/// `finish(message=...)` is not included; it ends the task rather than
+/// running an action.
+pub fn supported_actions() -> Vec<ActionSpec> {
+    ACTION_SPECS.to_vec()
+}
+
+/// Look up the spec of an action by name or alias
+pub fn find_action(name: &str) -> Option<&'static ActionSpec> {
+    ACTION_SPECS.iter().find(|spec| spec.matches(name))
+}
+
+/// Check a parsed `do(...)` action against its spec
+///
+/// Fails if the action is unknown or a required parameter is missing.
+/// Actions of other kinds, e.g. `finish`, are not checked.
+pub fn validate_action(action: &HashMap<String, Value>) -> Result<(), String> {
+    if action.get("_metadata").and_then(|v| v.as_str()) != Some("do") {
+        return Ok(());
+    }
+
+    let name = action.get("action").and_then(|v| v.as_str()).unwrap_or("");
+    let spec = find_action(name).ok_or_else(|| format!("Unknown action: {}", name))?;
+
+    let missing: Vec<&str> = spec
+        .required
+        .iter()
+        .copied()
+        .filter(|param| !action.contains_key(*param))
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!(
+            "{} is missing required parameters: {}",
+            spec.name,
+            missing.join(", ")
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::{do_action, finish_action};
+    use serde_json::json;
+
+    #[test]
+    fn test_find_action_by_alias() {
+        assert_eq!(find_action("Type_Name").map(|spec| spec.name), Some("Type"));
+        assert_eq!(
+            find_action("Impossible").map(|spec| spec.name),
+            Some("Error")
+        );
+        assert!(find_action("Teleport").is_none());
+        assert_eq!(supported_actions().len(), ACTION_SPECS.len());
+    }
+
+    #[test]
+    fn test_validate_action() {
+        let mut swipe = do_action("Swipe");
+        swipe.insert("start".to_string(), json!([100, 500]));
+        assert_eq!(
+            validate_action(&swipe),
+            Err("Swipe is missing required parameters: end".to_string())
+        );
+
+        swipe.insert("end".to_string(), json!([100, 200]));
+        assert_eq!(validate_action(&swipe), Ok(()));
+
+        assert_eq!(validate_action(&do_action("Back")), Ok(()));
+        assert_eq!(validate_action(&finish_action(None)), Ok(()));
+        assert_eq!(
+            validate_action(&do_action("Teleport")),
+            Err("Unknown action: Teleport".to_string())
+        );
+    }
+}
@@ -0,0 +1,201 @@
+//! ADB binary location and command construction shared by all ADB operations
+
+use super::connection::AdbConnection;
+use crate::config::TIMING_CONFIG;
+use crate::error::{AdbError, Result};
+use std::env;
+use std::process::Output;
+use std::sync::{OnceLock, RwLock};
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+/// stderr messages showing that a command failed before reaching the device
+///
+/// `daemon not running` is left out: adb prints it while starting the server
+/// itself and then goes on to run the command.
+const SERVER_DOWN_MARKERS: &[&str] = &[
+    "cannot connect to daemon",
+    "failed to start daemon",
+    "device offline",
+];
+
+/// Serializes server restarts so concurrent failures don't restart it repeatedly
+static SERVER_RESTART: Mutex<()> = Mutex::const_new(());
+
+/// ADB binary path and extra environment variables for spawned ADB processes
+#[derive(Debug, Clone)]
+struct AdbEnvironment {
+    path: String,
+    envs: Vec<(String, String)>,
+}
+
+/// Global ADB environment (path defaults to `PHONE_AGENT_ADB_PATH` or `adb`)
+static ADB_ENVIRONMENT: OnceLock<RwLock<AdbEnvironment>> = OnceLock::new();
+
+fn adb_environment() -> &'static RwLock<AdbEnvironment> {
+    ADB_ENVIRONMENT.get_or_init(|| {
+        RwLock::new(AdbEnvironment {
+            path: env::var("PHONE_AGENT_ADB_PATH").unwrap_or_else(|_| "adb".to_string()),
+            envs: Vec::new(),
+        })
+    })
+}
+
+/// Set the ADB binary used by all ADB operations
+pub fn set_adb_path(path: impl Into<String>) {
+    let mut guard = adb_environment().write().unwrap_or_else(|e| e.into_inner());
+    guard.path = path.into();
+}
+
+/// Get the ADB binary used by all ADB operations
+pub fn get_adb_path() -> String {
+    adb_environment()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .path
+        .clone()
+}
+
+/// Set an environment variable for spawned ADB processes
+///
+/// Useful for e.g. `ANDROID_ADB_SERVER_PORT` or `ADB_VENDOR_KEYS` in CI.
+pub fn set_adb_env(key: impl Into<String>, value: impl Into<String>) {
+    let key = key.into();
+    let mut guard = adb_environment().write().unwrap_or_else(|e| e.into_inner());
+    guard.envs.retain(|(k, _)| *k != key);
+    guard.envs.push((key, value.into()));
+}
+
+/// Get the extra environment variables for spawned ADB processes
+pub fn get_adb_envs() -> Vec<(String, String)> {
+    adb_environment()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .envs
+        .clone()
+}
+
+/// `-d <id>` arguments for `screencap` and `input`, empty for the default display
+///
+/// A display id targets e.g. the cover screen of a foldable.
+pub(crate) fn display_args(display_id: Option<u64>) -> Vec<String> {
+    match display_id {
+        Some(id) => vec!["-d".to_string(), id.to_string()],
+        None => Vec::new(),
+    }
+}
+
+/// Build an ADB command with the configured binary, environment and optional device specifier
+pub(crate) fn adb_command(device_id: Option<&str>) -> Command {
+    let mut cmd = Command::new(get_adb_path());
+    cmd.envs(get_adb_envs());
+    if let Some(id) = device_id {
+        cmd.arg("-s").arg(id);
+    }
+    cmd
+}
+
+/// Check whether a command failed because the ADB server or device connection is down
+///
+/// Anything on stdout means the command ran on the device, so it is never
+/// reported as a connection failure.
+fn is_server_down(output: &Output) -> bool {
+    if output.status.success() || !output.stdout.is_empty() {
+        return false;
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).to_lowercase();
+    SERVER_DOWN_MARKERS
+        .iter()
+        .any(|marker| stderr.contains(marker))
+}
+
+/// Run an ADB command, restarting a dead ADB server and retrying once
+///
+/// Only commands that failed to reach the device are retried, so actions such
+/// as taps or text input are never sent twice. Each attempt is abandoned (and
+/// the adb process killed) after `TIMING_CONFIG.device.command_timeout`
+/// seconds. Only a single retry is made per command, so a server that won't
+/// come back returns the failure instead of looping.
+pub(crate) async fn run_adb(cmd: &mut Command) -> Result<Output> {
+    cmd.kill_on_drop(true);
+
+    let output = output_with_timeout(cmd).await?;
+    if !is_server_down(&output) {
+        return Ok(output);
+    }
+
+    let _guard = SERVER_RESTART.lock().await;
+
+    // Another command may have restarted the server while we waited
+    let output = output_with_timeout(cmd).await?;
+    if !is_server_down(&output) {
+        return Ok(output);
+    }
+
+    warn!("ADB server is not running, restarting it");
+    if let Err(e) = AdbConnection::new().restart_server().await {
+        warn!("Failed to restart ADB server: {}", e);
+        return Ok(output);
+    }
+
+    output_with_timeout(cmd).await
+}
+
+/// Run a command to completion within the configured command timeout
+async fn output_with_timeout(cmd: &mut Command) -> Result<Output> {
+    let timeout = TIMING_CONFIG.device.command_timeout;
+    tokio::time::timeout(Duration::from_secs_f64(timeout), cmd.output())
+        .await
+        .map_err(|_| AdbError::Timeout(format!("ADB command timeout after {}s", timeout)))?
+        .map_err(AdbError::Io)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failed_output(stdout: &str, stderr: &str) -> Output {
+        #[cfg(unix)]
+        use std::os::unix::process::ExitStatusExt;
+        #[cfg(windows)]
+        use std::os::windows::process::ExitStatusExt;
+
+        Output {
+            status: std::process::ExitStatus::from_raw(1 << 8),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: stderr.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_is_server_down() {
+        assert!(is_server_down(&failed_output(
+            "",
+            "error: cannot connect to daemon at tcp:5037: Connection refused"
+        )));
+        assert!(is_server_down(&failed_output("", "error: device offline")));
+        assert!(!is_server_down(&failed_output(
+            "",
+            "error: device 'emulator-5554' not found"
+        )));
+
+        // The server was started on demand and the command itself failed
+        assert!(!is_server_down(&failed_output(
+            "",
+            "* daemon not running; starting now at tcp:5037\n* daemon started successfully\n"
+        )));
+        // Output from the device means the command already ran
+        assert!(!is_server_down(&failed_output(
+            "Events injected: 1\n",
+            "error: device offline"
+        )));
+    }
+
+    #[test]
+    fn test_display_args() {
+        assert!(display_args(None).is_empty());
+        assert_eq!(display_args(Some(1)), vec!["-d", "1"]);
+    }
+}
@@ -1,9 +1,16 @@
 //! ADB connection management for local and remote devices
 
+use super::command::{get_adb_envs, get_adb_path};
 use crate::config::TIMING_CONFIG;
 use crate::error::{AdbError, Result};
+use std::env;
+use std::fmt;
 use std::time::Duration;
 use tokio::process::Command;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior};
+use tracing::{info, warn};
 
 /// Type of ADB connection
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -11,6 +18,110 @@ pub enum ConnectionType {
     Usb,
     Wifi,
     Remote,
+    /// Android emulator (`emulator-5554`)
+    Emulator,
+}
+
+impl ConnectionType {
+    /// Guess the connection type from an ADB device serial
+    ///
+    /// - `emulator-*` serials are emulators
+    /// - `localhost`/`127.0.0.1` addresses and mDNS wireless debugging
+    ///   services are local Wi-Fi/TCP connections
+    /// - other `host:port` addresses are remote
+    /// - everything else is USB
+    pub fn from_device_id(device_id: &str) -> Self {
+        if device_id.starts_with("emulator-") {
+            return Self::Emulator;
+        }
+        if is_mdns_address(device_id) {
+            return Self::Wifi;
+        }
+        match device_id.rsplit_once(':') {
+            Some((host, _)) => {
+                let host = host.trim_start_matches('[').trim_end_matches(']');
+                if host == "localhost" || host == "::1" || host.starts_with("127.") {
+                    Self::Wifi
+                } else {
+                    Self::Remote
+                }
+            }
+            None => Self::Usb,
+        }
+    }
+}
+
+/// State of a device as listed by `adb devices`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceStatus {
+    /// Booted and accepting commands
+    Device,
+    Offline,
+    /// USB debugging hasn't been allowed on the device yet
+    Unauthorized,
+    Authorizing,
+    /// Booted into the recovery system
+    Recovery,
+    /// Waiting for `adb sideload`
+    Sideload,
+    /// In the bootloader (fastboot)
+    Bootloader,
+    /// Any other state, kept as reported
+    Other(String),
+}
+
+impl DeviceStatus {
+    /// Whether the device can run commands
+    pub fn is_ready(&self) -> bool {
+        matches!(self, Self::Device)
+    }
+
+    /// Describe the state for an error message, e.g. `device in recovery mode`
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Device => "device ready".to_string(),
+            Self::Offline => "device offline".to_string(),
+            Self::Unauthorized => {
+                "device unauthorized; allow USB debugging on the device".to_string()
+            }
+            Self::Authorizing => "device still authorizing".to_string(),
+            Self::Recovery => "device in recovery mode".to_string(),
+            Self::Sideload => "device in sideload mode".to_string(),
+            Self::Bootloader => "device in bootloader mode".to_string(),
+            Self::Other(status) => format!("device in state '{}'", status),
+        }
+    }
+}
+
+impl From<&str> for DeviceStatus {
+    fn from(status: &str) -> Self {
+        match status {
+            "device" => Self::Device,
+            "offline" => Self::Offline,
+            "unauthorized" => Self::Unauthorized,
+            "authorizing" => Self::Authorizing,
+            "recovery" => Self::Recovery,
+            "sideload" => Self::Sideload,
+            "bootloader" => Self::Bootloader,
+            other => Self::Other(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for DeviceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self {
+            Self::Device => "device",
+            Self::Offline => "offline",
+            Self::Unauthorized => "unauthorized",
+            Self::Authorizing => "authorizing",
+            Self::Recovery => "recovery",
+            Self::Sideload => "sideload",
+            Self::Bootloader => "bootloader",
+            Self::Other(status) => status,
+        };
+        f.write_str(status)
+    }
 }
 
 /// Information about a connected device
@@ -23,39 +134,111 @@ pub struct DeviceInfo {
     pub android_version: Option<String>,
 }
 
+impl DeviceInfo {
+    /// The listed status as a [`DeviceStatus`]
+    pub fn device_status(&self) -> DeviceStatus {
+        DeviceStatus::from(self.status.as_str())
+    }
+}
+
+/// A port forward listed by `adb forward --list` or `adb reverse --list`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortForward {
+    /// Device serial (or the transport name, for reverse forwards)
+    pub device_id: String,
+    /// Socket on the listening side, e.g. `tcp:8080`
+    pub local: String,
+    /// Socket connections are forwarded to, e.g. `tcp:8080`
+    pub remote: String,
+}
+
+/// Handle to a running connection heartbeat, see [`AdbConnection::start_heartbeat`]
+///
+/// The heartbeat stops when the handle is dropped.
+pub struct HeartbeatHandle {
+    alive: watch::Receiver<bool>,
+    task: JoinHandle<()>,
+}
+
+impl HeartbeatHandle {
+    /// Whether the device was online at the last heartbeat
+    pub fn is_alive(&self) -> bool {
+        *self.alive.borrow()
+    }
+
+    /// Wait until the device goes offline or comes back, returning the new state
+    pub async fn changed(&mut self) -> Option<bool> {
+        self.alive.changed().await.ok()?;
+        Some(*self.alive.borrow_and_update())
+    }
+
+    /// Stop the heartbeat
+    pub fn stop(self) {
+        self.task.abort();
+    }
+}
+
+impl Drop for HeartbeatHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
 /// Manages ADB connections to Android devices
 pub struct AdbConnection {
     adb_path: String,
+    default_port: Option<u16>,
 }
 
 impl AdbConnection {
     /// Create a new ADB connection manager
+    ///
+    /// The default connect port is read from `PHONE_AGENT_ADB_PORT`, if set.
     pub fn new() -> Self {
-        Self {
-            adb_path: "adb".to_string(),
-        }
+        Self::with_path(get_adb_path())
     }
 
     /// Create a new ADB connection manager with custom ADB path
     pub fn with_path(adb_path: String) -> Self {
-        Self { adb_path }
+        Self {
+            adb_path,
+            default_port: env::var("PHONE_AGENT_ADB_PORT")
+                .ok()
+                .and_then(|port| port.trim().parse().ok()),
+        }
+    }
+
+    /// Set the port used by [`connect`](Self::connect) for addresses without one
+    ///
+    /// Without a default port, connecting to a bare host is an error, since
+    /// wireless debugging on Android 11+ uses a random port.
+    pub fn with_default_port(mut self, port: u16) -> Self {
+        self.default_port = Some(port);
+        self
+    }
+
+    /// Build an ADB command with this connection's binary and the global ADB environment
+    fn command(&self) -> Command {
+        let mut cmd = Command::new(&self.adb_path);
+        cmd.envs(get_adb_envs());
+        cmd
+    }
+
+    /// Address [`connect`](Self::connect) dials for `address`
+    ///
+    /// This is the device id the device is listed under once connected, e.g.
+    /// `192.168.1.5:5555` for `192.168.1.5` with a default port of 5555.
+    pub fn device_address(&self, address: &str) -> Result<String> {
+        normalize_address(address, self.default_port)
     }
 
-    /// Connect to a remote device via TCP/IP
+    /// Connect to a remote device via TCP/IP or an mDNS service name
     pub async fn connect(&self, address: &str, timeout: u64) -> Result<String> {
-        // Validate and normalize address format
-        let address = if address.contains(':') {
-            address.to_string()
-        } else {
-            format!("{}:5555", address)
-        };
+        let address = normalize_address(address, self.default_port)?;
 
         let output = tokio::time::timeout(
             Duration::from_secs(timeout),
-            Command::new(&self.adb_path)
-                .arg("connect")
-                .arg(&address)
-                .output(),
+            self.command().arg("connect").arg(&address).output(),
         )
         .await
         .map_err(|_| AdbError::Timeout(format!("Connection timeout after {}s", timeout)))?
@@ -77,7 +260,7 @@ impl AdbConnection {
 
     /// Disconnect from a remote device
     pub async fn disconnect(&self, address: Option<&str>) -> Result<String> {
-        let mut cmd = Command::new(&self.adb_path);
+        let mut cmd = self.command();
         cmd.arg("disconnect");
 
         if let Some(addr) = address {
@@ -105,10 +288,7 @@ impl AdbConnection {
     pub async fn list_devices(&self) -> Result<Vec<DeviceInfo>> {
         let output = tokio::time::timeout(
             Duration::from_secs(5),
-            Command::new(&self.adb_path)
-                .arg("devices")
-                .arg("-l")
-                .output(),
+            self.command().arg("devices").arg("-l").output(),
         )
         .await
         .map_err(|_| AdbError::Timeout("List devices timeout after 5s".to_string()))?
@@ -129,14 +309,7 @@ impl AdbConnection {
                 let device_id = parts[0].to_string();
                 let status = parts[1].to_string();
 
-                // Determine connection type
-                let connection_type = if device_id.contains(':') {
-                    ConnectionType::Remote
-                } else if device_id.contains("emulator") {
-                    ConnectionType::Usb
-                } else {
-                    ConnectionType::Usb
-                };
+                let connection_type = ConnectionType::from_device_id(&device_id);
 
                 // Parse additional info
                 let mut model = None;
@@ -160,6 +333,28 @@ impl AdbConnection {
         Ok(devices)
     }
 
+    /// List wireless debugging services discovered via mDNS
+    ///
+    /// Returns full service names (e.g. `adb-XXXX._adb-tls-connect._tcp`)
+    /// that can be passed directly to [`AdbConnection::connect`].
+    pub async fn list_mdns(&self) -> Result<Vec<String>> {
+        let output = tokio::time::timeout(
+            Duration::from_secs(5),
+            self.command().arg("mdns").arg("services").output(),
+        )
+        .await
+        .map_err(|_| AdbError::Timeout("List mDNS services timeout after 5s".to_string()))?
+        .map_err(AdbError::Io)?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(AdbError::CommandFailed(stderr.trim().to_string()));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(parse_mdns_services(&stdout))
+    }
+
     /// Get detailed information about a device
     pub async fn get_device_info(&self, device_id: Option<&str>) -> Result<Option<DeviceInfo>> {
         let devices = self.list_devices().await?;
@@ -186,15 +381,135 @@ impl AdbConnection {
         if let Some(id) = device_id {
             Ok(devices
                 .iter()
-                .any(|d| d.device_id == id && d.status == "device"))
+                .any(|d| d.device_id == id && d.device_status().is_ready()))
+        } else {
+            Ok(devices.iter().any(|d| d.device_status().is_ready()))
+        }
+    }
+
+    /// Fail with a specific error unless the device can run commands
+    ///
+    /// A device in recovery, sideload or the bootloader is still listed, but
+    /// shell commands sent to it fail with confusing errors. This reports e.g.
+    /// "device in recovery mode" instead.
+    pub async fn check_device_ready(&self, device_id: Option<&str>) -> Result<()> {
+        let device = self.get_device_info(device_id).await?.ok_or_else(|| {
+            AdbError::DeviceNotFound(device_id.unwrap_or("no device connected").to_string())
+        })?;
+
+        let status = device.device_status();
+        if status.is_ready() {
+            Ok(())
         } else {
-            Ok(devices.iter().any(|d| d.status == "device"))
+            Err(AdbError::DeviceUnavailable(format!(
+                "{} ({})",
+                status.describe(),
+                device.device_id
+            )))
+        }
+    }
+
+    /// Wait until a device is ready, i.e. listed in the `device` state
+    ///
+    /// Right after `connect` a device can still be `offline` or
+    /// `authorizing`, and commands sent to it fail. Polls
+    /// [`is_connected`](Self::is_connected) every `poll_interval` and fails
+    /// with a timeout if the device isn't ready within `timeout`.
+    pub async fn wait_for_device(
+        &self,
+        device_id: Option<&str>,
+        timeout: Duration,
+        poll_interval: Duration,
+    ) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.is_connected(device_id).await? {
+                return Ok(());
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(AdbError::Timeout(format!(
+                    "{} not ready after {}s",
+                    device_id.unwrap_or("Device"),
+                    timeout.as_secs()
+                )));
+            }
+            tokio::time::sleep(poll_interval.min(deadline - now)).await;
+        }
+    }
+
+    /// Get the device state reported by `adb get-state` (e.g. `device`, `offline`)
+    pub async fn get_state(&self, device_id: Option<&str>) -> Result<String> {
+        let mut cmd = self.command();
+        if let Some(id) = device_id {
+            cmd.arg("-s").arg(id);
+        }
+        cmd.arg("get-state");
+
+        let output = tokio::time::timeout(Duration::from_secs(5), cmd.output())
+            .await
+            .map_err(|_| AdbError::Timeout("get-state timeout after 5s".to_string()))?
+            .map_err(AdbError::Io)?;
+
+        if !output.status.success() {
+            return Err(AdbError::DeviceNotFound(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// Periodically check the device with `adb get-state` in a background task
+    ///
+    /// Detects dropped connections before the next real command fails. When a
+    /// Wi-Fi or remote device goes offline, the heartbeat tries to reconnect it
+    /// with `adb connect`. Must be called from within a Tokio runtime.
+    pub fn start_heartbeat(&self, device_id: Option<&str>, interval: Duration) -> HeartbeatHandle {
+        let (tx, alive) = watch::channel(true);
+        let conn = Self::with_path(self.adb_path.clone());
+        let device_id = device_id.map(|id| id.to_string());
+
+        let task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let alive = conn.heartbeat(device_id.as_deref()).await;
+                tx.send_if_modified(|state| std::mem::replace(state, alive) != alive);
+            }
+        });
+
+        HeartbeatHandle { alive, task }
+    }
+
+    /// Run one heartbeat check, reconnecting network devices that went offline
+    async fn heartbeat(&self, device_id: Option<&str>) -> bool {
+        let error = match self.get_state(device_id).await {
+            Ok(state) if state == "device" => return true,
+            Ok(state) => state,
+            Err(e) => e.to_string(),
+        };
+        warn!(device_id = ?device_id, %error, "Heartbeat failed, device is not online");
+
+        let Some(address) = device_id.filter(|id| is_network_device(id)) else {
+            return false;
+        };
+        match self.connect(address, 10).await {
+            Ok(msg) => {
+                info!(device_id = address, "Heartbeat reconnect: {}", msg);
+                matches!(self.get_state(device_id).await, Ok(state) if state == "device")
+            }
+            Err(e) => {
+                warn!(device_id = address, error = %e, "Heartbeat reconnect failed");
+                false
+            }
         }
     }
 
     /// Enable TCP/IP debugging on a USB-connected device
     pub async fn enable_tcpip(&self, port: u16, device_id: Option<&str>) -> Result<String> {
-        let mut cmd = Command::new(&self.adb_path);
+        let mut cmd = self.command();
 
         if let Some(id) = device_id {
             cmd.arg("-s").arg(id);
@@ -224,7 +539,7 @@ impl AdbConnection {
 
     /// Get the IP address of a connected device
     pub async fn get_device_ip(&self, device_id: Option<&str>) -> Result<Option<String>> {
-        let mut cmd = Command::new(&self.adb_path);
+        let mut cmd = self.command();
 
         if let Some(id) = device_id {
             cmd.arg("-s").arg(id);
@@ -252,7 +567,7 @@ impl AdbConnection {
         }
 
         // Alternative: try wlan0 interface
-        let mut cmd = Command::new(&self.adb_path);
+        let mut cmd = self.command();
 
         if let Some(id) = device_id {
             cmd.arg("-s").arg(id);
@@ -282,14 +597,105 @@ impl AdbConnection {
         Ok(None)
     }
 
+    /// Forward connections to `local` on the host to `remote` on the device
+    ///
+    /// Sockets are ADB socket specs such as `tcp:8080` or `localabstract:name`;
+    /// a bare port number means `tcp:<port>`.
+    pub async fn forward(&self, local: &str, remote: &str, device_id: Option<&str>) -> Result<()> {
+        self.run_forward(
+            device_id,
+            &["forward", &socket_spec(local), &socket_spec(remote)],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Forward connections to `remote` on the device to `local` on the host
+    ///
+    /// Lets an app under test reach a server running on the host, e.g. a
+    /// mock backend. Sockets are given as for [`AdbConnection::forward`].
+    pub async fn reverse(&self, remote: &str, local: &str, device_id: Option<&str>) -> Result<()> {
+        self.run_forward(
+            device_id,
+            &["reverse", &socket_spec(remote), &socket_spec(local)],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// List forwards from the host to devices, optionally only those of one device
+    pub async fn forward_list(&self, device_id: Option<&str>) -> Result<Vec<PortForward>> {
+        let output = self.run_forward(None, &["forward", "--list"]).await?;
+        Ok(parse_forward_list(&output)
+            .into_iter()
+            .filter(|forward| device_id.is_none() || device_id == Some(forward.device_id.as_str()))
+            .collect())
+    }
+
+    /// List forwards from a device to the host
+    pub async fn reverse_list(&self, device_id: Option<&str>) -> Result<Vec<PortForward>> {
+        let output = self.run_forward(device_id, &["reverse", "--list"]).await?;
+        Ok(parse_forward_list(&output))
+    }
+
+    /// Remove the forward listening on `local` on the host
+    pub async fn remove_forward(&self, local: &str, device_id: Option<&str>) -> Result<()> {
+        self.run_forward(device_id, &["forward", "--remove", &socket_spec(local)])
+            .await?;
+        Ok(())
+    }
+
+    /// Remove the reverse forward listening on `remote` on the device
+    pub async fn remove_reverse(&self, remote: &str, device_id: Option<&str>) -> Result<()> {
+        self.run_forward(device_id, &["reverse", "--remove", &socket_spec(remote)])
+            .await?;
+        Ok(())
+    }
+
+    /// Remove all forwards and reverse forwards of a device
+    ///
+    /// Call this when a test run starts or ends so forwards from earlier runs
+    /// don't leak into it.
+    pub async fn remove_all_forwards(&self, device_id: Option<&str>) -> Result<()> {
+        for forward in self.forward_list(device_id).await? {
+            self.remove_forward(&forward.local, Some(&forward.device_id))
+                .await?;
+        }
+        self.run_forward(device_id, &["reverse", "--remove-all"])
+            .await?;
+        Ok(())
+    }
+
+    /// Run an `adb forward` / `adb reverse` command and return its stdout
+    async fn run_forward(&self, device_id: Option<&str>, args: &[&str]) -> Result<String> {
+        let mut cmd = self.command();
+        if let Some(id) = device_id {
+            cmd.arg("-s").arg(id);
+        }
+        cmd.args(args);
+
+        let output = tokio::time::timeout(Duration::from_secs(5), cmd.output())
+            .await
+            .map_err(|_| AdbError::Timeout(format!("adb {} timeout after 5s", args.join(" "))))?
+            .map_err(AdbError::Io)?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(AdbError::CommandFailed(format!(
+                "adb {} failed: {}",
+                args.join(" "),
+                stderr.trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
     /// Restart the ADB server
     pub async fn restart_server(&self) -> Result<String> {
         // Kill server
         tokio::time::timeout(
             Duration::from_secs(5),
-            Command::new(&self.adb_path)
-                .arg("kill-server")
-                .output(),
+            self.command().arg("kill-server").output(),
         )
         .await
         .map_err(|_| AdbError::Timeout("Kill server timeout after 5s".to_string()))?
@@ -303,9 +709,7 @@ impl AdbConnection {
         // Start server
         tokio::time::timeout(
             Duration::from_secs(5),
-            Command::new(&self.adb_path)
-                .arg("start-server")
-                .output(),
+            self.command().arg("start-server").output(),
         )
         .await
         .map_err(|_| AdbError::Timeout("Start server timeout after 5s".to_string()))?
@@ -321,6 +725,81 @@ impl Default for AdbConnection {
     }
 }
 
+/// Check whether an address is an mDNS service name rather than a host
+///
+/// Wireless debugging advertises names like `adb-SERIAL._adb-tls-connect._tcp`,
+/// which must not have a port appended.
+pub fn is_mdns_address(address: &str) -> bool {
+    let address = address.trim_end_matches('.');
+    address.contains("._adb") || address.ends_with("._tcp") || address.ends_with(".local")
+}
+
+/// Add the default port to an address without one (mDNS names are passed through)
+fn normalize_address(address: &str, default_port: Option<u16>) -> Result<String> {
+    if address.contains(':') || is_mdns_address(address) {
+        return Ok(address.to_string());
+    }
+    match default_port {
+        Some(port) => Ok(format!("{}:{}", address, port)),
+        None => Err(AdbError::ParseError(format!(
+            "No port in address '{}'; use <host>:<port> (shown in the wireless debugging settings) or set PHONE_AGENT_ADB_PORT",
+            address
+        ))),
+    }
+}
+
+/// Turn a bare port number into a `tcp:` socket spec; other specs pass through
+fn socket_spec(socket: &str) -> String {
+    let socket = socket.trim();
+    if !socket.is_empty() && socket.chars().all(|c| c.is_ascii_digit()) {
+        format!("tcp:{}", socket)
+    } else {
+        socket.to_string()
+    }
+}
+
+/// Parse `adb forward --list` / `adb reverse --list` output
+fn parse_forward_list(output: &str) -> Vec<PortForward> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            Some(PortForward {
+                device_id: parts.next()?.to_string(),
+                local: parts.next()?.to_string(),
+                remote: parts.next()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Whether a device is connected over the network and can be reconnected with `adb connect`
+fn is_network_device(device_id: &str) -> bool {
+    matches!(
+        ConnectionType::from_device_id(device_id),
+        ConnectionType::Wifi | ConnectionType::Remote
+    )
+}
+
+/// mDNS service types a device can be connected through; pairing services can't
+const CONNECTABLE_MDNS_SERVICES: &[&str] = &["_adb-tls-connect._tcp", "_adb._tcp"];
+
+/// Parse the output of `adb mdns services` into connectable service names
+fn parse_mdns_services(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter(|line| !line.starts_with("List of"))
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let service = parts.get(1)?.trim_end_matches('.');
+            if !CONNECTABLE_MDNS_SERVICES.contains(&service) {
+                return None;
+            }
+            Some(format!("{}.{}", parts[0], service))
+        })
+        .collect()
+}
+
 /// Quick helper to connect to a remote device
 pub async fn quick_connect(address: &str) -> Result<String> {
     let conn = AdbConnection::new();
@@ -332,3 +811,141 @@ pub async fn list_devices() -> Result<Vec<DeviceInfo>> {
     let conn = AdbConnection::new();
     conn.list_devices().await
 }
+
+/// Quick helper to check that a device can run commands
+pub async fn check_device_ready(device_id: Option<&str>) -> Result<()> {
+    let conn = AdbConnection::new();
+    conn.check_device_ready(device_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_mdns_address() {
+        assert!(is_mdns_address("adb-R58M123._adb-tls-connect._tcp"));
+        assert!(is_mdns_address("adb-R58M123._adb-tls-connect._tcp."));
+        assert!(is_mdns_address("pixel.local"));
+        assert!(!is_mdns_address("192.168.1.100"));
+        assert!(!is_mdns_address("emulator-5554"));
+    }
+
+    #[test]
+    fn test_connection_type_from_device_id() {
+        let cases = [
+            ("emulator-5554", ConnectionType::Emulator),
+            ("localhost:5555", ConnectionType::Wifi),
+            ("127.0.0.1:5555", ConnectionType::Wifi),
+            ("adb-R58M123._adb-tls-connect._tcp", ConnectionType::Wifi),
+            ("192.168.1.100:5555", ConnectionType::Remote),
+            ("R58M123ABC", ConnectionType::Usb),
+        ];
+        for (device_id, expected) in cases {
+            assert_eq!(
+                ConnectionType::from_device_id(device_id),
+                expected,
+                "{}",
+                device_id
+            );
+        }
+    }
+
+    #[test]
+    fn test_device_status() {
+        assert_eq!(DeviceStatus::from("device"), DeviceStatus::Device);
+        assert!(DeviceStatus::from("device").is_ready());
+        assert_eq!(DeviceStatus::from("recovery"), DeviceStatus::Recovery);
+        assert_eq!(
+            DeviceStatus::from("recovery").describe(),
+            "device in recovery mode"
+        );
+        assert!(!DeviceStatus::from("sideload").is_ready());
+        assert_eq!(
+            DeviceStatus::from("host"),
+            DeviceStatus::Other("host".to_string())
+        );
+        assert_eq!(DeviceStatus::Bootloader.to_string(), "bootloader");
+    }
+
+    #[test]
+    fn test_normalize_address() {
+        assert_eq!(
+            normalize_address("192.168.1.100:37123", None).unwrap(),
+            "192.168.1.100:37123"
+        );
+        assert_eq!(
+            normalize_address("192.168.1.100", Some(5555)).unwrap(),
+            "192.168.1.100:5555"
+        );
+        assert_eq!(
+            normalize_address("adb-R58M123._adb-tls-connect._tcp", None).unwrap(),
+            "adb-R58M123._adb-tls-connect._tcp"
+        );
+        assert!(matches!(
+            normalize_address("192.168.1.100", None),
+            Err(AdbError::ParseError(_))
+        ));
+    }
+
+    #[test]
+    fn test_is_network_device() {
+        assert!(is_network_device("192.168.1.100:5555"));
+        assert!(is_network_device("adb-R58M123._adb-tls-connect._tcp"));
+        assert!(!is_network_device("emulator-5554"));
+        assert!(!is_network_device("R58M123ABC"));
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_reports_offline() {
+        let conn = AdbConnection::with_path("/nonexistent/adb".to_string());
+        let mut heartbeat = conn.start_heartbeat(Some("R58M123ABC"), Duration::from_millis(10));
+        assert!(heartbeat.is_alive());
+
+        assert_eq!(heartbeat.changed().await, Some(false));
+        assert!(!heartbeat.is_alive());
+        heartbeat.stop();
+    }
+
+    #[test]
+    fn test_socket_spec() {
+        assert_eq!(socket_spec("8080"), "tcp:8080");
+        assert_eq!(socket_spec("tcp:9000"), "tcp:9000");
+        assert_eq!(socket_spec("localabstract:chrome"), "localabstract:chrome");
+    }
+
+    #[test]
+    fn test_parse_forward_list() {
+        let output = "emulator-5554 tcp:8080 tcp:8080
+            192.168.1.5:5555 tcp:9000 localabstract:chrome_devtools_remote
+";
+        let forwards = parse_forward_list(output);
+        assert_eq!(forwards.len(), 2);
+        assert_eq!(
+            forwards[1],
+            PortForward {
+                device_id: "192.168.1.5:5555".to_string(),
+                local: "tcp:9000".to_string(),
+                remote: "localabstract:chrome_devtools_remote".to_string(),
+            }
+        );
+        assert!(parse_forward_list("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_mdns_services() {
+        let output = "List of discovered mdns services\n\
+            adb-R58M123-abc\t_adb-tls-connect._tcp.\t192.168.1.5:37123\n\
+            adb-R58M123-abc\t_adb-tls-pairing._tcp\t192.168.1.5:41234\n\
+            emulator-xyz\t_adb._tcp.\t192.168.1.6:5555\n";
+        let services = parse_mdns_services(output);
+        assert_eq!(
+            services,
+            vec![
+                "adb-R58M123-abc._adb-tls-connect._tcp".to_string(),
+                "emulator-xyz._adb._tcp".to_string(),
+            ]
+        );
+        assert!(!services.iter().any(|s| s.contains("pairing")));
+    }
+}
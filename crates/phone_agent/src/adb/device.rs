@@ -1,31 +1,20 @@
 //! Device control utilities for Android automation
 
-use crate::config::{get_package_name, APP_PACKAGES, TIMING_CONFIG};
+use super::command::{adb_command, display_args, run_adb};
+use crate::config::{app_entries, get_package_name, TIMING_CONFIG};
 use crate::error::{AdbError, Result};
-use std::time::Duration;
-use tokio::process::Command;
+use std::time::{Duration, Instant};
+use tracing::warn;
 
-/// Build ADB command prefix with optional device specifier
-fn get_adb_prefix(device_id: Option<&str>) -> Vec<String> {
-    let mut prefix = vec!["adb".to_string()];
-    if let Some(id) = device_id {
-        prefix.push("-s".to_string());
-        prefix.push(id.to_string());
-    }
-    prefix
-}
+/// Interval between foreground checks while waiting for a launched app
+const LAUNCH_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
 /// Get the currently focused app name
 pub async fn get_current_app(device_id: Option<&str>) -> Result<String> {
-    let prefix = get_adb_prefix(device_id);
-
-    let mut cmd = Command::new(&prefix[0]);
-    for arg in &prefix[1..] {
-        cmd.arg(arg);
-    }
+    let mut cmd = adb_command(device_id);
     cmd.arg("shell").arg("dumpsys").arg("window");
 
-    let output = cmd.output().await.map_err(AdbError::Io)?;
+    let output = run_adb(&mut cmd).await?;
 
     let stdout = String::from_utf8_lossy(&output.stdout);
 
@@ -38,9 +27,9 @@ pub async fn get_current_app(device_id: Option<&str>) -> Result<String> {
     // Parse window focus info
     for line in stdout.lines() {
         if line.contains("mCurrentFocus") || line.contains("mFocusedApp") {
-            for (app_name, package) in APP_PACKAGES.entries() {
-                if line.contains(package) {
-                    return Ok(app_name.to_string());
+            for (app_name, package) in app_entries() {
+                if line.contains(&package) {
+                    return Ok(app_name);
                 }
             }
         }
@@ -50,21 +39,26 @@ pub async fn get_current_app(device_id: Option<&str>) -> Result<String> {
 }
 
 /// Tap at the specified coordinates
-pub async fn tap(x: i32, y: i32, device_id: Option<&str>, delay: Option<f64>) -> Result<()> {
+///
+/// `display_id` selects the display to tap on; `None` targets the default display.
+pub async fn tap(
+    x: i32,
+    y: i32,
+    device_id: Option<&str>,
+    display_id: Option<u64>,
+    delay: Option<f64>,
+) -> Result<()> {
     let delay = delay.unwrap_or(TIMING_CONFIG.device.default_tap_delay);
-    let prefix = get_adb_prefix(device_id);
 
-    let mut cmd = Command::new(&prefix[0]);
-    for arg in &prefix[1..] {
-        cmd.arg(arg);
-    }
+    let mut cmd = adb_command(device_id);
     cmd.arg("shell")
         .arg("input")
+        .args(display_args(display_id))
         .arg("tap")
         .arg(x.to_string())
         .arg(y.to_string());
 
-    cmd.output().await.map_err(AdbError::Io)?;
+    run_adb(&mut cmd).await?;
 
     tokio::time::sleep(Duration::from_secs_f64(delay)).await;
     Ok(())
@@ -75,22 +69,20 @@ pub async fn double_tap(
     x: i32,
     y: i32,
     device_id: Option<&str>,
+    display_id: Option<u64>,
     delay: Option<f64>,
 ) -> Result<()> {
     let delay = delay.unwrap_or(TIMING_CONFIG.device.default_double_tap_delay);
-    let prefix = get_adb_prefix(device_id);
 
     // First tap
-    let mut cmd = Command::new(&prefix[0]);
-    for arg in &prefix[1..] {
-        cmd.arg(arg);
-    }
+    let mut cmd = adb_command(device_id);
     cmd.arg("shell")
         .arg("input")
+        .args(display_args(display_id))
         .arg("tap")
         .arg(x.to_string())
         .arg(y.to_string());
-    cmd.output().await.map_err(AdbError::Io)?;
+    run_adb(&mut cmd).await?;
 
     tokio::time::sleep(Duration::from_secs_f64(
         TIMING_CONFIG.device.double_tap_interval,
@@ -98,16 +90,14 @@ pub async fn double_tap(
     .await;
 
     // Second tap
-    let mut cmd = Command::new(&prefix[0]);
-    for arg in &prefix[1..] {
-        cmd.arg(arg);
-    }
+    let mut cmd = adb_command(device_id);
     cmd.arg("shell")
         .arg("input")
+        .args(display_args(display_id))
         .arg("tap")
         .arg(x.to_string())
         .arg(y.to_string());
-    cmd.output().await.map_err(AdbError::Io)?;
+    run_adb(&mut cmd).await?;
 
     tokio::time::sleep(Duration::from_secs_f64(delay)).await;
     Ok(())
@@ -119,17 +109,15 @@ pub async fn long_press(
     y: i32,
     duration_ms: u32,
     device_id: Option<&str>,
+    display_id: Option<u64>,
     delay: Option<f64>,
 ) -> Result<()> {
     let delay = delay.unwrap_or(TIMING_CONFIG.device.default_long_press_delay);
-    let prefix = get_adb_prefix(device_id);
 
-    let mut cmd = Command::new(&prefix[0]);
-    for arg in &prefix[1..] {
-        cmd.arg(arg);
-    }
+    let mut cmd = adb_command(device_id);
     cmd.arg("shell")
         .arg("input")
+        .args(display_args(display_id))
         .arg("swipe")
         .arg(x.to_string())
         .arg(y.to_string())
@@ -137,13 +125,14 @@ pub async fn long_press(
         .arg(y.to_string())
         .arg(duration_ms.to_string());
 
-    cmd.output().await.map_err(AdbError::Io)?;
+    run_adb(&mut cmd).await?;
 
     tokio::time::sleep(Duration::from_secs_f64(delay)).await;
     Ok(())
 }
 
 /// Swipe from start to end coordinates
+#[allow(clippy::too_many_arguments)]
 pub async fn swipe(
     start_x: i32,
     start_y: i32,
@@ -151,10 +140,10 @@ pub async fn swipe(
     end_y: i32,
     duration_ms: Option<u32>,
     device_id: Option<&str>,
+    display_id: Option<u64>,
     delay: Option<f64>,
 ) -> Result<()> {
     let delay = delay.unwrap_or(TIMING_CONFIG.device.default_swipe_delay);
-    let prefix = get_adb_prefix(device_id);
 
     // Calculate duration based on distance if not provided
     let duration_ms = duration_ms.unwrap_or_else(|| {
@@ -163,12 +152,10 @@ pub async fn swipe(
         duration.clamp(1000, 2000)
     });
 
-    let mut cmd = Command::new(&prefix[0]);
-    for arg in &prefix[1..] {
-        cmd.arg(arg);
-    }
+    let mut cmd = adb_command(device_id);
     cmd.arg("shell")
         .arg("input")
+        .args(display_args(display_id))
         .arg("swipe")
         .arg(start_x.to_string())
         .arg(start_y.to_string())
@@ -176,7 +163,7 @@ pub async fn swipe(
         .arg(end_y.to_string())
         .arg(duration_ms.to_string());
 
-    cmd.output().await.map_err(AdbError::Io)?;
+    run_adb(&mut cmd).await?;
 
     tokio::time::sleep(Duration::from_secs_f64(delay)).await;
     Ok(())
@@ -185,15 +172,11 @@ pub async fn swipe(
 /// Press the back button
 pub async fn back(device_id: Option<&str>, delay: Option<f64>) -> Result<()> {
     let delay = delay.unwrap_or(TIMING_CONFIG.device.default_back_delay);
-    let prefix = get_adb_prefix(device_id);
 
-    let mut cmd = Command::new(&prefix[0]);
-    for arg in &prefix[1..] {
-        cmd.arg(arg);
-    }
+    let mut cmd = adb_command(device_id);
     cmd.arg("shell").arg("input").arg("keyevent").arg("4");
 
-    cmd.output().await.map_err(AdbError::Io)?;
+    run_adb(&mut cmd).await?;
 
     tokio::time::sleep(Duration::from_secs_f64(delay)).await;
     Ok(())
@@ -202,24 +185,191 @@ pub async fn back(device_id: Option<&str>, delay: Option<f64>) -> Result<()> {
 /// Press the home button
 pub async fn home(device_id: Option<&str>, delay: Option<f64>) -> Result<()> {
     let delay = delay.unwrap_or(TIMING_CONFIG.device.default_home_delay);
-    let prefix = get_adb_prefix(device_id);
 
-    let mut cmd = Command::new(&prefix[0]);
-    for arg in &prefix[1..] {
-        cmd.arg(arg);
-    }
+    let mut cmd = adb_command(device_id);
     cmd.arg("shell")
         .arg("input")
         .arg("keyevent")
         .arg("KEYCODE_HOME");
 
-    cmd.output().await.map_err(AdbError::Io)?;
+    run_adb(&mut cmd).await?;
 
     tokio::time::sleep(Duration::from_secs_f64(delay)).await;
     Ok(())
 }
 
+/// Get the battery level in percent
+pub async fn get_battery_level(device_id: Option<&str>) -> Result<u8> {
+    let mut cmd = adb_command(device_id);
+    cmd.arg("shell").arg("dumpsys").arg("battery");
+
+    let output = run_adb(&mut cmd).await?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    parse_battery_level(&stdout)
+        .ok_or_else(|| AdbError::ParseError("No battery level in dumpsys battery".to_string()))
+}
+
+/// Check whether the screen is on
+pub async fn is_screen_on(device_id: Option<&str>) -> Result<bool> {
+    let mut cmd = adb_command(device_id);
+    cmd.arg("shell").arg("dumpsys").arg("power");
+
+    let output = run_adb(&mut cmd).await?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    parse_screen_on(&stdout)
+        .ok_or_else(|| AdbError::ParseError("No screen state in dumpsys power".to_string()))
+}
+
+/// Turn the screen on (no-op if it is already on)
+pub async fn wake_screen(device_id: Option<&str>) -> Result<()> {
+    let mut cmd = adb_command(device_id);
+    cmd.arg("shell")
+        .arg("input")
+        .arg("keyevent")
+        .arg("KEYCODE_WAKEUP");
+
+    run_adb(&mut cmd).await?;
+    Ok(())
+}
+
+/// Check whether the keyguard (lock screen) is showing
+pub async fn is_locked(device_id: Option<&str>) -> Result<bool> {
+    let mut cmd = adb_command(device_id);
+    cmd.arg("shell").arg("dumpsys").arg("window").arg("policy");
+
+    let output = run_adb(&mut cmd).await?;
+    Ok(parse_locked(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Get the screen size in pixels, in the device's natural orientation
+///
+/// A `wm size` override takes precedence over the physical size, since
+/// screenshots and input both use it. `display_id` selects the display to
+/// measure; `None` measures the default display.
+pub async fn get_screen_size(
+    device_id: Option<&str>,
+    display_id: Option<u64>,
+) -> Result<(u32, u32)> {
+    let mut cmd = adb_command(device_id);
+    cmd.arg("shell")
+        .arg("wm")
+        .arg("size")
+        .args(display_args(display_id));
+
+    let output = run_adb(&mut cmd).await?;
+    parse_wm_size(&String::from_utf8_lossy(&output.stdout))
+        .ok_or_else(|| AdbError::ParseError("Failed to read screen size".to_string()))
+}
+
+/// Wake the device and dismiss the lock screen
+///
+/// Turns the screen on, swipes up to dismiss a swipe lock and, if a PIN is
+/// given, enters it. Does nothing if the device is already awake and unlocked.
+pub async fn wake_device(device_id: Option<&str>, pin: Option<&str>) -> Result<()> {
+    if pin.is_some_and(|pin| pin.is_empty() || !pin.chars().all(|c| c.is_ascii_digit())) {
+        return Err(AdbError::CommandFailed(
+            "PIN must contain only digits".to_string(),
+        ));
+    }
+
+    if is_screen_on(device_id).await? && !is_locked(device_id).await? {
+        return Ok(());
+    }
+
+    wake_screen(device_id).await?;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    if !is_locked(device_id).await? {
+        return Ok(());
+    }
+
+    let (width, height) = get_screen_size(device_id, None).await?;
+    let x = (width / 2) as i32;
+    swipe(
+        x,
+        (height * 8 / 10) as i32,
+        x,
+        (height * 2 / 10) as i32,
+        Some(300),
+        device_id,
+        None,
+        Some(0.5),
+    )
+    .await?;
+
+    if let Some(pin) = pin {
+        let mut cmd = adb_command(device_id);
+        cmd.arg("shell").arg("input").arg("text").arg(pin);
+        run_adb(&mut cmd).await?;
+
+        let mut cmd = adb_command(device_id);
+        cmd.arg("shell")
+            .arg("input")
+            .arg("keyevent")
+            .arg("KEYCODE_ENTER");
+        run_adb(&mut cmd).await?;
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+
+    Ok(())
+}
+
+/// Parse the keyguard state from `dumpsys window policy` output
+fn parse_locked(output: &str) -> bool {
+    output.lines().map(|line| line.trim()).any(|line| {
+        line.contains("mShowingLockscreen=true")
+            || line.contains("mDreamingLockscreen=true")
+            || line.contains("isKeyguardShowing=true")
+            || line == "showing=true"
+    })
+}
+
+/// Parse the screen size from `wm size` output, preferring an override size
+fn parse_wm_size(output: &str) -> Option<(u32, u32)> {
+    let size = |prefix: &str| {
+        let line = output
+            .lines()
+            .find_map(|line| line.trim().strip_prefix(prefix))?;
+        let (width, height) = line.trim().split_once('x')?;
+        Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+    };
+    size("Override size:").or_else(|| size("Physical size:"))
+}
+
+/// Parse `level: N` from `dumpsys battery` output
+fn parse_battery_level(output: &str) -> Option<u8> {
+    output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("level:"))
+        .and_then(|level| level.trim().parse().ok())
+}
+
+/// Parse the screen state from `dumpsys power` output
+fn parse_screen_on(output: &str) -> Option<bool> {
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some(state) = line.strip_prefix("mWakefulness=") {
+            return Some(state == "Awake");
+        }
+        if let Some(state) = line.strip_prefix("Display Power: state=") {
+            return Some(state == "ON");
+        }
+        if let Some(state) = line.strip_prefix("mScreenOn=") {
+            return Some(state == "true");
+        }
+    }
+    None
+}
+
 /// Launch an app by name
+///
+/// Relaunches up to `launch_retries` times while the app is not in the
+/// foreground. Splash screens and login activities may run under another
+/// package, so an app that never shows up is logged rather than treated as a
+/// failed launch.
 pub async fn launch_app(
     app_name: &str,
     device_id: Option<&str>,
@@ -232,22 +382,265 @@ pub async fn launch_app(
         None => return Ok(false),
     };
 
-    let prefix = get_adb_prefix(device_id);
+    let retries = TIMING_CONFIG.device.launch_retries;
+    for attempt in 0..=retries {
+        let mut cmd = adb_command(device_id);
+        cmd.arg("shell")
+            .arg("monkey")
+            .arg("-p")
+            .arg(&package)
+            .arg("-c")
+            .arg("android.intent.category.LAUNCHER")
+            .arg("1");
+
+        run_adb(&mut cmd).await?;
+
+        if wait_for_foreground(&package, device_id).await {
+            break;
+        }
+        if attempt < retries {
+            warn!(package, "App did not reach the foreground, launching again");
+        } else {
+            warn!(package, "App is still not in the foreground, continuing");
+        }
+    }
 
-    let mut cmd = Command::new(&prefix[0]);
-    for arg in &prefix[1..] {
-        cmd.arg(arg);
+    tokio::time::sleep(Duration::from_secs_f64(delay)).await;
+    Ok(true)
+}
+
+/// Poll until `package` is in the foreground or `launch_ready_timeout` passes
+async fn wait_for_foreground(package: &str, device_id: Option<&str>) -> bool {
+    let timeout = TIMING_CONFIG.device.launch_ready_timeout;
+    if timeout <= 0.0 {
+        return true;
     }
-    cmd.arg("shell")
-        .arg("monkey")
-        .arg("-p")
-        .arg(package)
-        .arg("-c")
-        .arg("android.intent.category.LAUNCHER")
-        .arg("1");
 
-    cmd.output().await.map_err(AdbError::Io)?;
+    let deadline = Instant::now() + Duration::from_secs_f64(timeout);
+    loop {
+        if let Ok(Some(foreground)) = get_foreground_package(device_id).await {
+            if foreground == package {
+                return true;
+            }
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(LAUNCH_POLL_INTERVAL).await;
+    }
+}
+
+/// Get the package of the focused window, if any
+pub async fn get_foreground_package(device_id: Option<&str>) -> Result<Option<String>> {
+    let mut cmd = adb_command(device_id);
+    cmd.arg("shell").arg("dumpsys").arg("window");
+
+    let output = run_adb(&mut cmd).await?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(parse_focused_package(&stdout))
+}
+
+/// Parse the focused package from `dumpsys window` output
+///
+/// Reads `mCurrentFocus=Window{.. u0 com.example/.MainActivity}`, falling back
+/// to `mFocusedApp` when no window has focus.
+fn parse_focused_package(output: &str) -> Option<String> {
+    let package_in = |line: &str| {
+        line.split_whitespace()
+            .find_map(|token| token.split_once('/'))
+            .map(|(package, _)| package.to_string())
+    };
+    ["mCurrentFocus", "mFocusedApp"].iter().find_map(|key| {
+        output
+            .lines()
+            .filter(|line| line.contains(key))
+            .find_map(package_in)
+    })
+}
+
+/// Open a URL or deep link with the app registered to handle it
+pub async fn open_url(url: &str, device_id: Option<&str>, delay: Option<f64>) -> Result<()> {
+    let delay = delay.unwrap_or(TIMING_CONFIG.device.default_launch_delay);
+
+    // `adb shell` joins its arguments into a device-side shell command line
+    let mut cmd = adb_command(device_id);
+    cmd.arg("shell")
+        .arg("am")
+        .arg("start")
+        .arg("-a")
+        .arg("android.intent.action.VIEW")
+        .arg("-d")
+        .arg(shell_quote(url));
+
+    let output = run_adb(&mut cmd).await?;
+    let combined = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    if combined.contains("Error") {
+        return Err(AdbError::CommandFailed(format!(
+            "Failed to open {}: {}",
+            url,
+            combined.trim()
+        )));
+    }
 
     tokio::time::sleep(Duration::from_secs_f64(delay)).await;
-    Ok(true)
+    Ok(())
+}
+
+/// Quote a string as a single word for the device shell
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Dump the most recent `lines` lines of the device log (`logcat -d -t`)
+pub async fn capture_logcat(device_id: Option<&str>, lines: usize) -> Result<String> {
+    let mut cmd = adb_command(device_id);
+    cmd.arg("logcat").arg("-d").arg("-t").arg(lines.to_string());
+
+    let output = run_adb(&mut cmd).await?;
+    if !output.status.success() {
+        return Err(AdbError::CommandFailed(format!(
+            "Failed to capture logcat: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Force-stop an app, given by name (see [`get_package_name`]) or package id
+pub async fn force_stop_app(app: &str, device_id: Option<&str>) -> Result<()> {
+    let package = resolve_package(app)?;
+
+    let mut cmd = adb_command(device_id);
+    cmd.arg("shell").arg("am").arg("force-stop").arg(&package);
+
+    let output = run_adb(&mut cmd).await?;
+    if !output.status.success() {
+        return Err(AdbError::CommandFailed(format!(
+            "Failed to force-stop {}: {}",
+            package,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Clear an app's data, given by name (see [`get_package_name`]) or package id
+///
+/// This also stops the app, leaving it as if freshly installed.
+pub async fn clear_app_data(app: &str, device_id: Option<&str>) -> Result<()> {
+    let package = resolve_package(app)?;
+
+    let mut cmd = adb_command(device_id);
+    cmd.arg("shell").arg("pm").arg("clear").arg(&package);
+
+    let output = run_adb(&mut cmd).await?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !stdout.contains("Success") {
+        return Err(AdbError::CommandFailed(format!(
+            "Failed to clear data of {}: {}",
+            package,
+            stdout.trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Resolve a friendly app name to its package, passing package ids through
+fn resolve_package(app: &str) -> Result<String> {
+    if let Some(package) = get_package_name(app) {
+        return Ok(package);
+    }
+
+    let is_package_id = app.contains('.')
+        && app
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_');
+    if is_package_id {
+        Ok(app.to_string())
+    } else {
+        Err(AdbError::CommandFailed(format!("Unknown app: {}", app)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::APP_PACKAGES;
+
+    #[test]
+    fn test_parse_battery_level() {
+        let output =
+            "Current Battery Service state:\n  AC powered: false\n  level: 85\n  scale: 100\n";
+        assert_eq!(parse_battery_level(output), Some(85));
+        assert_eq!(parse_battery_level("no battery"), None);
+    }
+
+    #[test]
+    fn test_parse_focused_package() {
+        let output = "  mCurrentFocus=Window{9b5a1c2 u0 com.android.settings/com.android.settings.Settings}\n  mFocusedApp=ActivityRecord{1d2e u0 com.android.settings/.Settings t42}\n";
+        assert_eq!(
+            parse_focused_package(output).as_deref(),
+            Some("com.android.settings")
+        );
+
+        let output = "  mCurrentFocus=null\n  mFocusedApp=ActivityRecord{1d2e u0 com.tencent.mm/.ui.LauncherUI t7}\n";
+        assert_eq!(
+            parse_focused_package(output).as_deref(),
+            Some("com.tencent.mm")
+        );
+        assert_eq!(parse_focused_package("mCurrentFocus=null"), None);
+    }
+
+    #[test]
+    fn test_parse_screen_on() {
+        assert_eq!(parse_screen_on("  mWakefulness=Awake\n"), Some(true));
+        assert_eq!(parse_screen_on("  mWakefulness=Asleep\n"), Some(false));
+        assert_eq!(parse_screen_on("Display Power: state=OFF\n"), Some(false));
+        assert_eq!(parse_screen_on(""), None);
+    }
+
+    #[test]
+    fn test_parse_locked() {
+        assert!(parse_locked("mShowingLockscreen=true mShowingDream=false"));
+        assert!(parse_locked("KeyguardServiceDelegate\n    showing=true"));
+        assert!(!parse_locked("mShowingLockscreen=false\n    showing=false"));
+    }
+
+    #[test]
+    fn test_parse_wm_size() {
+        assert_eq!(
+            parse_wm_size("Physical size: 1080x2400\nOverride size: 720x1600\n"),
+            Some((720, 1600))
+        );
+        assert_eq!(
+            parse_wm_size("Physical size: 1080x2400\n"),
+            Some((1080, 2400))
+        );
+        assert_eq!(parse_wm_size("Error: no display\n"), None);
+    }
+
+    #[test]
+    fn test_resolve_package() {
+        assert_eq!(
+            resolve_package("com.example.app").unwrap(),
+            "com.example.app"
+        );
+        assert!(resolve_package("Not An App").is_err());
+        for (name, package) in APP_PACKAGES.entries().take(3) {
+            assert_eq!(resolve_package(name).unwrap(), *package);
+        }
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(
+            shell_quote("https://example.com/?a=1&b=2"),
+            "'https://example.com/?a=1&b=2'"
+        );
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
 }
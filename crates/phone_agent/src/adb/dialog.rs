@@ -0,0 +1,169 @@
+//! Detection and dismissal of system ANR / crash dialogs
+//!
+//! Recognized dialogs, matched on the focused window in `dumpsys window`:
+//! - `Application Not Responding: <package>` (ANR): dismissed with **Wait**
+//!   (`android:id/aerr_wait`, or a button labelled "Wait" / "等待")
+//! - `Application Error: <package>` (crash): dismissed with **Close app**
+//!   (`android:id/aerr_close`, or a button labelled "Close app" / "Close" /
+//!   "OK" / "关闭应用" / "确定")
+//!
+//! Buttons are located in a `uiautomator dump` of the screen. If no known
+//! button is found, the dialog is dismissed with the back key.
+
+use super::command::{adb_command, run_adb};
+use super::device::{back, tap};
+use crate::error::Result;
+
+const WAIT_IDS: &[&str] = &["android:id/aerr_wait"];
+const WAIT_LABELS: &[&str] = &["Wait", "等待"];
+const CLOSE_IDS: &[&str] = &["android:id/aerr_close"];
+const CLOSE_LABELS: &[&str] = &["Close app", "Close", "OK", "关闭应用", "确定"];
+
+/// A system dialog interrupting the foreground app
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SystemDialog {
+    /// "App isn't responding" dialog for the given package
+    NotResponding(String),
+    /// "App has stopped" crash dialog for the given package
+    Crashed(String),
+}
+
+impl SystemDialog {
+    /// Resource IDs and labels of the button that dismisses this dialog
+    fn dismiss_button(&self) -> (&'static [&'static str], &'static [&'static str]) {
+        match self {
+            SystemDialog::NotResponding(_) => (WAIT_IDS, WAIT_LABELS),
+            SystemDialog::Crashed(_) => (CLOSE_IDS, CLOSE_LABELS),
+        }
+    }
+}
+
+/// Check whether an ANR or crash dialog has focus
+pub async fn detect_system_dialog(device_id: Option<&str>) -> Result<Option<SystemDialog>> {
+    let mut cmd = adb_command(device_id);
+    cmd.arg("shell").arg("dumpsys").arg("window").arg("windows");
+
+    let output = run_adb(&mut cmd).await?;
+    Ok(parse_focused_dialog(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+/// Dismiss an ANR or crash dialog if one has focus
+///
+/// Returns the dialog that was dismissed, if any.
+pub async fn dismiss_system_dialog(device_id: Option<&str>) -> Result<Option<SystemDialog>> {
+    let Some(dialog) = detect_system_dialog(device_id).await? else {
+        return Ok(None);
+    };
+
+    let mut cmd = adb_command(device_id);
+    cmd.arg("exec-out")
+        .arg("uiautomator")
+        .arg("dump")
+        .arg("/dev/tty");
+    let output = run_adb(&mut cmd).await?;
+    let hierarchy = String::from_utf8_lossy(&output.stdout);
+
+    let (ids, labels) = dialog.dismiss_button();
+    match find_button(&hierarchy, ids, labels) {
+        Some((x, y)) => tap(x, y, device_id, None, None).await?,
+        None => back(device_id, None).await?,
+    }
+
+    Ok(Some(dialog))
+}
+
+/// Parse the focused window from `dumpsys window windows` output
+fn parse_focused_dialog(output: &str) -> Option<SystemDialog> {
+    let focus = output
+        .lines()
+        .map(|line| line.trim())
+        .find(|line| line.starts_with("mCurrentFocus=") || line.starts_with("mFocusedWindow="))?;
+
+    let package_after = |marker: &str| {
+        let rest = &focus[focus.find(marker)? + marker.len()..];
+        Some(rest.trim().trim_end_matches('}').trim().to_string())
+    };
+
+    if let Some(package) = package_after("Application Not Responding:") {
+        return Some(SystemDialog::NotResponding(package));
+    }
+    package_after("Application Error:").map(SystemDialog::Crashed)
+}
+
+/// Find the centre of the first node matching a resource ID, then a label
+fn find_button(hierarchy: &str, ids: &[&str], labels: &[&str]) -> Option<(i32, i32)> {
+    let nodes: Vec<&str> = hierarchy.split("<node ").skip(1).collect();
+
+    let by_attr = |name: &str, values: &[&str]| {
+        values.iter().find_map(|value| {
+            nodes
+                .iter()
+                .find(|node| xml_attr(node, name) == Some(*value))
+                .and_then(|node| parse_bounds(xml_attr(node, "bounds")?))
+        })
+    };
+
+    by_attr("resource-id", ids).or_else(|| by_attr("text", labels))
+}
+
+/// Read an attribute value from the start of a `uiautomator` XML node
+fn xml_attr<'a>(node: &'a str, name: &str) -> Option<&'a str> {
+    let pattern = format!(" {}=\"", name);
+    let start = format!(" {}", node).find(&pattern)? + pattern.len() - 1;
+    let end = node[start..].find('"')? + start;
+    Some(&node[start..end])
+}
+
+/// Centre of a `[x1,y1][x2,y2]` bounds string
+fn parse_bounds(bounds: &str) -> Option<(i32, i32)> {
+    let numbers: Vec<i32> = bounds
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|part| !part.is_empty())
+        .filter_map(|part| part.parse().ok())
+        .collect();
+
+    match numbers[..] {
+        [x1, y1, x2, y2] => Some(((x1 + x2) / 2, (y1 + y2) / 2)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_focused_dialog() {
+        let anr = "  mCurrentFocus=Window{5e1c7a u0 Application Not Responding: com.example.app}\n";
+        assert_eq!(
+            parse_focused_dialog(anr),
+            Some(SystemDialog::NotResponding("com.example.app".to_string()))
+        );
+
+        let crash = "  mCurrentFocus=Window{5e1c7a u0 Application Error: com.example.app}\n";
+        assert_eq!(
+            parse_focused_dialog(crash),
+            Some(SystemDialog::Crashed("com.example.app".to_string()))
+        );
+
+        let normal = "  mCurrentFocus=Window{5e1c7a u0 com.example.app/.MainActivity}\n";
+        assert_eq!(parse_focused_dialog(normal), None);
+    }
+
+    #[test]
+    fn test_find_button() {
+        let hierarchy = r#"<hierarchy><node index="0" text="Close app" resource-id="android:id/aerr_close" bounds="[100,1000][980,1100]" /><node index="1" text="Wait" resource-id="" bounds="[100,1100][980,1200]" /></hierarchy>"#;
+
+        assert_eq!(
+            find_button(hierarchy, CLOSE_IDS, CLOSE_LABELS),
+            Some((540, 1050))
+        );
+        assert_eq!(
+            find_button(hierarchy, WAIT_IDS, WAIT_LABELS),
+            Some((540, 1150))
+        );
+        assert_eq!(find_button(hierarchy, &[], &["Retry"]), None);
+    }
+}
@@ -1,28 +1,71 @@
 //! Input utilities for Android device text input
 
+use super::command::{adb_command, run_adb};
 use crate::error::{AdbError, Result};
 use base64::{engine::general_purpose, Engine as _};
-use tokio::process::Command;
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+use tracing::warn;
 
-/// Build ADB command prefix with optional device specifier
-fn get_adb_prefix(device_id: Option<&str>) -> Vec<String> {
-    let mut prefix = vec!["adb".to_string()];
-    if let Some(id) = device_id {
-        prefix.push("-s".to_string());
-        prefix.push(id.to_string());
+/// IME id of ADB Keyboard
+const ADB_KEYBOARD_IME: &str = "com.android.adbkeyboard/.AdbIME";
+
+/// Characters the device shell would interpret in an `input text` argument
+const SHELL_SPECIAL_CHARS: &str = "\\'\"`$&|;<>()[]{}*?~#!";
+
+/// Delete key presses sent to clear a field when select-all isn't supported
+const CLEAR_KEY_PRESSES: usize = 64;
+
+/// Whether ADB Keyboard is installed, per device id
+static ADB_KEYBOARD_INSTALLED: OnceLock<RwLock<HashMap<String, bool>>> = OnceLock::new();
+
+fn adb_keyboard_installed() -> &'static RwLock<HashMap<String, bool>> {
+    ADB_KEYBOARD_INSTALLED.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// How text is typed on the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputBackend {
+    /// Broadcast to ADB Keyboard; handles any Unicode text
+    AdbKeyboard,
+    /// Built-in `input text`; needs no install, but is limited to ASCII
+    InputText,
+}
+
+impl InputBackend {
+    /// Pick the backend for `text`
+    ///
+    /// `input text` is used only when ADB Keyboard is missing and the text is
+    /// printable ASCII; everything else goes through ADB Keyboard.
+    pub fn for_text(text: &str, adb_keyboard_installed: bool) -> Self {
+        let printable_ascii = text.chars().all(|c| c.is_ascii() && !c.is_ascii_control());
+        if !adb_keyboard_installed && printable_ascii {
+            InputBackend::InputText
+        } else {
+            InputBackend::AdbKeyboard
+        }
     }
-    prefix
 }
 
 /// Type text into the currently focused input field using ADB Keyboard
+///
+/// Text with characters outside the Basic Multilingual Plane (most emoji) is
+/// sent as code points via `ADB_INPUT_CHARS`, since some ADB Keyboard builds
+/// garble astral-plane characters in the base64 path.
 pub async fn type_text(text: &str, device_id: Option<&str>) -> Result<()> {
-    let prefix = get_adb_prefix(device_id);
+    if has_astral_chars(text) {
+        match type_code_points(text, device_id).await {
+            Ok(()) => return Ok(()),
+            Err(e) => warn!(
+                "ADB_INPUT_CHARS broadcast failed ({}), emoji may not be typed correctly",
+                e
+            ),
+        }
+    }
+
     let encoded_text = general_purpose::STANDARD.encode(text.as_bytes());
 
-    let mut cmd = Command::new(&prefix[0]);
-    for arg in &prefix[1..] {
-        cmd.arg(arg);
-    }
+    let mut cmd = adb_command(device_id);
     cmd.arg("shell")
         .arg("am")
         .arg("broadcast")
@@ -32,63 +75,112 @@ pub async fn type_text(text: &str, device_id: Option<&str>) -> Result<()> {
         .arg("msg")
         .arg(&encoded_text);
 
-    cmd.output().await.map_err(AdbError::Io)?;
+    run_adb(&mut cmd).await?;
 
     Ok(())
 }
 
-/// Clear text in the currently focused input field
-pub async fn clear_text(device_id: Option<&str>) -> Result<()> {
-    let prefix = get_adb_prefix(device_id);
+/// Whether the text contains characters that need a UTF-16 surrogate pair
+fn has_astral_chars(text: &str) -> bool {
+    text.chars().any(|c| c as u32 > 0xFFFF)
+}
+
+/// Comma-separated Unicode code points, as expected by `--eia`
+fn code_points(text: &str) -> String {
+    text.chars()
+        .map(|c| (c as u32).to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Type text through ADB Keyboard's code point broadcast
+async fn type_code_points(text: &str, device_id: Option<&str>) -> Result<()> {
+    let mut cmd = adb_command(device_id);
+    cmd.arg("shell")
+        .arg("am")
+        .arg("broadcast")
+        .arg("-a")
+        .arg("ADB_INPUT_CHARS")
+        .arg("--eia")
+        .arg("chars")
+        .arg(code_points(text));
 
-    let mut cmd = Command::new(&prefix[0]);
-    for arg in &prefix[1..] {
-        cmd.arg(arg);
+    let output = run_adb(&mut cmd).await?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() || !broadcast_handled(&stdout) {
+        return Err(AdbError::CommandFailed(format!(
+            "{}{}",
+            stdout.trim(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
     }
+
+    Ok(())
+}
+
+/// Whether `am broadcast` output reports `result=-1` (RESULT_OK)
+///
+/// ADB Keyboard sets this for broadcasts it handled; an older keyboard that
+/// doesn't know the action leaves the default `result=0`.
+fn broadcast_handled(stdout: &str) -> bool {
+    stdout
+        .lines()
+        .filter_map(|line| line.split("result=").nth(1))
+        .any(|rest| rest.split(|c: char| c == ',' || c.is_whitespace()).next() == Some("-1"))
+}
+
+/// Clear text in the currently focused input field
+pub async fn clear_text(device_id: Option<&str>) -> Result<()> {
+    let mut cmd = adb_command(device_id);
     cmd.arg("shell")
         .arg("am")
         .arg("broadcast")
         .arg("-a")
         .arg("ADB_CLEAR_TEXT");
 
-    cmd.output().await.map_err(AdbError::Io)?;
+    run_adb(&mut cmd).await?;
 
     Ok(())
 }
 
-/// Detect current keyboard and switch to ADB Keyboard if needed
-pub async fn detect_and_set_adb_keyboard(device_id: Option<&str>) -> Result<String> {
-    let prefix = get_adb_prefix(device_id);
-
-    // Get current IME
-    let mut cmd = Command::new(&prefix[0]);
-    for arg in &prefix[1..] {
-        cmd.arg(arg);
-    }
+/// Get the device's current default IME
+async fn get_current_ime(device_id: Option<&str>) -> Result<String> {
+    let mut cmd = adb_command(device_id);
     cmd.arg("shell")
         .arg("settings")
         .arg("get")
         .arg("secure")
         .arg("default_input_method");
 
-    let output = cmd.output().await.map_err(AdbError::Io)?;
+    let output = run_adb(&mut cmd).await?;
 
     let stdout = String::from_utf8_lossy(&output.stdout);
     let stderr = String::from_utf8_lossy(&output.stderr);
-    let current_ime = format!("{}{}", stdout, stderr).trim().to_string();
+    Ok(format!("{}{}", stdout, stderr).trim().to_string())
+}
+
+/// Detect current keyboard and switch to ADB Keyboard if needed
+///
+/// `ime set` exits successfully even when ADB Keyboard isn't installed or
+/// enabled, so the switch is verified by reading the IME back. Returns an
+/// error if ADB Keyboard isn't active afterwards.
+pub async fn detect_and_set_adb_keyboard(device_id: Option<&str>) -> Result<String> {
+    let current_ime = get_current_ime(device_id).await?;
 
     // Switch to ADB Keyboard if not already set
-    if !current_ime.contains("com.android.adbkeyboard/.AdbIME") {
-        let mut cmd = Command::new(&prefix[0]);
-        for arg in &prefix[1..] {
-            cmd.arg(arg);
-        }
-        cmd.arg("shell")
-            .arg("ime")
-            .arg("set")
-            .arg("com.android.adbkeyboard/.AdbIME");
+    if !current_ime.contains(ADB_KEYBOARD_IME) {
+        let mut cmd = adb_command(device_id);
+        cmd.arg("shell").arg("ime").arg("set").arg(ADB_KEYBOARD_IME);
+
+        run_adb(&mut cmd).await?;
 
-        cmd.output().await.map_err(AdbError::Io)?;
+        let active_ime = get_current_ime(device_id).await?;
+        if !active_ime.contains(ADB_KEYBOARD_IME) {
+            return Err(AdbError::CommandFailed(format!(
+                "Failed to switch to ADB Keyboard (active IME: {}); make sure it is installed and enabled",
+                active_ime
+            )));
+        }
     }
 
     // Warm up the keyboard
@@ -97,17 +189,267 @@ pub async fn detect_and_set_adb_keyboard(device_id: Option<&str>) -> Result<Stri
     Ok(current_ime)
 }
 
-/// Restore the original keyboard IME
-pub async fn restore_keyboard(ime: &str, device_id: Option<&str>) -> Result<()> {
-    let prefix = get_adb_prefix(device_id);
+/// Check whether ADB Keyboard is installed and enabled
+///
+/// The result is cached per device, so `ime list` runs once per device rather
+/// than before every Type.
+pub async fn is_adb_keyboard_installed(device_id: Option<&str>) -> Result<bool> {
+    let key = device_id.unwrap_or_default().to_string();
+    if let Some(&installed) = adb_keyboard_installed().read().unwrap().get(&key) {
+        return Ok(installed);
+    }
+
+    let mut cmd = adb_command(device_id);
+    cmd.arg("shell").arg("ime").arg("list").arg("-s");
+
+    let output = run_adb(&mut cmd).await?;
+    let installed = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .any(|line| line.trim() == ADB_KEYBOARD_IME);
+    adb_keyboard_installed()
+        .write()
+        .unwrap()
+        .insert(key, installed);
+    Ok(installed)
+}
 
-    let mut cmd = Command::new(&prefix[0]);
-    for arg in &prefix[1..] {
-        cmd.arg(arg);
+/// Check whether an editable text field has input focus
+///
+/// Reads `dumpsys input_method`: a field is focused when the keyboard is
+/// shown or an app holds an input connection to the IME.
+pub async fn is_text_input_focused(device_id: Option<&str>) -> Result<bool> {
+    let mut cmd = adb_command(device_id);
+    cmd.arg("shell").arg("dumpsys").arg("input_method");
+
+    let output = run_adb(&mut cmd).await?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(has_text_input_focus(&stdout))
+}
+
+/// Check whether the soft keyboard is showing
+///
+/// Reads `mInputShown` from `dumpsys input_method`.
+pub async fn is_keyboard_visible(device_id: Option<&str>) -> Result<bool> {
+    let mut cmd = adb_command(device_id);
+    cmd.arg("shell").arg("dumpsys").arg("input_method");
+
+    let output = run_adb(&mut cmd).await?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(has_keyboard_shown(&stdout))
+}
+
+/// Whether `dumpsys input_method` output shows the soft keyboard
+fn has_keyboard_shown(dumpsys: &str) -> bool {
+    dumpsys
+        .lines()
+        .flat_map(str::split_whitespace)
+        .any(|field| field == "mInputShown=true")
+}
+
+/// Whether `dumpsys input_method` output shows a focused text field
+fn has_text_input_focus(dumpsys: &str) -> bool {
+    dumpsys.lines().map(str::trim).any(|line| {
+        line.split_whitespace()
+            .any(|field| field == "mInputShown=true")
+            || ["mServedInputConnectionWrapper=", "mServedInputConnection="]
+                .iter()
+                .any(|key| {
+                    line.strip_prefix(key)
+                        .is_some_and(|value| !value.is_empty() && !value.starts_with("null"))
+                })
+    })
+}
+
+/// Type printable ASCII text with the built-in `input text` command
+///
+/// Works with whatever keyboard is active, so ADB Keyboard isn't needed. With
+/// `clear`, the focused field is emptied first, see [`clear_focused_field`].
+pub async fn type_ascii_text(text: &str, clear: bool, device_id: Option<&str>) -> Result<()> {
+    if InputBackend::for_text(text, false) != InputBackend::InputText {
+        return Err(AdbError::CommandFailed(
+            "input text only supports printable ASCII".to_string(),
+        ));
+    }
+
+    if clear {
+        clear_focused_field(device_id).await?;
+    }
+
+    if text.is_empty() {
+        return Ok(());
+    }
+
+    for chunk in escape_input_text(text) {
+        let mut cmd = adb_command(device_id);
+        cmd.arg("shell").arg("input").arg("text").arg(chunk);
+
+        let output = run_adb(&mut cmd).await?;
+        if !output.status.success() {
+            return Err(AdbError::CommandFailed(format!(
+                "input text failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Empty the focused field with key events
+///
+/// Selects everything with Ctrl+A and deletes it. `input keycombination` only
+/// exists on Android 13 and later, so older devices fall back to moving to the
+/// end of the field and deleting up to 64 characters.
+async fn clear_focused_field(device_id: Option<&str>) -> Result<()> {
+    let mut cmd = adb_command(device_id);
+    cmd.arg("shell")
+        .arg("input")
+        .arg("keycombination")
+        .arg("KEYCODE_CTRL_LEFT")
+        .arg("KEYCODE_A");
+    let output = run_adb(&mut cmd).await?;
+
+    let mut cmd = adb_command(device_id);
+    cmd.arg("shell").arg("input").arg("keyevent");
+    if input_command_succeeded(&output) {
+        cmd.arg("KEYCODE_DEL");
+    } else {
+        cmd.arg("KEYCODE_MOVE_END")
+            .args(std::iter::repeat_n("KEYCODE_DEL", CLEAR_KEY_PRESSES));
     }
+    run_adb(&mut cmd).await?;
+
+    Ok(())
+}
+
+/// Whether an `input` command ran, rather than printing an error or its usage
+///
+/// Older `input` builds exit with status 0 even for unknown commands.
+fn input_command_succeeded(output: &std::process::Output) -> bool {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    output.status.success()
+        && ![stdout, stderr]
+            .iter()
+            .any(|s| s.contains("Error") || s.contains("Usage:"))
+}
+
+/// Escape text for `input text`: spaces become `%s`, shell characters get a backslash
+///
+/// `input text` has no escape for a literal `%s`, so the text is split after
+/// any `%` followed by `s` and each chunk is typed with its own command.
+fn escape_input_text(text: &str) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut escaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == ' ' {
+            escaped.push_str("%s");
+            continue;
+        }
+        if SHELL_SPECIAL_CHARS.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+        if c == '%' && chars.peek() == Some(&'s') {
+            chunks.push(std::mem::take(&mut escaped));
+        }
+    }
+    if !escaped.is_empty() {
+        chunks.push(escaped);
+    }
+    chunks
+}
+
+/// Restore the original keyboard IME
+pub async fn restore_keyboard(ime: &str, device_id: Option<&str>) -> Result<()> {
+    let mut cmd = adb_command(device_id);
     cmd.arg("shell").arg("ime").arg("set").arg(ime);
 
-    cmd.output().await.map_err(AdbError::Io)?;
+    run_adb(&mut cmd).await?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_has_text_input_focus() {
+        let focused = "  mServedView=com.android.internal.policy.DecorView{4c1f2e}\n\
+            \x20 mServedInputConnectionWrapper=android.view.inputmethod.IInputConnectionWrapper@9d1\n";
+        assert!(has_text_input_focus(focused));
+
+        let shown = "  mShowRequested=true mShowForced=false mInputShown=true\n";
+        assert!(has_text_input_focus(shown));
+
+        let unfocused = "  mServedInputConnectionWrapper=null\n\
+            \x20 mShowRequested=false mShowForced=false mInputShown=false\n";
+        assert!(!has_text_input_focus(unfocused));
+    }
+
+    #[test]
+    fn test_has_keyboard_shown() {
+        assert!(has_keyboard_shown(
+            "  mShowRequested=true mShowForced=false mInputShown=true\n"
+        ));
+        assert!(!has_keyboard_shown(
+            "  mServedInputConnectionWrapper=android.view.inputmethod.IInputConnectionWrapper@9d1\n\
+             \x20 mShowRequested=false mShowForced=false mInputShown=false\n"
+        ));
+    }
+
+    #[test]
+    fn test_emoji_and_cjk_use_code_points() {
+        let text = "你好😀";
+        assert!(has_astral_chars(text));
+        assert_eq!(code_points(text), "20320,22909,128512");
+    }
+
+    #[test]
+    fn test_cjk_only_uses_base64() {
+        assert!(!has_astral_chars("你好, world"));
+    }
+
+    #[test]
+    fn test_input_backend_for_text() {
+        assert_eq!(
+            InputBackend::for_text("hello world", false),
+            InputBackend::InputText
+        );
+        assert_eq!(
+            InputBackend::for_text("hello world", true),
+            InputBackend::AdbKeyboard
+        );
+        assert_eq!(
+            InputBackend::for_text("你好", false),
+            InputBackend::AdbKeyboard
+        );
+        assert_eq!(
+            InputBackend::for_text("line\nbreak", false),
+            InputBackend::AdbKeyboard
+        );
+    }
+
+    #[test]
+    fn test_broadcast_handled() {
+        assert!(broadcast_handled(
+            "Broadcasting: Intent { act=ADB_INPUT_CHARS flg=0x400000 (has extras) }\nBroadcast completed: result=-1\n"
+        ));
+        assert!(!broadcast_handled("Broadcast completed: result=0\n"));
+        assert!(!broadcast_handled(""));
+    }
+
+    #[test]
+    fn test_escape_input_text() {
+        assert_eq!(escape_input_text("hello world"), ["hello%sworld"]);
+        assert_eq!(escape_input_text("a&b (c)"), ["a\\&b%s\\(c\\)"]);
+        assert_eq!(escape_input_text("it's $5"), ["it\\'s%s\\$5"]);
+        assert_eq!(escape_input_text("user@mail.com"), ["user@mail.com"]);
+        assert_eq!(escape_input_text("100% off"), ["100%%soff"]);
+        assert_eq!(escape_input_text("100%s"), ["100%", "s"]);
+        assert_eq!(escape_input_text("100%sure"), ["100%", "sure"]);
+        assert_eq!(escape_input_text("%s %s"), ["%", "s%s%", "s"]);
+    }
+}
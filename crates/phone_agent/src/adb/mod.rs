@@ -1,17 +1,46 @@
 //! ADB (Android Debug Bridge) module for Android device control
 //!
 //! This module provides:
+//! - `command`: ADB binary path and environment configuration
 //! - `connection`: ADB connection management
 //! - `device`: Device control operations (tap, swipe, back, home, etc.)
+//! - `dialog`: ANR / crash dialog detection and dismissal
 //! - `input`: Text input handling
 //! - `screenshot`: Screenshot capture
+//! - `touch`: Raw touch injection via `sendevent`
 
+mod command;
 mod connection;
 mod device;
+mod dialog;
 mod input;
 mod screenshot;
+mod touch;
 
-pub use connection::{list_devices, quick_connect, AdbConnection, ConnectionType, DeviceInfo};
-pub use device::{back, double_tap, get_current_app, home, launch_app, long_press, swipe, tap};
-pub use input::{clear_text, detect_and_set_adb_keyboard, restore_keyboard, type_text};
-pub use screenshot::{get_screenshot, Screenshot};
+pub use command::{get_adb_envs, get_adb_path, set_adb_env, set_adb_path};
+pub use connection::{
+    check_device_ready, is_mdns_address, list_devices, quick_connect, AdbConnection,
+    ConnectionType, DeviceInfo, DeviceStatus, HeartbeatHandle, PortForward,
+};
+pub use device::{
+    back, capture_logcat, clear_app_data, double_tap, force_stop_app, get_battery_level,
+    get_current_app, get_foreground_package, get_screen_size, home, is_locked, is_screen_on,
+    launch_app, long_press, open_url, swipe, tap, wake_device, wake_screen,
+};
+pub use dialog::{detect_system_dialog, dismiss_system_dialog, SystemDialog};
+pub use input::{
+    clear_text, detect_and_set_adb_keyboard, is_adb_keyboard_installed, is_keyboard_visible,
+    is_text_input_focused, restore_keyboard, type_ascii_text, type_text, InputBackend,
+};
+pub use screenshot::{
+    crop as crop_screenshot, diff as screenshot_diff, downscale as downscale_screenshot,
+    get_screenshot, get_screenshot_image, image_mime_type, perceptual_hash, reconcile_screen_size,
+    stitch_horizontal, stitch_vertical, transform as transform_screenshot, Screenshot,
+    ScreenshotEncoding,
+};
+pub use touch::{
+    find_touch_device, gesture, smooth_path, swipe_path, touch_down, touch_move, touch_up,
+    TouchDevice, DEFAULT_GESTURE_SEGMENT_MS,
+};
+pub(crate) use command::adb_command;
+pub(crate) use touch::swipe_path_segments;
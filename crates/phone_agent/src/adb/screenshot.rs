@@ -1,12 +1,19 @@
 //! Screenshot utilities for capturing Android device screen
 
+use super::command::{adb_command, display_args, run_adb};
 use crate::error::{AdbError, Result};
 use base64::{engine::general_purpose, Engine as _};
-use image::{ImageBuffer, Rgb};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{self, CompressionType, PngEncoder};
+use image::{imageops, imageops::FilterType, DynamicImage, GrayImage, ImageBuffer, Rgb, RgbImage};
+use std::collections::HashMap;
+use std::env;
 use std::io::Cursor;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{OnceLock, RwLock};
 use std::time::Duration;
 use tempfile::tempdir;
-use tokio::process::Command;
 use tracing::{debug, warn};
 
 /// Represents a captured screenshot
@@ -16,32 +23,237 @@ pub struct Screenshot {
     pub width: u32,
     pub height: u32,
     pub is_sensitive: bool,
+    /// Encoding of `base64_data`, kept when the screenshot is cropped or scaled
+    pub encoding: ScreenshotEncoding,
 }
 
-/// Build ADB command prefix with optional device specifier
-fn get_adb_prefix(device_id: Option<&str>) -> Vec<String> {
-    let mut prefix = vec!["adb".to_string()];
-    if let Some(id) = device_id {
-        prefix.push("-s".to_string());
-        prefix.push(id.to_string());
+impl Screenshot {
+    /// Encode `img` with the default encoding (see [`ScreenshotEncoding::from_env`])
+    pub fn from_image(img: &DynamicImage) -> Result<Self> {
+        Self::encode(img, ScreenshotEncoding::from_env())
+    }
+
+    /// Encode `img` with the given encoding
+    pub fn encode(img: &DynamicImage, encoding: ScreenshotEncoding) -> Result<Self> {
+        let buffer = encoding.encode(img)?;
+        Ok(Self {
+            base64_data: general_purpose::STANDARD.encode(&buffer),
+            width: img.width(),
+            height: img.height(),
+            is_sensitive: false,
+            encoding,
+        })
+    }
+
+    /// Load a screenshot from an image file, e.g. a PNG saved earlier
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_image(&image::open(path)?)
+    }
+
+    /// MIME type of the encoded image, e.g. `image/jpeg`
+    pub fn mime_type(&self) -> &'static str {
+        image_mime_type(&self.base64_data)
+    }
+
+    /// Write the screenshot to `path` as a PNG file
+    ///
+    /// PNG data is written as-is; other encodings (e.g. JPEG) are converted.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let bytes = general_purpose::STANDARD.decode(&self.base64_data)?;
+        if image::guess_format(&bytes)? == image::ImageFormat::Png {
+            std::fs::write(path, &bytes)?;
+        } else {
+            image::load_from_memory(&bytes)?.save_with_format(path, image::ImageFormat::Png)?;
+        }
+        Ok(())
     }
-    prefix
 }
 
-/// Create a black fallback image when screenshot fails
-fn create_fallback_screenshot(is_sensitive: bool, reason: &str) -> Screenshot {
-    warn!("Creating fallback screenshot: {}", reason);
+/// MIME type of base64 image data, detected from the image header
+///
+/// Unrecognised data is labelled `image/png`.
+pub fn image_mime_type(base64_data: &str) -> &'static str {
+    // 16 base64 characters decode to 12 bytes, enough to identify the format
+    let header = base64_data.get(..16).unwrap_or(base64_data);
+    general_purpose::STANDARD
+        .decode(header)
+        .ok()
+        .and_then(|bytes| image::guess_format(&bytes).ok())
+        .map_or("image/png", |format| format.to_mime_type())
+}
+
+/// Image encoding for captured screenshots
+///
+/// Smaller encodings trade image quality or CPU time for upload size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScreenshotEncoding {
+    /// Lossless PNG with default compression
+    #[default]
+    Png,
+    /// Lossless PNG, faster to encode but larger
+    PngFast,
+    /// Lossless PNG, smallest but slowest to encode
+    PngBest,
+    /// Lossy JPEG with the given quality (1-100)
+    Jpeg(u8),
+}
+
+impl ScreenshotEncoding {
+    /// Parse `png`, `png-fast`, `png-best` or a JPEG quality from 1 to 100
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "png" => Some(Self::Png),
+            "png-fast" => Some(Self::PngFast),
+            "png-best" => Some(Self::PngBest),
+            quality => match quality.parse::<u8>() {
+                Ok(quality @ 1..=100) => Some(Self::Jpeg(quality)),
+                _ => None,
+            },
+        }
+    }
+
+    /// Encoding from `PHONE_AGENT_SCREENSHOT_QUALITY`, or PNG if unset or invalid
+    pub fn from_env() -> Self {
+        env::var("PHONE_AGENT_SCREENSHOT_QUALITY")
+            .ok()
+            .and_then(|v| Self::parse(&v))
+            .unwrap_or_default()
+    }
+
+    /// MIME type of the encoded image
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Jpeg(_) => "image/jpeg",
+            _ => "image/png",
+        }
+    }
+
+    /// Encode an image with this encoding
+    pub fn encode(&self, img: &DynamicImage) -> Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        match *self {
+            Self::Png => img.write_to(&mut Cursor::new(&mut buffer), image::ImageFormat::Png)?,
+            Self::PngFast | Self::PngBest => {
+                let compression = if *self == Self::PngFast {
+                    CompressionType::Fast
+                } else {
+                    CompressionType::Best
+                };
+                let encoder = PngEncoder::new_with_quality(
+                    &mut buffer,
+                    compression,
+                    png::FilterType::Adaptive,
+                );
+                img.write_with_encoder(encoder)?;
+            }
+            Self::Jpeg(quality) => {
+                // JPEG has no alpha channel
+                let encoder = JpegEncoder::new_with_quality(&mut buffer, quality.clamp(1, 100));
+                DynamicImage::ImageRgb8(img.to_rgb8()).write_with_encoder(encoder)?;
+            }
+        }
+        Ok(buffer)
+    }
+}
+
+/// Size of fallback frames when no capture has succeeded on a device
+const DEFAULT_FALLBACK_SIZE: (u32, u32) = (1080, 2400);
+
+/// Frame size of the last successful capture, per device id and display
+static LAST_FRAME_SIZES: OnceLock<RwLock<HashMap<FrameKey, (u32, u32)>>> = OnceLock::new();
+
+/// Device id and display a frame was captured from
+type FrameKey = (String, Option<u64>);
+
+fn frame_key(device_id: Option<&str>, display_id: Option<u64>) -> FrameKey {
+    (device_id.unwrap_or_default().to_string(), display_id)
+}
+
+fn last_frame_sizes() -> &'static RwLock<HashMap<FrameKey, (u32, u32)>> {
+    LAST_FRAME_SIZES.get_or_init(|| RwLock::new(HashMap::new()))
+}
 
-    let default_width = 1080u32;
-    let default_height = 2400u32;
+/// Size of a fallback frame for `device_id` and `display_id`
+///
+/// Once a display has been captured, fallbacks use the last real frame size,
+/// so coordinates stay valid after a sensitive screen. Before that, `configured`
+/// is used, or 1080x2400 if it is `None`.
+fn fallback_frame_size(
+    device_id: Option<&str>,
+    display_id: Option<u64>,
+    configured: Option<(u32, u32)>,
+) -> (u32, u32) {
+    let last = last_frame_sizes()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&frame_key(device_id, display_id))
+        .copied();
+    last.or(configured).unwrap_or(DEFAULT_FALLBACK_SIZE)
+}
+
+/// Remember the frame size of a successful capture for later fallbacks
+fn record_frame_size(device_id: Option<&str>, display_id: Option<u64>, size: (u32, u32)) {
+    last_frame_sizes()
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(frame_key(device_id, display_id), size);
+}
+
+static CAPTURE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Screenshot file on the device that is removed when dropped
+///
+/// Each capture gets its own file so concurrent agents sharing a device don't
+/// overwrite each other's captures. Removal also happens when the capture is
+/// cancelled or times out part-way through.
+struct RemoteCapture {
+    device_id: Option<String>,
+    path: String,
+}
+
+impl RemoteCapture {
+    fn new(device_id: Option<&str>) -> Self {
+        let path = format!(
+            "/sdcard/autoglm_{}_{}.png",
+            std::process::id(),
+            CAPTURE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        Self {
+            device_id: device_id.map(|id| id.to_string()),
+            path,
+        }
+    }
+}
+
+impl Drop for RemoteCapture {
+    fn drop(&mut self) {
+        let mut cmd = adb_command(self.device_id.as_deref());
+        cmd.arg("shell").arg("rm").arg("-f").arg(&self.path);
+
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            handle.spawn(async move {
+                if let Err(e) = cmd.output().await {
+                    debug!("Failed to remove screenshot from device: {}", e);
+                }
+            });
+        }
+    }
+}
+
+/// Create a black fallback image of the given size when screenshot fails
+fn create_fallback_screenshot(
+    (default_width, default_height): (u32, u32),
+    encoding: ScreenshotEncoding,
+    is_sensitive: bool,
+    reason: &str,
+) -> Screenshot {
+    warn!("Creating fallback screenshot: {}", reason);
 
     let black_img: ImageBuffer<Rgb<u8>, Vec<u8>> =
         ImageBuffer::from_pixel(default_width, default_height, Rgb([0, 0, 0]));
 
-    let mut buffer = Vec::new();
-    let mut cursor = Cursor::new(&mut buffer);
-    black_img
-        .write_to(&mut cursor, image::ImageFormat::Png)
+    let buffer = encoding
+        .encode(&DynamicImage::ImageRgb8(black_img))
         .unwrap();
 
     let base64_data = general_purpose::STANDARD.encode(&buffer);
@@ -51,32 +263,66 @@ fn create_fallback_screenshot(is_sensitive: bool, reason: &str) -> Screenshot {
         width: default_width,
         height: default_height,
         is_sensitive,
+        encoding,
     }
 }
 
 /// Capture a screenshot from the connected Android device
-pub async fn get_screenshot(device_id: Option<&str>, timeout: u64) -> Result<Screenshot> {
+///
+/// `display_id` selects the display to capture; `None` captures the default
+/// display. The frame is encoded with `encoding`. Failed captures yield a
+/// black frame of the last known screen size, flagged as sensitive when the
+/// screen refused capture. Until the display has been captured once, that
+/// frame is `fallback_size`, or 1080x2400 if `None`.
+pub async fn get_screenshot(
+    device_id: Option<&str>,
+    display_id: Option<u64>,
+    encoding: ScreenshotEncoding,
+    fallback_size: Option<(u32, u32)>,
+    timeout: u64,
+) -> Result<Screenshot> {
+    let img = match get_screenshot_image(device_id, display_id, timeout).await {
+        Ok(img) => img,
+        Err(AdbError::ScreenshotFailed { reason, sensitive }) => {
+            let size = fallback_frame_size(device_id, display_id, fallback_size);
+            return Ok(create_fallback_screenshot(
+                size, encoding, sensitive, &reason,
+            ));
+        }
+        Err(e) => return Err(e),
+    };
+
+    Screenshot::encode(&img, encoding)
+}
+
+/// Capture a screenshot from the connected Android device as a decoded image
+///
+/// For in-process analysis such as template matching or OCR, without the
+/// base64 round-trip of [`get_screenshot`]. Fails with
+/// [`AdbError::ScreenshotFailed`] instead of returning a black fallback frame.
+pub async fn get_screenshot_image(
+    device_id: Option<&str>,
+    display_id: Option<u64>,
+    timeout: u64,
+) -> Result<DynamicImage> {
     // Use a temp directory so the file doesn't exist until adb pull creates it
     let temp_dir = tempdir().map_err(AdbError::Io)?;
     let temp_path = temp_dir.path().join("screenshot.png");
-    let prefix = get_adb_prefix(device_id);
 
     debug!("Capturing screenshot with device_id: {:?}", device_id);
 
     // Execute screenshot command on device
-    let mut cmd = Command::new(&prefix[0]);
-    for arg in &prefix[1..] {
-        cmd.arg(arg);
-    }
+    let remote = RemoteCapture::new(device_id);
+    let mut cmd = adb_command(device_id);
     cmd.arg("shell")
         .arg("screencap")
+        .args(display_args(display_id))
         .arg("-p")
-        .arg("/sdcard/tmp.png");
+        .arg(&remote.path);
 
-    let output = tokio::time::timeout(Duration::from_secs(timeout), cmd.output())
+    let output = tokio::time::timeout(Duration::from_secs(timeout), run_adb(&mut cmd))
         .await
-        .map_err(|_| AdbError::Timeout(format!("Screenshot timeout after {}s", timeout)))?
-        .map_err(AdbError::Io)?;
+        .map_err(|_| AdbError::Timeout(format!("Screenshot timeout after {}s", timeout)))??;
 
     // Check for screenshot failure (sensitive screen)
     let stdout = String::from_utf8_lossy(&output.stdout);
@@ -86,23 +332,19 @@ pub async fn get_screenshot(device_id: Option<&str>, timeout: u64) -> Result<Scr
     debug!("screencap output: {}", combined);
 
     if combined.contains("Status: -1") || combined.contains("Failed") {
-        return Ok(create_fallback_screenshot(
-            true,
+        return Err(capture_failed(
             "screencap returned Status: -1 or Failed (sensitive screen)",
+            true,
         ));
     }
 
     // Pull screenshot to local temp path
-    let mut cmd = Command::new(&prefix[0]);
-    for arg in &prefix[1..] {
-        cmd.arg(arg);
-    }
-    cmd.arg("pull").arg("/sdcard/tmp.png").arg(&temp_path);
+    let mut cmd = adb_command(device_id);
+    cmd.arg("pull").arg(&remote.path).arg(&temp_path);
 
-    let pull_output = tokio::time::timeout(Duration::from_secs(5), cmd.output())
+    let pull_output = tokio::time::timeout(Duration::from_secs(5), run_adb(&mut cmd))
         .await
-        .map_err(|_| AdbError::Timeout("Screenshot pull timeout after 5s".to_string()))?
-        .map_err(AdbError::Io)?;
+        .map_err(|_| AdbError::Timeout("Screenshot pull timeout after 5s".to_string()))??;
 
     // Check if adb pull succeeded
     let pull_stdout = String::from_utf8_lossy(&pull_output.stdout);
@@ -113,17 +355,17 @@ pub async fn get_screenshot(device_id: Option<&str>, timeout: u64) -> Result<Scr
 
     // adb pull prints "pulled" on success, or error messages on failure
     if !pull_output.status.success() {
-        return Ok(create_fallback_screenshot(
+        return Err(capture_failed(
+            format!("adb pull failed: {}", pull_combined),
             false,
-            &format!("adb pull failed: {}", pull_combined),
         ));
     }
 
     // Check if file exists and has content
     if !temp_path.exists() {
-        return Ok(create_fallback_screenshot(
-            false,
+        return Err(capture_failed(
             "Screenshot file does not exist after adb pull",
+            false,
         ));
     }
 
@@ -132,43 +374,617 @@ pub async fn get_screenshot(device_id: Option<&str>, timeout: u64) -> Result<Scr
         .unwrap_or(0);
 
     if file_size == 0 {
-        return Ok(create_fallback_screenshot(
-            false,
-            "Screenshot file is empty (0 bytes)",
-        ));
+        return Err(capture_failed("Screenshot file is empty (0 bytes)", false));
     }
 
     debug!("Screenshot file size: {} bytes", file_size);
 
-    // Read and encode image
-    let img = match image::open(&temp_path) {
-        Ok(img) => img,
-        Err(e) => {
-            return Ok(create_fallback_screenshot(
-                false,
-                &format!("Failed to decode image: {}", e),
-            ));
-        }
-    };
+    // Decode image; cleanup is automatic when temp_dir goes out of scope
+    let img = image::open(&temp_path)
+        .map_err(|e| capture_failed(format!("Failed to decode image: {}", e), false))?;
 
-    let width = img.width();
-    let height = img.height();
+    debug!("Screenshot dimensions: {}x{}", img.width(), img.height());
+    record_frame_size(device_id, display_id, (img.width(), img.height()));
 
-    debug!("Screenshot dimensions: {}x{}", width, height);
+    Ok(img)
+}
 
-    let mut buffer = Vec::new();
-    let mut cursor = Cursor::new(&mut buffer);
-    img.write_to(&mut cursor, image::ImageFormat::Png)
-        .map_err(AdbError::Image)?;
+fn capture_failed(reason: impl Into<String>, sensitive: bool) -> AdbError {
+    AdbError::ScreenshotFailed {
+        reason: reason.into(),
+        sensitive,
+    }
+}
 
-    let base64_data = general_purpose::STANDARD.encode(&buffer);
+/// Largest relative difference between aspect ratios that still counts as equal
+const ASPECT_TOLERANCE: f64 = 0.02;
+
+/// Size of the input coordinate space for a screenshot frame
+///
+/// Some emulators return `screencap` frames stretched away from the `wm size`
+/// screen size, which would throw off taps computed from the frame. When the
+/// frame's aspect ratio differs from the screen's, rotated to the frame's
+/// orientation, that screen size is returned so relative coordinates can be
+/// mapped onto it instead. Otherwise the frame size is kept.
+pub fn reconcile_screen_size(frame: (u32, u32), screen: (u32, u32)) -> (u32, u32) {
+    let (width, height) = screen;
+    let screen = if (frame.0 > frame.1) != (width > height) {
+        (height, width)
+    } else {
+        (width, height)
+    };
 
-    // Cleanup is automatic when temp_dir goes out of scope
+    let aspect = |(w, h): (u32, u32)| w as f64 / h.max(1) as f64;
+    if (aspect(frame) / aspect(screen) - 1.0).abs() <= ASPECT_TOLERANCE {
+        frame
+    } else {
+        screen
+    }
+}
+
+/// Scale a screenshot down so neither side exceeds `max_dimension` pixels
+///
+/// The aspect ratio is kept and the result is re-encoded with the
+/// screenshot's encoding. Screenshots that already fit are returned unchanged.
+pub fn downscale(screenshot: &Screenshot, max_dimension: u32) -> Result<Screenshot> {
+    let longest = screenshot.width.max(screenshot.height);
+    if max_dimension == 0 || longest <= max_dimension {
+        return Ok(screenshot.clone());
+    }
 
+    let img = decode_image(screenshot)?;
+    let scaled = img.resize(max_dimension, max_dimension, FilterType::Triangle);
     Ok(Screenshot {
-        base64_data,
-        width,
-        height,
-        is_sensitive: false,
+        is_sensitive: screenshot.is_sensitive,
+        ..Screenshot::encode(&scaled, screenshot.encoding)?
+    })
+}
+
+/// Apply an image transformation to a screenshot, e.g. to blur part of it
+///
+/// The result is re-encoded with the screenshot's encoding and takes the
+/// size of the transformed image.
+pub fn transform(
+    screenshot: &Screenshot,
+    f: impl FnOnce(DynamicImage) -> DynamicImage,
+) -> Result<Screenshot> {
+    let img = f(decode_image(screenshot)?);
+    Ok(Screenshot {
+        is_sensitive: screenshot.is_sensitive,
+        ..Screenshot::encode(&img, screenshot.encoding)?
     })
 }
+
+/// Cut the `width` x `height` rectangle at `(x, y)` out of a screenshot
+///
+/// The rectangle is clamped to the frame.
+pub fn crop(
+    screenshot: &Screenshot,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> Result<Screenshot> {
+    let img = decode_image(screenshot)?;
+    let cropped = img.crop_imm(x, y, width.max(1), height.max(1));
+    Ok(Screenshot {
+        is_sensitive: screenshot.is_sensitive,
+        ..Screenshot::encode(&cropped, screenshot.encoding)?
+    })
+}
+
+/// Decode a screenshot's base64 PNG data into an image
+fn decode_image(screenshot: &Screenshot) -> Result<DynamicImage> {
+    let bytes = general_purpose::STANDARD.decode(&screenshot.base64_data)?;
+    Ok(image::load_from_memory(&bytes)?)
+}
+
+/// Compute a normalized difference score between two screenshots
+///
+/// Returns the mean absolute grayscale difference in `0.0..=1.0`, where 0 means
+/// identical. If the dimensions differ, `b` is resized to match `a`.
+pub fn diff(a: &Screenshot, b: &Screenshot) -> Result<f64> {
+    let img_a = decode_image(a)?.to_luma8();
+    let mut img_b = decode_image(b)?;
+    if img_b.width() != img_a.width() || img_b.height() != img_a.height() {
+        img_b = img_b.resize_exact(img_a.width(), img_a.height(), FilterType::Triangle);
+    }
+    let img_b = img_b.to_luma8();
+
+    Ok(mean_abs_diff(img_a.as_raw(), img_b.as_raw()))
+}
+
+/// Mean absolute difference of two grayscale buffers in `0.0..=1.0`
+fn mean_abs_diff(a: &[u8], b: &[u8]) -> f64 {
+    if a.is_empty() {
+        return 0.0;
+    }
+
+    let total: u64 = a
+        .iter()
+        .zip(b)
+        .map(|(pa, pb)| pa.abs_diff(*pb) as u64)
+        .sum();
+
+    total as f64 / (a.len() as f64 * 255.0)
+}
+
+/// Compute a 64-bit perceptual (difference) hash of a screenshot
+///
+/// Visually similar screens produce hashes with a small Hamming distance,
+/// which can be compared with `(a ^ b).count_ones()`.
+pub fn perceptual_hash(screenshot: &Screenshot) -> Result<u64> {
+    let small = decode_image(screenshot)?
+        .resize_exact(9, 8, FilterType::Triangle)
+        .to_luma8();
+
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+
+    Ok(hash)
+}
+
+/// Largest mean difference at which two regions count as the same content
+const OVERLAP_THRESHOLD: f64 = 0.02;
+
+/// Overlap scores closer than this count as a tie, won by the larger overlap
+const OVERLAP_TIE: f64 = 0.002;
+
+/// Width frames are narrowed to when searching for overlapping regions
+const OVERLAP_SEARCH_WIDTH: u32 = 108;
+
+/// Stitch frames captured while scrolling down into one tall screenshot
+///
+/// `frames[i + 1]` must continue `frames[i]`; the rows they share are found
+/// with the same difference metric as [`diff`] and kept only once. Frames of
+/// a different width are scaled to the width of the first. The result's
+/// coordinates don't correspond to the screen, so taps must be made on
+/// individual frames.
+pub fn stitch_vertical(frames: &[Screenshot]) -> Result<Screenshot> {
+    let images = decode_frames(frames)?;
+    Screenshot::encode(
+        &DynamicImage::ImageRgb8(stitch_rgb_vertical(&images)),
+        frames[0].encoding,
+    )
+}
+
+/// Stitch frames captured while scrolling right into one wide screenshot
+///
+/// Works like [`stitch_vertical`], with frames joined left to right.
+pub fn stitch_horizontal(frames: &[Screenshot]) -> Result<Screenshot> {
+    let images: Vec<RgbImage> = decode_frames(frames)?
+        .iter()
+        .map(imageops::rotate90)
+        .collect();
+    let stitched = imageops::rotate270(&stitch_rgb_vertical(&images));
+    Screenshot::encode(&DynamicImage::ImageRgb8(stitched), frames[0].encoding)
+}
+
+/// Decode frames as RGB images
+fn decode_frames(frames: &[Screenshot]) -> Result<Vec<RgbImage>> {
+    if frames.is_empty() {
+        return Err(AdbError::CommandFailed("No frames to stitch".to_string()));
+    }
+
+    frames
+        .iter()
+        .map(|frame| Ok(decode_image(frame)?.to_rgb8()))
+        .collect()
+}
+
+/// Join images top to bottom, dropping rows repeated between neighbours
+///
+/// Images are scaled to the width of the first one.
+fn stitch_rgb_vertical(images: &[RgbImage]) -> RgbImage {
+    let width = images[0].width();
+    let images: Vec<RgbImage> = images
+        .iter()
+        .map(|img| {
+            if img.width() == width {
+                img.clone()
+            } else {
+                let height = (img.height() * width / img.width().max(1)).max(1);
+                imageops::resize(img, width, height, FilterType::Triangle)
+            }
+        })
+        .collect();
+
+    // Only the width is reduced, so rows line up exactly at the true overlap
+    let narrow: Vec<GrayImage> = images
+        .iter()
+        .map(|img| {
+            imageops::resize(
+                img,
+                OVERLAP_SEARCH_WIDTH.min(width),
+                img.height(),
+                FilterType::Triangle,
+            )
+        })
+        .map(|img| DynamicImage::ImageRgb8(img).to_luma8())
+        .collect();
+
+    // Rows to skip at the top of each image
+    let mut skips = vec![0];
+    for pair in narrow.windows(2) {
+        skips.push(find_vertical_overlap(&pair[0], &pair[1]));
+    }
+
+    let total_height = images
+        .iter()
+        .zip(&skips)
+        .map(|(img, skip)| img.height() - skip)
+        .sum();
+    let mut out = RgbImage::new(width, total_height);
+    let mut y = 0;
+    for (img, &skip) in images.iter().zip(&skips) {
+        let height = img.height() - skip;
+        let strip = imageops::crop_imm(img, 0, skip, width, height).to_image();
+        imageops::replace(&mut out, &strip, 0, y as i64);
+        y += height;
+    }
+    out
+}
+
+/// Number of rows at the bottom of `top` that repeat at the top of `bottom`
+///
+/// The overlap with the lowest mean difference wins, rather than the first
+/// one under [`OVERLAP_THRESHOLD`], so a mostly plain screen shifted by a
+/// few lines of text can't pass as unchanged. Near ties go to the larger
+/// overlap, so plain backgrounds don't produce spurious small overlaps. The
+/// overlap is always shorter than `bottom`, which therefore adds at least
+/// one row.
+fn find_vertical_overlap(top: &GrayImage, bottom: &GrayImage) -> u32 {
+    let width = top.width() as usize;
+    let max_overlap = top.height().min(bottom.height()).saturating_sub(1);
+
+    let mut best: Option<(u32, f64)> = None;
+    for overlap in (1..=max_overlap).rev() {
+        // Smaller overlaps must beat the best one by more than a tie
+        let bound = best.map_or(OVERLAP_THRESHOLD, |(_, score)| score - OVERLAP_TIE);
+        if bound <= 0.0 {
+            break;
+        }
+
+        let start = (top.height() - overlap) as usize * width;
+        let a = &top.as_raw()[start..];
+        let b = &bottom.as_raw()[..overlap as usize * width];
+        if let Some(score) = mean_abs_diff_below(a, b, width, bound) {
+            best = Some((overlap, score));
+        }
+    }
+    best.map_or(0, |(overlap, _)| overlap)
+}
+
+/// [`mean_abs_diff`] of two images, or `None` as soon as it can't stay below `bound`
+///
+/// Rows of `width` pixels are summed one at a time, so clearly different
+/// regions are rejected early.
+fn mean_abs_diff_below(a: &[u8], b: &[u8], width: usize, bound: f64) -> Option<f64> {
+    let limit = bound * a.len() as f64 * 255.0;
+    let mut total = 0u64;
+    for (row_a, row_b) in a.chunks(width).zip(b.chunks(width)) {
+        total += row_a
+            .iter()
+            .zip(row_b)
+            .map(|(pa, pb)| pa.abs_diff(*pb) as u64)
+            .sum::<u64>();
+        if total as f64 >= limit {
+            return None;
+        }
+    }
+    Some(total as f64 / (a.len() as f64 * 255.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid_screenshot(width: u32, height: u32, value: u8) -> Screenshot {
+        let img: ImageBuffer<Rgb<u8>, Vec<u8>> =
+            ImageBuffer::from_pixel(width, height, Rgb([value, value, value]));
+        let mut buffer = Vec::new();
+        img.write_to(&mut Cursor::new(&mut buffer), image::ImageFormat::Png)
+            .unwrap();
+        Screenshot {
+            base64_data: general_purpose::STANDARD.encode(&buffer),
+            width,
+            height,
+            is_sensitive: false,
+            encoding: ScreenshotEncoding::Png,
+        }
+    }
+
+    #[test]
+    fn test_diff_identical() {
+        let a = solid_screenshot(20, 40, 128);
+        assert_eq!(diff(&a, &a.clone()).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_diff_black_and_white() {
+        let black = solid_screenshot(20, 40, 0);
+        let white = solid_screenshot(10, 20, 255);
+        let score = diff(&black, &white).unwrap();
+        assert!((score - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_perceptual_hash_stable() {
+        let a = solid_screenshot(20, 40, 64);
+        let b = solid_screenshot(40, 80, 64);
+        assert_eq!(perceptual_hash(&a).unwrap(), perceptual_hash(&b).unwrap());
+    }
+
+    #[test]
+    fn test_screenshot_encoding_parse() {
+        assert_eq!(
+            ScreenshotEncoding::parse("PNG"),
+            Some(ScreenshotEncoding::Png)
+        );
+        assert_eq!(
+            ScreenshotEncoding::parse("png-best"),
+            Some(ScreenshotEncoding::PngBest)
+        );
+        assert_eq!(
+            ScreenshotEncoding::parse("75"),
+            Some(ScreenshotEncoding::Jpeg(75))
+        );
+        assert_eq!(ScreenshotEncoding::parse("0"), None);
+        assert_eq!(ScreenshotEncoding::parse("webp"), None);
+    }
+
+    #[test]
+    fn test_screenshot_encoding_round_trip() {
+        let img = DynamicImage::ImageRgb8(ImageBuffer::from_pixel(16, 16, Rgb([200, 10, 10])));
+        for encoding in [
+            ScreenshotEncoding::Png,
+            ScreenshotEncoding::PngFast,
+            ScreenshotEncoding::PngBest,
+            ScreenshotEncoding::Jpeg(50),
+        ] {
+            let bytes = encoding.encode(&img).unwrap();
+            let decoded = image::load_from_memory(&bytes).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (16, 16));
+
+            let base64_data = general_purpose::STANDARD.encode(&bytes);
+            assert_eq!(image_mime_type(&base64_data), encoding.mime_type());
+        }
+        assert_eq!(image_mime_type("not an image"), "image/png");
+    }
+
+    /// Image of hashed pixels, so no two offsets look alike and overlaps are unambiguous
+    fn noise_image(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| {
+            let h = (y.wrapping_mul(2_654_435_761) ^ x.wrapping_mul(40_503)).rotate_left(13);
+            let v = (h >> 24) as u8;
+            Rgb([v, v.wrapping_mul(3), 255 - v])
+        })
+    }
+
+    fn rgb_screenshot(img: &RgbImage) -> Screenshot {
+        let mut buffer = Vec::new();
+        img.write_to(&mut Cursor::new(&mut buffer), image::ImageFormat::Png)
+            .unwrap();
+        Screenshot {
+            base64_data: general_purpose::STANDARD.encode(&buffer),
+            width: img.width(),
+            height: img.height(),
+            is_sensitive: false,
+            encoding: ScreenshotEncoding::Png,
+        }
+    }
+
+    #[test]
+    fn test_stitch_vertical_removes_overlap() {
+        let full = noise_image(20, 160);
+        let frames: Vec<Screenshot> = [0, 60]
+            .iter()
+            .map(|&y| rgb_screenshot(&imageops::crop_imm(&full, 0, y, 20, 100).to_image()))
+            .collect();
+
+        let stitched = stitch_vertical(&frames).unwrap();
+        assert_eq!((stitched.width, stitched.height), (20, 160));
+        assert_eq!(decode_image(&stitched).unwrap().to_rgb8(), full);
+    }
+
+    /// Light list screen with faint separators and one or two lines of grey text per item
+    fn list_image(width: u32, height: u32) -> RgbImage {
+        // Items of varying height, so the list doesn't repeat exactly
+        let mut items = Vec::new();
+        let mut top = 0;
+        for i in 0u32.. {
+            if top >= height {
+                break;
+            }
+            let h = (i.wrapping_mul(2_654_435_761) >> 16) % 48 + 56;
+            items.push((top, h, i.wrapping_mul(40_503)));
+            top += h;
+        }
+
+        RgbImage::from_fn(width, height, |x, y| {
+            let &(top, h, seed) = items.iter().rfind(|(top, _, _)| *top <= y).unwrap();
+            let row = y - top;
+            let line = |start: u32, len: u32| {
+                (start..start + 10).contains(&row) && (24..24 + len).contains(&x)
+            };
+            let v = if row == h - 1 {
+                235
+            } else if line(16, width / 4 + seed % (width / 2))
+                || (seed % 3 == 0 && line(32, width / 5 + (seed >> 8) % (width / 3)))
+            {
+                200
+            } else {
+                250
+            };
+            Rgb([v, v, v])
+        })
+    }
+
+    #[test]
+    fn test_stitch_vertical_low_contrast_list() {
+        let full = list_image(432, 1600);
+        let frames: Vec<Screenshot> = [0, 590]
+            .iter()
+            .map(|&y| rgb_screenshot(&imageops::crop_imm(&full, 0, y, 432, 960).to_image()))
+            .collect();
+
+        let stitched = stitch_vertical(&frames).unwrap();
+        assert_eq!((stitched.width, stitched.height), (432, 1550));
+        assert_eq!(
+            decode_image(&stitched).unwrap().to_rgb8(),
+            imageops::crop_imm(&full, 0, 0, 432, 1550).to_image()
+        );
+    }
+
+    #[test]
+    fn test_stitch_keeps_frame_that_barely_scrolled() {
+        let full = list_image(108, 400);
+        let frames: Vec<Screenshot> = [0, 8]
+            .iter()
+            .map(|&y| rgb_screenshot(&imageops::crop_imm(&full, 0, y, 108, 300).to_image()))
+            .collect();
+
+        let stitched = stitch_vertical(&frames).unwrap();
+        assert_eq!(stitched.height, 308);
+        assert_eq!(
+            decode_image(&stitched).unwrap().to_rgb8(),
+            imageops::crop_imm(&full, 0, 0, 108, 308).to_image()
+        );
+    }
+
+    #[test]
+    fn test_stitch_horizontal_removes_overlap() {
+        let full = imageops::rotate270(&noise_image(20, 160));
+        let frames: Vec<Screenshot> = [0, 60]
+            .iter()
+            .map(|&x| rgb_screenshot(&imageops::crop_imm(&full, x, 0, 100, 20).to_image()))
+            .collect();
+
+        let stitched = stitch_horizontal(&frames).unwrap();
+        assert_eq!((stitched.width, stitched.height), (160, 20));
+        assert_eq!(decode_image(&stitched).unwrap().to_rgb8(), full);
+    }
+
+    #[test]
+    fn test_screenshot_save_writes_png() {
+        let dir = tempdir().unwrap();
+        let img = noise_image(16, 24);
+        let encoded = ScreenshotEncoding::Jpeg(90)
+            .encode(&DynamicImage::ImageRgb8(img))
+            .unwrap();
+        let screenshot = Screenshot {
+            base64_data: general_purpose::STANDARD.encode(&encoded),
+            width: 16,
+            height: 24,
+            is_sensitive: false,
+            encoding: ScreenshotEncoding::Jpeg(90),
+        };
+
+        let path = dir.path().join("screen.png");
+        screenshot.save(&path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(
+            image::guess_format(&bytes).unwrap(),
+            image::ImageFormat::Png
+        );
+        assert_eq!(image::load_from_memory(&bytes).unwrap().width(), 16);
+    }
+
+    #[test]
+    fn test_transform() {
+        let screenshot = solid_screenshot(40, 80, 0);
+
+        let inverted = transform(&screenshot, |mut img| {
+            img.invert();
+            img
+        })
+        .unwrap();
+        assert_eq!((inverted.width, inverted.height), (40, 80));
+        assert_eq!(diff(&screenshot, &inverted).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_crop() {
+        let screenshot = solid_screenshot(400, 800, 128);
+
+        let cropped = crop(&screenshot, 100, 200, 50, 60).unwrap();
+        assert_eq!((cropped.width, cropped.height), (50, 60));
+
+        let clamped = crop(&screenshot, 380, 790, 50, 60).unwrap();
+        assert_eq!((clamped.width, clamped.height), (20, 10));
+    }
+
+    #[test]
+    fn test_crop_keeps_encoding() {
+        let img = DynamicImage::ImageRgb8(noise_image(40, 40));
+        let screenshot = Screenshot::encode(&img, ScreenshotEncoding::Jpeg(80)).unwrap();
+
+        let cropped = crop(&screenshot, 0, 0, 20, 20).unwrap();
+        assert_eq!(cropped.encoding, ScreenshotEncoding::Jpeg(80));
+        assert_eq!(cropped.mime_type(), "image/jpeg");
+    }
+
+    #[test]
+    fn test_downscale() {
+        let screenshot = solid_screenshot(400, 800, 128);
+
+        let scaled = downscale(&screenshot, 200).unwrap();
+        assert_eq!((scaled.width, scaled.height), (100, 200));
+        let img = decode_image(&scaled).unwrap();
+        assert_eq!((img.width(), img.height()), (100, 200));
+
+        let unchanged = downscale(&screenshot, 1000).unwrap();
+        assert_eq!(unchanged.base64_data, screenshot.base64_data);
+    }
+
+    #[test]
+    fn test_fallback_uses_last_frame_size() {
+        let device = Some("fallback-test-device");
+        let configured = Some((800, 1280));
+        assert_eq!(fallback_frame_size(device, None, configured), (800, 1280));
+
+        record_frame_size(device, None, (2560, 1600));
+        assert_eq!(fallback_frame_size(device, None, configured), (2560, 1600));
+        assert_eq!(
+            fallback_frame_size(device, Some(1), configured),
+            (800, 1280)
+        );
+        assert_eq!(
+            fallback_frame_size(Some("never-captured"), None, None),
+            DEFAULT_FALLBACK_SIZE
+        );
+
+        let fallback =
+            create_fallback_screenshot((2560, 1600), ScreenshotEncoding::Png, true, "test");
+        assert_eq!((fallback.width, fallback.height), (2560, 1600));
+        assert!(fallback.is_sensitive);
+    }
+
+    #[test]
+    fn test_reconcile_screen_size() {
+        let screen = (1080, 2400);
+        assert_eq!(reconcile_screen_size((1080, 2400), screen), screen);
+        // Same aspect ratio, e.g. a downscaled frame, keeps the frame
+        assert_eq!(reconcile_screen_size((540, 1200), screen), (540, 1200));
+        // Rotated frame
+        assert_eq!(reconcile_screen_size((2400, 1080), screen), (2400, 1080));
+        // Stretched frame, in either orientation
+        assert_eq!(reconcile_screen_size((1080, 1920), screen), screen);
+        assert_eq!(reconcile_screen_size((1920, 1080), screen), (2400, 1080));
+    }
+
+    #[test]
+    fn test_remote_capture_paths_are_unique() {
+        let first = RemoteCapture::new(Some("emulator-5554"));
+        let second = RemoteCapture::new(Some("emulator-5554"));
+        assert_ne!(first.path, second.path);
+        assert!(first.path.starts_with("/sdcard/autoglm_"));
+    }
+}
@@ -0,0 +1,393 @@
+//! Low-level touch injection via `sendevent`
+//!
+//! `input tap`/`input swipe` go through the Java input stack and are too slow
+//! and coarse for games and some anti-bot UIs. These helpers write raw
+//! multi-touch events to the touchscreen's `/dev/input/eventX` node instead.
+
+use super::command::{adb_command, run_adb};
+use super::device::get_screen_size;
+use crate::error::{AdbError, Result};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+const EV_SYN: u32 = 0;
+const EV_KEY: u32 = 1;
+const EV_ABS: u32 = 3;
+const SYN_REPORT: u32 = 0;
+const BTN_TOUCH: u32 = 0x14a;
+const ABS_MT_POSITION_X: u32 = 0x35;
+const ABS_MT_POSITION_Y: u32 = 0x36;
+const ABS_MT_TRACKING_ID: u32 = 0x39;
+
+/// Default duration of a gesture segment when none is given
+pub const DEFAULT_GESTURE_SEGMENT_MS: u32 = 100;
+
+/// Interval between interpolated move events within a gesture segment
+const GESTURE_STEP_MS: u32 = 16;
+
+/// Points sampled on the curve between each pair of waypoints of a swipe path
+const PATH_SAMPLES_PER_SEGMENT: usize = 8;
+
+static TOUCH_DEVICES: OnceLock<Mutex<HashMap<String, TouchDevice>>> = OnceLock::new();
+static NEXT_TRACKING_ID: AtomicI32 = AtomicI32::new(1);
+
+/// Touchscreen input device and its coordinate space
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TouchDevice {
+    /// Input device node, e.g. `/dev/input/event2`
+    pub path: String,
+    /// Maximum raw X value reported by the touch driver
+    pub max_x: i32,
+    /// Maximum raw Y value reported by the touch driver
+    pub max_y: i32,
+    /// Screen width in pixels
+    pub screen_width: u32,
+    /// Screen height in pixels
+    pub screen_height: u32,
+}
+
+impl TouchDevice {
+    /// Scale screen pixel coordinates to raw touch driver coordinates
+    ///
+    /// Assumes the natural (portrait) orientation.
+    pub fn to_raw(&self, x: i32, y: i32) -> (i32, i32) {
+        let scale = |value: i32, screen: u32, max: i32| {
+            if screen == 0 {
+                return value.clamp(0, max);
+            }
+            let raw = (value as f64 / screen as f64 * (max + 1) as f64) as i32;
+            raw.clamp(0, max)
+        };
+        (
+            scale(x, self.screen_width, self.max_x),
+            scale(y, self.screen_height, self.max_y),
+        )
+    }
+
+    fn down_commands(&self, x: i32, y: i32, tracking_id: i32) -> Vec<String> {
+        let (raw_x, raw_y) = self.to_raw(x, y);
+        vec![
+            self.event(EV_ABS, ABS_MT_TRACKING_ID, tracking_id),
+            self.event(EV_KEY, BTN_TOUCH, 1),
+            self.event(EV_ABS, ABS_MT_POSITION_X, raw_x),
+            self.event(EV_ABS, ABS_MT_POSITION_Y, raw_y),
+            self.event(EV_SYN, SYN_REPORT, 0),
+        ]
+    }
+
+    fn move_commands(&self, x: i32, y: i32) -> Vec<String> {
+        let (raw_x, raw_y) = self.to_raw(x, y);
+        vec![
+            self.event(EV_ABS, ABS_MT_POSITION_X, raw_x),
+            self.event(EV_ABS, ABS_MT_POSITION_Y, raw_y),
+            self.event(EV_SYN, SYN_REPORT, 0),
+        ]
+    }
+
+    fn up_commands(&self) -> Vec<String> {
+        vec![
+            self.event(EV_ABS, ABS_MT_TRACKING_ID, -1),
+            self.event(EV_KEY, BTN_TOUCH, 0),
+            self.event(EV_SYN, SYN_REPORT, 0),
+        ]
+    }
+
+    fn event(&self, event_type: u32, code: u32, value: i32) -> String {
+        format!("sendevent {} {} {} {}", self.path, event_type, code, value)
+    }
+
+    /// Build the shell commands for a gesture through `points`
+    ///
+    /// Segment `i` (from `points[i]` to `points[i + 1]`) takes `durations_ms[i]`;
+    /// missing durations reuse the last one given.
+    fn gesture_commands(&self, points: &[(i32, i32)], durations_ms: &[u32]) -> Vec<String> {
+        let Some(&(first_x, first_y)) = points.first() else {
+            return Vec::new();
+        };
+
+        let mut commands = self.down_commands(first_x, first_y, next_tracking_id());
+        for (i, pair) in points.windows(2).enumerate() {
+            let ((x1, y1), (x2, y2)) = (pair[0], pair[1]);
+            let duration = durations_ms
+                .get(i)
+                .or(durations_ms.last())
+                .copied()
+                .unwrap_or(DEFAULT_GESTURE_SEGMENT_MS);
+            let steps = (duration / GESTURE_STEP_MS).max(1);
+            let pause = duration as f64 / steps as f64 / 1000.0;
+
+            for step in 1..=steps {
+                let t = step as f64 / steps as f64;
+                let x = x1 + ((x2 - x1) as f64 * t).round() as i32;
+                let y = y1 + ((y2 - y1) as f64 * t).round() as i32;
+                commands.extend(self.move_commands(x, y));
+                if pause > 0.0 {
+                    commands.push(format!("sleep {:.3}", pause));
+                }
+            }
+        }
+        commands.extend(self.up_commands());
+        commands
+    }
+}
+
+fn next_tracking_id() -> i32 {
+    NEXT_TRACKING_ID.fetch_add(1, Ordering::Relaxed) & 0xffff
+}
+
+/// Find the touchscreen in `getevent -pl` output
+///
+/// Returns the device path and its maximum X/Y position values.
+fn parse_touch_device(output: &str) -> Option<(String, i32, i32)> {
+    let mut path: Option<&str> = None;
+    let mut max_x = None;
+    let mut max_y = None;
+
+    for line in output.lines() {
+        if let Some(rest) = line.strip_prefix("add device") {
+            if let (Some(p), Some(x), Some(y)) = (path, max_x, max_y) {
+                return Some((p.to_string(), x, y));
+            }
+            path = rest.split(':').nth(1).map(|p| p.trim());
+            max_x = None;
+            max_y = None;
+        } else if line.contains("ABS_MT_POSITION_X") {
+            max_x = parse_abs_max(line);
+        } else if line.contains("ABS_MT_POSITION_Y") {
+            max_y = parse_abs_max(line);
+        }
+    }
+
+    match (path, max_x, max_y) {
+        (Some(p), Some(x), Some(y)) => Some((p.to_string(), x, y)),
+        _ => None,
+    }
+}
+
+/// Extract the `max N` field from a `getevent -pl` axis line
+fn parse_abs_max(line: &str) -> Option<i32> {
+    line.split(',')
+        .map(|part| part.trim())
+        .find_map(|part| part.strip_prefix("max "))
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// Detect the touchscreen input device, caching the result per device
+pub async fn find_touch_device(device_id: Option<&str>) -> Result<TouchDevice> {
+    let key = device_id.unwrap_or_default().to_string();
+    let cache = TOUCH_DEVICES.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Some(device) = cache.lock().unwrap().get(&key) {
+        return Ok(device.clone());
+    }
+
+    let mut cmd = adb_command(device_id);
+    cmd.arg("shell").arg("getevent").arg("-pl");
+    let output = run_adb(&mut cmd).await?;
+    let (path, max_x, max_y) = parse_touch_device(&String::from_utf8_lossy(&output.stdout))
+        .ok_or_else(|| AdbError::CommandFailed("No touchscreen input device found".to_string()))?;
+
+    let (screen_width, screen_height) = get_screen_size(device_id, None).await?;
+
+    let device = TouchDevice {
+        path,
+        max_x,
+        max_y,
+        screen_width,
+        screen_height,
+    };
+    cache.lock().unwrap().insert(key, device.clone());
+    Ok(device)
+}
+
+/// Run a list of shell commands in a single `adb shell` invocation
+async fn run_commands(commands: &[String], device_id: Option<&str>) -> Result<()> {
+    let mut cmd = adb_command(device_id);
+    cmd.arg("shell").arg(commands.join("; "));
+
+    let output = run_adb(&mut cmd).await?;
+    if !output.status.success() {
+        return Err(AdbError::CommandFailed(format!(
+            "sendevent failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Put a finger down at screen coordinates
+pub async fn touch_down(x: i32, y: i32, device_id: Option<&str>) -> Result<()> {
+    let device = find_touch_device(device_id).await?;
+    run_commands(&device.down_commands(x, y, next_tracking_id()), device_id).await
+}
+
+/// Move the finger that is down to screen coordinates
+pub async fn touch_move(x: i32, y: i32, device_id: Option<&str>) -> Result<()> {
+    let device = find_touch_device(device_id).await?;
+    run_commands(&device.move_commands(x, y), device_id).await
+}
+
+/// Lift the finger that is down
+pub async fn touch_up(device_id: Option<&str>) -> Result<()> {
+    let device = find_touch_device(device_id).await?;
+    run_commands(&device.up_commands(), device_id).await
+}
+
+/// Draw a continuous touch path through screen coordinates
+///
+/// `durations_ms[i]` is the time spent moving from `points[i]` to
+/// `points[i + 1]`; missing entries reuse the last duration given.
+pub async fn gesture(
+    points: &[(i32, i32)],
+    durations_ms: &[u32],
+    device_id: Option<&str>,
+) -> Result<()> {
+    if points.is_empty() {
+        return Err(AdbError::CommandFailed(
+            "Gesture needs at least one point".to_string(),
+        ));
+    }
+
+    let device = find_touch_device(device_id).await?;
+    run_commands(&device.gesture_commands(points, durations_ms), device_id).await
+}
+
+/// Sample a smooth curve through `waypoints`
+///
+/// Uses a Catmull-Rom spline (piecewise cubic Bézier), so the curve passes
+/// through every waypoint. The result starts and ends at the first and last
+/// waypoint.
+pub fn smooth_path(waypoints: &[(i32, i32)]) -> Vec<(i32, i32)> {
+    if waypoints.len() < 2 {
+        return waypoints.to_vec();
+    }
+
+    let last = waypoints.len() - 1;
+    let at = |i: usize| waypoints[i.min(last)];
+    let mut path = vec![waypoints[0]];
+    for i in 0..last {
+        let (p0, p1, p2, p3) = (at(i.saturating_sub(1)), at(i), at(i + 1), at(i + 2));
+        for k in 1..=PATH_SAMPLES_PER_SEGMENT {
+            let t = k as f64 / PATH_SAMPLES_PER_SEGMENT as f64;
+            path.push((
+                catmull_rom(p0.0, p1.0, p2.0, p3.0, t),
+                catmull_rom(p0.1, p1.1, p2.1, p3.1, t),
+            ));
+        }
+    }
+    path
+}
+
+/// Catmull-Rom interpolation between `p1` (t = 0) and `p2` (t = 1)
+fn catmull_rom(p0: i32, p1: i32, p2: i32, p3: i32, t: f64) -> i32 {
+    let (p0, p1, p2, p3) = (p0 as f64, p1 as f64, p2 as f64, p3 as f64);
+    let value = 0.5
+        * (2.0 * p1
+            + (p2 - p0) * t
+            + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t * t
+            + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t * t * t);
+    value.round() as i32
+}
+
+/// Sampled path and per-segment duration for a swipe through `waypoints`
+pub(crate) fn swipe_path_segments(
+    waypoints: &[(i32, i32)],
+    duration_ms: u32,
+) -> (Vec<(i32, i32)>, u32) {
+    let path = smooth_path(waypoints);
+    let segments = path.len().saturating_sub(1).max(1) as u32;
+    (path, (duration_ms / segments).max(1))
+}
+
+/// Swipe along a smooth curve through `waypoints`, taking `duration_ms` in total
+///
+/// Unlike `input swipe`, the path can curve, e.g. for signatures, sliders or
+/// pattern unlocks.
+pub async fn swipe_path(
+    waypoints: &[(i32, i32)],
+    duration_ms: u32,
+    device_id: Option<&str>,
+) -> Result<()> {
+    let (path, segment_ms) = swipe_path_segments(waypoints, duration_ms);
+    gesture(&path, &[segment_ms], device_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GETEVENT: &str = "add device 1: /dev/input/event1
+  name:     \"gpio-keys\"
+  events:
+    KEY (0001): KEY_VOLUMEDOWN KEY_VOLUMEUP
+add device 2: /dev/input/event3
+  name:     \"fts_ts\"
+  events:
+    ABS (0003): ABS_MT_SLOT           : value 0, min 0, max 9, fuzz 0, flat 0, resolution 0
+                ABS_MT_POSITION_X     : value 0, min 0, max 4095, fuzz 0, flat 0, resolution 0
+                ABS_MT_POSITION_Y     : value 0, min 0, max 8191, fuzz 0, flat 0, resolution 0
+";
+
+    fn touch_device() -> TouchDevice {
+        TouchDevice {
+            path: "/dev/input/event3".to_string(),
+            max_x: 4095,
+            max_y: 8191,
+            screen_width: 1080,
+            screen_height: 2400,
+        }
+    }
+
+    #[test]
+    fn test_parse_touch_device() {
+        assert_eq!(
+            parse_touch_device(GETEVENT),
+            Some(("/dev/input/event3".to_string(), 4095, 8191))
+        );
+        assert_eq!(
+            parse_touch_device("add device 1: /dev/input/event0\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_to_raw_scales_to_driver_range() {
+        let device = touch_device();
+        assert_eq!(device.to_raw(0, 0), (0, 0));
+        assert_eq!(device.to_raw(540, 1200), (2048, 4096));
+        assert_eq!(device.to_raw(1080, 2400), (4095, 8191));
+    }
+
+    #[test]
+    fn test_gesture_commands() {
+        let device = touch_device();
+        let commands = device.gesture_commands(&[(0, 0), (540, 1200)], &[32]);
+
+        assert!(commands[0].starts_with("sendevent /dev/input/event3 3 57 "));
+        assert!(commands.contains(&"sendevent /dev/input/event3 3 53 2048".to_string()));
+        assert_eq!(
+            commands.iter().filter(|c| c.starts_with("sleep")).count(),
+            2
+        );
+        assert_eq!(
+            commands[commands.len() - 3],
+            "sendevent /dev/input/event3 3 57 -1"
+        );
+    }
+
+    #[test]
+    fn test_smooth_path_passes_through_waypoints() {
+        let waypoints = [(0, 0), (100, 100), (200, 0)];
+        let path = smooth_path(&waypoints);
+
+        assert_eq!(path.len(), 2 * PATH_SAMPLES_PER_SEGMENT + 1);
+        assert_eq!(path[0], (0, 0));
+        assert_eq!(path[PATH_SAMPLES_PER_SEGMENT], (100, 100));
+        assert_eq!(path.last(), Some(&(200, 0)));
+        // Curves instead of following the straight line to the middle waypoint
+        assert_ne!(path[PATH_SAMPLES_PER_SEGMENT / 2], (50, 50));
+
+        let (path, segment_ms) = swipe_path_segments(&waypoints, 1600);
+        assert_eq!(segment_ms, 1600 / (path.len() as u32 - 1));
+    }
+}
@@ -1,18 +1,153 @@
 //! Main PhoneAgent class for orchestrating phone automation
 
 use async_openai::types::ChatCompletionRequestMessage;
+use chrono::Local;
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
 use serde_json;
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, field, info, instrument, warn, Span};
 
 use crate::actions::{
-    finish_action, parse_action, ActionHandler, ConfirmationCallback, TakeoverCallback,
+    describe_action, finish_action, handle_error, normalize_coordinates, parse_action,
+    ActionHandler, ActionResult, AsyncConfirmationCallback, ConfirmationCallback, RelativeRegion,
+    TakeoverCallback,
 };
-use crate::config::{get_messages, get_system_prompt, Language};
-use crate::device_factory::get_device_factory;
+use crate::adb::{
+    crop_screenshot, downscale_screenshot, image_mime_type, reconcile_screen_size, screenshot_diff,
+    transform_screenshot, Screenshot, ScreenshotEncoding,
+};
+use crate::config::{get_messages, get_system_prompt, Language, TIMING_CONFIG};
+use crate::device_factory::{DeviceController, GlobalDeviceController};
 use crate::error::Result;
-use crate::model::{MessageBuilder, ModelClient, ModelConfig};
+use crate::metrics::{MetricsRecorder, RunMetrics};
+use crate::model::{
+    ImageDetail, ImageSource, MessageBuilder, ModelClient, ModelConfig, ScreenInfo,
+};
 use crate::screenshot_saver::ScreenshotSaver;
+use crate::transcript::render_markdown;
+
+/// Log a step detail at `info` level when `verbose` is set, `debug` otherwise
+macro_rules! step_event {
+    ($verbose:expr, $($arg:tt)+) => {
+        if $verbose {
+            info!($($arg)+)
+        } else {
+            debug!($($arg)+)
+        }
+    };
+}
+
+/// Largest difference between two frames for the screen to count as settled
+const SETTLE_THRESHOLD: f64 = 0.005;
+
+/// Time to let the screen react to a tap before checking that it changed
+const TAP_VERIFY_DELAY: Duration = Duration::from_millis(500);
+
+/// What to do when the device reports a sensitive screen (e.g. a password field)
+///
+/// Sensitive screens can't be captured, so the model only sees a black image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SensitivePolicy {
+    /// Send the black fallback image to the model and carry on
+    #[default]
+    Blackout,
+    /// Ask a human to handle the screen via the takeover callback, then re-capture
+    Pause,
+    /// Stop the task
+    Abort,
+}
+
+/// Optional device state to describe to the model with each screenshot
+///
+/// The foreground app is always included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ScreenInfoFields {
+    /// Screen width and height in pixels
+    pub screen_size: bool,
+    /// Portrait or landscape
+    pub orientation: bool,
+    /// Battery level in percent
+    pub battery: bool,
+    /// Local time of the screenshot
+    pub timestamp: bool,
+}
+
+/// Transforms screenshots before they are sent to the model, e.g. to redact them
+#[derive(Clone)]
+pub struct ScreenshotPreprocessor(Arc<dyn Fn(DynamicImage) -> DynamicImage + Send + Sync>);
+
+impl ScreenshotPreprocessor {
+    /// Wrap a transformation
+    pub fn new(f: impl Fn(DynamicImage) -> DynamicImage + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    /// Apply the transformation to an image
+    pub fn apply(&self, img: DynamicImage) -> DynamicImage {
+        (self.0)(img)
+    }
+}
+
+impl fmt::Debug for ScreenshotPreprocessor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ScreenshotPreprocessor(..)")
+    }
+}
+
+/// Lock screen PIN entered by [`PhoneAgent::ensure_ready`], hidden from `Debug` output
+#[derive(Clone, PartialEq, Eq)]
+pub struct UnlockPin(String);
+
+impl UnlockPin {
+    /// Wrap a PIN
+    pub fn new(pin: impl Into<String>) -> Self {
+        Self(pin.into())
+    }
+
+    /// The PIN digits
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for UnlockPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("UnlockPin(***)")
+    }
+}
+
+/// How screenshots are sent to the model
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImageStrategy {
+    /// Image detail level; `None` uses [`ModelConfig::image_detail`]
+    pub detail: Option<ImageDetail>,
+    /// Downscale screenshots so neither side exceeds this many pixels
+    pub max_dimension: Option<u32>,
+}
+
+impl ImageStrategy {
+    /// Create a strategy that sends screenshots as captured
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the image detail level
+    pub fn with_detail(mut self, detail: ImageDetail) -> Self {
+        self.detail = Some(detail);
+        self
+    }
+
+    /// Set the largest width or height, in pixels
+    pub fn with_max_dimension(mut self, max_dimension: u32) -> Self {
+        self.max_dimension = Some(max_dimension);
+        self
+    }
+}
 
 /// Configuration for the PhoneAgent
 #[derive(Debug, Clone)]
@@ -24,6 +159,84 @@ pub struct AgentConfig {
     pub verbose: bool,
     /// Directory to save screenshots (if set, screenshots will be saved to disk)
     pub screenshot_dir: Option<PathBuf>,
+    /// Save each session in `screenshot_dir` as one `.session.gz` archive
+    pub compress_sessions: bool,
+    /// Re-capture the screen after each Tap and flag taps that changed nothing
+    pub verify_taps: bool,
+    /// Fail Type actions when no text field is focused, so the model taps it first
+    pub check_text_focus: bool,
+    /// Press Back after Type when the soft keyboard is left showing
+    pub dismiss_keyboard: bool,
+    /// Case-insensitive patterns that make Type ask for confirmation first
+    pub type_confirmation_patterns: Vec<String>,
+    /// Times to re-prompt the model when its response contains no action
+    pub max_action_retries: usize,
+    /// Times to ask the model to reformat an action that couldn't be parsed
+    pub max_parse_retries: usize,
+    /// Keep the full conversation between tasks in [`PhoneAgent::run_chain`]
+    pub share_chain_context: bool,
+    /// How to handle screens that can't be captured for privacy reasons
+    pub sensitive_policy: SensitivePolicy,
+    /// Wake and unlock the device before starting a task (see [`PhoneAgent::ensure_ready`])
+    pub wake_screen: bool,
+    /// PIN entered when waking finds a PIN lock screen
+    pub unlock_pin: Option<UnlockPin>,
+    /// Dismiss ANR / crash dialogs before each step (see [`crate::adb::SystemDialog`])
+    pub auto_dismiss_dialogs: bool,
+    /// Screenshot encoding, `None` for `PHONE_AGENT_SCREENSHOT_QUALITY` or PNG
+    ///
+    /// Used by the controller [`PhoneAgent::new`] creates, like `display_id`.
+    pub screenshot_encoding: Option<ScreenshotEncoding>,
+    /// Send a warm-up request to the model when the agent is created
+    pub warmup: bool,
+    /// Extra device state included in the screen info of each step
+    pub screen_info_fields: ScreenInfoFields,
+    /// Actions the agent may execute; `None` allows all of them
+    pub allowed_actions: Option<HashSet<String>>,
+    /// Lines of logcat to save next to the screenshot when an action fails
+    ///
+    /// Requires `screenshot_dir`; `None` disables log capture.
+    pub logcat_on_failure: Option<usize>,
+    /// Plan actions without executing them on the device
+    pub plan_only: bool,
+    /// Also send the previous step's screenshot, so the model can compare screens
+    ///
+    /// Each extra image costs as many input tokens as the current one.
+    pub include_previous_screenshot: bool,
+    /// Prune the context before a model request when its serialized size exceeds this many bytes
+    pub max_context_bytes: Option<usize>,
+    /// Display for screenshots, taps and swipes, `None` for the default display
+    ///
+    /// Used by the controller [`PhoneAgent::new`] creates; controllers passed
+    /// to [`PhoneAgent::with_device`] choose their own display.
+    pub display_id: Option<u64>,
+    /// Size of the black frame used when a screen can't be captured
+    ///
+    /// Only used until a real screenshot has been captured from the device;
+    /// after that, fallbacks take the size of the last captured frame. Used by
+    /// the controller [`PhoneAgent::new`] creates, like `display_id`.
+    pub fallback_screen_size: Option<(u32, u32)>,
+    /// Applied to each screenshot before it is sent to the model
+    ///
+    /// Actions are still mapped onto the original screen size, and saved
+    /// screenshots are not affected.
+    pub screenshot_preprocessor: Option<ScreenshotPreprocessor>,
+    /// How the screenshot of a task's first step is sent to the model
+    pub first_step_image: ImageStrategy,
+    /// How screenshots of later steps are sent to the model
+    pub step_image: ImageStrategy,
+    /// Capture until this many consecutive frames are alike, so the model
+    /// doesn't see a screen mid-animation; `None` uses the first frame
+    pub settle_frames: Option<usize>,
+    /// Longest wait for the screen to settle before the latest frame is used
+    pub settle_timeout: Duration,
+    /// Pause between capturing the screen and executing the model's action,
+    /// on top of the device delays after each action in [`TIMING_CONFIG`]
+    pub pre_action_delay: Duration,
+    /// Range of the model's coordinates, e.g. 1000 for 0-1000 or 100 for 0-100
+    ///
+    /// Fractional coordinates of at most 1.0 are always read as 0-1.
+    pub coordinate_scale: f64,
 }
 
 impl Default for AgentConfig {
@@ -35,6 +248,35 @@ impl Default for AgentConfig {
             system_prompt: None,
             verbose: true,
             screenshot_dir: None,
+            compress_sessions: false,
+            verify_taps: false,
+            check_text_focus: false,
+            dismiss_keyboard: false,
+            type_confirmation_patterns: Vec::new(),
+            max_action_retries: 1,
+            max_parse_retries: 1,
+            share_chain_context: false,
+            sensitive_policy: SensitivePolicy::default(),
+            wake_screen: false,
+            unlock_pin: None,
+            auto_dismiss_dialogs: false,
+            screenshot_encoding: None,
+            warmup: false,
+            screen_info_fields: ScreenInfoFields::default(),
+            allowed_actions: None,
+            logcat_on_failure: None,
+            plan_only: false,
+            include_previous_screenshot: false,
+            max_context_bytes: None,
+            display_id: None,
+            fallback_screen_size: None,
+            screenshot_preprocessor: None,
+            first_step_image: ImageStrategy::default(),
+            step_image: ImageStrategy::default(),
+            settle_frames: None,
+            settle_timeout: Duration::from_secs(3),
+            pre_action_delay: Duration::ZERO,
+            coordinate_scale: 1000.0,
         }
     }
 }
@@ -70,6 +312,9 @@ impl AgentConfig {
     }
 
     /// Set verbose mode
+    ///
+    /// Verbose agents log each step's thinking, action and outcome as `info`
+    /// events; otherwise they are logged at `debug` level.
     pub fn with_verbose(mut self, verbose: bool) -> Self {
         self.verbose = verbose;
         self
@@ -81,24 +326,441 @@ impl AgentConfig {
         self
     }
 
+    /// Save each session as one gzip archive instead of loose files
+    ///
+    /// See [`crate::screenshot_saver::read_session`] for reading them back.
+    pub fn with_compress_sessions(mut self, compress: bool) -> Self {
+        self.compress_sessions = compress;
+        self
+    }
+
+    /// Set tap verification mode
+    pub fn with_verify_taps(mut self, verify_taps: bool) -> Self {
+        self.verify_taps = verify_taps;
+        self
+    }
+
+    /// Set whether Type checks for a focused text field before typing
+    ///
+    /// Off by default, since the check is unreliable on apps that draw their
+    /// own text fields.
+    pub fn with_text_focus_check(mut self, enabled: bool) -> Self {
+        self.check_text_focus = enabled;
+        self
+    }
+
+    /// Set whether to hide the soft keyboard after Type, so it can't cover the next tap
+    pub fn with_keyboard_dismiss(mut self, enabled: bool) -> Self {
+        self.dismiss_keyboard = enabled;
+        self
+    }
+
+    /// Ask the confirmation callback before typing text matching any of these patterns
+    ///
+    /// Patterns are case-insensitive regular expressions, e.g. `"transfer"` or
+    /// `r"rm\s+-rf"`; see [`ActionHandler::with_type_confirmation`].
+    pub fn with_type_confirmation_patterns<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.type_confirmation_patterns = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set how many times to re-prompt the model for a missing action
+    pub fn with_max_action_retries(mut self, retries: usize) -> Self {
+        self.max_action_retries = retries;
+        self
+    }
+
+    /// Set how many times to ask the model to reformat an unparseable action
+    ///
+    /// Once exhausted, the unparseable response is treated as a finish.
+    pub fn with_max_parse_retries(mut self, retries: usize) -> Self {
+        self.max_parse_retries = retries;
+        self
+    }
+
+    /// Set whether chained tasks share the full conversation context
+    pub fn with_share_chain_context(mut self, share: bool) -> Self {
+        self.share_chain_context = share;
+        self
+    }
+
+    /// Set how sensitive screens are handled
+    pub fn with_sensitive_policy(mut self, policy: SensitivePolicy) -> Self {
+        self.sensitive_policy = policy;
+        self
+    }
+
+    /// Enable or disable waking the screen before each task
+    pub fn with_wake_screen(mut self, wake_screen: bool) -> Self {
+        self.wake_screen = wake_screen;
+        self
+    }
+
+    /// Set the PIN used to unlock the device when waking it
+    pub fn with_unlock_pin(mut self, pin: impl Into<String>) -> Self {
+        self.unlock_pin = Some(UnlockPin::new(pin));
+        self
+    }
+
+    /// Enable or disable dismissing ANR / crash dialogs before each step
+    ///
+    /// "App isn't responding" dialogs are answered with Wait and crash
+    /// dialogs with Close app, so the model never sees them.
+    pub fn with_auto_dismiss_dialogs(mut self, dismiss: bool) -> Self {
+        self.auto_dismiss_dialogs = dismiss;
+        self
+    }
+
+    /// Set the screenshot encoding, e.g. JPEG to save bandwidth
+    pub fn with_screenshot_encoding(mut self, encoding: ScreenshotEncoding) -> Self {
+        self.screenshot_encoding = Some(encoding);
+        self
+    }
+
+    /// Set the display to automate, e.g. the cover screen of a foldable
+    pub fn with_display_id(mut self, display_id: u64) -> Self {
+        self.display_id = Some(display_id);
+        self
+    }
+
+    /// Set how the first step's screenshot is sent, e.g. at a higher resolution
+    pub fn with_first_step_image(mut self, strategy: ImageStrategy) -> Self {
+        self.first_step_image = strategy;
+        self
+    }
+
+    /// Set how screenshots of steps after the first are sent
+    pub fn with_step_image(mut self, strategy: ImageStrategy) -> Self {
+        self.step_image = strategy;
+        self
+    }
+
+    /// Set the size of fallback frames, e.g. `(2560, 1600)` for a landscape tablet
+    pub fn with_fallback_screen_size(mut self, width: u32, height: u32) -> Self {
+        self.fallback_screen_size = Some((width, height));
+        self
+    }
+
+    /// Wait for `frames` consecutive similar frames when capturing the screen
+    pub fn with_settle_frames(mut self, frames: usize) -> Self {
+        self.settle_frames = Some(frames);
+        self
+    }
+
+    /// Set how long to wait for the screen to settle (default: 3 seconds)
+    pub fn with_settle_timeout(mut self, timeout: Duration) -> Self {
+        self.settle_timeout = timeout;
+        self
+    }
+
+    /// Wait before each action, for devices still painting the UI when the model decides
+    pub fn with_pre_action_delay(mut self, delay: Duration) -> Self {
+        self.pre_action_delay = delay;
+        self
+    }
+
+    /// Set the range of the model's coordinates (default: 1000)
+    ///
+    /// A scale that isn't a positive, finite number is ignored with a warning.
+    pub fn with_coordinate_scale(mut self, scale: f64) -> Self {
+        if scale.is_finite() && scale > 0.0 {
+            self.coordinate_scale = scale;
+        } else {
+            warn!(scale, "ignoring invalid coordinate scale");
+        }
+        self
+    }
+
+    /// Transform screenshots before they are sent to the model
+    ///
+    /// Use this to blur or black out private content, such as notification
+    /// previews, before it leaves the machine.
+    pub fn with_screenshot_preprocessor(
+        mut self,
+        f: impl Fn(DynamicImage) -> DynamicImage + Send + Sync + 'static,
+    ) -> Self {
+        self.screenshot_preprocessor = Some(ScreenshotPreprocessor::new(f));
+        self
+    }
+
+    /// Enable or disable warming up the model when the agent is created
+    pub fn with_warmup(mut self, warmup: bool) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    /// Set which extra device state is included in the screen info
+    pub fn with_screen_info_fields(mut self, fields: ScreenInfoFields) -> Self {
+        self.screen_info_fields = fields;
+        self
+    }
+
+    /// Restrict the agent to the named actions, e.g. `["Tap", "Swipe", "Back"]`
+    ///
+    /// Any other action is rejected and the rejection is reported to the model
+    /// on the next step.
+    pub fn with_allowed_actions(mut self, actions: HashSet<String>) -> Self {
+        self.allowed_actions = Some(actions);
+        self
+    }
+
+    /// Save the last `lines` lines of logcat next to the screenshot when an action fails
+    pub fn with_logcat_on_failure(mut self, lines: usize) -> Self {
+        self.logcat_on_failure = Some(lines);
+        self
+    }
+
+    /// Enable or disable planning mode, where actions are decided but not executed
+    ///
+    /// Useful with [`PhoneAgent::step_with_screenshot`] when the screen comes
+    /// from somewhere the agent can't control.
+    pub fn with_plan_only(mut self, plan_only: bool) -> Self {
+        self.plan_only = plan_only;
+        self
+    }
+
+    /// Enable or disable sending the previous screenshot alongside the current one
+    ///
+    /// Helps the model judge what its last action did, at roughly twice the
+    /// image tokens per step.
+    pub fn with_include_previous_screenshot(mut self, include: bool) -> Self {
+        self.include_previous_screenshot = include;
+        self
+    }
+
+    /// Limit the serialized size of the context sent to the model
+    ///
+    /// When exceeded, images are dropped from all but the latest message,
+    /// then the oldest turns after the task prompt are dropped until the
+    /// context fits (the latest message is always kept).
+    pub fn with_max_context_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_context_bytes = Some(max_bytes);
+        self
+    }
+
     /// Get the system prompt (custom or default based on language)
     pub fn get_system_prompt(&self) -> String {
         self.system_prompt
             .clone()
             .unwrap_or_else(|| get_system_prompt(self.lang))
     }
+
+    /// The resolved settings of a run, as written by [`dump_effective_config`](Self::dump_effective_config)
+    ///
+    /// Includes the system prompt actually used, the model parameters (with
+    /// the API key redacted), the agent settings and [`TIMING_CONFIG`].
+    pub fn effective_config(&self, model_config: &ModelConfig) -> serde_json::Value {
+        serde_json::json!({
+            "lang": self.lang,
+            "system_prompt": self.get_system_prompt(),
+            "custom_system_prompt": self.system_prompt.is_some(),
+            "model": model_config,
+            "agent": {
+                "max_steps": self.max_steps,
+                "device_id": self.device_id,
+                "display_id": self.display_id,
+                "verify_taps": self.verify_taps,
+                "check_text_focus": self.check_text_focus,
+                "dismiss_keyboard": self.dismiss_keyboard,
+                "type_confirmation_patterns": self.type_confirmation_patterns,
+                "max_action_retries": self.max_action_retries,
+                "max_parse_retries": self.max_parse_retries,
+                "share_chain_context": self.share_chain_context,
+                "sensitive_policy": format!("{:?}", self.sensitive_policy),
+                "wake_screen": self.wake_screen,
+                "unlock_pin": self.unlock_pin.is_some(),
+                "auto_dismiss_dialogs": self.auto_dismiss_dialogs,
+                "screenshot_encoding": self.screenshot_encoding.map(|e| format!("{:?}", e)),
+                "allowed_actions": self.allowed_actions,
+                "plan_only": self.plan_only,
+                "include_previous_screenshot": self.include_previous_screenshot,
+                "max_context_bytes": self.max_context_bytes,
+                "fallback_screen_size": self.fallback_screen_size,
+                "settle_frames": self.settle_frames,
+                "settle_timeout_ms": self.settle_timeout.as_millis() as u64,
+                "pre_action_delay_ms": self.pre_action_delay.as_millis() as u64,
+                "coordinate_scale": self.coordinate_scale,
+            },
+            "timing": &*TIMING_CONFIG,
+        })
+    }
+
+    /// Write the resolved settings of a run to `path` as JSON, e.g. for a bug report
+    ///
+    /// See [`effective_config`](Self::effective_config) for what is included.
+    pub fn dump_effective_config(
+        &self,
+        model_config: &ModelConfig,
+        path: impl AsRef<Path>,
+    ) -> Result<()> {
+        let config = self.effective_config(model_config);
+        let json = serde_json::to_string_pretty(&config).map_err(|e| {
+            crate::error::AdbError::ParseError(format!("Failed to serialize config: {}", e))
+        })?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
 }
 
+/// Callback type for observing each finished step
+pub type StepCallback = Box<dyn Fn(&StepResult) + Send + Sync>;
+
 /// Result of a single agent step
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StepResult {
     pub success: bool,
     pub finished: bool,
     pub action: Option<HashMap<String, serde_json::Value>>,
+    /// Human-readable summary of the action, e.g. `Tap at (540, 960)`
+    pub action_summary: Option<String>,
     pub thinking: String,
     pub message: Option<String>,
 }
 
+/// Why a task run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    /// The model finished the task with `finish`
+    Completed,
+    /// The task ended unsuccessfully, e.g. the model reported it impossible
+    /// with the `Error` action or the user cancelled a sensitive action
+    Failed,
+    /// The step budget ran out first
+    MaxSteps,
+}
+
+/// Outcome of running a single task
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunOutcome {
+    /// The task as given by the caller
+    pub task: String,
+    /// Final message from the agent
+    pub message: String,
+    /// Whether the last executed action succeeded
+    pub success: bool,
+    /// Whether the task finished (false when max steps were reached)
+    pub finished: bool,
+    /// Why the task ended
+    pub finished_reason: FinishReason,
+    /// Number of steps executed
+    pub steps: usize,
+    /// Structured result from `finish(message=.., data={..})`, if the model gave one
+    pub result_data: Option<serde_json::Value>,
+}
+
+/// Summarize earlier chained tasks for the prompt of the next one
+fn format_previous_results(outcomes: &[RunOutcome]) -> String {
+    let mut text = String::from("Results of previous tasks:");
+    for (i, outcome) in outcomes.iter().enumerate() {
+        text.push_str(&format!(
+            "\n{}. {}: {}",
+            i + 1,
+            outcome.task,
+            outcome.message
+        ));
+    }
+    text
+}
+
+/// Serialized size of a conversation in bytes, as sent to the model
+fn context_bytes(context: &[ChatCompletionRequestMessage]) -> usize {
+    context
+        .iter()
+        .map(|m| serde_json::to_string(m).map(|s| s.len()).unwrap_or(0))
+        .sum()
+}
+
+/// Shrink `context` to at most `max_bytes`, returning how many messages were dropped
+///
+/// Images go first (except in the latest message), then the oldest messages
+/// after the system prompt and task prompt. The latest message is always kept,
+/// so the result can still exceed the limit. `screenshot_paths` is kept in
+/// step with the remaining user messages.
+fn prune_context(
+    context: &mut Vec<ChatCompletionRequestMessage>,
+    screenshot_paths: &mut Vec<Option<PathBuf>>,
+    max_bytes: usize,
+) -> usize {
+    if context.len() < 2 || context_bytes(context) <= max_bytes {
+        return 0;
+    }
+
+    let last = context.len() - 1;
+    for message in &mut context[..last] {
+        *message = MessageBuilder::remove_images_from_message(message.clone());
+    }
+
+    // Keep everything up to and including the first user message (the task),
+    // whose screenshot is `screenshot_paths[0]`
+    let is_user =
+        |m: &ChatCompletionRequestMessage| matches!(m, ChatCompletionRequestMessage::User(_));
+    let start = match context.iter().position(is_user) {
+        Some(task) => task + 1,
+        None => return 0,
+    };
+
+    let mut dropped = 0;
+    while start < context.len() - 1 && context_bytes(context) > max_bytes {
+        if is_user(&context.remove(start)) && screenshot_paths.len() > 1 {
+            screenshot_paths.remove(1);
+        }
+        dropped += 1;
+    }
+    dropped
+}
+
+/// Map the coordinates of an action given on a zoomed view onto the screen
+fn map_from_region(action: &mut HashMap<String, serde_json::Value>, region: RelativeRegion) {
+    let map_point = |value: &mut serde_json::Value| {
+        let coords: Vec<i64> = value
+            .as_array()
+            .map(|values| values.iter().filter_map(|v| v.as_i64()).collect())
+            .unwrap_or_default();
+        if let [x, y, ..] = coords[..] {
+            let (x, y) = region.to_screen((x, y));
+            *value = serde_json::json!([x, y]);
+        }
+    };
+
+    for key in ["element", "start", "end"] {
+        if let Some(value) = action.get_mut(key) {
+            map_point(value);
+        }
+    }
+    if let Some(points) = action.get_mut("points").and_then(|v| v.as_array_mut()) {
+        points.iter_mut().for_each(map_point);
+    }
+
+    // Offsets are scaled like distances, not moved like points
+    let scales = [
+        ("dx", region.right - region.left),
+        ("dy", region.bottom - region.top),
+    ];
+    for (key, span) in scales {
+        if let Some(offset) = action.get(key).and_then(|v| v.as_i64()) {
+            action.insert(key.to_string(), serde_json::json!(offset * span / 1000));
+        }
+    }
+}
+
+/// Result of an action that was planned but not executed
+fn planned_result(action: &HashMap<String, serde_json::Value>) -> ActionResult {
+    let name = action.get("action").and_then(|v| v.as_str());
+    if action.get("_metadata").and_then(|v| v.as_str()) == Some("finish") {
+        let message = action.get("message").and_then(|v| v.as_str());
+        ActionResult::finish(message.map(|s| s.to_string()))
+    } else if matches!(name, Some("Error" | "Impossible")) {
+        handle_error(action)
+    } else {
+        ActionResult::success()
+    }
+}
+
 /// AI-powered agent for automating Android phone interactions
 ///
 /// The agent uses a vision-language model to understand screen content
@@ -108,9 +770,26 @@ pub struct PhoneAgent {
     agent_config: AgentConfig,
     model_client: ModelClient,
     action_handler: ActionHandler,
+    device: Arc<dyn DeviceController>,
     context: Vec<ChatCompletionRequestMessage>,
     step_count: usize,
     screenshot_saver: Option<ScreenshotSaver>,
+    /// Feedback about the previous action to include in the next prompt
+    action_feedback: Option<String>,
+    /// Caller-provided hint for the next step, see [`PhoneAgent::set_step_hint`]
+    step_hint: Option<String>,
+    /// Saved screenshot for each user message in `context`, if any
+    screenshot_paths: Vec<Option<PathBuf>>,
+    /// Screenshot of the previous step, kept when it is sent along with the next
+    previous_screenshot: Option<String>,
+    /// Latency samples of the current run
+    metrics: MetricsRecorder,
+    /// Screen size reported by the device and whether it was read for a landscape frame
+    ///
+    /// Fetched on the first action and again when the frame's orientation changes.
+    screen_size: Option<((u32, u32), bool)>,
+    /// Called after each step of [`run`](Self::run) and its variants
+    step_callback: Option<StepCallback>,
 }
 
 impl PhoneAgent {
@@ -126,32 +805,112 @@ impl PhoneAgent {
         agent_config: Option<AgentConfig>,
         confirmation_callback: Option<ConfirmationCallback>,
         takeover_callback: Option<TakeoverCallback>,
+    ) -> Result<Self> {
+        let mut device = GlobalDeviceController::new();
+        if let Some(config) = &agent_config {
+            if let Some(display_id) = config.display_id {
+                device = device.with_display_id(display_id);
+            }
+            if let Some(encoding) = config.screenshot_encoding {
+                device = device.with_screenshot_encoding(encoding);
+            }
+            if let Some((width, height)) = config.fallback_screen_size {
+                device = device.with_fallback_screen_size(width, height);
+            }
+        }
+
+        Self::with_device(
+            model_config,
+            agent_config,
+            confirmation_callback,
+            takeover_callback,
+            Arc::new(device),
+        )
+        .await
+    }
+
+    /// Create a new PhoneAgent that drives the given device controller
+    ///
+    /// Useful for testing the agent loop offline with a mock device that
+    /// returns canned screenshots and records input events.
+    pub async fn with_device(
+        model_config: Option<ModelConfig>,
+        agent_config: Option<AgentConfig>,
+        confirmation_callback: Option<ConfirmationCallback>,
+        takeover_callback: Option<TakeoverCallback>,
+        device: Arc<dyn DeviceController>,
     ) -> Result<Self> {
         let model_config = model_config.unwrap_or_default();
         let agent_config = agent_config.unwrap_or_default();
 
-        let model_client = ModelClient::new(model_config.clone());
-        let action_handler = ActionHandler::new(
+        let model_client = ModelClient::new(model_config.clone()).map_err(|e| {
+            crate::error::AdbError::CommandFailed(format!("Invalid model config: {}", e))
+        })?;
+        let mut action_handler = ActionHandler::new(
             agent_config.device_id.clone(),
             confirmation_callback,
             takeover_callback,
-        );
+        )
+        .with_device(device.clone())
+        .with_text_focus_check(agent_config.check_text_focus)
+        .with_keyboard_dismiss(agent_config.dismiss_keyboard)
+        .with_type_confirmation(&agent_config.type_confirmation_patterns)?;
+        if let Some(allowed) = agent_config.allowed_actions.clone() {
+            action_handler = action_handler.with_allowed_actions(allowed);
+        }
+
 
         // Initialize screenshot saver if directory is configured
         let screenshot_saver = if let Some(ref dir) = agent_config.screenshot_dir {
-            Some(ScreenshotSaver::new(dir).await?)
+            if agent_config.compress_sessions {
+                Some(ScreenshotSaver::new_compressed(dir).await?)
+            } else {
+                Some(ScreenshotSaver::new(dir).await?)
+            }
         } else {
             None
         };
 
-        Ok(Self {
+        let agent = Self {
             model_config,
             agent_config,
             model_client,
             action_handler,
+            device,
             context: Vec::new(),
             step_count: 0,
             screenshot_saver,
+            action_feedback: None,
+            step_hint: None,
+            screenshot_paths: Vec::new(),
+            previous_screenshot: None,
+            metrics: MetricsRecorder::default(),
+            screen_size: None,
+            step_callback: None,
+        };
+
+        // A failed warm-up only costs latency; the first step reports real errors
+        if agent.agent_config.warmup {
+            if let Err(e) = agent.warmup().await {
+                warn!(error = %e, "model warm-up failed");
+            }
+        }
+
+        Ok(agent)
+    }
+
+    /// Prime the model server with a tiny request
+    ///
+    /// Sends the system prompt with the real model parameters and a one-token
+    /// limit, so the first step of a task doesn't pay for loading the model.
+    pub async fn warmup(&self) -> Result<()> {
+        let messages = vec![
+            MessageBuilder::create_system_message(&self.agent_config.get_system_prompt()),
+            MessageBuilder::create_user_message("Hi", None),
+        ];
+
+        self.model_client.warmup(messages).await.map_err(|e| {
+            crate::error::AdbError::CommandFailed(format!("Model warm-up failed: {}", e))
         })
     }
 
@@ -163,160 +922,456 @@ impl PhoneAgent {
     /// # Returns
     /// Final message from the agent
     pub async fn run(&mut self, task: &str) -> Result<String> {
-        self.context.clear();
-        self.step_count = 0;
-
-        // First step with user prompt
-        let result = self.execute_step(Some(task), true).await?;
-
-        if result.finished {
-            return Ok(result.message.unwrap_or_else(|| "Task completed".to_string()));
-        }
+        self.clear_context();
+        self.action_feedback = None;
+        self.metrics.clear();
 
-        // Continue until finished or max steps reached
-        while self.step_count < self.agent_config.max_steps {
-            let result = self.execute_step(None, false).await?;
+        let outcome = self.run_task(task).await?;
+        Ok(outcome.message)
+    }
 
-            if result.finished {
-                return Ok(result.message.unwrap_or_else(|| "Task completed".to_string()));
-            }
-        }
+    /// Continue the last run with a follow-up instruction
+    ///
+    /// Unlike [`run`](Self::run), the conversation so far is kept, so the model
+    /// picks up from the current screen with what it already knows, e.g.
+    /// "now share it with Alice". The follow-up gets a fresh step budget.
+    ///
+    /// # Returns
+    /// Final message from the agent
+    pub async fn continue_run(&mut self, follow_up: &str) -> Result<String> {
+        self.action_feedback = None;
 
-        Ok("Max steps reached".to_string())
+        let outcome = self.run_task(follow_up).await?;
+        Ok(outcome.message)
     }
 
-    /// Execute a single step of the agent
+    /// Run several tasks in sequence, letting later tasks see earlier results
     ///
-    /// Useful for manual control or debugging.
-    ///
-    /// # Arguments
-    /// * `task` - Task description (only needed for first step)
+    /// Each task gets its own step budget. The final messages of completed
+    /// tasks are passed on to later tasks; with
+    /// [`AgentConfig::with_share_chain_context`] the full conversation is kept
+    /// instead. Tasks run in order regardless of earlier outcomes.
     ///
     /// # Returns
-    /// StepResult with step details
-    pub async fn step(&mut self, task: Option<&str>) -> Result<StepResult> {
-        let is_first = self.context.is_empty();
+    /// One outcome per task, in order
+    pub async fn run_chain(&mut self, tasks: &[&str]) -> Result<Vec<RunOutcome>> {
+        self.clear_context();
+        self.action_feedback = None;
+        self.metrics.clear();
 
-        if is_first && task.is_none() {
-            return Err(crate::error::AdbError::CommandFailed(
-                "Task is required for the first step".to_string(),
-            ));
+        let mut outcomes: Vec<RunOutcome> = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            let prompt = if self.agent_config.share_chain_context || outcomes.is_empty() {
+                task.to_string()
+            } else {
+                self.clear_context();
+                format!("{}\n\n{}", task, format_previous_results(&outcomes))
+            };
+
+            let mut outcome = self.run_task(&prompt).await?;
+            outcome.task = task.to_string();
+            outcomes.push(outcome);
         }
 
-        self.execute_step(task, is_first).await
+        Ok(outcomes)
+    }
+
+    /// Run a task until it finishes or the step budget is exhausted
+    #[instrument(name = "task", skip(self), fields(steps = field::Empty))]
+    async fn run_task(&mut self, task: &str) -> Result<RunOutcome> {
+        self.step_count = 0;
+
+        if self.agent_config.wake_screen {
+            self.ensure_ready(None).await?;
+        } else {
+            self.device
+                .check_device_ready(self.agent_config.device_id.as_deref())
+                .await?;
+        }
+
+        // First step with user prompt
+        let mut result = self.execute_step(Some(task), true, None).await?;
+        self.notify_step(&result);
+
+        // Continue until finished or max steps reached
+        while !result.finished && self.step_count < self.agent_config.max_steps {
+            result = self.execute_step(None, false, None).await?;
+            self.notify_step(&result);
+        }
+
+        Span::current().record("steps", self.step_count);
+        info!(
+            finished = result.finished,
+            success = result.success,
+            "task ended"
+        );
+
+        let result_data = match &result.action {
+            Some(action) if result.finished => action.get("data").cloned(),
+            _ => None,
+        };
+
+        let finished_reason = match (result.finished, result.success) {
+            (false, _) => FinishReason::MaxSteps,
+            (true, true) => FinishReason::Completed,
+            (true, false) => FinishReason::Failed,
+        };
+
+        let message = if result.finished {
+            result
+                .message
+                .unwrap_or_else(|| "Task completed".to_string())
+        } else {
+            "Max steps reached".to_string()
+        };
+
+        Ok(RunOutcome {
+            task: task.to_string(),
+            message,
+            success: result.success,
+            finished: result.finished,
+            finished_reason,
+            steps: self.step_count,
+            result_data,
+        })
+    }
+
+    /// Make sure the device is awake and unlocked
+    ///
+    /// Turns the screen on and swipes away a swipe lock; `pin`, or else
+    /// [`AgentConfig::unlock_pin`], is entered on a PIN lock screen. Does
+    /// nothing if the device is already ready. Fails with
+    /// [`AdbError::DeviceUnavailable`](crate::error::AdbError::DeviceUnavailable)
+    /// if the device is e.g. in recovery mode.
+    pub async fn ensure_ready(&self, pin: Option<&str>) -> Result<()> {
+        let device_id = self.agent_config.device_id.as_deref();
+        let pin = pin.or(self.agent_config.unlock_pin.as_ref().map(UnlockPin::as_str));
+        self.device.check_device_ready(device_id).await?;
+        self.device.wake_device(device_id, pin).await
+    }
+
+    /// Execute a single step of the agent
+    ///
+    /// Useful for manual control or debugging.
+    ///
+    /// # Arguments
+    /// * `task` - Task description (only needed for first step)
+    ///
+    /// # Returns
+    /// StepResult with step details
+    pub async fn step(&mut self, task: Option<&str>) -> Result<StepResult> {
+        let is_first = self.context.is_empty();
+
+        if is_first && task.is_none() {
+            return Err(crate::error::AdbError::CommandFailed(
+                "Task is required for the first step".to_string(),
+            ));
+        }
+
+        self.execute_step(task, is_first, None).await
+    }
+
+    /// Execute a single step on a screenshot captured elsewhere
+    ///
+    /// Skips the device capture and uses `screenshot` and `current_app` as the
+    /// current screen, e.g. frames from scrcpy or a cloud device. The action is
+    /// still executed on the agent's device unless
+    /// [`AgentConfig::with_plan_only`] is set.
+    pub async fn step_with_screenshot(
+        &mut self,
+        task: Option<&str>,
+        screenshot: Screenshot,
+        current_app: &str,
+    ) -> Result<StepResult> {
+        let is_first = self.context.is_empty();
+
+        if is_first && task.is_none() {
+            return Err(crate::error::AdbError::CommandFailed(
+                "Task is required for the first step".to_string(),
+            ));
+        }
+
+        self.execute_step(task, is_first, Some((screenshot, current_app.to_string())))
+            .await
+    }
+
+    /// Ask the model what it would do on a saved screenshot, without a device
+    ///
+    /// Runs the first step of `task` on the image at `image_path` with
+    /// `current_app` as the foreground app and returns the parsed action
+    /// without executing it. The conversation in progress, if any, is left
+    /// as it was: a pending step hint is kept for the next real step, and
+    /// nothing is saved to the screenshot directory or counted in
+    /// [`PhoneAgent::metrics`]. Useful for iterating on prompts or labelling
+    /// screenshots.
+    pub async fn plan_from_image(
+        &mut self,
+        task: &str,
+        image_path: impl AsRef<Path>,
+        current_app: &str,
+    ) -> Result<StepResult> {
+        let screenshot = Screenshot::load(image_path)?;
+
+        let context = std::mem::take(&mut self.context);
+        let screenshot_paths = std::mem::take(&mut self.screenshot_paths);
+        let previous_screenshot = self.previous_screenshot.take();
+        let action_feedback = self.action_feedback.take();
+        let step_hint = self.step_hint.take();
+        let screenshot_saver = self.screenshot_saver.take();
+        let metrics = std::mem::take(&mut self.metrics);
+        let step_count = self.step_count;
+        let plan_only = std::mem::replace(&mut self.agent_config.plan_only, true);
+
+        let screen = Some((screenshot, current_app.to_string()));
+        let result = self.execute_step(Some(task), true, screen).await;
+
+        self.context = context;
+        self.screenshot_paths = screenshot_paths;
+        self.previous_screenshot = previous_screenshot;
+        self.action_feedback = action_feedback;
+        self.step_hint = step_hint;
+        self.screenshot_saver = screenshot_saver;
+        self.metrics = metrics;
+        self.step_count = step_count;
+        self.agent_config.plan_only = plan_only;
+
+        result
     }
 
     /// Reset the agent state for a new task
     pub async fn reset(&mut self) {
-        self.context.clear();
+        self.clear_context();
         self.step_count = 0;
+        self.action_feedback = None;
+        self.metrics.clear();
 
         // Create a new session directory for screenshots in interactive mode
         if let Some(ref mut saver) = self.screenshot_saver {
             if let Err(e) = saver.new_session().await {
-                eprintln!("Warning: Failed to create new screenshot session: {}", e);
+                warn!(error = %e, "failed to create a new screenshot session");
             }
         }
     }
 
     /// Execute a single step of the agent loop
+    ///
+    /// The screen is captured from the device unless `screen` (a screenshot and
+    /// the current app) is given.
+    #[instrument(
+        name = "step",
+        skip_all,
+        fields(step = self.step_count + 1, action = field::Empty, duration_ms = field::Empty)
+    )]
     async fn execute_step(
         &mut self,
         user_prompt: Option<&str>,
         is_first: bool,
+        screen: Option<(Screenshot, String)>,
     ) -> Result<StepResult> {
         self.step_count += 1;
+        let started = Instant::now();
+        // A caller-supplied frame can't be compared with a fresh device capture
+        let captured = screen.is_none();
 
-        // Capture current screen state
-        let factory = get_device_factory().read().await;
-        let screenshot = factory
-            .get_screenshot(self.agent_config.device_id.as_deref(), 10)
-            .await?;
-        let current_app = factory
-            .get_current_app(self.agent_config.device_id.as_deref())
-            .await?;
-        drop(factory);
+        let (screenshot, current_app) = match screen {
+            Some(screen) => screen,
+            None => match self.capture_screen().await? {
+                Some(screen) => screen,
+                None => {
+                    warn!("sensitive screen detected, aborting task");
+                    let msgs = get_messages(self.agent_config.lang);
+                    return Ok(StepResult {
+                        success: false,
+                        finished: true,
+                        action: None,
+                        action_summary: None,
+                        thinking: String::new(),
+                        message: Some(
+                            msgs.get("sensitive_screen_abort")
+                                .copied()
+                                .unwrap_or("Sensitive screen detected, task aborted")
+                                .to_string(),
+                        ),
+                    });
+                }
+            },
+        };
 
         // Save screenshot to disk if configured
+        let mut screenshot_path = None;
         if let Some(ref mut saver) = self.screenshot_saver {
-            if let Err(e) = saver.save(&screenshot.base64_data).await {
-                eprintln!("Warning: Failed to save screenshot: {}", e);
+            match saver.save(&screenshot.base64_data).await {
+                Ok(path) => screenshot_path = Some(path),
+                Err(e) => warn!(error = %e, "failed to save screenshot"),
             }
         }
 
+        let screen_info = MessageBuilder::build_screen_info(
+            &self
+                .screen_info(current_app, screenshot.width, screenshot.height)
+                .await,
+        );
+
+        let strategy = if is_first {
+            &self.agent_config.first_step_image
+        } else {
+            &self.agent_config.step_image
+        };
+        let model_view = self.model_view(&screenshot)?;
+        let (image, detail) = self.image_for_model(&model_view, strategy);
+
         // Build messages
         if is_first {
-            self.context.push(MessageBuilder::create_system_message(
-                &self.agent_config.get_system_prompt(),
-            ));
+            // A chained or continued task keeps the existing system prompt
+            if self.context.is_empty() {
+                self.context.push(MessageBuilder::create_system_message(
+                    &self.agent_config.get_system_prompt(),
+                ));
+            }
 
-            let screen_info = MessageBuilder::build_screen_info(&current_app);
-            let text_content = format!("{}\n\n{}", user_prompt.unwrap_or(""), screen_info);
+            let mut text_content = format!("{}\n\n{}", user_prompt.unwrap_or(""), screen_info);
+            if let Some(hint) = self.step_hint.take() {
+                text_content.push_str(&format!("\n\n{}", hint));
+            }
 
-            self.context.push(MessageBuilder::create_user_message(
-                &text_content,
-                Some(&screenshot.base64_data),
-            ));
+            self.context
+                .push(MessageBuilder::create_user_message_with_detail(
+                    &text_content,
+                    Some(&image),
+                    detail,
+                ));
         } else {
-            let screen_info = MessageBuilder::build_screen_info(&current_app);
-            let text_content = format!("** Screen Info **\n\n{}", screen_info);
+            let mut text_content = format!("** Screen Info **\n\n{}", screen_info);
+            if let Some(feedback) = self.action_feedback.take() {
+                text_content.push_str(&format!("\n\n{}", feedback));
+            }
+            if let Some(hint) = self.step_hint.take() {
+                text_content.push_str(&format!("\n\n{}", hint));
+            }
 
-            self.context.push(MessageBuilder::create_user_message(
-                &text_content,
-                Some(&screenshot.base64_data),
-            ));
+            // The previous screen goes first, followed by the current one
+            let images: Vec<ImageSource<'_>> = self
+                .previous_screenshot
+                .iter()
+                .chain(Some(&image))
+                .map(|data| ImageSource::Base64 {
+                    data,
+                    mime_type: image_mime_type(data),
+                })
+                .collect();
+
+            self.context
+                .push(MessageBuilder::create_user_message_with_images(
+                    &text_content,
+                    &images,
+                    detail,
+                ));
+        }
+
+        if self.agent_config.include_previous_screenshot {
+            // The previous screen is sent with later steps, so it uses their size
+            let previous = if is_first {
+                self.image_for_model(&model_view, &self.agent_config.step_image)
+                    .0
+            } else {
+                image
+            };
+            self.previous_screenshot = Some(previous);
+        }
+
+        self.screenshot_paths.push(screenshot_path);
+
+        if let Some(max_bytes) = self.agent_config.max_context_bytes {
+            let dropped = prune_context(&mut self.context, &mut self.screenshot_paths, max_bytes);
+            if dropped > 0 {
+                info!(dropped, "pruned oldest messages to fit the context limit");
+            }
         }
 
         // Get model response
         let msgs = get_messages(self.agent_config.lang);
-        if self.agent_config.verbose {
-            println!("\n{}", "=".repeat(50));
-            println!(
-                "\u{1F4AD} {}:",
-                msgs.get("thinking").copied().unwrap_or("Thinking")
-            );
-            println!("{}", "-".repeat(50));
-        }
+        let verbose = self.agent_config.verbose;
+        step_event!(verbose, step = self.step_count, "requesting model response");
 
-        let response = match self.model_client.request(self.context.clone()).await {
-            Ok(r) => r,
-            Err(e) => {
-                if self.agent_config.verbose {
-                    eprintln!("Model error: {}", e);
+        let mut messages = self.context.clone();
+        let mut nudges = 0;
+        let mut reformats = 0;
+        let (response, parsed) = loop {
+            let response = match self.model_client.request(messages.clone()).await {
+                Ok(r) => {
+                    self.metrics.record_model(&r);
+                    r
                 }
-                return Ok(StepResult {
-                    success: false,
-                    finished: true,
-                    action: None,
-                    thinking: String::new(),
-                    message: Some(format!("Model error: {}", e)),
-                });
+                Err(e) => {
+                    warn!(error = %e, "model request failed");
+                    return Ok(StepResult {
+                        success: false,
+                        finished: true,
+                        action: None,
+                        action_summary: None,
+                        thinking: String::new(),
+                        message: Some(format!("Model error: {}", e)),
+                    });
+                }
+            };
+
+            // Re-prompt when the model only produced reasoning without an action
+            if !response.action_found && nudges < self.agent_config.max_action_retries {
+                nudges += 1;
+                warn!("no action in model response, asking the model to output one");
+                messages.push(MessageBuilder::create_assistant_message(
+                    &response.raw_content,
+                ));
+                messages.push(MessageBuilder::create_user_message(
+                    msgs.get("no_action_nudge")
+                        .copied()
+                        .unwrap_or("Please output an action."),
+                    None,
+                ));
+                continue;
             }
+
+            // Ask the model to reformat an action that couldn't be parsed
+            let parsed = parse_action(&response.action);
+            if let Err(e) = &parsed {
+                if reformats < self.agent_config.max_parse_retries {
+                    reformats += 1;
+                    warn!(error = %e, "failed to parse action, asking the model to reformat");
+                    messages.push(MessageBuilder::create_assistant_message(
+                        &response.raw_content,
+                    ));
+                    messages.push(MessageBuilder::create_user_message(
+                        &format!(
+                            "{}\n{}",
+                            msgs.get("parse_error_nudge")
+                                .copied()
+                                .unwrap_or("Please output a valid action."),
+                            e
+                        ),
+                        None,
+                    ));
+                    continue;
+                }
+            }
+            break (response, parsed);
         };
 
         // Parse action from response
-        let action = match parse_action(&response.action) {
+        let mut action = match parsed {
             Ok(a) => a,
             Err(_) => {
-                if self.agent_config.verbose {
-                    eprintln!("Failed to parse action, treating as finish");
-                }
+                warn!("failed to parse action, treating it as finish");
                 finish_action(Some(&response.action))
             }
         };
+        normalize_coordinates(&mut action, self.agent_config.coordinate_scale);
 
-        if self.agent_config.verbose {
-            println!("{}", "-".repeat(50));
-            println!(
-                "\u{1F3AF} {}:",
-                msgs.get("action").copied().unwrap_or("Action")
-            );
-            println!(
-                "{}",
-                serde_json::to_string_pretty(&action).unwrap_or_else(|_| format!("{:?}", action))
-            );
-            println!("{}\n", "=".repeat(50));
-        }
+        step_event!(
+            verbose,
+            thinking = %response.thinking,
+            action = %serde_json::Value::from_iter(action.clone()),
+            "model chose an action"
+        );
 
         // Remove image from context to save space
         if let Some(last) = self.context.pop() {
@@ -324,48 +1379,87 @@ impl PhoneAgent {
                 .push(MessageBuilder::remove_images_from_message(last));
         }
 
+        // Let the UI finish painting before acting on what the model saw
+        let pre_action_delay = self.agent_config.pre_action_delay;
+        if !self.agent_config.plan_only && !pre_action_delay.is_zero() {
+            tokio::time::sleep(pre_action_delay).await;
+        }
+
         // Execute action
-        let result = self
-            .action_handler
-            .execute(&action, screenshot.width, screenshot.height)
-            .await;
+        let action_started = Instant::now();
+        let mut result = if self.agent_config.plan_only {
+            planned_result(&action)
+        } else {
+            let (width, height) = self.input_size(&screenshot).await;
+            let result = self.action_handler.execute(&action, width, height).await;
+            match result.refine_region {
+                Some(region) => {
+                    let answer = self
+                        .model_config
+                        .format_assistant_message(&response.thinking, &response.action);
+                    self.refine_action(&model_view, region, &answer, (width, height))
+                        .await
+                }
+                None => result,
+            }
+        };
+        self.metrics
+            .record_step(action_started.elapsed().as_secs_f64());
+
+        // Tell the model why its action failed
+        if !result.success {
+            self.action_feedback = result.message.clone();
+            self.save_logcat().await;
+        }
+
+        // Verify that a tap changed the screen
+        if self.agent_config.verify_taps
+            && captured
+            && !self.agent_config.plan_only
+            && result.success
+            && action.get("action").and_then(|v| v.as_str()) == Some("Tap")
+            && self.tap_missed(&screenshot).await
+        {
+            let note = get_messages(self.agent_config.lang)
+                .get("tap_no_change")
+                .copied()
+                .unwrap_or_default()
+                .to_string();
+            warn!("screen did not change after tap");
+            self.action_feedback = Some(note.clone());
+            result.message = Some(note);
+        }
 
         // Add assistant response to context
         self.context.push(MessageBuilder::create_assistant_message(
-            &format!(
-                "<think>{}</think><answer>{}</answer>",
-                response.thinking, response.action
-            ),
+            &self
+                .model_config
+                .format_assistant_message(&response.thinking, &response.action),
         ));
 
         // Check if finished
         let finished = action.get("_metadata").and_then(|v| v.as_str()) == Some("finish")
             || result.should_finish;
 
-        if finished && self.agent_config.verbose {
-            let action_msg = action
-                .get("message")
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
-            let display_msg = result
+        if finished {
+            let message = result
                 .message
-                .as_ref()
-                .or(action_msg.as_ref())
-                .map(|s| s.as_str())
-                .unwrap_or(msgs.get("done").copied().unwrap_or("Done"));
-
-            println!("\n\u{1F389} {}", "=".repeat(48));
-            println!(
-                "\u{2705} {}: {}",
-                msgs.get("task_completed").copied().unwrap_or("Task Completed"),
-                display_msg
-            );
-            println!("{}\n", "=".repeat(50));
+                .as_deref()
+                .or_else(|| action.get("message").and_then(|v| v.as_str()))
+                .unwrap_or_default();
+            step_event!(verbose, success = result.success, message, "task finished");
         }
 
+        let action_summary = describe_action(&action, screenshot.width, screenshot.height);
+        let span = Span::current();
+        span.record("action", action_summary.as_str());
+        span.record("duration_ms", started.elapsed().as_millis() as u64);
+        info!(success = result.success, finished, "step completed");
+
         Ok(StepResult {
             success: result.success,
             finished,
+            action_summary: Some(action_summary),
             action: Some(action.clone()),
             thinking: response.thinking,
             message: result.message.or_else(|| {
@@ -377,6 +1471,343 @@ impl PhoneAgent {
         })
     }
 
+    /// Get the system prompt that the next run will use
+    pub fn system_prompt(&self) -> String {
+        self.agent_config.get_system_prompt()
+    }
+
+    /// Replace the system prompt
+    ///
+    /// Takes effect on the next [`run`](Self::run) or after [`reset`](Self::reset);
+    /// a conversation in progress keeps its original prompt.
+    pub fn set_system_prompt(&mut self, prompt: impl Into<String>) {
+        self.agent_config.system_prompt = Some(prompt.into());
+    }
+
+    /// Add a hint to the next step's user message, e.g. "the item you want is red"
+    ///
+    /// The hint is appended after the screen info of the next step only and
+    /// then cleared; set it again to guide later steps. `None` removes a hint
+    /// that hasn't been sent yet.
+    pub fn set_step_hint(&mut self, hint: Option<String>) {
+        self.step_hint = hint;
+    }
+
+    /// Confirm sensitive actions with an async callback, e.g. a GUI dialog
+    ///
+    /// Replaces the confirmation callback passed to [`new`](Self::new).
+    pub fn set_async_confirmation_callback(&mut self, callback: AsyncConfirmationCallback) {
+        self.action_handler
+            .set_async_confirmation_callback(callback);
+    }
+
+    /// Observe each step of a run, e.g. to stream progress to another process
+    ///
+    /// Only steps taken by [`run`](Self::run) and its variants are reported;
+    /// [`step`](Self::step) returns its result directly.
+    pub fn set_step_callback(&mut self, callback: StepCallback) {
+        self.step_callback = Some(callback);
+    }
+
+    fn notify_step(&self, result: &StepResult) {
+        if let Some(callback) = &self.step_callback {
+            callback(result);
+        }
+    }
+
+    /// Render the conversation so far as Markdown
+    ///
+    /// Includes the system prompt and each step's thinking and action.
+    /// Screenshots saved by the screenshot saver are linked, not inlined.
+    pub fn export_transcript(&self) -> String {
+        render_markdown(&self.context, &self.screenshot_paths)
+    }
+
+    fn clear_context(&mut self) {
+        self.context.clear();
+        self.screenshot_paths.clear();
+        self.previous_screenshot = None;
+    }
+
+    /// Size to map the model's relative coordinates onto for `screenshot`
+    ///
+    /// Uses the device's screen size when the frame is stretched away from
+    /// it, and the frame size otherwise or if the screen size can't be read.
+    async fn input_size(&mut self, screenshot: &Screenshot) -> (u32, u32) {
+        let frame = (screenshot.width, screenshot.height);
+        let landscape = frame.0 > frame.1;
+        if self
+            .screen_size
+            .is_none_or(|(_, was_landscape)| was_landscape != landscape)
+        {
+            self.screen_size = match self
+                .device
+                .get_screen_size(self.agent_config.device_id.as_deref())
+                .await
+            {
+                Ok(size) => Some((size, landscape)),
+                Err(e) => {
+                    warn!(error = %e, "Failed to read screen size, using screenshot size");
+                    None
+                }
+            };
+        }
+
+        let Some((screen, _)) = self.screen_size else {
+            return frame;
+        };
+        let size = reconcile_screen_size(frame, screen);
+        if size != frame {
+            debug!(?frame, ?size, "Screenshot size differs from screen size");
+        }
+        size
+    }
+
+    /// Ask the model again with a zoomed view of `region` and run its answer
+    ///
+    /// Used for the Zoom action. `answer` is the model's Zoom response; the
+    /// exchange is not kept in the context. Coordinates in the refined action
+    /// refer to the zoomed view and are mapped back onto the screen.
+    async fn refine_action(
+        &mut self,
+        screenshot: &Screenshot,
+        region: RelativeRegion,
+        answer: &str,
+        (width, height): (u32, u32),
+    ) -> ActionResult {
+        let (x, y, crop_width, crop_height) = region.to_pixels(screenshot.width, screenshot.height);
+        let zoomed = match crop_screenshot(screenshot, x, y, crop_width, crop_height) {
+            Ok(zoomed) => zoomed,
+            Err(e) => return ActionResult::failure(format!("Failed to zoom in: {}", e)),
+        };
+
+        let msgs = get_messages(self.agent_config.lang);
+        let mut messages = self.context.clone();
+        messages.push(MessageBuilder::create_assistant_message(answer));
+        messages.push(MessageBuilder::create_user_message_with_detail(
+            msgs.get("zoom_prompt")
+                .copied()
+                .unwrap_or("This is a zoomed-in view of the region you selected."),
+            Some(&zoomed.base64_data),
+            self.model_config.image_detail.clone(),
+        ));
+
+        let response = match self.model_client.request(messages).await {
+            Ok(r) => {
+                self.metrics.record_model(&r);
+                r
+            }
+            Err(e) => return ActionResult::failure(format!("Model error while zooming: {}", e)),
+        };
+        let mut refined = match parse_action(&response.action) {
+            Ok(action) => action,
+            Err(e) => return ActionResult::failure(e),
+        };
+        if refined.get("action").and_then(|v| v.as_str()) == Some("Zoom") {
+            return ActionResult::failure("Zoom cannot be used on a zoomed-in view");
+        }
+        normalize_coordinates(&mut refined, self.agent_config.coordinate_scale);
+
+        map_from_region(&mut refined, region);
+        debug!(action = ?refined, "executing refined action");
+        let mut result = self.action_handler.execute(&refined, width, height).await;
+        if result.message.is_none() {
+            result.message = Some(format!(
+                "Zoomed in, then: {}",
+                describe_action(&refined, width, height)
+            ));
+        }
+        result
+    }
+
+    /// The screenshot as the model sees it, after the configured preprocessor
+    ///
+    /// Fails rather than falling back to the unprocessed screenshot, so a
+    /// redaction error never sends the original screen.
+    fn model_view(&self, screenshot: &Screenshot) -> Result<Screenshot> {
+        match &self.agent_config.screenshot_preprocessor {
+            Some(preprocessor) => transform_screenshot(screenshot, |img| preprocessor.apply(img)),
+            None => Ok(screenshot.clone()),
+        }
+    }
+
+    /// Screenshot data and detail level to send to the model, per `strategy`
+    fn image_for_model(
+        &self,
+        screenshot: &Screenshot,
+        strategy: &ImageStrategy,
+    ) -> (String, ImageDetail) {
+        let detail = strategy
+            .detail
+            .clone()
+            .unwrap_or_else(|| self.model_config.image_detail.clone());
+        let data = match strategy.max_dimension {
+            Some(max_dimension) => match downscale_screenshot(screenshot, max_dimension) {
+                Ok(scaled) => scaled.base64_data,
+                Err(e) => {
+                    warn!(error = %e, "failed to downscale screenshot, sending it as captured");
+                    screenshot.base64_data.clone()
+                }
+            },
+            None => screenshot.base64_data.clone(),
+        };
+        (data, detail)
+    }
+
+    /// Collect the screen info for a step, as configured by
+    /// [`AgentConfig::screen_info_fields`]
+    async fn screen_info(&self, current_app: String, width: u32, height: u32) -> ScreenInfo {
+        let fields = self.agent_config.screen_info_fields;
+        let mut info = ScreenInfo::new(current_app);
+
+        if fields.screen_size {
+            info.screen_size = Some((width, height));
+        }
+        if fields.orientation {
+            let orientation = if width > height {
+                "landscape"
+            } else {
+                "portrait"
+            };
+            info.orientation = Some(orientation.to_string());
+        }
+        if fields.battery {
+            match self
+                .device
+                .get_battery_level(self.agent_config.device_id.as_deref())
+                .await
+            {
+                Ok(level) => info.battery_level = Some(level),
+                Err(e) => warn!(error = %e, "failed to read battery level"),
+            }
+        }
+        if fields.timestamp {
+            info.timestamp = Some(Local::now().format("%Y-%m-%d %H:%M:%S").to_string());
+        }
+
+        info
+    }
+
+    /// Capture the screen and foreground app from the device
+    ///
+    /// Applies [`AgentConfig::sensitive_policy`]; returns `None` when the task
+    /// should be aborted because of a sensitive screen.
+    async fn capture_screen(&self) -> Result<Option<(Screenshot, String)>> {
+        if self.agent_config.auto_dismiss_dialogs {
+            match self
+                .device
+                .dismiss_system_dialog(self.agent_config.device_id.as_deref())
+                .await
+            {
+                Ok(Some(dialog)) => info!(?dialog, "dismissed system dialog"),
+                Ok(None) => {}
+                Err(e) => warn!(error = %e, "failed to check for system dialogs"),
+            }
+        }
+
+        // Capture current screen state
+        let mut screenshot = self.capture_settled().await?;
+
+        if screenshot.is_sensitive {
+            let msgs = get_messages(self.agent_config.lang);
+            match self.agent_config.sensitive_policy {
+                SensitivePolicy::Blackout => {}
+                SensitivePolicy::Pause => {
+                    self.action_handler.request_takeover(
+                        msgs.get("sensitive_screen_takeover")
+                            .copied()
+                            .unwrap_or("Sensitive screen detected, please handle it manually"),
+                    );
+                    screenshot = self.capture_settled().await?;
+                }
+                SensitivePolicy::Abort => return Ok(None),
+            }
+        }
+        let current_app = self
+            .device
+            .get_current_app(self.agent_config.device_id.as_deref())
+            .await?;
+
+        Ok(Some((screenshot, current_app)))
+    }
+
+    /// Capture a screenshot once the screen has stopped changing
+    ///
+    /// With [`AgentConfig::settle_frames`] set, frames are captured until that
+    /// many in a row are alike. Gives up at [`AgentConfig::settle_timeout`] or
+    /// on a sensitive screen and returns the latest frame.
+    async fn capture_settled(&self) -> Result<Screenshot> {
+        let device_id = self.agent_config.device_id.as_deref();
+        let mut frame = self.device.get_screenshot(device_id, 10).await?;
+        let Some(settle_frames) = self.agent_config.settle_frames else {
+            return Ok(frame);
+        };
+
+        let deadline = tokio::time::Instant::now() + self.agent_config.settle_timeout;
+        let mut alike = 1;
+        while alike < settle_frames {
+            if frame.is_sensitive || tokio::time::Instant::now() >= deadline {
+                debug!(alike, "screen did not settle, using the latest frame");
+                break;
+            }
+
+            let next = self.device.get_screenshot(device_id, 10).await?;
+            // Frames that can't be compared count as changed
+            let settled = screenshot_diff(&frame, &next)
+                .map(|score| score < SETTLE_THRESHOLD)
+                .unwrap_or(false);
+            alike = if settled { alike + 1 } else { 1 };
+            frame = next;
+        }
+        Ok(frame)
+    }
+
+    /// Whether the screen looks the same as `before` a short while after a tap
+    ///
+    /// A failed capture or frames that can't be compared skip the check.
+    async fn tap_missed(&self, before: &Screenshot) -> bool {
+        tokio::time::sleep(TAP_VERIFY_DELAY).await;
+        let after = match self
+            .device
+            .get_screenshot(self.agent_config.device_id.as_deref(), 10)
+            .await
+        {
+            Ok(after) => after,
+            Err(e) => {
+                warn!(error = %e, "failed to capture the screen to verify a tap");
+                return false;
+            }
+        };
+
+        screenshot_diff(before, &after)
+            .map(|score| score < SETTLE_THRESHOLD)
+            .unwrap_or(false)
+    }
+
+    /// Save recent logcat output next to the current screenshot, if configured
+    async fn save_logcat(&self) {
+        let (Some(lines), Some(saver)) =
+            (self.agent_config.logcat_on_failure, &self.screenshot_saver)
+        else {
+            return;
+        };
+
+        let log = match self
+            .device
+            .capture_logcat(self.agent_config.device_id.as_deref(), lines)
+            .await
+        {
+            Ok(log) => log,
+            Err(e) => {
+                warn!(error = %e, "failed to capture logcat");
+                return;
+            }
+        };
+        if let Err(e) = saver.save_text("logcat", &log).await {
+            warn!(error = %e, "failed to save logcat");
+        }
+    }
+
     /// Get the current conversation context
     pub fn context(&self) -> &[ChatCompletionRequestMessage] {
         &self.context
@@ -387,6 +1818,26 @@ impl PhoneAgent {
         self.step_count
     }
 
+    /// Get the directory screenshots of the current run are saved to
+    ///
+    /// This is the timestamped session directory inside
+    /// [`AgentConfig::screenshot_dir`], or `None` if screenshots aren't saved.
+    /// With [`AgentConfig::compress_sessions`] it is the session's archive.
+    pub fn current_session_dir(&self) -> Option<&Path> {
+        self.screenshot_saver
+            .as_ref()
+            .map(|saver| saver.session_dir())
+    }
+
+    /// Get latency metrics of the current run
+    ///
+    /// Covers every step since the last [`run`](Self::run),
+    /// [`run_chain`](Self::run_chain) or [`reset`](Self::reset). Inference
+    /// and device action times are reported separately.
+    pub fn metrics(&self) -> RunMetrics {
+        self.metrics.summary()
+    }
+
     /// Get the model config
     pub fn model_config(&self) -> &ModelConfig {
         &self.model_config
@@ -396,11 +1847,25 @@ impl PhoneAgent {
     pub fn agent_config(&self) -> &AgentConfig {
         &self.agent_config
     }
+
+    /// Write the resolved settings of this agent to `path` as JSON
+    ///
+    /// See [`AgentConfig::effective_config`].
+    pub fn dump_effective_config(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.agent_config
+            .dump_effective_config(&self.model_config, path)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::model::{CompletionRequest, ModelError, ModelTransport, TextStream};
+    use futures::future::BoxFuture;
+    use futures::StreamExt;
+    use image::{Rgb, RgbImage};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
 
     #[test]
     fn test_agent_config_default() {
@@ -408,6 +1873,7 @@ mod tests {
         assert_eq!(config.max_steps, 100);
         assert_eq!(config.lang, Language::Chinese);
         assert!(config.verbose);
+        assert_eq!(config.max_action_retries, 1);
     }
 
     #[test]
@@ -424,12 +1890,576 @@ mod tests {
         assert!(!config.verbose);
     }
 
+    #[tokio::test]
+    async fn test_set_system_prompt() {
+        let agent_config = AgentConfig::new().with_lang(Language::English);
+        let mut agent = PhoneAgent::new(None, Some(agent_config), None, None)
+            .await
+            .unwrap();
+        assert_eq!(agent.system_prompt(), get_system_prompt(Language::English));
+
+        agent.set_system_prompt("Custom prompt");
+        assert_eq!(agent.system_prompt(), "Custom prompt");
+    }
+
+    /// Transport that answers every request with the same action
+    struct CannedTransport(&'static str);
+
+    impl ModelTransport for CannedTransport {
+        fn complete<'a>(
+            &'a self,
+            _request: CompletionRequest,
+        ) -> BoxFuture<'a, std::result::Result<String, ModelError>> {
+            Box::pin(async move { Ok(self.0.to_string()) })
+        }
+
+        fn stream<'a>(
+            &'a self,
+            _request: CompletionRequest,
+        ) -> BoxFuture<'a, std::result::Result<TextStream, ModelError>> {
+            let chunk: std::result::Result<String, ModelError> = Ok(self.0.to_string());
+            Box::pin(async move { Ok(futures::stream::iter([chunk]).boxed()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_plan_from_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("screen.png");
+        DynamicImage::new_rgb8(108, 240).save(&path).unwrap();
+
+        let model_config = ModelConfig::default()
+            .with_stream_callback(|_| {})
+            .with_transport(CannedTransport(
+                "Open the chat. do(action=\"Tap\", element=[500, 100])",
+            ));
+        let agent_config = AgentConfig::new().with_verbose(false);
+        let mut agent = PhoneAgent::new(Some(model_config), Some(agent_config), None, None)
+            .await
+            .unwrap();
+        agent.set_step_hint(Some("The chat is at the top".to_string()));
+
+        let result = agent
+            .plan_from_image("Message Alice", &path, "WeChat")
+            .await
+            .unwrap();
+        assert!(result.success);
+        assert!(!result.finished);
+        assert_eq!(result.thinking, "Open the chat.");
+        assert_eq!(result.action_summary.as_deref(), Some("Tap at (54, 24)"));
+        assert!(agent.context.is_empty());
+        assert!(!agent.agent_config.plan_only);
+        assert_eq!(agent.step_hint.as_deref(), Some("The chat is at the top"));
+        assert_eq!(agent.metrics().steps, 0);
+
+        assert!(agent
+            .plan_from_image("Message Alice", dir.path().join("missing.png"), "WeChat")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_set_step_hint() {
+        let mut agent = PhoneAgent::new(None, None, None, None).await.unwrap();
+        assert!(agent.step_hint.is_none());
+
+        agent.set_step_hint(Some("The item you want is red".to_string()));
+        assert_eq!(agent.step_hint.as_deref(), Some("The item you want is red"));
+
+        agent.set_step_hint(None);
+        assert!(agent.step_hint.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_screenshot_preprocessor() {
+        let config =
+            AgentConfig::new().with_screenshot_preprocessor(|img| img.crop_imm(0, 0, 10, 20));
+        let agent = PhoneAgent::new(None, Some(config), None, None)
+            .await
+            .unwrap();
+
+        let mut png = Vec::new();
+        DynamicImage::new_rgb8(100, 200)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let screenshot = Screenshot {
+            base64_data: base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &png),
+            width: 100,
+            height: 200,
+            is_sensitive: false,
+            encoding: ScreenshotEncoding::Png,
+        };
+
+        let view = agent.model_view(&screenshot).unwrap();
+        assert_eq!((view.width, view.height), (10, 20));
+        assert_eq!((screenshot.width, screenshot.height), (100, 200));
+    }
+
+    #[tokio::test]
+    async fn test_step_with_screenshot_requires_task() {
+        let mut agent = PhoneAgent::new(None, None, None, None).await.unwrap();
+        let screenshot = Screenshot {
+            base64_data: String::new(),
+            width: 1080,
+            height: 2400,
+            is_sensitive: false,
+            encoding: ScreenshotEncoding::Png,
+        };
+
+        let result = agent
+            .step_with_screenshot(None, screenshot, "System Home")
+            .await;
+        assert!(result.is_err());
+        assert_eq!(agent.step_count(), 0);
+    }
+
+    #[test]
+    fn test_prune_context() {
+        let mut context = vec![
+            MessageBuilder::create_system_message("System"),
+            MessageBuilder::create_user_message("Task", None),
+        ];
+        for step in 0..5 {
+            context.push(MessageBuilder::create_assistant_message(&"x".repeat(100)));
+            context.push(MessageBuilder::create_user_message(
+                &format!("Step {}", step),
+                None,
+            ));
+        }
+        let mut paths: Vec<Option<PathBuf>> = (0..6)
+            .map(|i| Some(PathBuf::from(format!("{}.png", i))))
+            .collect();
+
+        let limit = context_bytes(&context) - 200;
+        let dropped = prune_context(&mut context, &mut paths, limit);
+
+        assert!(dropped >= 2);
+        assert!(context_bytes(&context) <= limit);
+        assert_eq!(context.len(), 12 - dropped);
+        assert_eq!(paths.len(), 6 - dropped / 2);
+        assert_eq!(paths[0], Some(PathBuf::from("0.png")));
+        assert_eq!(paths.last().unwrap(), &Some(PathBuf::from("5.png")));
+
+        // Nothing to do when the context already fits
+        assert_eq!(prune_context(&mut context, &mut paths, usize::MAX), 0);
+    }
+
+    #[test]
+    fn test_map_from_region() {
+        let region = RelativeRegion::new(200, 400, 400, 600);
+        let mut tap = parse_action("do(action=\"Tap\", element=[500, 1000])").unwrap();
+        map_from_region(&mut tap, region);
+        assert_eq!(tap["element"], serde_json::json!([300, 600]));
+
+        let mut swipe =
+            parse_action("do(action=\"Swipe\", start=[0, 0], end=[1000, 500])").unwrap();
+        map_from_region(&mut swipe, region);
+        assert_eq!(swipe["start"], serde_json::json!([200, 400]));
+        assert_eq!(swipe["end"], serde_json::json!([400, 500]));
+    }
+
+    #[test]
+    fn test_planned_result() {
+        let planned = planned_result(&finish_action(Some("Done")));
+        assert!(planned.should_finish);
+        assert_eq!(planned.message.as_deref(), Some("Done"));
+
+        let tap = parse_action("do(action=\"Tap\", element=[500, 500])").unwrap();
+        let planned = planned_result(&tap);
+        assert!(planned.success && !planned.should_finish);
+    }
+
+    #[tokio::test]
+    async fn test_current_session_dir() {
+        let agent = PhoneAgent::new(None, None, None, None).await.unwrap();
+        assert!(agent.current_session_dir().is_none());
+
+        let dir = tempfile::tempdir().unwrap();
+        let base_dir = dir.path().join("screenshots");
+        let agent_config = AgentConfig::new().with_screenshot_dir(&base_dir);
+        let agent = PhoneAgent::new(None, Some(agent_config), None, None)
+            .await
+            .unwrap();
+        let session_dir = agent.current_session_dir().unwrap();
+        assert!(session_dir.starts_with(&base_dir));
+        assert!(session_dir.is_dir());
+    }
+
+    #[test]
+    fn test_agent_config_sensitive_policy() {
+        let config = AgentConfig::new();
+        assert_eq!(config.sensitive_policy, SensitivePolicy::Blackout);
+
+        let config = config.with_sensitive_policy(SensitivePolicy::Abort);
+        assert_eq!(config.sensitive_policy, SensitivePolicy::Abort);
+    }
+
+    #[test]
+    fn test_agent_config_image_strategies() {
+        let config = AgentConfig::new()
+            .with_first_step_image(ImageStrategy::new().with_detail(ImageDetail::High))
+            .with_step_image(ImageStrategy::new().with_max_dimension(720));
+
+        assert_eq!(config.first_step_image.detail, Some(ImageDetail::High));
+        assert_eq!(config.first_step_image.max_dimension, None);
+        assert_eq!(config.step_image.max_dimension, Some(720));
+        assert_eq!(AgentConfig::default().step_image, ImageStrategy::default());
+    }
+
+    #[test]
+    fn test_agent_config_warmup() {
+        assert!(!AgentConfig::default().warmup);
+        assert!(AgentConfig::new().with_warmup(true).warmup);
+    }
+
+    #[test]
+    fn test_agent_config_allowed_actions() {
+        assert!(AgentConfig::default().allowed_actions.is_none());
+        let config = AgentConfig::new().with_allowed_actions(["Back".to_string()].into());
+        assert!(config.allowed_actions.unwrap().contains("Back"));
+    }
+
+    #[test]
+    fn test_agent_config_parse_retries() {
+        assert_eq!(AgentConfig::default().max_parse_retries, 1);
+        assert_eq!(
+            AgentConfig::new()
+                .with_max_parse_retries(3)
+                .max_parse_retries,
+            3
+        );
+    }
+
+    /// Time a [`FrameDevice`] screenshot takes
+    const CAPTURE_TIME: Duration = Duration::from_millis(100);
+
+    /// Device that serves screenshots from a list, starting over at the end
+    #[derive(Default)]
+    struct FrameDevice {
+        frames: Vec<Screenshot>,
+        captures: AtomicUsize,
+        last_capture: Mutex<Option<tokio::time::Instant>>,
+        /// Time from the latest screenshot to each tap
+        taps: Mutex<Vec<Duration>>,
+    }
+
+    impl FrameDevice {
+        fn new(shades: &[u8]) -> Self {
+            let frames = shades
+                .iter()
+                .map(|&shade| {
+                    let img = RgbImage::from_pixel(100, 200, Rgb([shade; 3]));
+                    Screenshot::encode(&DynamicImage::ImageRgb8(img), ScreenshotEncoding::Png)
+                        .unwrap()
+                })
+                .collect();
+            Self {
+                frames,
+                ..Self::default()
+            }
+        }
+    }
+
+    impl DeviceController for FrameDevice {
+        fn get_screenshot<'a>(
+            &'a self,
+            _device_id: Option<&'a str>,
+            _timeout: u64,
+        ) -> BoxFuture<'a, Result<Screenshot>> {
+            Box::pin(async move {
+                tokio::time::sleep(CAPTURE_TIME).await;
+                let index = self.captures.fetch_add(1, Ordering::SeqCst);
+                *self.last_capture.lock().unwrap() = Some(tokio::time::Instant::now());
+                Ok(self.frames[index % self.frames.len()].clone())
+            })
+        }
+
+        fn get_current_app<'a>(
+            &'a self,
+            _device_id: Option<&'a str>,
+        ) -> BoxFuture<'a, Result<String>> {
+            Box::pin(async { Ok("System Home".to_string()) })
+        }
+
+        fn get_screen_size<'a>(
+            &'a self,
+            _device_id: Option<&'a str>,
+        ) -> BoxFuture<'a, Result<(u32, u32)>> {
+            Box::pin(async { Ok((100, 200)) })
+        }
+
+        fn tap<'a>(
+            &'a self,
+            _x: i32,
+            _y: i32,
+            _device_id: Option<&'a str>,
+            _delay: Option<f64>,
+        ) -> BoxFuture<'a, Result<()>> {
+            if let Some(captured) = *self.last_capture.lock().unwrap() {
+                self.taps.lock().unwrap().push(captured.elapsed());
+            }
+            Box::pin(async { Ok(()) })
+        }
+
+        fn double_tap<'a>(
+            &'a self,
+            _x: i32,
+            _y: i32,
+            _device_id: Option<&'a str>,
+            _delay: Option<f64>,
+        ) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn long_press<'a>(
+            &'a self,
+            _x: i32,
+            _y: i32,
+            _duration_ms: u32,
+            _device_id: Option<&'a str>,
+            _delay: Option<f64>,
+        ) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn swipe<'a>(
+            &'a self,
+            _start_x: i32,
+            _start_y: i32,
+            _end_x: i32,
+            _end_y: i32,
+            _duration_ms: Option<u32>,
+            _device_id: Option<&'a str>,
+            _delay: Option<f64>,
+        ) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn back<'a>(
+            &'a self,
+            _device_id: Option<&'a str>,
+            _delay: Option<f64>,
+        ) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn home<'a>(
+            &'a self,
+            _device_id: Option<&'a str>,
+            _delay: Option<f64>,
+        ) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn launch_app<'a>(
+            &'a self,
+            _app_name: &'a str,
+            _device_id: Option<&'a str>,
+            _delay: Option<f64>,
+        ) -> BoxFuture<'a, Result<bool>> {
+            Box::pin(async { Ok(true) })
+        }
+
+        fn type_text<'a>(
+            &'a self,
+            _text: &'a str,
+            _device_id: Option<&'a str>,
+        ) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn clear_text<'a>(&'a self, _device_id: Option<&'a str>) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn detect_and_set_adb_keyboard<'a>(
+            &'a self,
+            _device_id: Option<&'a str>,
+        ) -> BoxFuture<'a, Result<String>> {
+            Box::pin(async { Ok(String::new()) })
+        }
+
+        fn restore_keyboard<'a>(
+            &'a self,
+            _ime: &'a str,
+            _device_id: Option<&'a str>,
+        ) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    async fn frame_agent(device: Arc<FrameDevice>, config: AgentConfig) -> PhoneAgent {
+        PhoneAgent::with_device(None, Some(config.with_verbose(false)), None, None, device)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_capture_settled_waits_for_alike_frames() {
+        let device = Arc::new(FrameDevice::new(&[0, 255, 0, 255, 100, 100, 100]));
+        let config = AgentConfig::new().with_settle_frames(3);
+        let agent = frame_agent(device.clone(), config).await;
+
+        let frame = agent.capture_settled().await.unwrap();
+        assert_eq!(device.captures.load(Ordering::SeqCst), 7);
+        assert_eq!(frame.base64_data, device.frames[4].base64_data);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_capture_settled_stops_at_timeout() {
+        let device = Arc::new(FrameDevice::new(&[0, 255]));
+        let config = AgentConfig::new()
+            .with_settle_frames(3)
+            .with_settle_timeout(Duration::from_secs(1));
+        let agent = frame_agent(device.clone(), config).await;
+
+        let started = tokio::time::Instant::now();
+        agent.capture_settled().await.unwrap();
+        // One capture before the deadline is set, then one per CAPTURE_TIME
+        assert_eq!(device.captures.load(Ordering::SeqCst), 11);
+        assert_eq!(started.elapsed(), CAPTURE_TIME * 11);
+
+        // Without settle_frames a single frame is used
+        let device = Arc::new(FrameDevice::new(&[0, 255]));
+        let agent = frame_agent(device.clone(), AgentConfig::new()).await;
+        agent.capture_settled().await.unwrap();
+        assert_eq!(device.captures.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_agent_config_effective_config() {
+        let config = AgentConfig::new()
+            .with_lang(Language::English)
+            .with_max_steps(7);
+        let model_config = ModelConfig::default()
+            .with_api_key("sk-0123456789abcdef")
+            .with_headers(HashMap::from([(
+                "X-Api-Token".to_string(),
+                "tok-0123456789abcdef".to_string(),
+            )]));
+
+        let effective = config.effective_config(&model_config);
+        assert_eq!(effective["system_prompt"], config.get_system_prompt());
+        assert_eq!(effective["custom_system_prompt"], false);
+        assert_eq!(effective["agent"]["max_steps"], 7);
+        assert_eq!(effective["model"]["api_key"], "sk-***");
+        assert_eq!(effective["model"]["headers"]["X-Api-Token"], "tok***");
+        assert!(effective["timing"]["device"]["default_tap_delay"].is_number());
+    }
+
+    /// Agent on `device` whose model always taps the middle of the screen
+    async fn tapping_agent(device: Arc<FrameDevice>, config: AgentConfig) -> PhoneAgent {
+        let model_config = ModelConfig::default()
+            .with_stream_callback(|_| {})
+            .with_transport(CannedTransport("do(action=\"Tap\", element=[500, 500])"));
+        PhoneAgent::with_device(
+            Some(model_config),
+            Some(config.with_verbose(false)),
+            None,
+            None,
+            device,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_pre_action_delay() {
+        let delay = Duration::from_secs(2);
+
+        // The delay falls between the capture the model saw and the tap
+        let device = Arc::new(FrameDevice::new(&[0]));
+        let config = AgentConfig::new().with_pre_action_delay(delay);
+        let mut agent = tapping_agent(device.clone(), config.clone()).await;
+        agent.step(Some("Tap the middle")).await.unwrap();
+        let taps = device.taps.lock().unwrap().clone();
+        assert_eq!(taps.len(), 1);
+        assert!(taps[0] >= delay);
+
+        let device = Arc::new(FrameDevice::new(&[0]));
+        let mut agent = tapping_agent(device.clone(), AgentConfig::new()).await;
+        agent.step(Some("Tap the middle")).await.unwrap();
+        assert!(device.taps.lock().unwrap()[0] < delay);
+
+        // Plan-only steps execute nothing, so there is nothing to wait for
+        let device = Arc::new(FrameDevice::new(&[0]));
+        let mut agent = tapping_agent(device.clone(), config.with_plan_only(true)).await;
+        let started = tokio::time::Instant::now();
+        agent.step(Some("Tap the middle")).await.unwrap();
+        assert!(started.elapsed() < delay);
+        assert!(device.taps.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_agent_config_unlock_pin_is_hidden() {
+        let config = AgentConfig::new().with_unlock_pin("1234");
+        assert_eq!(
+            config.unlock_pin.as_ref().map(UnlockPin::as_str),
+            Some("1234")
+        );
+        assert!(!format!("{:?}", config).contains("1234"));
+        assert_eq!(
+            config.effective_config(&ModelConfig::default())["agent"]["unlock_pin"],
+            true
+        );
+    }
+
+    #[test]
+    fn test_agent_config_coordinate_scale() {
+        assert_eq!(AgentConfig::default().coordinate_scale, 1000.0);
+        assert_eq!(
+            AgentConfig::new()
+                .with_coordinate_scale(100.0)
+                .coordinate_scale,
+            100.0
+        );
+    }
+
+    #[test]
+    fn test_agent_config_ignores_invalid_coordinate_scale() {
+        for scale in [0.0, -100.0, f64::NAN, f64::INFINITY] {
+            let config = AgentConfig::new().with_coordinate_scale(scale);
+            assert_eq!(config.coordinate_scale, 1000.0);
+        }
+    }
+
+    #[test]
+    fn test_agent_config_verify_taps() {
+        assert!(!AgentConfig::default().verify_taps);
+        assert!(AgentConfig::new().with_verify_taps(true).verify_taps);
+        assert!(!AgentConfig::default().check_text_focus);
+        assert!(
+            AgentConfig::new()
+                .with_text_focus_check(true)
+                .check_text_focus
+        );
+    }
+
+    #[test]
+    fn test_format_previous_results() {
+        let outcomes = vec![RunOutcome {
+            task: "Open WeChat".to_string(),
+            message: "WeChat is open".to_string(),
+            success: true,
+            finished: true,
+            finished_reason: FinishReason::Completed,
+            steps: 2,
+            result_data: None,
+        }];
+
+        assert_eq!(
+            format_previous_results(&outcomes),
+            "Results of previous tasks:\n1. Open WeChat: WeChat is open"
+        );
+    }
+
     #[test]
     fn test_step_result() {
         let result = StepResult {
             success: true,
             finished: false,
             action: None,
+            action_summary: None,
             thinking: "Test thinking".to_string(),
             message: Some("Test message".to_string()),
         };
@@ -438,4 +2468,44 @@ mod tests {
         assert!(!result.finished);
         assert_eq!(result.thinking, "Test thinking");
     }
+
+    #[test]
+    fn test_step_result_serde() {
+        let result = StepResult {
+            success: true,
+            finished: false,
+            action: Some(parse_action("do(action=\"Tap\", element=[500, 300])").unwrap()),
+            action_summary: Some("Tap at (540, 720)".to_string()),
+            thinking: "Tap the search box".to_string(),
+            message: None,
+        };
+
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["action"]["element"], serde_json::json!([500, 300]));
+        assert_eq!(json["message"], serde_json::Value::Null);
+
+        let parsed: StepResult = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.action, result.action);
+        assert_eq!(parsed.thinking, result.thinking);
+    }
+
+    #[test]
+    fn test_run_outcome_serde() {
+        let outcome = RunOutcome {
+            task: "Open Settings".to_string(),
+            message: "Max steps reached".to_string(),
+            success: false,
+            finished: false,
+            finished_reason: FinishReason::MaxSteps,
+            steps: 3,
+            result_data: None,
+        };
+
+        let json = serde_json::to_string(&outcome).unwrap();
+        assert!(json.contains("\"finished_reason\":\"max_steps\""));
+
+        let parsed: RunOutcome = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.finished_reason, FinishReason::MaxSteps);
+        assert_eq!(parsed.steps, 3);
+    }
 }
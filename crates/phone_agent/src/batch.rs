@@ -0,0 +1,155 @@
+//! Batch execution of task queues across several devices
+
+use futures::future::join_all;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+use crate::actions::{ConfirmationCallback, TakeoverCallback};
+use crate::agent::{AgentConfig, FinishReason, PhoneAgent, RunOutcome};
+use crate::device_factory::{DeviceFactory, DeviceType};
+use crate::model::ModelConfig;
+
+/// Runs a queue of tasks concurrently across several devices
+///
+/// Each device gets its own [`PhoneAgent`] and device factory, and pulls the
+/// next task from a shared queue once it is done with the previous one.
+/// Nobody is watching a batch run, so sensitive actions are declined and
+/// takeover requests are only logged.
+#[derive(Debug, Clone)]
+pub struct BatchRunner {
+    device_ids: Vec<String>,
+    model_config: ModelConfig,
+    agent_config: AgentConfig,
+}
+
+impl BatchRunner {
+    /// Create a runner for the given devices
+    ///
+    /// `agent_config` is used for every device, with its device ID replaced.
+    pub fn new(
+        device_ids: Vec<String>,
+        model_config: ModelConfig,
+        agent_config: AgentConfig,
+    ) -> Self {
+        Self {
+            device_ids,
+            model_config,
+            agent_config,
+        }
+    }
+
+    /// Get the device IDs tasks are distributed across
+    pub fn device_ids(&self) -> &[String] {
+        &self.device_ids
+    }
+
+    /// Run all tasks, returning one outcome per task in input order
+    ///
+    /// A device that fails to start or errors out stops taking tasks; its
+    /// current task is reported as failed and the other devices carry on.
+    /// Tasks left over when every device has failed are reported as failed too.
+    pub async fn run(&self, tasks: &[&str]) -> Vec<RunOutcome> {
+        let queue: Mutex<VecDeque<usize>> = Mutex::new((0..tasks.len()).collect());
+        let results: Mutex<Vec<Option<RunOutcome>>> = Mutex::new(vec![None; tasks.len()]);
+
+        join_all(
+            self.device_ids
+                .iter()
+                .map(|device_id| self.run_device(device_id, tasks, &queue, &results)),
+        )
+        .await;
+
+        results
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .zip(tasks)
+            .map(|(outcome, task)| {
+                outcome.unwrap_or_else(|| failed_outcome(task, "No device available"))
+            })
+            .collect()
+    }
+
+    /// Work through the queue on one device until it is empty or the device fails
+    async fn run_device(
+        &self,
+        device_id: &str,
+        tasks: &[&str],
+        queue: &Mutex<VecDeque<usize>>,
+        results: &Mutex<Vec<Option<RunOutcome>>>,
+    ) {
+        let agent_config = self.agent_config.clone().with_device_id(device_id);
+        let takeover_device = device_id.to_string();
+        let confirmation: ConfirmationCallback = Box::new(|_| false);
+        let takeover: TakeoverCallback = Box::new(move |message| {
+            warn!(device_id = %takeover_device, message, "takeover requested during batch run");
+        });
+
+        let agent = PhoneAgent::with_device(
+            Some(self.model_config.clone()),
+            Some(agent_config),
+            Some(confirmation),
+            Some(takeover),
+            Arc::new(DeviceFactory::new(DeviceType::Adb)),
+        )
+        .await;
+
+        let mut agent = match agent {
+            Ok(agent) => agent,
+            Err(e) => {
+                warn!(device_id, error = %e, "failed to start agent");
+                return;
+            }
+        };
+
+        loop {
+            let Some(index) = queue.lock().unwrap().pop_front() else {
+                break;
+            };
+            let task = tasks[index];
+            info!(device_id, task, "starting batch task");
+
+            match agent.run_chain(&[task]).await {
+                Ok(mut outcomes) => {
+                    results.lock().unwrap()[index] = outcomes.pop();
+                }
+                Err(e) => {
+                    warn!(device_id, error = %e, "device failed, removing it from the batch");
+                    results.lock().unwrap()[index] = Some(failed_outcome(
+                        task,
+                        &format!("Device {} failed: {}", device_id, e),
+                    ));
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn failed_outcome(task: &str, message: &str) -> RunOutcome {
+    RunOutcome {
+        task: task.to_string(),
+        message: message.to_string(),
+        success: false,
+        finished: false,
+        finished_reason: FinishReason::Failed,
+        steps: 0,
+        result_data: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_batch_without_devices_fails_all_tasks() {
+        let runner = BatchRunner::new(Vec::new(), ModelConfig::default(), AgentConfig::default());
+        let outcomes = runner.run(&["Open Settings", "Open WeChat"]).await;
+
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!(outcomes[1].task, "Open WeChat");
+        assert!(outcomes.iter().all(|o| !o.success && !o.finished));
+    }
+}
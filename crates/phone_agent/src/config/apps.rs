@@ -1,6 +1,10 @@
 //! App package mappings for Android devices
 
+use crate::error::{AdbError, Result};
 use phf::phf_map;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{OnceLock, RwLock};
 
 /// App name to package name mapping
 pub static APP_PACKAGES: phf::Map<&'static str, &'static str> = phf_map! {
@@ -190,20 +194,171 @@ pub static APP_PACKAGES: phf::Map<&'static str, &'static str> = phf_map! {
     "WhatsApp" => "com.whatsapp",
 };
 
+/// Apps registered at runtime, taking precedence over [`APP_PACKAGES`]
+fn app_overrides() -> &'static RwLock<HashMap<String, String>> {
+    static OVERRIDES: OnceLock<RwLock<HashMap<String, String>>> = OnceLock::new();
+    OVERRIDES.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Register an app at runtime, or point a built-in app name at another package
+pub fn register_app(app_name: &str, package: &str) {
+    app_overrides()
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(app_name.to_string(), package.to_string());
+}
+
+/// Load app overrides from a file, e.g. `~/.config/autoglm/apps.toml`
+///
+/// The file is TOML mapping app names to package names:
+///
+/// ```toml
+/// [apps]
+/// "My Bank" = "com.example.bank"
+/// Notes = "com.example.notes"  # comments are allowed
+/// ```
+///
+/// The `[apps]` header is optional. The whole file is checked before any
+/// entry is registered, so a malformed file changes nothing. Returns the
+/// number of apps loaded.
+pub fn load_app_overrides(path: impl AsRef<Path>) -> Result<usize> {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path)?;
+    let entries = parse_app_overrides(&text)
+        .map_err(|e| AdbError::ParseError(format!("{}: {}", path.display(), e)))?;
+
+    let count = entries.len();
+    app_overrides()
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .extend(entries);
+    Ok(count)
+}
+
+/// Parse the `name = "package"` entries of an app overrides file
+fn parse_app_overrides(text: &str) -> std::result::Result<Vec<(String, String)>, String> {
+    let mut table: toml::Table = toml::from_str(text).map_err(|e| e.to_string())?;
+    let apps = match table.remove("apps") {
+        Some(toml::Value::Table(apps)) if table.is_empty() => apps,
+        Some(toml::Value::Table(_)) => {
+            return Err("entries outside [apps] are not allowed with an [apps] table".to_string())
+        }
+        // An app that happens to be called "apps"
+        Some(value) => {
+            table.insert("apps".to_string(), value);
+            table
+        }
+        None => table,
+    };
+
+    apps.into_iter()
+        .map(|(app_name, value)| match value {
+            toml::Value::String(package) if is_package_name(&package) => Ok((app_name, package)),
+            toml::Value::String(package) => Err(format!(
+                "{}: '{}' is not a valid package name",
+                app_name, package
+            )),
+            other => Err(format!(
+                "{}: expected a quoted package name, found a {}",
+                app_name,
+                other.type_str()
+            )),
+        })
+        .collect()
+}
+
+/// Whether `package` looks like an Android package name, e.g. `com.example.app`
+fn is_package_name(package: &str) -> bool {
+    let segments: Vec<&str> = package.split('.').collect();
+    segments.len() >= 2
+        && segments.iter().all(|segment| {
+            segment.starts_with(|c: char| c.is_ascii_alphabetic())
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+}
+
+/// Get all app name to package pairs, runtime overrides first
+pub fn app_entries() -> Vec<(String, String)> {
+    let overrides = app_overrides().read().unwrap_or_else(|e| e.into_inner());
+    let mut entries: Vec<_> = overrides
+        .iter()
+        .map(|(name, package)| (name.clone(), package.clone()))
+        .collect();
+    entries.extend(
+        APP_PACKAGES
+            .entries()
+            .filter(|(name, _)| !overrides.contains_key(**name))
+            .map(|(name, package)| (name.to_string(), package.to_string())),
+    );
+    entries
+}
+
 /// Get the package name for an app
-pub fn get_package_name(app_name: &str) -> Option<&'static str> {
-    APP_PACKAGES.get(app_name).copied()
+pub fn get_package_name(app_name: &str) -> Option<String> {
+    let overrides = app_overrides().read().unwrap_or_else(|e| e.into_inner());
+    overrides.get(app_name).cloned().or_else(|| {
+        APP_PACKAGES
+            .get(app_name)
+            .map(|package| package.to_string())
+    })
 }
 
 /// Get the app name from a package name
-pub fn get_app_name(package_name: &str) -> Option<&'static str> {
-    APP_PACKAGES
-        .entries()
-        .find(|(_, pkg)| **pkg == package_name)
-        .map(|(name, _)| *name)
+pub fn get_app_name(package_name: &str) -> Option<String> {
+    app_entries()
+        .into_iter()
+        .find(|(_, pkg)| pkg == package_name)
+        .map(|(name, _)| name)
 }
 
 /// Get a list of all supported app names
-pub fn list_supported_apps() -> Vec<&'static str> {
-    APP_PACKAGES.keys().copied().collect()
+pub fn list_supported_apps() -> Vec<String> {
+    app_entries().into_iter().map(|(name, _)| name).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_app_overrides() {
+        let text = "# my apps\n[apps]\n\"My Bank\" = \"com.example.bank\"\nNotes = \"com.example.notes\"  # notes\n";
+        assert_eq!(
+            parse_app_overrides(text).unwrap(),
+            vec![
+                ("My Bank".to_string(), "com.example.bank".to_string()),
+                ("Notes".to_string(), "com.example.notes".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_app_overrides_errors() {
+        let err = parse_app_overrides("Notes = com.example.notes").unwrap_err();
+        assert!(err.contains("line 1"), "{}", err);
+        assert!(parse_app_overrides("Notes = \"notes\"").is_err());
+        assert!(parse_app_overrides("[other]").is_err());
+        assert!(parse_app_overrides("= \"com.example.app\"").is_err());
+        assert!(parse_app_overrides("Notes = 3").is_err());
+    }
+
+    #[test]
+    fn test_register_app() {
+        register_app("Test Override App", "com.example.override");
+        assert_eq!(
+            get_package_name("Test Override App").as_deref(),
+            Some("com.example.override")
+        );
+        assert_eq!(
+            get_app_name("com.example.override").as_deref(),
+            Some("Test Override App")
+        );
+        assert!(list_supported_apps().contains(&"Test Override App".to_string()));
+        assert_eq!(
+            get_package_name("WeChat").as_deref(),
+            Some("com.tencent.mm")
+        );
+    }
 }
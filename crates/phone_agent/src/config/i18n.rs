@@ -34,6 +34,7 @@ pub static MESSAGES_ZH: phf::Map<&'static str, &'static str> = phf_map! {
     "thinking" => "思考过程",
     "action" => "执行动作",
     "task_completed" => "任务完成",
+    "task_failed" => "任务失败",
     "done" => "完成",
     "starting_task" => "开始执行任务",
     "final_result" => "最终结果",
@@ -52,6 +53,12 @@ pub static MESSAGES_ZH: phf::Map<&'static str, &'static str> = phf_map! {
     "time_to_first_token" => "首 Token 延迟 (TTFT)",
     "time_to_thinking_end" => "思考完成延迟",
     "total_inference_time" => "总推理时间",
+    "no_action_nudge" => "请按要求的格式输出一个操作指令。",
+    "parse_error_nudge" => "无法解析该操作指令，请重新输出一个合法的 do(...) 或 finish(...) 指令。",
+    "zoom_prompt" => "这是你选择区域的放大图。坐标现在相对于这张放大图，从左上角 (0,0) 到右下角 (999,999)。请输出要执行的操作。",
+    "sensitive_screen_takeover" => "检测到敏感页面，请手动处理后继续",
+    "sensitive_screen_abort" => "检测到敏感页面，任务已中止",
+    "tap_no_change" => "点击后屏幕没有变化，可能没有点中目标",
 };
 
 /// English messages
@@ -59,6 +66,7 @@ pub static MESSAGES_EN: phf::Map<&'static str, &'static str> = phf_map! {
     "thinking" => "Thinking",
     "action" => "Action",
     "task_completed" => "Task Completed",
+    "task_failed" => "Task Failed",
     "done" => "Done",
     "starting_task" => "Starting task",
     "final_result" => "Final Result",
@@ -77,6 +85,12 @@ pub static MESSAGES_EN: phf::Map<&'static str, &'static str> = phf_map! {
     "time_to_first_token" => "Time to First Token (TTFT)",
     "time_to_thinking_end" => "Time to Thinking End",
     "total_inference_time" => "Total Inference Time",
+    "no_action_nudge" => "Please output an action in the required format.",
+    "parse_error_nudge" => "The action could not be parsed. Please output it again as a valid do(...) or finish(...) call.",
+    "zoom_prompt" => "This is a zoomed-in view of the region you selected. Coordinates now refer to this view, from (0,0) at the top left to (999,999) at the bottom right. Please output the action to perform.",
+    "sensitive_screen_takeover" => "Sensitive screen detected, please handle it manually",
+    "sensitive_screen_abort" => "Sensitive screen detected, task aborted",
+    "tap_no_change" => "Screen did not change after Tap; the tap may have missed its target",
 };
 
 /// Get UI messages dictionary by language
@@ -11,7 +11,10 @@ mod i18n;
 mod prompts;
 mod timing;
 
-pub use apps::{get_app_name, get_package_name, list_supported_apps, APP_PACKAGES};
+pub use apps::{
+    app_entries, get_app_name, get_package_name, list_supported_apps, load_app_overrides,
+    register_app, APP_PACKAGES,
+};
 pub use i18n::{get_message, get_messages, Language, MESSAGES_EN, MESSAGES_ZH};
 pub use prompts::get_system_prompt;
 pub use timing::{
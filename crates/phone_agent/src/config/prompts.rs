@@ -72,6 +72,8 @@ fn get_system_prompt_zh() -> String {
     等待页面加载，x为需要等待多少秒。
 - finish(message=\"xxx\")
     finish是结束任务的操作，表示准确完整完成任务，message是终止信息。
+- do(action=\"Error\", message=\"xxx\")
+    Error表示任务无法完成（例如应用不存在或缺少必要信息），message是无法完成的原因。
 
 必须遵循的规则：
 1. 在执行任何操作前，先检查当前app是否是目标app，如果不是，先执行 Launch。
@@ -164,6 +166,12 @@ Your output should STRICTLY follow the format:
   <answer>
   finish(message=\"Task completed.\")
   </answer>
+- **Error**
+  Give up when the task cannot be completed, explaining why.
+  **Example**:
+  <answer>
+  do(action=\"Error\", message=\"The app is not installed.\")
+  </answer>
 
 
 REMEMBER:
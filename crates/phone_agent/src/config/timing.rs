@@ -1,15 +1,18 @@
 //! Timing configuration for device operations
 
 use lazy_static::lazy_static;
+use serde::Serialize;
 use std::env;
 
 /// Action timing configuration for text input operations
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ActionTimingConfig {
     pub keyboard_switch_delay: f64,
     pub text_clear_delay: f64,
     pub text_input_delay: f64,
     pub keyboard_restore_delay: f64,
+    /// Longest a Wait action may sleep, in seconds
+    pub max_wait: f64,
 }
 
 impl Default for ActionTimingConfig {
@@ -31,12 +34,16 @@ impl Default for ActionTimingConfig {
                 .ok()
                 .and_then(|v| v.parse().ok())
                 .unwrap_or(1.0),
+            max_wait: env::var("PHONE_AGENT_MAX_WAIT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(30.0),
         }
     }
 }
 
 /// Device timing configuration for device operations
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct DeviceTimingConfig {
     pub default_tap_delay: f64,
     pub default_double_tap_delay: f64,
@@ -46,6 +53,12 @@ pub struct DeviceTimingConfig {
     pub default_back_delay: f64,
     pub default_home_delay: f64,
     pub default_launch_delay: f64,
+    /// Seconds to wait for a launched app to reach the foreground (0 disables the check)
+    pub launch_ready_timeout: f64,
+    /// Extra launch attempts when the app doesn't reach the foreground
+    pub launch_retries: u32,
+    /// Seconds before a single device command is abandoned
+    pub command_timeout: f64,
 }
 
 impl Default for DeviceTimingConfig {
@@ -83,12 +96,24 @@ impl Default for DeviceTimingConfig {
                 .ok()
                 .and_then(|v| v.parse().ok())
                 .unwrap_or(1.0),
+            launch_ready_timeout: env::var("PHONE_AGENT_LAUNCH_READY_TIMEOUT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(5.0),
+            launch_retries: env::var("PHONE_AGENT_LAUNCH_RETRIES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1),
+            command_timeout: env::var("PHONE_AGENT_COMMAND_TIMEOUT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(30.0),
         }
     }
 }
 
 /// Connection timing configuration for ADB connection operations
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ConnectionTimingConfig {
     pub adb_restart_delay: f64,
     pub server_restart_delay: f64,
@@ -110,7 +135,7 @@ impl Default for ConnectionTimingConfig {
 }
 
 /// Master timing configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct TimingConfig {
     pub action: ActionTimingConfig,
     pub device: DeviceTimingConfig,
@@ -1,31 +1,171 @@
 //! Device factory for selecting device backend (currently ADB only)
 
 use crate::adb;
-use crate::error::Result;
+use crate::error::{AdbError, Result};
+use futures::future::BoxFuture;
+use image::DynamicImage;
+use std::fmt;
+use std::str::FromStr;
 use std::sync::OnceLock;
+use std::time::Duration;
 use tokio::sync::RwLock;
+use tracing::instrument;
 
 /// Type of device connection
+///
+/// Only `Adb` has a backend in this crate; the factory reports HDC and iOS
+/// operations as unsupported.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum DeviceType {
+    /// Android devices via ADB
     #[default]
     Adb,
-    // XCTest and HDC are not implemented in this version
+    /// HarmonyOS devices via HDC
+    Hdc,
+    /// iOS devices via libimobiledevice and WebDriverAgent
+    Ios,
+}
+
+impl DeviceType {
+    /// Human-readable name of the tool that talks to this kind of device
+    pub fn tool_name(self) -> &'static str {
+        match self {
+            Self::Adb => "ADB",
+            Self::Hdc => "HDC",
+            Self::Ios => "libimobiledevice",
+        }
+    }
+
+    /// Command used to check that the tool is installed
+    pub fn tool_cmd(self) -> &'static str {
+        match self {
+            Self::Adb => "adb",
+            Self::Hdc => "hdc",
+            Self::Ios => "idevice_id",
+        }
+    }
 }
 
+impl FromStr for DeviceType {
+    type Err = AdbError;
+
+    /// Parse "adb", "hdc" or "ios" (case-insensitive)
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "adb" => Ok(Self::Adb),
+            "hdc" => Ok(Self::Hdc),
+            "ios" => Ok(Self::Ios),
+            _ => Err(AdbError::ParseError(format!("Invalid device type: {}", s))),
+        }
+    }
+}
+
+impl fmt::Display for DeviceType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Adb => "adb",
+            Self::Hdc => "hdc",
+            Self::Ios => "ios",
+        })
+    }
+}
+
+/// Direction in which to scroll page content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl ScrollDirection {
+    /// Parse a direction from a string such as "down" or "Up"
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "up" => Some(Self::Up),
+            "down" => Some(Self::Down),
+            "left" => Some(Self::Left),
+            "right" => Some(Self::Right),
+            _ => None,
+        }
+    }
+
+    /// Swipe endpoints `(start_x, start_y, end_x, end_y)` that scroll content this way
+    ///
+    /// Scrolling down moves the finger upwards, so later content comes into view.
+    pub fn swipe_points(self, width: u32, height: u32) -> (i32, i32, i32, i32) {
+        let (w, h) = (width as i32, height as i32);
+        match self {
+            Self::Down => (w / 2, h * 7 / 10, w / 2, h * 3 / 10),
+            Self::Up => (w / 2, h * 3 / 10, w / 2, h * 7 / 10),
+            Self::Right => (w * 8 / 10, h / 2, w * 2 / 10, h / 2),
+            Self::Left => (w * 2 / 10, h / 2, w * 8 / 10, h / 2),
+        }
+    }
+}
+
+/// Largest screenshot difference at which scrolling is considered to have ended
+const SCROLL_END_THRESHOLD: f64 = 0.005;
+
+/// Most flings [`DeviceController::fling_to_edge`] sends before giving up
+const MAX_EDGE_FLINGS: usize = 10;
+
+/// Duration of a fling swipe; short enough for the content to keep moving
+const FLING_DURATION_MS: u32 = 100;
+
+/// Time for a fling's momentum to die down before the next screenshot
+const FLING_SETTLE: Duration = Duration::from_millis(500);
+
 /// Factory for device-specific implementations
 ///
-/// Currently only supports ADB (Android) devices.
-/// XCTest (iOS) and HDC (HarmonyOS) support are not included in this Rust port.
+/// Currently only supports ADB (Android) devices. Factories for
+/// [`DeviceType::Hdc`] and [`DeviceType::Ios`] return an error from every
+/// operation, since those backends are not included in this Rust port.
 #[derive(Debug, Clone)]
 pub struct DeviceFactory {
     device_type: DeviceType,
+    display_id: Option<u64>,
+    screenshot_encoding: adb::ScreenshotEncoding,
+    fallback_screen_size: Option<(u32, u32)>,
 }
 
 impl DeviceFactory {
     /// Create a new device factory
     pub fn new(device_type: DeviceType) -> Self {
-        Self { device_type }
+        Self {
+            device_type,
+            display_id: None,
+            screenshot_encoding: adb::ScreenshotEncoding::from_env(),
+            fallback_screen_size: None,
+        }
+    }
+
+    /// Target screenshots, taps and swipes at the given display
+    ///
+    /// Passed as `-d <id>` to `screencap` and `input`, e.g. for the cover
+    /// screen of a foldable. By default the device's default display is used.
+    pub fn with_display_id(mut self, display_id: u64) -> Self {
+        self.display_id = Some(display_id);
+        self
+    }
+
+    /// Encode screenshots with the given encoding, e.g. JPEG to save bandwidth
+    ///
+    /// Defaults to `PHONE_AGENT_SCREENSHOT_QUALITY`, or PNG if that is unset.
+    pub fn with_screenshot_encoding(mut self, encoding: adb::ScreenshotEncoding) -> Self {
+        self.screenshot_encoding = encoding;
+        self
+    }
+
+    /// Set the size of black fallback frames, e.g. `(2560, 1600)` for a landscape tablet
+    ///
+    /// Only used until a display has been captured once; after that,
+    /// fallbacks take the size of its last captured frame. Defaults to
+    /// 1080x2400.
+    pub fn with_fallback_screen_size(mut self, width: u32, height: u32) -> Self {
+        self.fallback_screen_size = Some((width, height));
+        self
     }
 
     /// Get the device type
@@ -33,25 +173,65 @@ impl DeviceFactory {
         self.device_type
     }
 
+    /// Get the targeted display, `None` for the default display
+    pub fn display_id(&self) -> Option<u64> {
+        self.display_id
+    }
+
+    /// Error returned by operations on device types without a backend
+    fn unsupported(&self) -> AdbError {
+        AdbError::CommandFailed(format!(
+            "{} devices are not supported yet",
+            self.device_type.tool_name()
+        ))
+    }
+
     /// Get screenshot from device
+    #[instrument(level = "debug", skip(self))]
     pub async fn get_screenshot(
         &self,
         device_id: Option<&str>,
         timeout: u64,
     ) -> Result<adb::Screenshot> {
         match self.device_type {
-            DeviceType::Adb => adb::get_screenshot(device_id, timeout).await,
+            DeviceType::Adb => {
+                adb::get_screenshot(
+                    device_id,
+                    self.display_id,
+                    self.screenshot_encoding,
+                    self.fallback_screen_size,
+                    timeout,
+                )
+                .await
+            }
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
+        }
+    }
+
+    /// Get screenshot from device as a decoded image
+    #[instrument(level = "debug", skip(self))]
+    pub async fn get_screenshot_image(
+        &self,
+        device_id: Option<&str>,
+        timeout: u64,
+    ) -> Result<DynamicImage> {
+        match self.device_type {
+            DeviceType::Adb => adb::get_screenshot_image(device_id, self.display_id, timeout).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
         }
     }
 
     /// Get current app name
+    #[instrument(level = "debug", skip(self))]
     pub async fn get_current_app(&self, device_id: Option<&str>) -> Result<String> {
         match self.device_type {
             DeviceType::Adb => adb::get_current_app(device_id).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
         }
     }
 
     /// Tap at coordinates
+    #[instrument(level = "debug", skip(self))]
     pub async fn tap(
         &self,
         x: i32,
@@ -60,11 +240,13 @@ impl DeviceFactory {
         delay: Option<f64>,
     ) -> Result<()> {
         match self.device_type {
-            DeviceType::Adb => adb::tap(x, y, device_id, delay).await,
+            DeviceType::Adb => adb::tap(x, y, device_id, self.display_id, delay).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
         }
     }
 
     /// Double tap at coordinates
+    #[instrument(level = "debug", skip(self))]
     pub async fn double_tap(
         &self,
         x: i32,
@@ -73,11 +255,13 @@ impl DeviceFactory {
         delay: Option<f64>,
     ) -> Result<()> {
         match self.device_type {
-            DeviceType::Adb => adb::double_tap(x, y, device_id, delay).await,
+            DeviceType::Adb => adb::double_tap(x, y, device_id, self.display_id, delay).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
         }
     }
 
     /// Long press at coordinates
+    #[instrument(level = "debug", skip(self))]
     pub async fn long_press(
         &self,
         x: i32,
@@ -87,11 +271,15 @@ impl DeviceFactory {
         delay: Option<f64>,
     ) -> Result<()> {
         match self.device_type {
-            DeviceType::Adb => adb::long_press(x, y, duration_ms, device_id, delay).await,
+            DeviceType::Adb => {
+                adb::long_press(x, y, duration_ms, device_id, self.display_id, delay).await
+            }
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
         }
     }
 
     /// Swipe from start to end
+    #[instrument(level = "debug", skip(self))]
     pub async fn swipe(
         &self,
         start_x: i32,
@@ -104,26 +292,312 @@ impl DeviceFactory {
     ) -> Result<()> {
         match self.device_type {
             DeviceType::Adb => {
-                adb::swipe(start_x, start_y, end_x, end_y, duration_ms, device_id, delay).await
+                adb::swipe(
+                    start_x,
+                    start_y,
+                    end_x,
+                    end_y,
+                    duration_ms,
+                    device_id,
+                    self.display_id,
+                    delay,
+                )
+                .await
             }
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
+        }
+    }
+
+    /// Scroll page content once in the given direction
+    pub async fn scroll(
+        &self,
+        direction: ScrollDirection,
+        width: u32,
+        height: u32,
+        device_id: Option<&str>,
+    ) -> Result<()> {
+        let (start_x, start_y, end_x, end_y) = direction.swipe_points(width, height);
+        self.swipe(start_x, start_y, end_x, end_y, None, device_id, None)
+            .await
+    }
+
+    /// Fling in `direction` until the screen stops changing, e.g. to reach the top of a list
+    ///
+    /// Returns whether the edge was reached within a few flings.
+    pub async fn fling_to_edge(
+        &self,
+        direction: ScrollDirection,
+        device_id: Option<&str>,
+    ) -> Result<bool> {
+        DeviceController::fling_to_edge(self, direction, device_id).await
+    }
+
+    /// Scroll down until a screenshot matches the predicate
+    ///
+    /// The predicate runs on each full-resolution frame (including the frame
+    /// before the first scroll), so callers can do template matching on it.
+    /// Returns the matching screenshot, or `None` after `max_scrolls` scrolls.
+    pub async fn scroll_until<F>(
+        &self,
+        device_id: Option<&str>,
+        max_scrolls: usize,
+        predicate: F,
+    ) -> Result<Option<adb::Screenshot>>
+    where
+        F: Fn(&adb::Screenshot) -> bool,
+    {
+        let mut screenshot = self.get_screenshot(device_id, 10).await?;
+        if predicate(&screenshot) {
+            return Ok(Some(screenshot));
+        }
+
+        for _ in 0..max_scrolls {
+            self.scroll(
+                ScrollDirection::Down,
+                screenshot.width,
+                screenshot.height,
+                device_id,
+            )
+            .await?;
+
+            screenshot = self.get_screenshot(device_id, 10).await?;
+            if predicate(&screenshot) {
+                return Ok(Some(screenshot));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Scroll through content and stitch the frames into one long screenshot
+    ///
+    /// Captures up to `max_frames` frames, scrolling in `direction` between
+    /// them, and stops early once scrolling no longer changes the screen or a
+    /// sensitive screen comes up. Overlapping regions are only kept once, but
+    /// sticky headers and footers repeat in every frame.
+    ///
+    /// The stitched image is for reading only: its coordinates don't map to
+    /// the screen, so taps must be made against a single, fresh frame.
+    pub async fn capture_scrolling(
+        &self,
+        direction: ScrollDirection,
+        max_frames: usize,
+        device_id: Option<&str>,
+    ) -> Result<adb::Screenshot> {
+        let mut frames = vec![self.get_screenshot(device_id, 10).await?];
+
+        while frames.len() < max_frames {
+            let last = &frames[frames.len() - 1];
+            self.scroll(direction, last.width, last.height, device_id)
+                .await?;
+
+            let frame = self.get_screenshot(device_id, 10).await?;
+            let last = &frames[frames.len() - 1];
+            if frame.is_sensitive || adb::screenshot_diff(last, &frame)? < SCROLL_END_THRESHOLD {
+                break;
+            }
+            frames.push(frame);
+        }
+
+        match direction {
+            ScrollDirection::Down => adb::stitch_vertical(&frames),
+            ScrollDirection::Right => adb::stitch_horizontal(&frames),
+            ScrollDirection::Up => {
+                frames.reverse();
+                adb::stitch_vertical(&frames)
+            }
+            ScrollDirection::Left => {
+                frames.reverse();
+                adb::stitch_horizontal(&frames)
+            }
+        }
+    }
+
+    /// Get the battery level in percent
+    #[instrument(level = "debug", skip(self))]
+    pub async fn get_battery_level(&self, device_id: Option<&str>) -> Result<u8> {
+        match self.device_type {
+            DeviceType::Adb => adb::get_battery_level(device_id).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
+        }
+    }
+
+    /// Check whether the screen is on
+    #[instrument(level = "debug", skip(self))]
+    pub async fn is_screen_on(&self, device_id: Option<&str>) -> Result<bool> {
+        match self.device_type {
+            DeviceType::Adb => adb::is_screen_on(device_id).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
+        }
+    }
+
+    /// Turn the screen on
+    #[instrument(level = "debug", skip(self))]
+    pub async fn wake_screen(&self, device_id: Option<&str>) -> Result<()> {
+        match self.device_type {
+            DeviceType::Adb => adb::wake_screen(device_id).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
+        }
+    }
+
+    /// Get the screen size in pixels, honouring a `wm size` override
+    #[instrument(level = "debug", skip(self))]
+    pub async fn get_screen_size(&self, device_id: Option<&str>) -> Result<(u32, u32)> {
+        match self.device_type {
+            DeviceType::Adb => adb::get_screen_size(device_id, self.display_id).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
+        }
+    }
+
+    /// Fail with a specific error if the device can't run commands, e.g. in recovery
+    #[instrument(level = "debug", skip(self))]
+    pub async fn check_device_ready(&self, device_id: Option<&str>) -> Result<()> {
+        match self.device_type {
+            DeviceType::Adb => adb::check_device_ready(device_id).await,
+            // Only ADB lists device states; other tools fail on the first command
+            DeviceType::Hdc | DeviceType::Ios => Ok(()),
+        }
+    }
+
+    /// Wake the device and dismiss a swipe lock, entering `pin` if given
+    #[instrument(level = "debug", skip(self, pin))]
+    pub async fn wake_device(&self, device_id: Option<&str>, pin: Option<&str>) -> Result<()> {
+        match self.device_type {
+            DeviceType::Adb => adb::wake_device(device_id, pin).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
+        }
+    }
+
+    /// Dismiss an ANR or crash dialog if one has focus, returning it
+    #[instrument(level = "debug", skip(self))]
+    pub async fn dismiss_system_dialog(
+        &self,
+        device_id: Option<&str>,
+    ) -> Result<Option<adb::SystemDialog>> {
+        match self.device_type {
+            DeviceType::Adb => adb::dismiss_system_dialog(device_id).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
+        }
+    }
+
+    /// Open a URL or deep link with the app registered to handle it
+    #[instrument(level = "debug", skip(self))]
+    pub async fn open_url(
+        &self,
+        url: &str,
+        device_id: Option<&str>,
+        delay: Option<f64>,
+    ) -> Result<()> {
+        match self.device_type {
+            DeviceType::Adb => adb::open_url(url, device_id, delay).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
+        }
+    }
+
+    /// Dump the most recent `lines` lines of the device log
+    #[instrument(level = "debug", skip(self))]
+    pub async fn capture_logcat(&self, device_id: Option<&str>, lines: usize) -> Result<String> {
+        match self.device_type {
+            DeviceType::Adb => adb::capture_logcat(device_id, lines).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
+        }
+    }
+
+    /// Force-stop an app, given by name or package id
+    #[instrument(level = "debug", skip(self))]
+    pub async fn force_stop_app(&self, app: &str, device_id: Option<&str>) -> Result<()> {
+        match self.device_type {
+            DeviceType::Adb => adb::force_stop_app(app, device_id).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
+        }
+    }
+
+    /// Clear an app's data, given by name or package id
+    ///
+    /// Useful for resetting an app to a known state before a task.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn clear_app_data(&self, app: &str, device_id: Option<&str>) -> Result<()> {
+        match self.device_type {
+            DeviceType::Adb => adb::clear_app_data(app, device_id).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
+        }
+    }
+
+    /// Put a finger down at screen coordinates using raw touch events
+    #[instrument(level = "debug", skip(self))]
+    pub async fn touch_down(&self, x: i32, y: i32, device_id: Option<&str>) -> Result<()> {
+        match self.device_type {
+            DeviceType::Adb => adb::touch_down(x, y, device_id).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
+        }
+    }
+
+    /// Move the finger that is down using raw touch events
+    #[instrument(level = "debug", skip(self))]
+    pub async fn touch_move(&self, x: i32, y: i32, device_id: Option<&str>) -> Result<()> {
+        match self.device_type {
+            DeviceType::Adb => adb::touch_move(x, y, device_id).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
+        }
+    }
+
+    /// Lift the finger that is down using raw touch events
+    #[instrument(level = "debug", skip(self))]
+    pub async fn touch_up(&self, device_id: Option<&str>) -> Result<()> {
+        match self.device_type {
+            DeviceType::Adb => adb::touch_up(device_id).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
+        }
+    }
+
+    /// Draw a touch path through screen coordinates with per-segment durations
+    #[instrument(level = "debug", skip(self, points, durations_ms), fields(points = points.len()))]
+    pub async fn gesture(
+        &self,
+        points: &[(i32, i32)],
+        durations_ms: &[u32],
+        device_id: Option<&str>,
+    ) -> Result<()> {
+        match self.device_type {
+            DeviceType::Adb => adb::gesture(points, durations_ms, device_id).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
+        }
+    }
+
+    /// Swipe along a smooth curve through waypoints in screen coordinates
+    #[instrument(level = "debug", skip(self, points), fields(points = points.len()))]
+    pub async fn swipe_path(
+        &self,
+        points: &[(i32, i32)],
+        duration_ms: u32,
+        device_id: Option<&str>,
+    ) -> Result<()> {
+        match self.device_type {
+            DeviceType::Adb => adb::swipe_path(points, duration_ms, device_id).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
         }
     }
 
     /// Press back button
+    #[instrument(level = "debug", skip(self))]
     pub async fn back(&self, device_id: Option<&str>, delay: Option<f64>) -> Result<()> {
         match self.device_type {
             DeviceType::Adb => adb::back(device_id, delay).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
         }
     }
 
     /// Press home button
+    #[instrument(level = "debug", skip(self))]
     pub async fn home(&self, device_id: Option<&str>, delay: Option<f64>) -> Result<()> {
         match self.device_type {
             DeviceType::Adb => adb::home(device_id, delay).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
         }
     }
 
     /// Launch an app
+    #[instrument(level = "debug", skip(self))]
     pub async fn launch_app(
         &self,
         app_name: &str,
@@ -132,34 +606,84 @@ impl DeviceFactory {
     ) -> Result<bool> {
         match self.device_type {
             DeviceType::Adb => adb::launch_app(app_name, device_id, delay).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
         }
     }
 
     /// Type text
+    #[instrument(level = "debug", skip(self, text), fields(chars = text.chars().count()))]
     pub async fn type_text(&self, text: &str, device_id: Option<&str>) -> Result<()> {
         match self.device_type {
             DeviceType::Adb => adb::type_text(text, device_id).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
         }
     }
 
     /// Clear text
+    #[instrument(level = "debug", skip(self))]
     pub async fn clear_text(&self, device_id: Option<&str>) -> Result<()> {
         match self.device_type {
             DeviceType::Adb => adb::clear_text(device_id).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
         }
     }
 
     /// Detect and set ADB keyboard
+    #[instrument(level = "debug", skip(self))]
     pub async fn detect_and_set_adb_keyboard(&self, device_id: Option<&str>) -> Result<String> {
         match self.device_type {
             DeviceType::Adb => adb::detect_and_set_adb_keyboard(device_id).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
         }
     }
 
     /// Restore keyboard
+    #[instrument(level = "debug", skip(self))]
     pub async fn restore_keyboard(&self, ime: &str, device_id: Option<&str>) -> Result<()> {
         match self.device_type {
             DeviceType::Adb => adb::restore_keyboard(ime, device_id).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
+        }
+    }
+
+    /// Check whether ADB Keyboard is installed
+    #[instrument(level = "debug", skip(self))]
+    pub async fn is_adb_keyboard_installed(&self, device_id: Option<&str>) -> Result<bool> {
+        match self.device_type {
+            DeviceType::Adb => adb::is_adb_keyboard_installed(device_id).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
+        }
+    }
+
+    /// Check whether an editable text field has input focus
+    #[instrument(level = "debug", skip(self))]
+    pub async fn is_text_input_focused(&self, device_id: Option<&str>) -> Result<bool> {
+        match self.device_type {
+            DeviceType::Adb => adb::is_text_input_focused(device_id).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
+        }
+    }
+
+    /// Check whether the soft keyboard is showing
+    #[instrument(level = "debug", skip(self))]
+    pub async fn is_keyboard_visible(&self, device_id: Option<&str>) -> Result<bool> {
+        match self.device_type {
+            DeviceType::Adb => adb::is_keyboard_visible(device_id).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
+        }
+    }
+
+    /// Type printable ASCII text without ADB Keyboard, optionally clearing the field first
+    #[instrument(level = "debug", skip(self, text), fields(chars = text.len()))]
+    pub async fn type_ascii_text(
+        &self,
+        text: &str,
+        clear: bool,
+        device_id: Option<&str>,
+    ) -> Result<()> {
+        match self.device_type {
+            DeviceType::Adb => adb::type_ascii_text(text, clear, device_id).await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
         }
     }
 
@@ -167,6 +691,7 @@ impl DeviceFactory {
     pub async fn list_devices(&self) -> Result<Vec<adb::DeviceInfo>> {
         match self.device_type {
             DeviceType::Adb => adb::list_devices().await,
+            DeviceType::Hdc | DeviceType::Ios => Err(self.unsupported()),
         }
     }
 }
@@ -177,6 +702,930 @@ impl Default for DeviceFactory {
     }
 }
 
+/// Device operations used by the agent and the action handler
+///
+/// [`DeviceFactory`] implements this for real devices, and
+/// [`GlobalDeviceController`] forwards to the global factory. Tests can
+/// implement it to feed canned screenshots and record input events.
+///
+/// Methods with a default cover capabilities not every controller has. Those
+/// that can't be approximated, such as reading the battery or sending
+/// intents, fail with [`AdbError::CommandFailed`] unless overridden.
+pub trait DeviceController: Send + Sync {
+    /// Get screenshot from device
+    fn get_screenshot<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+        timeout: u64,
+    ) -> BoxFuture<'a, Result<adb::Screenshot>>;
+
+    /// Get current app name
+    fn get_current_app<'a>(&'a self, device_id: Option<&'a str>) -> BoxFuture<'a, Result<String>>;
+
+    /// Tap at coordinates
+    fn tap<'a>(
+        &'a self,
+        x: i32,
+        y: i32,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// Double tap at coordinates
+    fn double_tap<'a>(
+        &'a self,
+        x: i32,
+        y: i32,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// Long press at coordinates
+    fn long_press<'a>(
+        &'a self,
+        x: i32,
+        y: i32,
+        duration_ms: u32,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// Swipe from start to end
+    #[allow(clippy::too_many_arguments)]
+    fn swipe<'a>(
+        &'a self,
+        start_x: i32,
+        start_y: i32,
+        end_x: i32,
+        end_y: i32,
+        duration_ms: Option<u32>,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// Press back button
+    fn back<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// Press home button
+    fn home<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// Launch an app
+    fn launch_app<'a>(
+        &'a self,
+        app_name: &'a str,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<bool>>;
+
+    /// Type text
+    fn type_text<'a>(
+        &'a self,
+        text: &'a str,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// Clear text
+    fn clear_text<'a>(&'a self, device_id: Option<&'a str>) -> BoxFuture<'a, Result<()>>;
+
+    /// Detect and set ADB keyboard, returning the original IME
+    fn detect_and_set_adb_keyboard<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<String>>;
+
+    /// Restore keyboard
+    fn restore_keyboard<'a>(
+        &'a self,
+        ime: &'a str,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// Scroll page content once in the given direction
+    fn scroll<'a>(
+        &'a self,
+        direction: ScrollDirection,
+        width: u32,
+        height: u32,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<()>> {
+        let (start_x, start_y, end_x, end_y) = direction.swipe_points(width, height);
+        self.swipe(start_x, start_y, end_x, end_y, None, device_id, None)
+    }
+
+    /// Fling in `direction` until the screen stops changing, e.g. to reach the top of a list
+    ///
+    /// Sends up to ten fast swipes, comparing screenshots between them.
+    /// Returns whether the edge was reached; a sensitive screen can't be
+    /// compared and counts as not reached.
+    fn fling_to_edge<'a>(
+        &'a self,
+        direction: ScrollDirection,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            let mut last = self.get_screenshot(device_id, 10).await?;
+            for _ in 0..MAX_EDGE_FLINGS {
+                let (start_x, start_y, end_x, end_y) =
+                    direction.swipe_points(last.width, last.height);
+                self.swipe(
+                    start_x,
+                    start_y,
+                    end_x,
+                    end_y,
+                    Some(FLING_DURATION_MS),
+                    device_id,
+                    None,
+                )
+                .await?;
+                tokio::time::sleep(FLING_SETTLE).await;
+
+                let frame = self.get_screenshot(device_id, 10).await?;
+                if frame.is_sensitive {
+                    return Ok(false);
+                }
+                if adb::screenshot_diff(&last, &frame)? < SCROLL_END_THRESHOLD {
+                    return Ok(true);
+                }
+                last = frame;
+            }
+            Ok(false)
+        })
+    }
+
+    /// Get the screen size in pixels
+    ///
+    /// Defaults to the dimensions of a fresh screenshot.
+    fn get_screen_size<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<(u32, u32)>> {
+        Box::pin(async move {
+            let screenshot = self.get_screenshot(device_id, 10).await?;
+            Ok((screenshot.width, screenshot.height))
+        })
+    }
+
+    /// Get the battery level in percent
+    fn get_battery_level<'a>(&'a self, _device_id: Option<&'a str>) -> BoxFuture<'a, Result<u8>> {
+        Box::pin(async {
+            Err(AdbError::CommandFailed(
+                "Battery level is not supported by this device".to_string(),
+            ))
+        })
+    }
+
+    /// Check whether the screen is on
+    ///
+    /// Controllers without a notion of screen state report it as always on.
+    fn is_screen_on<'a>(&'a self, _device_id: Option<&'a str>) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async { Ok(true) })
+    }
+
+    /// Turn the screen on
+    fn wake_screen<'a>(&'a self, _device_id: Option<&'a str>) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Fail with a specific error if the device can't run commands, e.g. in recovery
+    ///
+    /// Controllers that can't tell report the device as ready.
+    fn check_device_ready<'a>(&'a self, _device_id: Option<&'a str>) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Wake the device and dismiss the lock screen, entering `pin` if given
+    ///
+    /// Defaults to turning the screen on if it is off.
+    fn wake_device<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+        _pin: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            if !self.is_screen_on(device_id).await? {
+                self.wake_screen(device_id).await?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Open a URL or deep link
+    fn open_url<'a>(
+        &'a self,
+        _url: &'a str,
+        _device_id: Option<&'a str>,
+        _delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async {
+            Err(AdbError::CommandFailed(
+                "Opening URLs is not supported by this device".to_string(),
+            ))
+        })
+    }
+
+    /// Check whether ADB Keyboard is installed
+    ///
+    /// Controllers are assumed to have it unless they say otherwise.
+    fn is_adb_keyboard_installed<'a>(
+        &'a self,
+        _device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async { Ok(true) })
+    }
+
+    /// Check whether an editable text field has input focus
+    ///
+    /// Controllers that can't tell report a focused field, so typing goes ahead.
+    fn is_text_input_focused<'a>(
+        &'a self,
+        _device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async { Ok(true) })
+    }
+
+    /// Check whether the soft keyboard is showing
+    ///
+    /// Controllers that can't tell report it hidden, so nothing is dismissed.
+    fn is_keyboard_visible<'a>(
+        &'a self,
+        _device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async { Ok(false) })
+    }
+
+    /// Type printable ASCII text without ADB Keyboard, optionally clearing the field first
+    fn type_ascii_text<'a>(
+        &'a self,
+        _text: &'a str,
+        _clear: bool,
+        _device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async {
+            Err(AdbError::CommandFailed(
+                "ASCII text input is not supported by this device".to_string(),
+            ))
+        })
+    }
+
+    /// Dump the most recent `lines` lines of the device log
+    fn capture_logcat<'a>(
+        &'a self,
+        _device_id: Option<&'a str>,
+        _lines: usize,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async {
+            Err(AdbError::CommandFailed(
+                "Log capture is not supported by this device".to_string(),
+            ))
+        })
+    }
+
+    /// Dismiss an ANR or crash dialog if one has focus, returning it
+    ///
+    /// Controllers that can't inspect system dialogs never find one.
+    fn dismiss_system_dialog<'a>(
+        &'a self,
+        _device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Option<adb::SystemDialog>>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    /// Draw a touch path through screen coordinates with per-segment durations
+    fn gesture<'a>(
+        &'a self,
+        _points: &'a [(i32, i32)],
+        _durations_ms: &'a [u32],
+        _device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async {
+            Err(AdbError::CommandFailed(
+                "Gesture is not supported by this device".to_string(),
+            ))
+        })
+    }
+
+    /// Swipe along a smooth curve through waypoints in screen coordinates
+    ///
+    /// Draws the sampled curve with [`gesture`](Self::gesture) by default.
+    fn swipe_path<'a>(
+        &'a self,
+        points: &'a [(i32, i32)],
+        duration_ms: u32,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let (path, segment_ms) = adb::swipe_path_segments(points, duration_ms);
+            self.gesture(&path, &[segment_ms], device_id).await
+        })
+    }
+}
+
+impl DeviceController for DeviceFactory {
+    fn get_screenshot<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+        timeout: u64,
+    ) -> BoxFuture<'a, Result<adb::Screenshot>> {
+        Box::pin(DeviceFactory::get_screenshot(self, device_id, timeout))
+    }
+
+    fn get_current_app<'a>(&'a self, device_id: Option<&'a str>) -> BoxFuture<'a, Result<String>> {
+        Box::pin(DeviceFactory::get_current_app(self, device_id))
+    }
+
+    fn tap<'a>(
+        &'a self,
+        x: i32,
+        y: i32,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(DeviceFactory::tap(self, x, y, device_id, delay))
+    }
+
+    fn double_tap<'a>(
+        &'a self,
+        x: i32,
+        y: i32,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(DeviceFactory::double_tap(self, x, y, device_id, delay))
+    }
+
+    fn long_press<'a>(
+        &'a self,
+        x: i32,
+        y: i32,
+        duration_ms: u32,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(DeviceFactory::long_press(
+            self,
+            x,
+            y,
+            duration_ms,
+            device_id,
+            delay,
+        ))
+    }
+
+    fn swipe<'a>(
+        &'a self,
+        start_x: i32,
+        start_y: i32,
+        end_x: i32,
+        end_y: i32,
+        duration_ms: Option<u32>,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(DeviceFactory::swipe(
+            self,
+            start_x,
+            start_y,
+            end_x,
+            end_y,
+            duration_ms,
+            device_id,
+            delay,
+        ))
+    }
+
+    fn back<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(DeviceFactory::back(self, device_id, delay))
+    }
+
+    fn home<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(DeviceFactory::home(self, device_id, delay))
+    }
+
+    fn launch_app<'a>(
+        &'a self,
+        app_name: &'a str,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(DeviceFactory::launch_app(self, app_name, device_id, delay))
+    }
+
+    fn type_text<'a>(
+        &'a self,
+        text: &'a str,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(DeviceFactory::type_text(self, text, device_id))
+    }
+
+    fn clear_text<'a>(&'a self, device_id: Option<&'a str>) -> BoxFuture<'a, Result<()>> {
+        Box::pin(DeviceFactory::clear_text(self, device_id))
+    }
+
+    fn detect_and_set_adb_keyboard<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(DeviceFactory::detect_and_set_adb_keyboard(self, device_id))
+    }
+
+    fn restore_keyboard<'a>(
+        &'a self,
+        ime: &'a str,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(DeviceFactory::restore_keyboard(self, ime, device_id))
+    }
+
+    fn get_screen_size<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<(u32, u32)>> {
+        Box::pin(DeviceFactory::get_screen_size(self, device_id))
+    }
+
+    fn get_battery_level<'a>(&'a self, device_id: Option<&'a str>) -> BoxFuture<'a, Result<u8>> {
+        Box::pin(DeviceFactory::get_battery_level(self, device_id))
+    }
+
+    fn is_screen_on<'a>(&'a self, device_id: Option<&'a str>) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(DeviceFactory::is_screen_on(self, device_id))
+    }
+
+    fn wake_screen<'a>(&'a self, device_id: Option<&'a str>) -> BoxFuture<'a, Result<()>> {
+        Box::pin(DeviceFactory::wake_screen(self, device_id))
+    }
+
+    fn check_device_ready<'a>(&'a self, device_id: Option<&'a str>) -> BoxFuture<'a, Result<()>> {
+        Box::pin(DeviceFactory::check_device_ready(self, device_id))
+    }
+
+    fn wake_device<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+        pin: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(DeviceFactory::wake_device(self, device_id, pin))
+    }
+
+    fn open_url<'a>(
+        &'a self,
+        url: &'a str,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(DeviceFactory::open_url(self, url, device_id, delay))
+    }
+
+    fn is_adb_keyboard_installed<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(DeviceFactory::is_adb_keyboard_installed(self, device_id))
+    }
+
+    fn is_text_input_focused<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(DeviceFactory::is_text_input_focused(self, device_id))
+    }
+
+    fn is_keyboard_visible<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(DeviceFactory::is_keyboard_visible(self, device_id))
+    }
+
+    fn type_ascii_text<'a>(
+        &'a self,
+        text: &'a str,
+        clear: bool,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(DeviceFactory::type_ascii_text(self, text, clear, device_id))
+    }
+
+    fn capture_logcat<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+        lines: usize,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(DeviceFactory::capture_logcat(self, device_id, lines))
+    }
+
+    fn dismiss_system_dialog<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Option<adb::SystemDialog>>> {
+        Box::pin(DeviceFactory::dismiss_system_dialog(self, device_id))
+    }
+
+    fn gesture<'a>(
+        &'a self,
+        points: &'a [(i32, i32)],
+        durations_ms: &'a [u32],
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(DeviceFactory::gesture(
+            self,
+            points,
+            durations_ms,
+            device_id,
+        ))
+    }
+
+    fn swipe_path<'a>(
+        &'a self,
+        points: &'a [(i32, i32)],
+        duration_ms: u32,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(DeviceFactory::swipe_path(
+            self,
+            points,
+            duration_ms,
+            device_id,
+        ))
+    }
+}
+
+/// Device controller that forwards every call to the global device factory
+///
+/// Changes made with [`set_device_type`] take effect on the next call. The
+/// target display, screenshot encoding and fallback frame size are kept per
+/// controller, so agents with different settings don't affect each other.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GlobalDeviceController {
+    display_id: Option<u64>,
+    screenshot_encoding: Option<adb::ScreenshotEncoding>,
+    fallback_screen_size: Option<(u32, u32)>,
+}
+
+impl GlobalDeviceController {
+    /// Create a controller for the default display
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Target screenshots, taps and swipes at the given display
+    pub fn with_display_id(mut self, display_id: u64) -> Self {
+        self.display_id = Some(display_id);
+        self
+    }
+
+    /// Encode screenshots with the given encoding instead of the global factory's
+    pub fn with_screenshot_encoding(mut self, encoding: adb::ScreenshotEncoding) -> Self {
+        self.screenshot_encoding = Some(encoding);
+        self
+    }
+
+    /// Set the size of black fallback frames instead of the global factory's
+    pub fn with_fallback_screen_size(mut self, width: u32, height: u32) -> Self {
+        self.fallback_screen_size = Some((width, height));
+        self
+    }
+
+    /// The global factory with this controller's display and screenshot settings
+    async fn factory(&self) -> DeviceFactory {
+        let factory = get_device_factory().read().await.clone();
+        DeviceFactory {
+            display_id: self.display_id.or(factory.display_id),
+            screenshot_encoding: self
+                .screenshot_encoding
+                .unwrap_or(factory.screenshot_encoding),
+            fallback_screen_size: self.fallback_screen_size.or(factory.fallback_screen_size),
+            ..factory
+        }
+    }
+}
+
+impl DeviceController for GlobalDeviceController {
+    fn get_screenshot<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+        timeout: u64,
+    ) -> BoxFuture<'a, Result<adb::Screenshot>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.get_screenshot(device_id, timeout).await
+        })
+    }
+
+    fn get_current_app<'a>(&'a self, device_id: Option<&'a str>) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.get_current_app(device_id).await
+        })
+    }
+
+    fn tap<'a>(
+        &'a self,
+        x: i32,
+        y: i32,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.tap(x, y, device_id, delay).await
+        })
+    }
+
+    fn double_tap<'a>(
+        &'a self,
+        x: i32,
+        y: i32,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.double_tap(x, y, device_id, delay).await
+        })
+    }
+
+    fn long_press<'a>(
+        &'a self,
+        x: i32,
+        y: i32,
+        duration_ms: u32,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory
+                .long_press(x, y, duration_ms, device_id, delay)
+                .await
+        })
+    }
+
+    fn swipe<'a>(
+        &'a self,
+        start_x: i32,
+        start_y: i32,
+        end_x: i32,
+        end_y: i32,
+        duration_ms: Option<u32>,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory
+                .swipe(
+                    start_x,
+                    start_y,
+                    end_x,
+                    end_y,
+                    duration_ms,
+                    device_id,
+                    delay,
+                )
+                .await
+        })
+    }
+
+    fn back<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.back(device_id, delay).await
+        })
+    }
+
+    fn home<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.home(device_id, delay).await
+        })
+    }
+
+    fn launch_app<'a>(
+        &'a self,
+        app_name: &'a str,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.launch_app(app_name, device_id, delay).await
+        })
+    }
+
+    fn type_text<'a>(
+        &'a self,
+        text: &'a str,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.type_text(text, device_id).await
+        })
+    }
+
+    fn clear_text<'a>(&'a self, device_id: Option<&'a str>) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.clear_text(device_id).await
+        })
+    }
+
+    fn detect_and_set_adb_keyboard<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.detect_and_set_adb_keyboard(device_id).await
+        })
+    }
+
+    fn restore_keyboard<'a>(
+        &'a self,
+        ime: &'a str,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.restore_keyboard(ime, device_id).await
+        })
+    }
+
+    fn get_screen_size<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<(u32, u32)>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.get_screen_size(device_id).await
+        })
+    }
+
+    fn get_battery_level<'a>(&'a self, device_id: Option<&'a str>) -> BoxFuture<'a, Result<u8>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.get_battery_level(device_id).await
+        })
+    }
+
+    fn is_screen_on<'a>(&'a self, device_id: Option<&'a str>) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.is_screen_on(device_id).await
+        })
+    }
+
+    fn wake_screen<'a>(&'a self, device_id: Option<&'a str>) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.wake_screen(device_id).await
+        })
+    }
+
+    fn check_device_ready<'a>(&'a self, device_id: Option<&'a str>) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.check_device_ready(device_id).await
+        })
+    }
+
+    fn wake_device<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+        pin: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.wake_device(device_id, pin).await
+        })
+    }
+
+    fn open_url<'a>(
+        &'a self,
+        url: &'a str,
+        device_id: Option<&'a str>,
+        delay: Option<f64>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.open_url(url, device_id, delay).await
+        })
+    }
+
+    fn capture_logcat<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+        lines: usize,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.capture_logcat(device_id, lines).await
+        })
+    }
+
+    fn is_adb_keyboard_installed<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.is_adb_keyboard_installed(device_id).await
+        })
+    }
+
+    fn is_text_input_focused<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.is_text_input_focused(device_id).await
+        })
+    }
+
+    fn is_keyboard_visible<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.is_keyboard_visible(device_id).await
+        })
+    }
+
+    fn type_ascii_text<'a>(
+        &'a self,
+        text: &'a str,
+        clear: bool,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.type_ascii_text(text, clear, device_id).await
+        })
+    }
+
+    fn dismiss_system_dialog<'a>(
+        &'a self,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Option<adb::SystemDialog>>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.dismiss_system_dialog(device_id).await
+        })
+    }
+
+    fn gesture<'a>(
+        &'a self,
+        points: &'a [(i32, i32)],
+        durations_ms: &'a [u32],
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.gesture(points, durations_ms, device_id).await
+        })
+    }
+
+    fn swipe_path<'a>(
+        &'a self,
+        points: &'a [(i32, i32)],
+        duration_ms: u32,
+        device_id: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let factory = self.factory().await;
+            factory.swipe_path(points, duration_ms, device_id).await
+        })
+    }
+}
+
 /// Global device factory instance
 static DEVICE_FACTORY: OnceLock<RwLock<DeviceFactory>> = OnceLock::new();
 
@@ -201,4 +1650,38 @@ mod tests {
         let factory = DeviceFactory::default();
         assert_eq!(factory.device_type(), DeviceType::Adb);
     }
+
+    #[test]
+    fn test_device_type_parse_and_display() {
+        for device_type in [DeviceType::Adb, DeviceType::Hdc, DeviceType::Ios] {
+            let parsed: DeviceType = device_type.to_string().parse().unwrap();
+            assert_eq!(parsed, device_type);
+        }
+        assert_eq!("HDC".parse::<DeviceType>().unwrap(), DeviceType::Hdc);
+        assert_eq!(DeviceType::Ios.tool_cmd(), "idevice_id");
+        assert!("xctest".parse::<DeviceType>().is_err());
+    }
+
+    #[test]
+    fn test_scroll_direction_parse() {
+        assert_eq!(ScrollDirection::parse("Down"), Some(ScrollDirection::Down));
+        assert_eq!(ScrollDirection::parse("left"), Some(ScrollDirection::Left));
+        assert_eq!(ScrollDirection::parse("sideways"), None);
+    }
+
+    #[test]
+    fn test_scroll_down_swipes_upwards() {
+        let (start_x, start_y, end_x, end_y) = ScrollDirection::Down.swipe_points(1000, 2000);
+        assert_eq!((start_x, end_x), (500, 500));
+        assert!(start_y > end_y);
+    }
+
+    #[tokio::test]
+    async fn test_display_id_is_per_controller() {
+        let cover = GlobalDeviceController::new().with_display_id(1);
+        let main = GlobalDeviceController::new();
+        assert_eq!(cover.factory().await.display_id(), Some(1));
+        assert_eq!(main.factory().await.display_id(), None);
+        assert_eq!(get_device_factory().read().await.display_id(), None);
+    }
 }
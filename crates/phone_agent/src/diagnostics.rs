@@ -0,0 +1,188 @@
+//! Versions of the device backend tools, for bug reports
+
+use std::env;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::process::Command;
+use tracing::debug;
+
+use crate::adb::{adb_command, get_adb_envs};
+use crate::device_factory::DeviceType;
+use crate::error::{AdbError, Result};
+use crate::xctest::wda_info;
+
+/// Timeout for each version query
+const VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Port the ADB server listens on unless `ANDROID_ADB_SERVER_PORT` is set
+const DEFAULT_ADB_SERVER_PORT: u16 = 5037;
+
+/// Versions of the tools behind a device backend
+///
+/// Fields the backend doesn't have, or that couldn't be read, are `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackendVersions {
+    /// Backend the versions were read for
+    pub device_type: DeviceType,
+    /// Version of the command-line tool, e.g. `1.0.41` for adb
+    pub client: Option<String>,
+    /// Version of the ADB server, in the same format as the client version
+    pub server: Option<String>,
+    /// OS version of the device, e.g. `Android 14 (SDK 34)`
+    pub device_os: Option<String>,
+    /// Version of WebDriverAgent (iOS)
+    pub wda: Option<String>,
+}
+
+/// Read the versions of the tools behind `device_type`
+///
+/// The device OS version is read from `device_id`, or the only connected
+/// device. For iOS, WebDriverAgent is queried at `wda_url` when given. Fails
+/// only if the backend's command-line tool can't be run.
+pub async fn backend_versions(
+    device_type: DeviceType,
+    device_id: Option<&str>,
+    wda_url: Option<&str>,
+) -> Result<BackendVersions> {
+    let mut versions = BackendVersions {
+        device_type,
+        client: None,
+        server: None,
+        device_os: None,
+        wda: None,
+    };
+
+    match device_type {
+        DeviceType::Adb => {
+            let output = run_tool(adb_command(None).arg("version")).await?;
+            versions.client = parse_adb_client_version(&output);
+            versions.server = match adb_server_version().await {
+                // The client version ends in the server's protocol version
+                Ok(version) => Some(format!("1.0.{}", version)),
+                Err(e) => {
+                    debug!(error = %e, "failed to read ADB server version");
+                    None
+                }
+            };
+
+            let getprop = |name: &str| {
+                let mut cmd = adb_command(device_id);
+                cmd.args(["shell", "getprop", name]);
+                cmd
+            };
+            let release = run_tool(&mut getprop("ro.build.version.release")).await;
+            let sdk = run_tool(&mut getprop("ro.build.version.sdk")).await;
+            if let (Ok(release), Ok(sdk)) = (release, sdk) {
+                versions.device_os = Some(format!("Android {} (SDK {})", release, sdk));
+            }
+        }
+        DeviceType::Hdc => {
+            let output = run_tool(Command::new("hdc").arg("-v")).await?;
+            versions.client = Some(output.trim_start_matches("Ver:").trim().to_string());
+        }
+        DeviceType::Ios => {
+            let output = run_tool(Command::new("ideviceinfo").arg("--version")).await?;
+            versions.client = Some(output);
+
+            let mut cmd = Command::new("ideviceinfo");
+            if let Some(id) = device_id {
+                cmd.arg("-u").arg(id);
+            }
+            cmd.args(["-k", "ProductVersion"]);
+            versions.device_os = run_tool(&mut cmd).await.ok().map(|v| format!("iOS {}", v));
+
+            if let Some(url) = wda_url {
+                versions.wda = match wda_info(url).await {
+                    Ok(info) => info.build_version,
+                    Err(e) => {
+                        debug!(error = %e, "failed to read WebDriverAgent status");
+                        None
+                    }
+                };
+            }
+        }
+    }
+
+    Ok(versions)
+}
+
+/// Run a version command and return its trimmed stdout
+async fn run_tool(cmd: &mut Command) -> Result<String> {
+    cmd.kill_on_drop(true);
+    let output = tokio::time::timeout(VERSION_TIMEOUT, cmd.output())
+        .await
+        .map_err(|_| AdbError::Timeout("version check timeout after 5s".to_string()))?
+        .map_err(AdbError::Io)?;
+
+    if !output.status.success() {
+        return Err(AdbError::CommandFailed(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Parse `adb version` output, e.g. `Android Debug Bridge version 1.0.41`
+fn parse_adb_client_version(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix("Android Debug Bridge version "))
+        .map(|version| version.trim().to_string())
+}
+
+/// Ask the running ADB server for its protocol version, e.g. 41
+async fn adb_server_version() -> Result<u32> {
+    let port = get_adb_envs()
+        .into_iter()
+        .find(|(key, _)| key == "ANDROID_ADB_SERVER_PORT")
+        .map(|(_, value)| value)
+        .or_else(|| env::var("ANDROID_ADB_SERVER_PORT").ok())
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(DEFAULT_ADB_SERVER_PORT);
+
+    let query = async {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+        let request = "host:version";
+        stream
+            .write_all(format!("{:04x}{}", request.len(), request).as_bytes())
+            .await?;
+        let mut reply = [0u8; 12];
+        stream.read_exact(&mut reply).await?;
+        Ok::<_, std::io::Error>(reply)
+    };
+    let reply = tokio::time::timeout(VERSION_TIMEOUT, query)
+        .await
+        .map_err(|_| AdbError::Timeout("ADB server version timeout after 5s".to_string()))??;
+
+    parse_server_reply(&reply).ok_or_else(|| {
+        AdbError::ParseError(format!(
+            "Unexpected ADB server reply: {}",
+            String::from_utf8_lossy(&reply)
+        ))
+    })
+}
+
+/// Parse the reply to `host:version`: `OKAY`, a 4-digit hex length, then the hex version
+fn parse_server_reply(reply: &[u8]) -> Option<u32> {
+    let reply = std::str::from_utf8(reply).ok()?;
+    let version = reply.strip_prefix("OKAY")?.get(4..8)?;
+    u32::from_str_radix(version, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_adb_versions() {
+        let output = "Android Debug Bridge version 1.0.41\n\
+            Version 35.0.2-12147458\n\
+            Installed as /usr/local/bin/adb\n";
+        assert_eq!(parse_adb_client_version(output).as_deref(), Some("1.0.41"));
+        assert_eq!(parse_adb_client_version(""), None);
+
+        assert_eq!(parse_server_reply(b"OKAY00040029"), Some(41));
+        assert_eq!(parse_server_reply(b"FAIL0004oops"), None);
+    }
+}
@@ -13,6 +13,14 @@ pub enum AdbError {
     #[error("Device not found: {0}")]
     DeviceNotFound(String),
 
+    #[error("Device unavailable: {0}")]
+    DeviceUnavailable(String),
+
+    /// The device didn't return a usable frame; `sensitive` is set when the
+    /// screen refused capture, e.g. a payment or password screen
+    #[error("Screenshot failed: {reason}")]
+    ScreenshotFailed { reason: String, sensitive: bool },
+
     #[error("App not found: {0}")]
     AppNotFound(String),
 
@@ -0,0 +1,217 @@
+//! Job files describing a task together with its model and agent settings
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::agent::AgentConfig;
+use crate::config::Language;
+use crate::error::{AdbError, Result};
+use crate::model::{redact_api_key, ModelConfig};
+
+/// A repeatable automation job loaded from a YAML, TOML or JSON file
+///
+/// Every field except `task` is optional; unset fields keep the value of the
+/// configuration they are applied to.
+///
+/// ```yaml
+/// task: Open WeChat and send "hello" to Alice
+/// base_url: http://localhost:8000/v1
+/// model: autoglm-phone-9b
+/// max_steps: 50
+/// lang: en
+/// device_id: emulator-5554
+/// screenshot_dir: ./screenshots
+/// ```
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobSpec {
+    /// Task to execute
+    pub task: String,
+    /// Model API base URL
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Model name
+    #[serde(default)]
+    pub model: Option<String>,
+    /// API key for model authentication
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// Maximum steps per task
+    #[serde(default)]
+    pub max_steps: Option<usize>,
+    /// Language for system prompt and messages (`cn` or `en`)
+    #[serde(default)]
+    pub lang: Option<String>,
+    /// ADB device ID
+    #[serde(default)]
+    pub device_id: Option<String>,
+    /// Directory to save screenshots
+    #[serde(default)]
+    pub screenshot_dir: Option<PathBuf>,
+    /// Verbose console output
+    #[serde(default)]
+    pub verbose: Option<bool>,
+}
+
+impl fmt::Debug for JobSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JobSpec")
+            .field("task", &self.task)
+            .field("base_url", &self.base_url)
+            .field("model", &self.model)
+            .field("api_key", &self.api_key.as_deref().map(redact_api_key))
+            .field("max_steps", &self.max_steps)
+            .field("lang", &self.lang)
+            .field("device_id", &self.device_id)
+            .field("screenshot_dir", &self.screenshot_dir)
+            .field("verbose", &self.verbose)
+            .finish()
+    }
+}
+
+impl JobSpec {
+    /// Load a job from a file
+    ///
+    /// Files ending in `.json` are parsed as JSON, files ending in `.toml` as
+    /// TOML, and everything else as YAML.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+
+        match ext.as_deref() {
+            Some("json") => Self::from_json(&content),
+            Some("toml") => Self::from_toml(&content),
+            _ => Self::from_yaml(&content),
+        }
+    }
+
+    /// Parse a job from a YAML string
+    pub fn from_yaml(content: &str) -> Result<Self> {
+        serde_norway::from_str(content)
+            .map_err(|e| AdbError::ParseError(format!("Invalid job YAML: {}", e)))
+    }
+
+    /// Parse a job from a JSON string
+    pub fn from_json(content: &str) -> Result<Self> {
+        serde_json::from_str(content)
+            .map_err(|e| AdbError::ParseError(format!("Invalid job JSON: {}", e)))
+    }
+
+    /// Parse a job from a TOML string
+    pub fn from_toml(content: &str) -> Result<Self> {
+        toml::from_str(content)
+            .map_err(|e| AdbError::ParseError(format!("Invalid job TOML: {}", e)))
+    }
+
+    /// Get the job language, if set
+    pub fn language(&self) -> Option<Language> {
+        self.lang.as_deref().map(Language::from_str)
+    }
+
+    /// Apply the job's model settings on top of an existing model config
+    pub fn apply_to_model_config(&self, mut config: ModelConfig) -> ModelConfig {
+        if let Some(ref base_url) = self.base_url {
+            config.base_url = base_url.clone();
+        }
+        if let Some(ref model) = self.model {
+            config.model_name = model.clone();
+        }
+        if let Some(ref api_key) = self.api_key {
+            config.api_key = api_key.clone();
+        }
+        if let Some(lang) = self.language() {
+            config.lang = lang;
+        }
+        config
+    }
+
+    /// Apply the job's agent settings on top of an existing agent config
+    pub fn apply_to_agent_config(&self, mut config: AgentConfig) -> AgentConfig {
+        if let Some(max_steps) = self.max_steps {
+            config.max_steps = max_steps;
+        }
+        if let Some(lang) = self.language() {
+            config.lang = lang;
+        }
+        if let Some(ref device_id) = self.device_id {
+            config.device_id = Some(device_id.clone());
+        }
+        if let Some(ref screenshot_dir) = self.screenshot_dir {
+            config.screenshot_dir = Some(screenshot_dir.clone());
+        }
+        if let Some(verbose) = self.verbose {
+            config.verbose = verbose;
+        }
+        config
+    }
+
+    /// Build model and agent configs from defaults plus the job's settings
+    pub fn to_configs(&self) -> (ModelConfig, AgentConfig) {
+        (
+            self.apply_to_model_config(ModelConfig::default()),
+            self.apply_to_agent_config(AgentConfig::default()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_from_yaml() {
+        let job = JobSpec::from_yaml(
+            "task: Open WeChat\nmodel: custom-model\nmax_steps: 20\nlang: en\ndevice_id: emulator-5554\n",
+        )
+        .unwrap();
+
+        let (model_config, agent_config) = job.to_configs();
+        assert_eq!(job.task, "Open WeChat");
+        assert_eq!(model_config.model_name, "custom-model");
+        assert_eq!(model_config.lang, Language::English);
+        assert_eq!(agent_config.max_steps, 20);
+        assert_eq!(agent_config.device_id, Some("emulator-5554".to_string()));
+    }
+
+    #[test]
+    fn test_job_from_toml() {
+        let job = JobSpec::from_toml(
+            r#"
+task = "Open WeChat"
+model = "custom-model"
+max_steps = 20
+lang = "en"
+device_id = "emulator-5554"
+"#,
+        )
+        .unwrap();
+
+        let (model_config, agent_config) = job.to_configs();
+        assert_eq!(job.task, "Open WeChat");
+        assert_eq!(model_config.model_name, "custom-model");
+        assert_eq!(model_config.lang, Language::English);
+        assert_eq!(agent_config.max_steps, 20);
+        assert_eq!(agent_config.device_id, Some("emulator-5554".to_string()));
+    }
+
+    #[test]
+    fn test_job_from_json_keeps_unset_fields() {
+        let job = JobSpec::from_json(r#"{"task": "Open Settings", "verbose": false}"#).unwrap();
+        let agent_config = job.apply_to_agent_config(AgentConfig::new().with_max_steps(7));
+
+        assert_eq!(agent_config.max_steps, 7);
+        assert!(!agent_config.verbose);
+    }
+
+    #[test]
+    fn test_job_rejects_unknown_fields() {
+        assert!(JobSpec::from_yaml("task: x\nmax_step: 3\n").is_err());
+        assert!(JobSpec::from_toml("task = \"x\"\nmax_step = 3\n").is_err());
+    }
+}
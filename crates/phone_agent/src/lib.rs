@@ -46,41 +46,74 @@ pub mod xctest;
 // Core functionality
 pub mod actions;
 pub mod agent;
+pub mod batch;
 pub mod device_factory;
+pub mod diagnostics;
+pub mod job;
+pub mod metrics;
 pub mod model;
 pub mod screenshot_saver;
+pub mod transcript;
 
 // Re-export commonly used types and functions
 pub use error::{AdbError, Result};
 
 // Config re-exports
 pub use config::{
-    get_app_name, get_message, get_messages, get_package_name, get_system_prompt,
-    list_supported_apps, ActionTimingConfig, ConnectionTimingConfig, DeviceTimingConfig, Language,
-    TimingConfig, APP_PACKAGES, MESSAGES_EN, MESSAGES_ZH, TIMING_CONFIG,
+    app_entries, get_app_name, get_message, get_messages, get_package_name, get_system_prompt,
+    list_supported_apps, load_app_overrides, register_app, ActionTimingConfig,
+    ConnectionTimingConfig, DeviceTimingConfig, Language, TimingConfig, APP_PACKAGES, MESSAGES_EN,
+    MESSAGES_ZH, TIMING_CONFIG,
 };
 
 // ADB re-exports
 pub use adb::{
-    back, clear_text, detect_and_set_adb_keyboard, double_tap, get_current_app, get_screenshot,
-    home, launch_app, list_devices, long_press, quick_connect, restore_keyboard, swipe, tap,
-    type_text, AdbConnection, ConnectionType, DeviceInfo, Screenshot,
+    back, capture_logcat, check_device_ready, clear_app_data, clear_text, crop_screenshot,
+    detect_and_set_adb_keyboard, detect_system_dialog, dismiss_system_dialog, double_tap,
+    downscale_screenshot, force_stop_app, get_adb_path, get_battery_level, get_current_app,
+    get_foreground_package, get_screen_size, get_screenshot, get_screenshot_image, home,
+    is_adb_keyboard_installed, is_keyboard_visible, is_locked, is_screen_on,
+    is_text_input_focused, launch_app, list_devices, long_press, open_url, perceptual_hash,
+    quick_connect, reconcile_screen_size, restore_keyboard, screenshot_diff, set_adb_env,
+    set_adb_path, stitch_horizontal, stitch_vertical, swipe, tap, touch_down, touch_move,
+    touch_up, transform_screenshot, type_ascii_text, type_text, wake_device, wake_screen,
+    AdbConnection, ConnectionType, DeviceInfo, DeviceStatus, HeartbeatHandle, InputBackend,
+    PortForward, Screenshot, ScreenshotEncoding, SystemDialog, TouchDevice,
 };
 
 // Device factory re-exports
-pub use device_factory::{get_device_factory, set_device_type, DeviceFactory, DeviceType};
+pub use device_factory::{
+    get_device_factory, set_device_type, DeviceController, DeviceFactory, DeviceType,
+    GlobalDeviceController, ScrollDirection,
+};
 
 // Model re-exports
-pub use model::{MessageBuilder, ModelClient, ModelConfig, ModelResponse};
+pub use model::{
+    parse_response, redact_api_key, CompletionRequest, ImageDetail, ImageSource, MessageBuilder,
+    ModelClient, ModelConfig, ModelError, ModelResponse, ModelTransport, OpenAiTransport,
+    ResponseTags, ScreenInfo, StreamCallback, TextStream, DEFAULT_ASSISTANT_TEMPLATE,
+    RESERVED_HEADERS,
+};
 
 // Actions re-exports
 pub use actions::{
-    do_action, finish_action, parse_action, ActionHandler, ActionResult, ConfirmationCallback,
+    absolute_to_relative, describe_action, do_action, find_action, finish_action,
+    normalize_coordinates, parse_action, parse_action_typed, relative_to_absolute,
+    supported_actions, validate_action, Action, ActionHandler, ActionResult, ActionSpec,
+    AsyncConfirmationCallback, ConfirmationCallback, RelativePoint, RelativeRegion,
     TakeoverCallback,
 };
 
+// Job re-exports
+pub use job::JobSpec;
+
 // Agent re-exports
-pub use agent::{AgentConfig, PhoneAgent, StepResult};
+pub use agent::{
+    AgentConfig, FinishReason, ImageStrategy, PhoneAgent, RunOutcome, ScreenInfoFields,
+    ScreenshotPreprocessor, SensitivePolicy, StepCallback, StepResult, UnlockPin,
+};
+pub use batch::BatchRunner;
+pub use metrics::{LatencyStats, RunMetrics};
 
 // Screenshot saver re-exports
-pub use screenshot_saver::ScreenshotSaver;
+pub use screenshot_saver::{extract_session, read_session, ScreenshotSaver, SessionEntry};
@@ -0,0 +1,145 @@
+//! Latency metrics aggregated across the steps of a run
+
+use crate::model::ModelResponse;
+
+/// Summary statistics of a series of durations, in seconds
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LatencyStats {
+    /// Number of samples
+    pub count: usize,
+    /// Sum of all samples
+    pub total: f64,
+    /// Mean of all samples
+    pub mean: f64,
+    /// Median (nearest-rank)
+    pub p50: f64,
+    /// 95th percentile (nearest-rank)
+    pub p95: f64,
+}
+
+impl LatencyStats {
+    /// Compute statistics over `samples`; all zero when there are none
+    pub fn from_samples(samples: &[f64]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let percentile = |p: f64| {
+            let rank = (p * sorted.len() as f64).ceil() as usize;
+            sorted[rank.clamp(1, sorted.len()) - 1]
+        };
+
+        let total: f64 = sorted.iter().sum();
+        Self {
+            count: sorted.len(),
+            total,
+            mean: total / sorted.len() as f64,
+            p50: percentile(0.50),
+            p95: percentile(0.95),
+        }
+    }
+}
+
+/// Performance metrics of the agent's current run
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunMetrics {
+    /// Number of steps executed
+    pub steps: usize,
+    /// Time to first token of each model request
+    pub time_to_first_token: LatencyStats,
+    /// Time until the model finished thinking, per request
+    pub time_to_thinking_end: LatencyStats,
+    /// Total inference time of each model request (including re-prompts)
+    pub inference: LatencyStats,
+    /// Time spent executing each action on the device
+    pub action: LatencyStats,
+}
+
+/// Collects per-step samples for [`RunMetrics`]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MetricsRecorder {
+    steps: usize,
+    time_to_first_token: Vec<f64>,
+    time_to_thinking_end: Vec<f64>,
+    inference: Vec<f64>,
+    action: Vec<f64>,
+}
+
+impl MetricsRecorder {
+    /// Record the timings of one model request
+    pub(crate) fn record_model(&mut self, response: &ModelResponse) {
+        self.time_to_first_token
+            .extend(response.time_to_first_token);
+        self.time_to_thinking_end
+            .extend(response.time_to_thinking_end);
+        self.inference.extend(response.total_time);
+    }
+
+    /// Record a completed step and the time its action took
+    pub(crate) fn record_step(&mut self, action_secs: f64) {
+        self.steps += 1;
+        self.action.push(action_secs);
+    }
+
+    /// Drop all samples
+    pub(crate) fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Summarize the samples collected so far
+    pub(crate) fn summary(&self) -> RunMetrics {
+        RunMetrics {
+            steps: self.steps,
+            time_to_first_token: LatencyStats::from_samples(&self.time_to_first_token),
+            time_to_thinking_end: LatencyStats::from_samples(&self.time_to_thinking_end),
+            inference: LatencyStats::from_samples(&self.inference),
+            action: LatencyStats::from_samples(&self.action),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_stats() {
+        let samples: Vec<f64> = (1..=20).map(|i| i as f64).collect();
+        let stats = LatencyStats::from_samples(&samples);
+
+        assert_eq!(stats.count, 20);
+        assert_eq!(stats.total, 210.0);
+        assert_eq!(stats.mean, 10.5);
+        assert_eq!(stats.p50, 10.0);
+        assert_eq!(stats.p95, 19.0);
+        assert_eq!(LatencyStats::from_samples(&[]), LatencyStats::default());
+    }
+
+    #[test]
+    fn test_metrics_recorder() {
+        let mut recorder = MetricsRecorder::default();
+        recorder.record_model(&ModelResponse {
+            thinking: String::new(),
+            action: String::new(),
+            raw_content: String::new(),
+            action_found: true,
+            truncated: false,
+            time_to_first_token: Some(0.5),
+            time_to_thinking_end: None,
+            total_time: Some(2.0),
+        });
+        recorder.record_step(1.5);
+
+        let metrics = recorder.summary();
+        assert_eq!(metrics.steps, 1);
+        assert_eq!(metrics.time_to_first_token.total, 0.5);
+        assert_eq!(metrics.time_to_thinking_end.count, 0);
+        assert_eq!(metrics.inference.mean, 2.0);
+        assert_eq!(metrics.action.total, 1.5);
+
+        recorder.clear();
+        assert_eq!(recorder.summary(), RunMetrics::default());
+    }
+}
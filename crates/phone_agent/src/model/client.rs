@@ -1,27 +1,94 @@
 //! Model client for AI inference using OpenAI-compatible API
 
-use async_openai::{
-    config::OpenAIConfig,
-    types::{
-        ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
-        ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
-        ChatCompletionRequestUserMessageContent, ChatCompletionRequestUserMessageContentPart,
-        CreateChatCompletionRequestArgs, ImageDetail, ImageUrl,
-    },
-    Client,
+use async_openai::types::{
+    ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
+    ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
+    ChatCompletionRequestUserMessageContent, ChatCompletionRequestUserMessageContentPart,
+    ImageDetail, ImageUrl,
 };
 use futures::StreamExt;
-use serde::{Deserialize, Serialize};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize, Serializer};
 use serde_json::json;
-use std::io::{self, Write};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
 use std::time::Instant;
+use tracing::{debug, info, instrument, warn};
+
+use crate::adb::image_mime_type;
+use crate::config::Language;
+
+use super::transport::{CompletionRequest, ModelError, ModelTransport, OpenAiTransport};
+
+/// Callback type for streamed thinking tokens
+pub type StreamCallback = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// Tags used by models that wrap their output as `<think>..</think><answer>..</answer>`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResponseTags {
+    pub think_start: String,
+    pub think_end: String,
+    pub answer_start: String,
+    pub answer_end: String,
+}
+
+impl Default for ResponseTags {
+    fn default() -> Self {
+        Self {
+            think_start: "<think>".to_string(),
+            think_end: "</think>".to_string(),
+            answer_start: "<answer>".to_string(),
+            answer_end: "</answer>".to_string(),
+        }
+    }
+}
+
+/// Default action-start markers for AutoGLM models
+fn default_action_markers() -> Vec<String> {
+    vec!["finish(message=".to_string(), "do(action=".to_string()]
+}
+
+/// Default template for the model's turns in the conversation history
+pub const DEFAULT_ASSISTANT_TEMPLATE: &str = "<think>{thinking}</think><answer>{action}</answer>";
 
-use crate::config::{get_message, Language};
+/// Headers set by the client itself; entries in [`ModelConfig::headers`] with these names are ignored
+pub const RESERVED_HEADERS: &[&str] = &["authorization", "content-type", "openai-beta"];
+
+/// Mask an API key for display, e.g. `sk-***`
+///
+/// The `EMPTY` placeholder used with local servers isn't secret and is kept.
+pub fn redact_api_key(api_key: &str) -> String {
+    if api_key.is_empty() || api_key == "EMPTY" {
+        return api_key.to_string();
+    }
+    match api_key.char_indices().nth(3) {
+        Some((i, _)) if api_key.chars().count() > 8 => format!("{}***", &api_key[..i]),
+        _ => "***".to_string(),
+    }
+}
+
+fn serialize_redacted<S: Serializer>(api_key: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&redact_api_key(api_key))
+}
+
+fn serialize_redacted_headers<S: Serializer>(
+    headers: &HashMap<String, String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(
+        headers
+            .iter()
+            .map(|(name, value)| (name, redact_api_key(value))),
+    )
+}
 
 /// Configuration for the AI model
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ModelConfig {
     pub base_url: String,
+    /// Redacted when serialized or debug-printed, see [`redact_api_key`]
+    #[serde(serialize_with = "serialize_redacted")]
     pub api_key: String,
     pub model_name: String,
     pub max_tokens: u32,
@@ -29,6 +96,50 @@ pub struct ModelConfig {
     pub top_p: f32,
     pub frequency_penalty: f32,
     pub lang: Language,
+    /// Detail level requested for screenshots sent to the vision model
+    pub image_detail: ImageDetail,
+    /// Receives thinking token deltas as they stream in
+    #[serde(skip)]
+    pub stream_callback: Option<StreamCallback>,
+    /// Strings that start an action in the model output, checked in order
+    pub action_markers: Vec<String>,
+    /// Think/answer tags used when no action marker is found
+    pub response_tags: Option<ResponseTags>,
+    /// How the model's turns are written back into the conversation history
+    ///
+    /// `{thinking}` and `{action}` are replaced with the parsed response.
+    pub assistant_template: String,
+    /// HTTP(S) proxy all model requests go through
+    pub proxy: Option<String>,
+    /// Extra headers sent with every model request; values are redacted when serialized
+    #[serde(serialize_with = "serialize_redacted_headers")]
+    pub headers: HashMap<String, String>,
+    /// Backend for model requests; `None` uses [`OpenAiTransport`]
+    #[serde(skip)]
+    pub transport: Option<Arc<dyn ModelTransport>>,
+}
+
+impl fmt::Debug for ModelConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ModelConfig")
+            .field("base_url", &self.base_url)
+            .field("api_key", &redact_api_key(&self.api_key))
+            .field("model_name", &self.model_name)
+            .field("max_tokens", &self.max_tokens)
+            .field("temperature", &self.temperature)
+            .field("top_p", &self.top_p)
+            .field("frequency_penalty", &self.frequency_penalty)
+            .field("lang", &self.lang)
+            .field("image_detail", &self.image_detail)
+            .field("stream_callback", &self.stream_callback.is_some())
+            .field("action_markers", &self.action_markers)
+            .field("response_tags", &self.response_tags)
+            .field("assistant_template", &self.assistant_template)
+            .field("proxy", &self.proxy)
+            .field("headers", &self.headers.keys().collect::<Vec<_>>())
+            .field("transport", &self.transport.is_some())
+            .finish()
+    }
 }
 
 impl Default for ModelConfig {
@@ -42,6 +153,14 @@ impl Default for ModelConfig {
             top_p: 0.85,
             frequency_penalty: 0.2,
             lang: Language::Chinese,
+            image_detail: ImageDetail::Auto,
+            stream_callback: None,
+            action_markers: default_action_markers(),
+            response_tags: Some(ResponseTags::default()),
+            assistant_template: DEFAULT_ASSISTANT_TEMPLATE.to_string(),
+            proxy: None,
+            headers: HashMap::new(),
+            transport: None,
         }
     }
 }
@@ -67,6 +186,160 @@ impl ModelConfig {
         self.lang = lang;
         self
     }
+
+    /// Set the image detail level (`Low` saves tokens, `High` helps dense UIs)
+    pub fn with_image_detail(mut self, image_detail: ImageDetail) -> Self {
+        self.image_detail = image_detail;
+        self
+    }
+
+    /// Set a callback that receives thinking token deltas
+    ///
+    /// Streamed thinking text is passed to the callback as it arrives, e.g.
+    /// to show it live. Action text is never forwarded.
+    pub fn with_stream_callback(mut self, callback: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.stream_callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Set the strings that mark the start of an action, checked in order
+    ///
+    /// The marker is kept as the start of the parsed action, e.g. `do(action=`.
+    pub fn with_action_markers<I, S>(mut self, markers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.action_markers = markers.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the think/answer tags, or `None` to disable tag parsing
+    pub fn with_response_tags(mut self, tags: Option<ResponseTags>) -> Self {
+        self.response_tags = tags;
+        self
+    }
+
+    /// Set the template for the model's turns in the history, e.g. `"{thinking}\n{action}"`
+    ///
+    /// Use this to match the format the model was trained on.
+    pub fn with_assistant_template(mut self, template: impl Into<String>) -> Self {
+        self.assistant_template = template.into();
+        self
+    }
+
+    /// Write a model turn for the history using [`Self::assistant_template`]
+    pub fn format_assistant_message(&self, thinking: &str, action: &str) -> String {
+        // Split first so placeholders inside the response itself are left alone
+        self.assistant_template
+            .split("{action}")
+            .map(|part| part.replace("{thinking}", thinking))
+            .collect::<Vec<_>>()
+            .join(action)
+    }
+
+    /// Route model requests through an HTTP(S) proxy, e.g. `http://proxy:3128`
+    pub fn with_proxy(mut self, url: impl Into<String>) -> Self {
+        self.proxy = Some(url.into());
+        self
+    }
+
+    /// Send extra headers with every model request
+    ///
+    /// `Authorization` is always set from the API key, and `Content-Type` and
+    /// `OpenAI-Beta` by the client; see [`RESERVED_HEADERS`].
+    pub fn with_headers(mut self, headers: HashMap<String, String>) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Send model requests through a custom transport instead of the OpenAI API
+    ///
+    /// `base_url`, `api_key`, `proxy` and `headers` only apply to the default
+    /// transport.
+    pub fn with_transport(mut self, transport: impl ModelTransport + 'static) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+}
+
+/// Build the header map for the custom headers, skipping reserved names
+fn build_header_map(
+    headers: &HashMap<String, String>,
+) -> Result<HeaderMap, Box<dyn std::error::Error + Send + Sync>> {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+        let name = HeaderName::from_bytes(name.as_bytes())?;
+        if RESERVED_HEADERS.contains(&name.as_str()) {
+            warn!(header = %name, "Ignoring reserved model request header");
+            continue;
+        }
+        map.insert(name, HeaderValue::from_str(value)?);
+    }
+    Ok(map)
+}
+
+/// Build the HTTP client used for model requests, applying proxy and headers
+pub(super) fn build_http_client(
+    config: &ModelConfig,
+) -> Result<reqwest::Client, Box<dyn std::error::Error + Send + Sync>> {
+    let mut builder =
+        reqwest::Client::builder().default_headers(build_header_map(&config.headers)?);
+    if let Some(proxy) = &config.proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Image attached to a user message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageSource<'a> {
+    /// Base64-encoded image data, sent as a `data:` URL
+    Base64 {
+        /// Image bytes encoded as base64, without a `data:` prefix
+        data: &'a str,
+        /// e.g. `image/png` or `image/jpeg`
+        mime_type: &'a str,
+    },
+    /// HTTP(S) URL the model endpoint fetches itself
+    Url(&'a str),
+}
+
+impl ImageSource<'_> {
+    /// URL to put in the message's `image_url` field
+    pub fn to_url(&self) -> String {
+        match self {
+            ImageSource::Base64 { data, mime_type } => {
+                format!("data:{};base64,{}", mime_type, data)
+            }
+            ImageSource::Url(url) => url.to_string(),
+        }
+    }
+}
+
+/// Device state described to the model alongside each screenshot
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScreenInfo {
+    /// Name of the foreground app
+    pub current_app: String,
+    /// Screen width and height in pixels
+    pub screen_size: Option<(u32, u32)>,
+    /// `portrait` or `landscape`
+    pub orientation: Option<String>,
+    /// Battery level in percent
+    pub battery_level: Option<u8>,
+    /// Local time the screenshot was taken
+    pub timestamp: Option<String>,
+}
+
+impl ScreenInfo {
+    /// Create screen info with only the current app set
+    pub fn new(current_app: impl Into<String>) -> Self {
+        Self {
+            current_app: current_app.into(),
+            ..Self::default()
+        }
+    }
 }
 
 /// Response from the AI model
@@ -75,6 +348,11 @@ pub struct ModelResponse {
     pub thinking: String,
     pub action: String,
     pub raw_content: String,
+    /// Whether an action marker was found (false means `action` is the raw content)
+    pub action_found: bool,
+    /// Whether the stream failed partway and the action was salvaged from the
+    /// content received before the error
+    pub truncated: bool,
     /// Time to first token (seconds)
     pub time_to_first_token: Option<f64>,
     /// Time to thinking end (seconds)
@@ -83,178 +361,226 @@ pub struct ModelResponse {
     pub total_time: Option<f64>,
 }
 
-/// Client for interacting with OpenAI-compatible vision-language models
+/// Client for interacting with vision-language models
+///
+/// Requests go through the configured [`ModelTransport`], by default an
+/// OpenAI-compatible API.
 pub struct ModelClient {
     config: ModelConfig,
-    client: Client<OpenAIConfig>,
+    transport: Arc<dyn ModelTransport>,
 }
 
 impl ModelClient {
     /// Create a new ModelClient
-    pub fn new(config: ModelConfig) -> Self {
-        let openai_config = OpenAIConfig::new()
-            .with_api_base(&config.base_url)
-            .with_api_key(&config.api_key);
+    ///
+    /// Fails if the default transport can't be built from the config's proxy
+    /// or headers.
+    pub fn new(config: ModelConfig) -> Result<Self, ModelError> {
+        let transport = match &config.transport {
+            Some(transport) => transport.clone(),
+            None => Arc::new(OpenAiTransport::new(&config)?),
+        };
 
-        let client = Client::with_config(openai_config);
+        Ok(Self { config, transport })
+    }
 
-        Self { config, client }
+    /// Forward a thinking delta to the stream callback, if any
+    fn emit_thinking(&self, text: &str) {
+        if let Some(callback) = &self.config.stream_callback {
+            callback(text);
+        }
     }
 
-    /// Test connection to the model API by sending a simple request
-    pub async fn test_connection(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let request = CreateChatCompletionRequestArgs::default()
-            .model(&self.config.model_name)
-            .max_tokens(5_u32)
-            .temperature(0.0_f32)
-            .messages(vec![ChatCompletionRequestUserMessageArgs::default()
-                .content("Hi")
-                .build()?
-                .into()])
-            .build()?;
-
-        let response = self.client.chat().create(request).await?;
-
-        // Check if we got a valid response
-        if response.choices.is_empty() {
-            return Err("Received empty response from API".into());
+    /// Replace the API key in an error message, in case a server echoes it back
+    fn redact_error(&self, error: ModelError) -> ModelError {
+        let api_key = &self.config.api_key;
+        let message = error.to_string();
+        if api_key.is_empty() || api_key == "EMPTY" || !message.contains(api_key.as_str()) {
+            return error;
         }
+        message
+            .replace(api_key.as_str(), &redact_api_key(api_key))
+            .into()
+    }
+
+    /// Test connection to the model API by sending a simple request
+    pub async fn test_connection(&self) -> Result<(), ModelError> {
+        self.send_test_request()
+            .await
+            .map_err(|e| self.redact_error(e))
+    }
+
+    async fn send_test_request(&self) -> Result<(), ModelError> {
+        let messages = vec![ChatCompletionRequestUserMessageArgs::default()
+            .content("Hi")
+            .build()?
+            .into()];
+        let request = CompletionRequest {
+            max_tokens: 5,
+            temperature: 0.0,
+            ..CompletionRequest::new(&self.config, messages)
+        };
+
+        self.transport.complete(request).await?;
+
+        Ok(())
+    }
+
+    /// Send a minimal request with the configured sampling parameters
+    ///
+    /// Primes the server (model load, prompt cache for `messages`) so the
+    /// first real request doesn't pay the cold-start cost. Only a single
+    /// token is generated and the response is discarded.
+    pub async fn warmup(
+        &self,
+        messages: Vec<ChatCompletionRequestMessage>,
+    ) -> Result<(), ModelError> {
+        let request = CompletionRequest {
+            max_tokens: 1,
+            ..CompletionRequest::new(&self.config, messages)
+        };
+
+        let started = Instant::now();
+        self.transport
+            .complete(request)
+            .await
+            .map_err(|e| self.redact_error(e))?;
+        debug!(
+            duration_ms = started.elapsed().as_millis() as u64,
+            "model warm-up finished"
+        );
 
         Ok(())
     }
 
     /// Send a request to the model
+    #[instrument(
+        name = "model_request",
+        skip_all,
+        fields(model = %self.config.model_name, messages = messages.len())
+    )]
     pub async fn request(
         &self,
         messages: Vec<ChatCompletionRequestMessage>,
-    ) -> Result<ModelResponse, Box<dyn std::error::Error + Send + Sync>> {
+    ) -> Result<ModelResponse, ModelError> {
+        self.request_stream(messages)
+            .await
+            .map_err(|e| self.redact_error(e))
+    }
+
+    async fn request_stream(
+        &self,
+        messages: Vec<ChatCompletionRequestMessage>,
+    ) -> Result<ModelResponse, ModelError> {
         let start_time = Instant::now();
         let mut time_to_first_token: Option<f64> = None;
         let mut time_to_thinking_end: Option<f64> = None;
 
-        let request = CreateChatCompletionRequestArgs::default()
-            .model(&self.config.model_name)
-            .max_tokens(self.config.max_tokens)
-            .temperature(self.config.temperature)
-            .top_p(self.config.top_p)
-            .frequency_penalty(self.config.frequency_penalty)
-            .messages(messages)
-            .stream(true)
-            .build()?;
-
-        let mut stream = self.client.chat().create_stream(request).await?;
+        let request = CompletionRequest::new(&self.config, messages);
+        let mut stream = self.transport.stream(request).await?;
 
         let mut raw_content = String::new();
         let mut buffer = String::new();
-        let action_markers = ["finish(message=", "do(action="];
+        let action_markers = &self.config.action_markers;
         let mut in_action_phase = false;
         let mut first_token_received = false;
+        let mut stream_error = None;
 
         while let Some(result) = stream.next().await {
             match result {
-                Ok(response) => {
-                    for choice in response.choices {
-                        if let Some(content) = choice.delta.content {
-                            raw_content.push_str(&content);
-
-                            // Record time to first token
-                            if !first_token_received {
-                                time_to_first_token = Some(start_time.elapsed().as_secs_f64());
-                                first_token_received = true;
-                            }
+                Ok(content) if content.is_empty() => {}
+                Ok(content) => {
+                    raw_content.push_str(&content);
 
-                            if in_action_phase {
-                                continue;
-                            }
+                    // Record time to first token
+                    if !first_token_received {
+                        time_to_first_token = Some(start_time.elapsed().as_secs_f64());
+                        first_token_received = true;
+                    }
 
-                            buffer.push_str(&content);
-
-                            // Check if any marker is fully present in buffer
-                            let mut marker_found = false;
-                            for marker in &action_markers {
-                                if buffer.contains(marker) {
-                                    // Marker found, print everything before it
-                                    let parts: Vec<&str> = buffer.splitn(2, marker).collect();
-                                    print!("{}", parts[0]);
-                                    println!();
-                                    io::stdout().flush().ok();
-                                    in_action_phase = true;
-                                    marker_found = true;
-
-                                    // Record time to thinking end
-                                    if time_to_thinking_end.is_none() {
-                                        time_to_thinking_end =
-                                            Some(start_time.elapsed().as_secs_f64());
-                                    }
-
-                                    break;
-                                }
-                            }
+                    if in_action_phase {
+                        continue;
+                    }
 
-                            if marker_found {
-                                continue;
-                            }
+                    buffer.push_str(&content);
 
-                            // Check if buffer ends with a prefix of any marker
-                            let mut is_potential_marker = false;
-                            for marker in &action_markers {
-                                for i in 1..marker.len() {
-                                    if buffer.ends_with(&marker[..i]) {
-                                        is_potential_marker = true;
-                                        break;
-                                    }
-                                }
-                                if is_potential_marker {
-                                    break;
-                                }
-                            }
+                    // Check if any marker is fully present in buffer
+                    let mut marker_found = false;
+                    for marker in action_markers {
+                        if buffer.contains(marker.as_str()) {
+                            // Marker found, print everything before it
+                            let parts: Vec<&str> = buffer.splitn(2, marker.as_str()).collect();
+                            self.emit_thinking(parts[0]);
+                            in_action_phase = true;
+                            marker_found = true;
 
-                            if !is_potential_marker {
-                                print!("{}", buffer);
-                                io::stdout().flush().ok();
-                                buffer.clear();
+                            // Record time to thinking end
+                            if time_to_thinking_end.is_none() {
+                                time_to_thinking_end = Some(start_time.elapsed().as_secs_f64());
                             }
+
+                            break;
                         }
                     }
+
+                    if marker_found {
+                        continue;
+                    }
+
+                    // Hold back only a trailing partial marker; everything
+                    // before it can't start a marker and is shown right away
+                    let held = partial_marker_len(&buffer, action_markers);
+                    let ready = buffer.len() - held;
+                    if ready > 0 {
+                        self.emit_thinking(&buffer[..ready]);
+                        buffer.drain(..ready);
+                    }
                 }
                 Err(e) => {
-                    return Err(Box::new(e));
+                    stream_error = Some(e);
+                    break;
                 }
             }
         }
 
+        // A held partial marker that never completed is just thinking text
+        if !in_action_phase && !buffer.is_empty() {
+            self.emit_thinking(&buffer);
+        }
+
         let total_time = start_time.elapsed().as_secs_f64();
 
         // Parse thinking and action from response
         let (thinking, action) = self.parse_response(&raw_content);
+        let action_found = action.is_some();
 
-        // Print performance metrics
-        let lang = self.config.lang;
-        println!();
-        println!("{}", "=".repeat(50));
-        println!("⏱️  {}:", get_message("performance_metrics", lang));
-        println!("{}", "-".repeat(50));
-        if let Some(ttft) = time_to_first_token {
-            println!("{}: {:.3}s", get_message("time_to_first_token", lang), ttft);
-        }
-        if let Some(ttte) = time_to_thinking_end {
-            println!(
-                "{}:        {:.3}s",
-                get_message("time_to_thinking_end", lang),
-                ttte
-            );
+        // A stream that broke off may still have delivered a whole action
+        let truncated = stream_error.is_some();
+        if let Some(e) = stream_error {
+            match action.as_deref() {
+                Some(action) if is_complete_action(action) => {
+                    warn!(error = %e, "model stream failed after a complete action, using it");
+                }
+                _ => return Err(e),
+            }
         }
-        println!(
-            "{}:          {:.3}s",
-            get_message("total_inference_time", lang),
-            total_time
+        let action = action.unwrap_or_else(|| raw_content.clone());
+
+        info!(
+            time_to_first_token = time_to_first_token,
+            time_to_thinking_end = time_to_thinking_end,
+            total_time = total_time,
+            action_found = action_found,
+            truncated = truncated,
+            "model response received"
         );
-        println!("{}", "=".repeat(50));
 
         Ok(ModelResponse {
             thinking,
             action,
             raw_content,
+            action_found,
+            truncated,
             time_to_first_token,
             time_to_thinking_end,
             total_time: Some(total_time),
@@ -262,38 +588,53 @@ impl ModelClient {
     }
 
     /// Parse the model response into thinking and action parts
-    fn parse_response(&self, content: &str) -> (String, String) {
-        // Rule 1: Check for finish(message=
-        if content.contains("finish(message=") {
-            let parts: Vec<&str> = content.splitn(2, "finish(message=").collect();
-            let thinking = parts[0].trim().to_string();
-            let action = format!("finish(message={}", parts[1]);
-            return (thinking, action);
-        }
+    ///
+    /// See [`parse_response`].
+    fn parse_response(&self, content: &str) -> (String, Option<String>) {
+        parse_response(
+            content,
+            &self.config.action_markers,
+            self.config.response_tags.as_ref(),
+        )
+    }
+}
 
-        // Rule 2: Check for do(action=
-        if content.contains("do(action=") {
-            let parts: Vec<&str> = content.splitn(2, "do(action=").collect();
-            let thinking = parts[0].trim().to_string();
-            let action = format!("do(action={}", parts[1]);
-            return (thinking, action);
+/// Split a complete model response into thinking and action parts
+///
+/// Rules, in order:
+/// 1. Split at the first of `markers` found in the response (e.g.
+///    `finish(message=` or `do(action=`); the action keeps its marker.
+/// 2. Otherwise, if `tags` are given, take the thinking from before the answer
+///    start tag (with think tags removed) and the action from inside it.
+/// 3. Otherwise the action is `None` and the thinking is empty.
+pub fn parse_response(
+    content: &str,
+    markers: &[String],
+    tags: Option<&ResponseTags>,
+) -> (String, Option<String>) {
+    // Rule 1: Split at the first configured action marker found
+    for marker in markers {
+        if let Some((thinking, rest)) = content.split_once(marker.as_str()) {
+            let action = format!("{}{}", marker, rest);
+            return (thinking.trim().to_string(), Some(action));
         }
+    }
 
-        // Rule 3: Fallback to legacy XML tag parsing
-        if content.contains("<answer>") {
-            let parts: Vec<&str> = content.splitn(2, "<answer>").collect();
-            let thinking = parts[0]
-                .replace("<think>", "")
-                .replace("</think>", "")
+    // Rule 2: Fallback to think/answer tag parsing
+    if let Some(tags) = tags {
+        if let Some((thinking, answer)) = content.split_once(tags.answer_start.as_str()) {
+            let thinking = thinking
+                .replace(&tags.think_start, "")
+                .replace(&tags.think_end, "")
                 .trim()
                 .to_string();
-            let action = parts[1].replace("</answer>", "").trim().to_string();
-            return (thinking, action);
+            let action = answer.replace(&tags.answer_end, "").trim().to_string();
+            return (thinking, Some(action));
         }
-
-        // Rule 4: No markers found, return content as action
-        (String::new(), content.to_string())
     }
+
+    // Rule 3: No markers found
+    (String::new(), None)
 }
 
 /// Helper for building conversation messages
@@ -313,15 +654,63 @@ impl MessageBuilder {
     pub fn create_user_message(
         text: &str,
         image_base64: Option<&str>,
+    ) -> ChatCompletionRequestMessage {
+        Self::create_user_message_with_detail(text, image_base64, ImageDetail::Auto)
+    }
+
+    /// Create a user message with optional image at the given detail level
+    ///
+    /// The image's MIME type is detected from its data.
+    pub fn create_user_message_with_detail(
+        text: &str,
+        image_base64: Option<&str>,
+        detail: ImageDetail,
+    ) -> ChatCompletionRequestMessage {
+        let image = image_base64.map(|data| ImageSource::Base64 {
+            data,
+            mime_type: image_mime_type(data),
+        });
+        Self::create_user_message_with_image(text, image, detail)
+    }
+
+    /// Create a user message with an optional base64 or remote image
+    pub fn create_user_message_with_image(
+        text: &str,
+        image: Option<ImageSource<'_>>,
+        detail: ImageDetail,
+    ) -> ChatCompletionRequestMessage {
+        Self::create_user_message_with_images(text, image.as_slice(), detail)
+    }
+
+    /// Create a user message with several base64 images, in order
+    ///
+    /// Every image is billed as a full set of image tokens, so sending the
+    /// previous and current screen roughly doubles a step's input size.
+    pub fn create_user_message_multi(text: &str, images: &[&str]) -> ChatCompletionRequestMessage {
+        let images: Vec<ImageSource<'_>> = images
+            .iter()
+            .map(|data| ImageSource::Base64 {
+                data,
+                mime_type: image_mime_type(data),
+            })
+            .collect();
+        Self::create_user_message_with_images(text, &images, ImageDetail::Auto)
+    }
+
+    /// Create a user message with any number of base64 or remote images, in order
+    pub fn create_user_message_with_images(
+        text: &str,
+        images: &[ImageSource<'_>],
+        detail: ImageDetail,
     ) -> ChatCompletionRequestMessage {
         let mut content_parts: Vec<ChatCompletionRequestUserMessageContentPart> = Vec::new();
 
-        if let Some(img) = image_base64 {
+        for image in images {
             content_parts.push(ChatCompletionRequestUserMessageContentPart::ImageUrl(
                 async_openai::types::ChatCompletionRequestMessageContentPartImage {
                     image_url: ImageUrl {
-                        url: format!("data:image/png;base64,{}", img),
-                        detail: Some(ImageDetail::Auto),
+                        url: image.to_url(),
+                        detail: Some(detail.clone()),
                     },
                 },
             ));
@@ -372,17 +761,87 @@ impl MessageBuilder {
     }
 
     /// Build screen info string for the model
-    pub fn build_screen_info(current_app: &str) -> String {
-        json!({
-            "current_app": current_app
+    ///
+    /// Only the fields of `info` that are set are included.
+    pub fn build_screen_info(info: &ScreenInfo) -> String {
+        let mut value = json!({
+            "current_app": info.current_app
+        });
+        if let Some((width, height)) = info.screen_size {
+            value["screen_width"] = json!(width);
+            value["screen_height"] = json!(height);
+        }
+        if let Some(ref orientation) = info.orientation {
+            value["orientation"] = json!(orientation);
+        }
+        if let Some(battery) = info.battery_level {
+            value["battery_level"] = json!(battery);
+        }
+        if let Some(ref timestamp) = info.timestamp {
+            value["timestamp"] = json!(timestamp);
+        }
+        value.to_string()
+    }
+}
+
+/// Length of the longest suffix of `buffer` that is a proper prefix of a marker
+///
+/// Only this suffix is ambiguous while streaming: it may turn out to be the
+/// start of an action marker once more tokens arrive.
+fn partial_marker_len(buffer: &str, markers: &[String]) -> usize {
+    markers
+        .iter()
+        .flat_map(|marker| {
+            marker
+                .char_indices()
+                .skip(1)
+                .map(|(i, _)| &marker[..i])
+                .filter(|prefix| buffer.ends_with(prefix))
+                .map(str::len)
         })
-        .to_string()
+        .max()
+        .unwrap_or(0)
+}
+
+/// Whether `action` is a whole call, e.g. `do(action="Back")`
+///
+/// The call must end with the parenthesis that closes its first one; brackets
+/// inside quoted strings are ignored.
+fn is_complete_action(action: &str) -> bool {
+    let action = action.trim();
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in action.char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '(' => depth += 1,
+            ')' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1 == action.len();
+                }
+            }
+            _ => {}
+        }
     }
+    false
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::model::TextStream;
 
     #[test]
     fn test_model_config_default() {
@@ -403,10 +862,262 @@ mod tests {
         assert_eq!(config.lang, Language::English);
     }
 
+    #[test]
+    fn test_model_config_redacts_api_key() {
+        let config = ModelConfig::default().with_api_key("sk-0123456789abcdef");
+
+        let debug = format!("{:?}", config);
+        assert!(!debug.contains("0123456789abcdef"), "{}", debug);
+        assert!(debug.contains("sk-***"), "{}", debug);
+
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(!json.contains("0123456789abcdef"), "{}", json);
+
+        assert_eq!(redact_api_key("EMPTY"), "EMPTY");
+        assert_eq!(redact_api_key("short"), "***");
+    }
+
+    #[test]
+    fn test_model_config_image_detail() {
+        let config = ModelConfig::default();
+        assert!(matches!(config.image_detail, ImageDetail::Auto));
+
+        let config = config.with_image_detail(ImageDetail::Low);
+        assert!(matches!(config.image_detail, ImageDetail::Low));
+    }
+
+    #[test]
+    fn test_model_config_proxy_and_headers() {
+        let headers = HashMap::from([
+            ("X-Gateway-Key".to_string(), "secret".to_string()),
+            ("Authorization".to_string(), "Bearer other".to_string()),
+        ]);
+        let config = ModelConfig::default()
+            .with_proxy("http://proxy:3128")
+            .with_headers(headers);
+
+        assert_eq!(config.proxy.as_deref(), Some("http://proxy:3128"));
+        let map = build_header_map(&config.headers).unwrap();
+        assert_eq!(map.len(), 1);
+        assert_eq!(map["x-gateway-key"], "secret");
+        assert!(build_http_client(&config).is_ok());
+
+        let invalid = HashMap::from([("bad header".to_string(), "x".to_string())]);
+        assert!(build_header_map(&invalid).is_err());
+        assert!(ModelClient::new(ModelConfig::default().with_headers(invalid)).is_err());
+    }
+
+    #[test]
+    fn test_model_config_stream_callback() {
+        let received = Arc::new(std::sync::Mutex::new(String::new()));
+        let sink = received.clone();
+        let config = ModelConfig::default()
+            .with_stream_callback(move |delta| sink.lock().unwrap().push_str(delta));
+
+        let client = ModelClient::new(config).unwrap();
+        client.emit_thinking("Hello ");
+        client.emit_thinking("world");
+
+        assert_eq!(*received.lock().unwrap(), "Hello world");
+    }
+
+    /// Transport that streams a canned response in fixed-size chunks
+    struct FakeTransport(&'static str);
+
+    impl ModelTransport for FakeTransport {
+        fn complete<'a>(
+            &'a self,
+            _request: CompletionRequest,
+        ) -> futures::future::BoxFuture<'a, Result<String, ModelError>> {
+            Box::pin(async move { Ok(self.0.to_string()) })
+        }
+
+        fn stream<'a>(
+            &'a self,
+            _request: CompletionRequest,
+        ) -> futures::future::BoxFuture<'a, Result<TextStream, ModelError>> {
+            let chunks: Vec<Result<String, ModelError>> = self
+                .0
+                .as_bytes()
+                .chunks(4)
+                .map(|chunk| Ok(String::from_utf8_lossy(chunk).into_owned()))
+                .collect();
+            Box::pin(async move { Ok(futures::stream::iter(chunks).boxed()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_with_custom_transport() {
+        let config = ModelConfig::default()
+            .with_stream_callback(|_| {})
+            .with_transport(FakeTransport(
+                "Tapping the icon do(action=\"Tap\", element=[500, 300])",
+            ));
+        let client = ModelClient::new(config).unwrap();
+
+        client.test_connection().await.unwrap();
+        let response = client.request(Vec::new()).await.unwrap();
+        assert_eq!(response.thinking, "Tapping the icon");
+        assert_eq!(response.action, "do(action=\"Tap\", element=[500, 300])");
+        assert!(response.action_found);
+        assert!(!response.truncated);
+    }
+
+    #[test]
+    fn test_parse_response_markers() {
+        let client = ModelClient::new(ModelConfig::default()).unwrap();
+
+        let (thinking, action) =
+            client.parse_response("Open the app first.do(action=\"Launch\", app=\"WeChat\")");
+        assert_eq!(thinking, "Open the app first.");
+        assert_eq!(
+            action.as_deref(),
+            Some("do(action=\"Launch\", app=\"WeChat\")")
+        );
+
+        let (_, action) = client.parse_response("I am still thinking about it");
+        assert!(action.is_none());
+    }
+
+    #[test]
+    fn test_format_assistant_message() {
+        let config = ModelConfig::default();
+        assert_eq!(
+            config.format_assistant_message("look", "do(action=\"Back\")"),
+            "<think>look</think><answer>do(action=\"Back\")</answer>"
+        );
+
+        let config = config.with_assistant_template("Reasoning: {thinking}\nAction: {action}");
+        assert_eq!(
+            config.format_assistant_message("says {action}", "finish(message=\"ok\")"),
+            "Reasoning: says {action}\nAction: finish(message=\"ok\")"
+        );
+    }
+
+    #[test]
+    fn test_parse_response_custom_markers() {
+        let config = ModelConfig::default()
+            .with_action_markers(["ACTION:"])
+            .with_response_tags(Some(ResponseTags {
+                think_start: "[reason]".to_string(),
+                think_end: "[/reason]".to_string(),
+                answer_start: "[act]".to_string(),
+                answer_end: "[/act]".to_string(),
+            }));
+        let client = ModelClient::new(config).unwrap();
+
+        let (thinking, action) = client.parse_response("Scroll down.ACTION: Swipe");
+        assert_eq!(thinking, "Scroll down.");
+        assert_eq!(action.as_deref(), Some("ACTION: Swipe"));
+
+        let (thinking, action) = client.parse_response("[reason]Done[/reason][act]finish[/act]");
+        assert_eq!(thinking, "Done");
+        assert_eq!(action.as_deref(), Some("finish"));
+
+        let (_, action) = client.parse_response("do(action=\"Back\")");
+        assert!(action.is_none());
+    }
+
+    #[test]
+    fn test_parse_response_free_function() {
+        let markers = default_action_markers();
+        let tags = ResponseTags::default();
+
+        let (thinking, action) =
+            parse_response("Done.finish(message=\"ok\")", &markers, Some(&tags));
+        assert_eq!(thinking, "Done.");
+        assert_eq!(action.as_deref(), Some("finish(message=\"ok\")"));
+
+        // Tags are the fallback when no marker matches
+        let (thinking, action) = parse_response(
+            "<think>Reply</think><answer>Hello</answer>",
+            &markers,
+            Some(&tags),
+        );
+        assert_eq!(thinking, "Reply");
+        assert_eq!(action.as_deref(), Some("Hello"));
+
+        assert_eq!(
+            parse_response("<answer>Hello</answer>", &markers, None),
+            (String::new(), None)
+        );
+    }
+
     #[test]
     fn test_build_screen_info() {
-        let info = MessageBuilder::build_screen_info("WeChat");
+        let info = MessageBuilder::build_screen_info(&ScreenInfo::new("WeChat"));
         assert!(info.contains("WeChat"));
         assert!(info.contains("current_app"));
+        assert!(!info.contains("battery_level"));
+
+        let info = MessageBuilder::build_screen_info(&ScreenInfo {
+            screen_size: Some((1080, 2400)),
+            orientation: Some("portrait".to_string()),
+            battery_level: Some(80),
+            ..ScreenInfo::new("WeChat")
+        });
+        let value: serde_json::Value = serde_json::from_str(&info).unwrap();
+        assert_eq!(value["screen_width"], 1080);
+        assert_eq!(value["orientation"], "portrait");
+        assert_eq!(value["battery_level"], 80);
+        assert!(value.get("timestamp").is_none());
+    }
+
+    #[test]
+    fn test_image_source_to_url() {
+        let jpeg = ImageSource::Base64 {
+            data: "aGVsbG8=",
+            mime_type: "image/jpeg",
+        };
+        assert_eq!(jpeg.to_url(), "data:image/jpeg;base64,aGVsbG8=");
+
+        let remote = ImageSource::Url("https://example.com/step_001.png");
+        assert_eq!(remote.to_url(), "https://example.com/step_001.png");
+    }
+
+    #[test]
+    fn test_create_user_message_multi() {
+        let image_count = |message: &ChatCompletionRequestMessage| match message {
+            ChatCompletionRequestMessage::User(user_msg) => match &user_msg.content {
+                ChatCompletionRequestUserMessageContent::Array(parts) => parts
+                    .iter()
+                    .filter(|p| {
+                        matches!(p, ChatCompletionRequestUserMessageContentPart::ImageUrl(_))
+                    })
+                    .count(),
+                _ => 0,
+            },
+            _ => 0,
+        };
+
+        let message =
+            MessageBuilder::create_user_message_multi("Before and after", &["YQ==", "Yg=="]);
+        assert_eq!(image_count(&message), 2);
+
+        let message = MessageBuilder::remove_images_from_message(message);
+        assert_eq!(image_count(&message), 0);
+    }
+
+    #[test]
+    fn test_is_complete_action() {
+        assert!(is_complete_action("do(action=\"Back\")"));
+        assert!(is_complete_action(
+            "do(action=\"Type\", text=\"a (b) c\")\n"
+        ));
+        assert!(is_complete_action("finish(message='done :)')"));
+        assert!(!is_complete_action("do(action=\"Tap\", element=[500,"));
+        assert!(!is_complete_action("finish(message=\"Found it)"));
+        assert!(!is_complete_action("do(action=\"Back\") trailing"));
+    }
+
+    #[test]
+    fn test_partial_marker_len() {
+        let markers = default_action_markers();
+
+        assert_eq!(partial_marker_len("Tap the send button", &markers), 0);
+        assert_eq!(partial_marker_len("I should tap the send", &markers), 1);
+        assert_eq!(partial_marker_len("Next step: do(act", &markers), 6);
+        assert_eq!(partial_marker_len("All set. finish(", &markers), 7);
+        assert_eq!(partial_marker_len("完成任务", &markers), 0);
     }
 }
@@ -1,8 +1,16 @@
 //! Model client module for AI inference
 //!
 //! This module provides:
-//! - `client`: OpenAI-compatible model client
+//! - `client`: Model client with streaming and response parsing
+//! - `transport`: Pluggable backends, OpenAI-compatible by default
 
 mod client;
+mod transport;
 
-pub use client::{MessageBuilder, ModelClient, ModelConfig, ModelResponse};
+pub use async_openai::types::ImageDetail;
+pub use client::{
+    parse_response, redact_api_key, ImageSource, MessageBuilder, ModelClient, ModelConfig,
+    ModelResponse, ResponseTags, ScreenInfo, StreamCallback, DEFAULT_ASSISTANT_TEMPLATE,
+    RESERVED_HEADERS,
+};
+pub use transport::{CompletionRequest, ModelError, ModelTransport, OpenAiTransport, TextStream};
@@ -0,0 +1,140 @@
+//! Transports that carry chat completion requests to a model backend
+
+use async_openai::{
+    config::OpenAIConfig,
+    types::{
+        ChatCompletionRequestMessage, CreateChatCompletionRequest, CreateChatCompletionRequestArgs,
+    },
+    Client,
+};
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+
+use super::client::{build_http_client, ModelConfig};
+
+/// Boxed error returned by model requests
+pub type ModelError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Stream of content deltas produced by [`ModelTransport::stream`]
+pub type TextStream = BoxStream<'static, Result<String, ModelError>>;
+
+/// A single chat completion request
+#[derive(Debug, Clone)]
+pub struct CompletionRequest {
+    pub model: String,
+    /// Conversation so far, in the OpenAI message format
+    pub messages: Vec<ChatCompletionRequestMessage>,
+    pub max_tokens: u32,
+    pub temperature: f32,
+    pub top_p: f32,
+    pub frequency_penalty: f32,
+}
+
+impl CompletionRequest {
+    /// Build a request with the sampling parameters from `config`
+    pub fn new(config: &ModelConfig, messages: Vec<ChatCompletionRequestMessage>) -> Self {
+        Self {
+            model: config.model_name.clone(),
+            messages,
+            max_tokens: config.max_tokens,
+            temperature: config.temperature,
+            top_p: config.top_p,
+            frequency_penalty: config.frequency_penalty,
+        }
+    }
+}
+
+/// Backend that runs chat completions for [`ModelClient`](super::ModelClient)
+///
+/// The default is [`OpenAiTransport`]. Implement this to target a
+/// non-OpenAI API (converting the messages as needed), a local server, or a
+/// fake that returns canned responses in tests.
+pub trait ModelTransport: Send + Sync {
+    /// Run a completion and return the whole response text
+    fn complete<'a>(
+        &'a self,
+        request: CompletionRequest,
+    ) -> BoxFuture<'a, Result<String, ModelError>>;
+
+    /// Run a completion, yielding the response text as it is generated
+    fn stream<'a>(
+        &'a self,
+        request: CompletionRequest,
+    ) -> BoxFuture<'a, Result<TextStream, ModelError>>;
+}
+
+/// Transport for OpenAI-compatible chat completion APIs
+pub struct OpenAiTransport {
+    client: Client<OpenAIConfig>,
+}
+
+impl OpenAiTransport {
+    /// Create a transport for the endpoint, key, proxy and headers in `config`
+    ///
+    /// Fails if the proxy URL or a header is invalid.
+    pub fn new(config: &ModelConfig) -> Result<Self, ModelError> {
+        let openai_config = OpenAIConfig::new()
+            .with_api_base(&config.base_url)
+            .with_api_key(&config.api_key);
+
+        let mut client = Client::with_config(openai_config);
+        if config.proxy.is_some() || !config.headers.is_empty() {
+            client = client.with_http_client(build_http_client(config)?);
+        }
+
+        Ok(Self { client })
+    }
+
+    fn build_request(
+        request: CompletionRequest,
+        stream: bool,
+    ) -> Result<CreateChatCompletionRequest, ModelError> {
+        Ok(CreateChatCompletionRequestArgs::default()
+            .model(request.model)
+            .max_tokens(request.max_tokens)
+            .temperature(request.temperature)
+            .top_p(request.top_p)
+            .frequency_penalty(request.frequency_penalty)
+            .messages(request.messages)
+            .stream(stream)
+            .build()?)
+    }
+}
+
+impl ModelTransport for OpenAiTransport {
+    fn complete<'a>(
+        &'a self,
+        request: CompletionRequest,
+    ) -> BoxFuture<'a, Result<String, ModelError>> {
+        Box::pin(async move {
+            let request = Self::build_request(request, false)?;
+            let response = self.client.chat().create(request).await?;
+            let choice = response
+                .choices
+                .into_iter()
+                .next()
+                .ok_or("Received empty response from API")?;
+            Ok(choice.message.content.unwrap_or_default())
+        })
+    }
+
+    fn stream<'a>(
+        &'a self,
+        request: CompletionRequest,
+    ) -> BoxFuture<'a, Result<TextStream, ModelError>> {
+        Box::pin(async move {
+            let request = Self::build_request(request, true)?;
+            let stream = self.client.chat().create_stream(request).await?;
+            let deltas = stream.map(|result| match result {
+                Ok(response) => Ok(response
+                    .choices
+                    .into_iter()
+                    .filter_map(|choice| choice.delta.content)
+                    .collect::<String>()),
+                Err(e) => Err(Box::new(e) as ModelError),
+            });
+            Ok(deltas.boxed())
+        })
+    }
+}
@@ -2,21 +2,55 @@
 
 use base64::{engine::general_purpose, Engine as _};
 use chrono::{DateTime, Local};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
 use tokio::fs;
+use tokio::io::AsyncWriteExt;
 use tracing::{debug, info};
 
 use crate::error::{AdbError, Result};
 
+/// File extension of compressed session archives
+const SESSION_ARCHIVE_EXT: &str = "session.gz";
+
 /// Manages screenshot persistence with timestamped directories and filenames
+///
+/// In compressed mode (see [`ScreenshotSaver::new_compressed`]) each session
+/// is a single `.session.gz` archive instead of a directory of files.
 #[derive(Debug, Clone)]
 pub struct ScreenshotSaver {
     /// Base directory for saving screenshots
     base_dir: PathBuf,
-    /// Session directory (created at session start with timestamp)
+    /// Session directory, or archive file in compressed mode
     session_dir: PathBuf,
     /// Step counter for ordering screenshots
     step_count: usize,
+    /// Whether files are appended to a gzip archive
+    compressed: bool,
+}
+
+/// A file saved in a compressed session archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionEntry {
+    /// Step the file belongs to
+    pub step: usize,
+    /// File name it would have in an uncompressed session directory
+    pub name: String,
+    /// File contents
+    pub data: Vec<u8>,
+}
+
+/// One JSON line of a session archive
+#[derive(Serialize, Deserialize)]
+struct ArchiveRecord {
+    step: usize,
+    name: String,
+    /// Base64-encoded file contents
+    data: String,
 }
 
 impl ScreenshotSaver {
@@ -48,6 +82,27 @@ impl ScreenshotSaver {
             base_dir,
             session_dir,
             step_count: 0,
+            compressed: false,
+        })
+    }
+
+    /// Create a ScreenshotSaver that writes each session to one gzip archive
+    ///
+    /// The archive is named `yyyy-mm-dd_HH-MM-SS-mmm.session.gz`. Every saved
+    /// file is appended to it as it is saved; use [`read_session`] or
+    /// [`extract_session`] to get the files back.
+    pub async fn new_compressed(base_dir: impl AsRef<Path>) -> Result<Self> {
+        let base_dir = base_dir.as_ref().to_path_buf();
+        fs::create_dir_all(&base_dir).await.map_err(AdbError::Io)?;
+
+        let session_dir = base_dir.join(archive_name(Local::now()));
+        info!("Screenshot session archive: {}", session_dir.display());
+
+        Ok(Self {
+            base_dir,
+            session_dir,
+            step_count: 0,
+            compressed: true,
         })
     }
 
@@ -70,6 +125,11 @@ impl ScreenshotSaver {
             self.step_count,
             now.format("%Y-%m-%d_%H-%M-%S-%3f")
         );
+        if self.compressed {
+            self.append_to_archive(filename, base64_data.to_string())
+                .await?;
+            return Ok(self.session_dir.clone());
+        }
         let file_path = self.session_dir.join(&filename);
 
         // Decode base64 and write to file
@@ -90,7 +150,64 @@ impl ScreenshotSaver {
         Ok(file_path)
     }
 
+    /// Save a text file alongside the current step's screenshot
+    ///
+    /// Filename format: `step_NNN_<name>.txt`, e.g. `step_004_logcat.txt`
+    pub async fn save_text(&self, name: &str, contents: &str) -> Result<PathBuf> {
+        let filename = format!("step_{:03}_{}.txt", self.step_count, name);
+        if self.compressed {
+            self.append_to_archive(filename, general_purpose::STANDARD.encode(contents))
+                .await?;
+            return Ok(self.session_dir.clone());
+        }
+        let file_path = self.session_dir.join(&filename);
+
+        fs::write(&file_path, contents)
+            .await
+            .map_err(AdbError::Io)?;
+
+        debug!("Saved {}: {}", name, file_path.display());
+        Ok(file_path)
+    }
+
+    /// Append a file to the session archive as its own gzip member
+    ///
+    /// Concatenated gzip members form a valid gzip file, so a session cut
+    /// short by a crash keeps every file saved before it.
+    async fn append_to_archive(&self, name: String, data: String) -> Result<()> {
+        let record = ArchiveRecord {
+            step: self.step_count,
+            name,
+            data,
+        };
+        let mut line = serde_json::to_vec(&record)
+            .map_err(|e| AdbError::CommandFailed(format!("Failed to encode record: {}", e)))?;
+        line.push(b'\n');
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&line).map_err(AdbError::Io)?;
+        let compressed = encoder.finish().map_err(AdbError::Io)?;
+
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.session_dir)
+            .await
+            .map_err(AdbError::Io)?;
+        file.write_all(&compressed).await.map_err(AdbError::Io)?;
+
+        debug!(
+            "Appended {} to {} ({} bytes)",
+            record.name,
+            self.session_dir.display(),
+            compressed.len()
+        );
+        Ok(())
+    }
+
     /// Get the session directory path
+    ///
+    /// In compressed mode this is the session's `.session.gz` archive.
     pub fn session_dir(&self) -> &Path {
         &self.session_dir
     }
@@ -110,9 +227,24 @@ impl ScreenshotSaver {
         self.step_count = 0;
     }
 
+    /// Whether sessions are saved as compressed archives
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
     /// Create a new session directory (for new task in interactive mode)
     pub async fn new_session(&mut self) -> Result<()> {
         let session_start: DateTime<Local> = Local::now();
+        if self.compressed {
+            self.session_dir = self.base_dir.join(archive_name(session_start));
+            self.step_count = 0;
+            info!(
+                "New screenshot session archive: {}",
+                self.session_dir.display()
+            );
+            return Ok(());
+        }
+
         let session_name = session_start.format("%Y-%m-%d_%H-%M-%S-%3f").to_string();
         self.session_dir = self.base_dir.join(&session_name);
 
@@ -131,6 +263,59 @@ impl ScreenshotSaver {
     }
 }
 
+/// File name of the archive for a session started at `start`
+fn archive_name(start: DateTime<Local>) -> String {
+    format!(
+        "{}.{}",
+        start.format("%Y-%m-%d_%H-%M-%S-%3f"),
+        SESSION_ARCHIVE_EXT
+    )
+}
+
+/// List the files saved in a compressed session archive, in the order saved
+pub async fn read_session(path: impl AsRef<Path>) -> Result<Vec<SessionEntry>> {
+    let compressed = fs::read(path.as_ref()).await.map_err(AdbError::Io)?;
+    let reader = BufReader::new(MultiGzDecoder::new(compressed.as_slice()));
+
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        let line = line.map_err(AdbError::Io)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: ArchiveRecord = serde_json::from_str(&line)
+            .map_err(|e| AdbError::ParseError(format!("Invalid session record: {}", e)))?;
+        entries.push(SessionEntry {
+            step: record.step,
+            name: record.name,
+            data: general_purpose::STANDARD.decode(record.data)?,
+        });
+    }
+    Ok(entries)
+}
+
+/// Write the files of a compressed session archive into `out_dir`
+///
+/// The files get the names they would have had in an uncompressed session
+/// directory. Returns their paths.
+pub async fn extract_session(
+    path: impl AsRef<Path>,
+    out_dir: impl AsRef<Path>,
+) -> Result<Vec<PathBuf>> {
+    let out_dir = out_dir.as_ref();
+    fs::create_dir_all(out_dir).await.map_err(AdbError::Io)?;
+
+    let mut paths = Vec::new();
+    for entry in read_session(path).await? {
+        let file_path = out_dir.join(&entry.name);
+        fs::write(&file_path, &entry.data)
+            .await
+            .map_err(AdbError::Io)?;
+        paths.push(file_path);
+    }
+    Ok(paths)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -172,4 +357,47 @@ mod tests {
             .unwrap()
             .starts_with("step_001_"));
     }
+
+    #[tokio::test]
+    async fn test_save_text() {
+        let temp_dir = tempdir().unwrap();
+        let saver = ScreenshotSaver::new(temp_dir.path()).await.unwrap();
+
+        let path = saver.save_text("logcat", "E/App: crash").await.unwrap();
+
+        assert_eq!(path.file_name().unwrap(), "step_000_logcat.txt");
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "E/App: crash");
+    }
+
+    #[tokio::test]
+    async fn test_compressed_session() {
+        let temp_dir = tempdir().unwrap();
+        let mut saver = ScreenshotSaver::new_compressed(temp_dir.path())
+            .await
+            .unwrap();
+
+        let archive = saver
+            .save(&general_purpose::STANDARD.encode(b"first"))
+            .await
+            .unwrap();
+        saver.save_text("logcat", "E/App: crash").await.unwrap();
+        saver
+            .save(&general_purpose::STANDARD.encode(b"second"))
+            .await
+            .unwrap();
+
+        assert!(archive.to_str().unwrap().ends_with(".session.gz"));
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+
+        let entries = read_session(&archive).await.unwrap();
+        let steps: Vec<usize> = entries.iter().map(|entry| entry.step).collect();
+        assert_eq!(steps, vec![1, 1, 2]);
+        assert_eq!(entries[0].data, b"first");
+        assert_eq!(entries[1].name, "step_001_logcat.txt");
+
+        let out_dir = temp_dir.path().join("extracted");
+        let paths = extract_session(&archive, &out_dir).await.unwrap();
+        assert_eq!(paths.len(), 3);
+        assert_eq!(std::fs::read(&paths[2]).unwrap(), b"second");
+    }
 }
@@ -0,0 +1,148 @@
+//! Markdown rendering of agent conversations for reports
+
+use async_openai::types::{
+    ChatCompletionRequestAssistantMessageContent, ChatCompletionRequestAssistantMessageContentPart,
+    ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageContent,
+    ChatCompletionRequestSystemMessageContentPart, ChatCompletionRequestUserMessageContent,
+    ChatCompletionRequestUserMessageContentPart,
+};
+use std::path::PathBuf;
+
+/// Render a conversation as Markdown
+///
+/// Each user message starts a new step. `screenshots[i]` is the saved
+/// screenshot for the i-th user message, if any; screenshots are linked,
+/// never inlined.
+pub fn render_markdown(
+    context: &[ChatCompletionRequestMessage],
+    screenshots: &[Option<PathBuf>],
+) -> String {
+    let mut out = String::from("# Agent Transcript\n");
+    let mut step = 0;
+
+    for message in context {
+        match message {
+            ChatCompletionRequestMessage::System(msg) => {
+                out.push_str("\n## System Prompt\n\n");
+                out.push_str(system_text(&msg.content).trim());
+                out.push('\n');
+            }
+            ChatCompletionRequestMessage::User(msg) => {
+                step += 1;
+                out.push_str(&format!("\n## Step {}\n\n", step));
+                out.push_str(user_text(&msg.content).trim());
+                out.push('\n');
+                if let Some(Some(path)) = screenshots.get(step - 1) {
+                    out.push_str(&format!(
+                        "\n![Step {} screenshot]({})\n",
+                        step,
+                        path.display()
+                    ));
+                }
+            }
+            ChatCompletionRequestMessage::Assistant(msg) => {
+                let content = msg.content.as_ref().map(assistant_text).unwrap_or_default();
+                let (thinking, action) = split_think_answer(&content);
+                if !thinking.is_empty() {
+                    out.push_str(&format!("\n**Thinking:** {}\n", thinking));
+                }
+                if !action.is_empty() {
+                    out.push_str(&format!("\n**Action:** `{}`\n", action));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    out
+}
+
+/// Split `<think>..</think><answer>..</answer>` into its two parts
+fn split_think_answer(content: &str) -> (String, String) {
+    let between = |start: &str, end: &str| {
+        let from = content.find(start)? + start.len();
+        let to = content[from..].find(end)? + from;
+        Some(content[from..to].trim().to_string())
+    };
+
+    match between("<answer>", "</answer>") {
+        Some(action) => (between("<think>", "</think>").unwrap_or_default(), action),
+        None => (String::new(), content.trim().to_string()),
+    }
+}
+
+fn system_text(content: &ChatCompletionRequestSystemMessageContent) -> String {
+    match content {
+        ChatCompletionRequestSystemMessageContent::Text(text) => text.clone(),
+        ChatCompletionRequestSystemMessageContent::Array(parts) => parts
+            .iter()
+            .map(|part| match part {
+                ChatCompletionRequestSystemMessageContentPart::Text(t) => t.text.as_str(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+fn user_text(content: &ChatCompletionRequestUserMessageContent) -> String {
+    match content {
+        ChatCompletionRequestUserMessageContent::Text(text) => text.clone(),
+        ChatCompletionRequestUserMessageContent::Array(parts) => parts
+            .iter()
+            .filter_map(|part| match part {
+                ChatCompletionRequestUserMessageContentPart::Text(t) => Some(t.text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+fn assistant_text(content: &ChatCompletionRequestAssistantMessageContent) -> String {
+    match content {
+        ChatCompletionRequestAssistantMessageContent::Text(text) => text.clone(),
+        ChatCompletionRequestAssistantMessageContent::Array(parts) => parts
+            .iter()
+            .filter_map(|part| match part {
+                ChatCompletionRequestAssistantMessageContentPart::Text(t) => Some(t.text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::MessageBuilder;
+
+    #[test]
+    fn test_render_markdown() {
+        let context = vec![
+            MessageBuilder::create_system_message("You are a phone agent."),
+            MessageBuilder::create_user_message("Open Settings", Some("aGVsbG8=")),
+            MessageBuilder::create_assistant_message(
+                "<think>Settings is on the home screen</think><answer>do(action=\"Launch\", app=\"Settings\")</answer>",
+            ),
+        ];
+        let screenshots = vec![Some(PathBuf::from("shots/step_001.png"))];
+
+        let markdown = render_markdown(&context, &screenshots);
+
+        assert!(markdown.contains("## System Prompt\n\nYou are a phone agent."));
+        assert!(markdown.contains("## Step 1\n\nOpen Settings"));
+        assert!(markdown.contains("![Step 1 screenshot](shots/step_001.png)"));
+        assert!(markdown.contains("**Thinking:** Settings is on the home screen"));
+        assert!(markdown.contains("**Action:** `do(action=\"Launch\", app=\"Settings\")`"));
+        assert!(!markdown.contains("aGVsbG8="));
+    }
+
+    #[test]
+    fn test_split_think_answer_without_tags() {
+        assert_eq!(
+            split_think_answer("finish(message=\"done\")"),
+            (String::new(), "finish(message=\"done\")".to_string())
+        );
+    }
+}
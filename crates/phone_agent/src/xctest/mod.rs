@@ -1,11 +1,14 @@
-//! XCTest module for iOS device control (placeholder)
+//! XCTest module for iOS device control
 //!
-//! This module will provide iOS device automation via XCTest framework.
-//! Currently not implemented in the Rust port.
+//! This module provides:
+//! - `wda`: WebDriverAgent status checks
+//!
+//! Device control is not implemented in the Rust port yet. Planned:
+//! - connection.rs: iOS device connection management
+//! - device.rs: iOS device control operations
+//! - input.rs: iOS text input handling
+//! - screenshot.rs: iOS screenshot capture
+
+mod wda;
 
-// Placeholder for future iOS support
-// Will contain:
-// - connection.rs: iOS device connection management
-// - device.rs: iOS device control operations
-// - input.rs: iOS text input handling
-// - screenshot.rs: iOS screenshot capture
+pub use wda::{check_wda_status, wda_info, WdaInfo};
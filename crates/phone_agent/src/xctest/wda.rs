@@ -0,0 +1,130 @@
+//! WebDriverAgent HTTP API
+
+use serde_json::Value;
+use std::time::Duration;
+
+use crate::error::{AdbError, Result};
+
+/// Timeout for requests to WebDriverAgent
+const WDA_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What WebDriverAgent reports about itself at `/status`
+#[derive(Debug, Clone, PartialEq)]
+pub struct WdaInfo {
+    /// Whether WebDriverAgent accepts commands
+    pub ready: bool,
+    /// Status message, e.g. "WebDriverAgent is ready to accept commands"
+    pub message: Option<String>,
+    /// Id of the current session, if one is open
+    pub session_id: Option<String>,
+    /// OS name, e.g. "iOS"
+    pub os_name: Option<String>,
+    /// OS version of the device, e.g. "17.2"
+    pub os_version: Option<String>,
+    /// IP address of the device
+    pub ip: Option<String>,
+    /// Version of the WebDriverAgent build, if it reports one
+    pub build_version: Option<String>,
+    /// Capabilities of the current session
+    pub capabilities: Option<Value>,
+}
+
+/// Check whether WebDriverAgent is running at `wda_url`
+///
+/// Returns `Ok(false)` when nothing answers or `/status` doesn't return 200,
+/// and an error on timeout.
+pub async fn check_wda_status(wda_url: &str) -> Result<bool> {
+    match get_status(wda_url).await {
+        Ok(response) => Ok(response.status().is_success()),
+        Err(e) if e.is_connect() => Ok(false),
+        Err(e) => Err(wda_error(e)),
+    }
+}
+
+/// Read the status, session and capabilities of WebDriverAgent at `wda_url`
+pub async fn wda_info(wda_url: &str) -> Result<WdaInfo> {
+    let response = get_status(wda_url).await.map_err(wda_error)?;
+    if !response.status().is_success() {
+        return Err(AdbError::CommandFailed(format!(
+            "WebDriverAgent status returned {}",
+            response.status()
+        )));
+    }
+
+    let body = response.bytes().await.map_err(wda_error)?;
+    let status: Value = serde_json::from_slice(&body)
+        .map_err(|e| AdbError::ParseError(format!("Invalid WebDriverAgent status: {}", e)))?;
+    Ok(parse_status(&status))
+}
+
+async fn get_status(wda_url: &str) -> reqwest::Result<reqwest::Response> {
+    let client = reqwest::Client::builder().timeout(WDA_TIMEOUT).build()?;
+    client
+        .get(format!("{}/status", wda_url.trim_end_matches('/')))
+        .send()
+        .await
+}
+
+fn wda_error(e: reqwest::Error) -> AdbError {
+    if e.is_timeout() {
+        AdbError::Timeout("WDA status check timeout".to_string())
+    } else {
+        AdbError::CommandFailed(format!("WebDriverAgent request failed: {}", e))
+    }
+}
+
+/// Parse the JSON body of WebDriverAgent's `/status`
+fn parse_status(status: &Value) -> WdaInfo {
+    let value = &status["value"];
+    let text = |v: &Value| v.as_str().map(|s| s.to_string());
+
+    WdaInfo {
+        // Older builds only report `state: "success"`
+        ready: value["ready"]
+            .as_bool()
+            .unwrap_or_else(|| value["state"] == "success"),
+        message: text(&value["message"]),
+        session_id: text(&status["sessionId"]).or_else(|| text(&value["sessionId"])),
+        os_name: text(&value["os"]["name"]),
+        os_version: text(&value["os"]["version"]),
+        ip: text(&value["ios"]["ip"]),
+        build_version: text(&value["build"]["version"]),
+        capabilities: value
+            .get("capabilities")
+            .filter(|caps| !caps.is_null())
+            .cloned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_status() {
+        let status = json!({
+            "value": {
+                "message": "WebDriverAgent is ready to accept commands",
+                "state": "success",
+                "os": { "name": "iOS", "version": "17.2", "sdkVersion": "17.0" },
+                "ios": { "ip": "192.168.1.20" },
+                "build": { "version": "8.7.1" },
+                "ready": true
+            },
+            "sessionId": "3A1B7C2D"
+        });
+
+        let info = parse_status(&status);
+        assert!(info.ready);
+        assert_eq!(info.session_id.as_deref(), Some("3A1B7C2D"));
+        assert_eq!(info.os_version.as_deref(), Some("17.2"));
+        assert_eq!(info.ip.as_deref(), Some("192.168.1.20"));
+        assert_eq!(info.build_version.as_deref(), Some("8.7.1"));
+        assert_eq!(info.capabilities, None);
+
+        let legacy = parse_status(&json!({ "value": { "state": "success" } }));
+        assert!(legacy.ready);
+        assert!(!parse_status(&json!({})).ready);
+    }
+}